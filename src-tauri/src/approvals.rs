@@ -0,0 +1,278 @@
+// Aprobación interactiva de orígenes desconocidos y clave de API para clientes sin origen
+use crate::origins::{host_matches, origin_host, web_host, Permission};
+use crate::SharedAppState;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::{mpsc, oneshot};
+
+/// Cabecera con la clave de API (peticiones sin Origin ni Referer)
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Tiempo que una petición espera la respuesta del usuario
+pub const APPROVAL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Evento emitido al frontend con cada origen nuevo por aprobar
+pub const APPROVAL_EVENT: &str = "origin-approval-requested";
+
+/// Orígenes distintos en espera a la vez; los siguientes se rechazan sin preguntar
+const MAX_PENDING_ORIGINS: usize = 10;
+
+/// Intervalo mínimo entre dos veces que se trae la ventana al frente
+const FOCUS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Respuesta del usuario
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalChoice {
+    /// Solo las peticiones en espera
+    AllowOnce,
+    /// Se agrega a los orígenes permitidos
+    AllowAlways,
+    /// Se agrega a los orígenes rechazados
+    Deny,
+}
+
+impl ApprovalChoice {
+    pub fn allows(&self) -> bool {
+        !matches!(self, ApprovalChoice::Deny)
+    }
+}
+
+/// Origen en espera de aprobación
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PendingOrigin {
+    /// Hostname del origen
+    pub origin: String,
+    /// Permisos pedidos por las peticiones en espera
+    pub permissions: Vec<Permission>,
+    pub requested_at: String,
+}
+
+/// Peticiones en espera por origen
+#[derive(Debug, Default)]
+pub struct Approvals {
+    pending: HashMap<String, (PendingOrigin, Vec<oneshot::Sender<ApprovalChoice>>)>,
+    notifier: Option<mpsc::UnboundedSender<PendingOrigin>>,
+}
+
+impl Approvals {
+    /// Canal hacia la interfaz (ver `run`)
+    pub fn set_notifier(&mut self, notifier: mpsc::UnboundedSender<PendingOrigin>) {
+        self.notifier = Some(notifier);
+    }
+
+    /// Registrar una petición en espera. Solo la primera de cada origen avisa al usuario.
+    /// `None` si el origen no es un sitio http(s) (ver `origins::web_host`) o si
+    /// ya hay demasiados orígenes distintos esperando.
+    pub fn request(
+        &mut self,
+        origin: &str,
+        permission: Permission,
+    ) -> Option<oneshot::Receiver<ApprovalChoice>> {
+        let host = web_host(origin)?.to_lowercase();
+        if !self.pending.contains_key(&host) && self.pending.len() >= MAX_PENDING_ORIGINS {
+            return None;
+        }
+        let (sender, receiver) = oneshot::channel();
+
+        match self.pending.get_mut(&host) {
+            Some((pending, waiters)) => {
+                if !pending.permissions.contains(&permission) {
+                    pending.permissions.push(permission);
+                }
+                waiters.push(sender);
+            }
+            None => {
+                let pending = PendingOrigin {
+                    origin: host.clone(),
+                    permissions: vec![permission],
+                    requested_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                };
+                if let Some(notifier) = &self.notifier {
+                    let _ = notifier.send(pending.clone());
+                }
+                self.pending.insert(host, (pending, vec![sender]));
+            }
+        }
+        Some(receiver)
+    }
+
+    /// Orígenes en espera, del más antiguo al más nuevo
+    pub fn pending(&self) -> Vec<PendingOrigin> {
+        let mut pending: Vec<PendingOrigin> =
+            self.pending.values().map(|(p, _)| p.clone()).collect();
+        pending.sort_by(|a, b| a.requested_at.cmp(&b.requested_at));
+        pending
+    }
+
+    /// Olvidar un origen cuyas peticiones ya dejaron de esperar
+    pub fn prune(&mut self, origin: &str) {
+        let host = origin_host(origin).to_lowercase();
+        let abandoned = self.pending.get_mut(&host).is_some_and(|(_, waiters)| {
+            waiters.retain(|w| !w.is_closed());
+            waiters.is_empty()
+        });
+        if abandoned {
+            self.pending.remove(&host);
+        }
+    }
+
+    /// Responder a las peticiones en espera de un origen
+    pub fn resolve(&mut self, origin: &str, choice: ApprovalChoice) -> Option<PendingOrigin> {
+        let host = origin_host(origin).to_lowercase();
+        let (pending, waiters) = self.pending.remove(&host)?;
+        for waiter in waiters {
+            let _ = waiter.send(choice);
+        }
+        Some(pending)
+    }
+}
+
+/// El origen fue rechazado por el usuario
+pub fn is_denied(denied: &[String], origin: &str) -> bool {
+    let host = origin_host(origin);
+    !host.is_empty()
+        && denied
+            .iter()
+            .any(|pattern| host_matches(host, pattern.trim()))
+}
+
+/// Comparar la clave recibida con la esperada (tiempo constante)
+pub fn api_key_matches(expected: &str, provided: Option<&str>) -> bool {
+    let Some(provided) = provided.map(str::trim) else {
+        return false;
+    };
+    if expected.is_empty() || provided.len() != expected.len() {
+        return false;
+    }
+    provided
+        .bytes()
+        .zip(expected.bytes())
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+/// La ventana se puede traer al frente otra vez
+fn may_focus(last: Option<std::time::Instant>, now: std::time::Instant) -> bool {
+    last.map_or(true, |last| now.duration_since(last) >= FOCUS_INTERVAL)
+}
+
+/// Avisar a la interfaz de cada origen nuevo por aprobar. La ventana se trae
+/// al frente como mucho una vez cada `FOCUS_INTERVAL`.
+pub async fn run(app: AppHandle, state: SharedAppState) {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    state.write().await.origin_approvals.set_notifier(sender);
+
+    let mut last_focus = None;
+    while let Some(pending) = receiver.recv().await {
+        state.write().await.add_log(
+            "WARN",
+            &format!("Unknown origin {} is waiting for approval", pending.origin),
+        );
+        let now = std::time::Instant::now();
+        if may_focus(last_focus, now) {
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.show();
+                let _ = window.set_focus();
+            }
+            last_focus = Some(now);
+        }
+        let _ = app.emit(APPROVAL_EVENT, pending);
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_waiters_share_one_notification() {
+        let (sender, mut notifications) = mpsc::unbounded_channel();
+        let mut approvals = Approvals::default();
+        approvals.set_notifier(sender);
+
+        let first = approvals
+            .request("https://shop.example.com", Permission::ListPrinters)
+            .unwrap();
+        let second = approvals
+            .request("https://Shop.example.com:8443", Permission::Print)
+            .unwrap();
+
+        let notified = notifications.try_recv().unwrap();
+        assert_eq!(notified.origin, "shop.example.com");
+        assert!(notifications.try_recv().is_err());
+        assert_eq!(
+            approvals.pending()[0].permissions,
+            vec![Permission::ListPrinters, Permission::Print]
+        );
+
+        let resolved = approvals.resolve("shop.example.com", ApprovalChoice::AllowOnce);
+        assert!(resolved.is_some());
+        assert_eq!(first.await.unwrap(), ApprovalChoice::AllowOnce);
+        assert_eq!(second.await.unwrap(), ApprovalChoice::AllowOnce);
+        assert!(approvals.pending().is_empty());
+    }
+
+    #[test]
+    fn test_prune_abandoned_requests() {
+        let mut approvals = Approvals::default();
+        let waiting = approvals.request("https://shop.example.com", Permission::Print);
+        assert!(waiting.is_some());
+        approvals.prune("https://shop.example.com");
+        assert_eq!(approvals.pending().len(), 1);
+
+        drop(waiting);
+        approvals.prune("https://shop.example.com");
+        assert!(approvals.pending().is_empty());
+    }
+
+    #[test]
+    fn test_only_web_origins_are_prompted() {
+        let (sender, mut notifications) = mpsc::unbounded_channel();
+        let mut approvals = Approvals::default();
+        approvals.set_notifier(sender);
+
+        for origin in ["null", "file://", "chrome-extension://abcdef", "https://com"] {
+            assert!(approvals.request(origin, Permission::Print).is_none(), "{}", origin);
+        }
+        assert!(notifications.try_recv().is_err());
+        assert!(approvals.pending().is_empty());
+    }
+
+    #[test]
+    fn test_pending_origins_are_capped() {
+        let mut approvals = Approvals::default();
+        let mut waiting = Vec::new();
+        for i in 0..MAX_PENDING_ORIGINS {
+            let origin = format!("https://shop{}.example.com", i);
+            waiting.push(approvals.request(&origin, Permission::Print).unwrap());
+        }
+        assert!(approvals.request("https://other.example.com", Permission::Print).is_none());
+        // Un origen que ya espera puede sumar peticiones
+        assert!(approvals.request("https://shop0.example.com", Permission::Print).is_some());
+    }
+
+    #[test]
+    fn test_focus_is_throttled() {
+        let start = std::time::Instant::now();
+        assert!(may_focus(None, start));
+        assert!(!may_focus(Some(start), start + FOCUS_INTERVAL / 2));
+        assert!(may_focus(Some(start), start + FOCUS_INTERVAL));
+    }
+
+    #[test]
+    fn test_denied_and_api_key() {
+        let denied = vec!["shop.example.com".to_string()];
+        assert!(is_denied(&denied, "https://shop.example.com"));
+        assert!(!is_denied(&denied, "https://other.example.com"));
+        assert!(!is_denied(&denied, ""));
+
+        assert!(api_key_matches("abc123", Some("abc123")));
+        assert!(!api_key_matches("abc123", Some("abc124")));
+        assert!(!api_key_matches("abc123", None));
+        assert!(!api_key_matches("", Some("")));
+    }
+}
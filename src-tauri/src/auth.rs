@@ -0,0 +1,158 @@
+// Renovación del token de acceso usando el refresh_token guardado
+use crate::graphql::{self, GraphQLClientError, GraphQLResult};
+use crate::persistence;
+use crate::SharedAppState;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use std::future::Future;
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Duration};
+
+/// Renovar cuando falten menos de 5 minutos para el vencimiento
+const REFRESH_MARGIN_SECS: i64 = 5 * 60;
+
+/// Intervalo del chequeo proactivo
+const REFRESH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Evita renovaciones concurrentes (HTTP server, comandos y tarea de fondo)
+static REFRESH_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Leer el claim `exp` (segundos Unix) de un JWT sin verificar la firma
+pub fn token_expiry(token: &str) -> Option<i64> {
+    let payload = token.split('.').nth(1)?;
+    let bytes = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&bytes).ok()?;
+    claims.get("exp")?.as_i64()
+}
+
+/// Verificar si un token está vencido o por vencer
+pub fn needs_refresh(token: &str, now: i64) -> bool {
+    match token_expiry(token) {
+        Some(exp) => exp - now <= REFRESH_MARGIN_SECS,
+        // Token opaco: solo se renueva cuando el backend lo rechace
+        None => false,
+    }
+}
+
+/// Renovar el token y persistir el nuevo par.
+/// `stale_token` es el token que falló; si otro proceso ya lo renovó, no se repite.
+pub async fn refresh_session(state: &SharedAppState, stale_token: Option<&str>) -> GraphQLResult<String> {
+    let _guard = REFRESH_LOCK.lock().await;
+
+    let (api, current_token, refresh_token) = {
+        let app_state = state.read().await;
+        (
+            app_state.api.clone(),
+            app_state.auth.token.clone(),
+            app_state.auth.refresh_token.clone(),
+        )
+    };
+
+    if let (Some(current), Some(stale)) = (current_token.as_deref(), stale_token) {
+        if current != stale {
+            return Ok(current.to_string());
+        }
+    }
+
+    let refresh_token = refresh_token.ok_or(GraphQLClientError::GraphQL {
+        message: "No refresh token available".to_string(),
+        code: Some("UNAUTHENTICATED".to_string()),
+    })?;
+
+    let response = graphql::refresh_token(&api, &refresh_token).await?;
+
+    let mut app_state = state.write().await;
+    app_state.auth.token = Some(response.token.clone());
+    app_state.auth.refresh_token = Some(response.refresh_token);
+    app_state.add_log("INFO", "Access token refreshed");
+
+    if let Err(e) = persistence::save_session(&app_state) {
+        log::warn!("Failed to save refreshed session: {}", e);
+    }
+
+    Ok(response.token)
+}
+
+/// Ejecutar una operación autenticada; si el token fue rechazado, renovarlo y reintentar una vez
+pub async fn with_token_refresh<T, F, Fut>(state: &SharedAppState, operation: F) -> GraphQLResult<T>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = GraphQLResult<T>>,
+{
+    let token = state
+        .read()
+        .await
+        .auth
+        .token
+        .clone()
+        .ok_or(GraphQLClientError::GraphQL {
+            message: "Not logged in".to_string(),
+            code: Some("UNAUTHENTICATED".to_string()),
+        })?;
+
+    match operation(token.clone()).await {
+        Err(e) if e.is_unauthenticated() => {
+            log::info!("Token rejected ({}), refreshing", e);
+            let new_token = refresh_session(state, Some(&token)).await?;
+            operation(new_token).await
+        }
+        result => result,
+    }
+}
+
+/// Tarea de fondo: renovar el token antes de que venza
+pub async fn run_refresh_loop(state: SharedAppState) {
+    loop {
+        sleep(REFRESH_CHECK_INTERVAL).await;
+
+        let token = {
+            let app_state = state.read().await;
+            if !app_state.auth.is_logged_in {
+                continue;
+            }
+            app_state.auth.token.clone()
+        };
+
+        let Some(token) = token else { continue };
+
+        if needs_refresh(&token, chrono::Utc::now().timestamp()) {
+            if let Err(e) = refresh_session(&state, Some(&token)).await {
+                log::warn!("Proactive token refresh failed: {}", e);
+            }
+        }
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_jwt(claims: serde_json::Value) -> String {
+        let header = URL_SAFE_NO_PAD.encode(br#"{"alg":"HS256","typ":"JWT"}"#);
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        format!("{}.{}.signature", header, payload)
+    }
+
+    #[test]
+    fn test_token_expiry() {
+        let token = make_jwt(serde_json::json!({ "sub": "user", "exp": 1_900_000_000 }));
+        assert_eq!(token_expiry(&token), Some(1_900_000_000));
+        assert_eq!(token_expiry("opaque-token"), None);
+        assert_eq!(token_expiry(&make_jwt(serde_json::json!({ "sub": "user" }))), None);
+    }
+
+    #[test]
+    fn test_needs_refresh() {
+        let now = 1_800_000_000;
+        let soon = make_jwt(serde_json::json!({ "exp": now + 60 }));
+        let later = make_jwt(serde_json::json!({ "exp": now + 3600 }));
+        let expired = make_jwt(serde_json::json!({ "exp": now - 10 }));
+
+        assert!(needs_refresh(&soon, now));
+        assert!(!needs_refresh(&later, now));
+        assert!(needs_refresh(&expired, now));
+        assert!(!needs_refresh("opaque-token", now));
+    }
+}
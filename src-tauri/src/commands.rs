@@ -1,18 +1,30 @@
 // Comandos Tauri - equivalentes a los endpoints de Flask
-use crate::graphql;
+use crate::approvals::{ApprovalChoice, PendingOrigin};
+use crate::auth;
+use crate::device::{self, DeviceInfo};
+use crate::graphql::{self, ApiConfig, ApiEnvironment};
+use crate::history::{self, ExportFormat, HistoryPage, HistoryQuery, JobSource};
+use crate::http_server::HttpServerStatus;
+use crate::license::{self, LicenseConfig};
+use crate::logging;
+use crate::origins::{self, AllowedOrigin, Permission};
 use crate::persistence;
-use crate::printer;
-use crate::state::{AuthState, LogEntry};
+use crate::pipeline::{self, Content, Job};
+use crate::pools;
+use crate::printer::{self, DefaultPrinter};
+use crate::quota::{self, QuotaStatus};
+use crate::settings::{PrintProfile, Settings};
+use crate::spool::{self, SpoolFormat, SpooledJob};
+use crate::state::{AuthState, LogEntry, PrinterHealth};
+use crate::tls::{self, TlsFiles};
 use crate::SharedAppState;
 use chrono::Local;
 use printpdf::{BuiltinFont, Color, Mm, PdfDocument, Pt, Rgb};
 use printpdf::svg::{Svg, SvgTransform};
 use serde::{Deserialize, Serialize};
-use std::io::{BufWriter, Write};
-use std::time::SystemTime;
+use std::collections::HashMap;
+use std::io::BufWriter;
 use tauri::State;
-use tempfile::Builder;
-use tokio::time::{sleep, Duration};
 
 const ISIPRINT_LOGO_SVG: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 1021 793">
     <g transform="translate(0,793) scale(0.1,-0.1)" fill="#000000">
@@ -54,18 +66,6 @@ fn mm_to_pt(mm: f64) -> Pt {
     Pt((mm as f32) * 72.0 / 25.4)
 }
 
-/// Dominios permitidos para las solicitudes
-const DOMINIOS_PERMITIDOS: &[&str] = &[
-    "localhost",
-    "127.0.0.1",
-    "*.integrate.com.bo",
-    "*.isipass.net",
-    "*.isipay.me",
-    "*.idematica.net",
-    "*.quickpay.com.bo",
-    "*.isipass.com.bo",
-];
-
 /// Respuesta genérica para los comandos
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CommandResponse<T> {
@@ -109,7 +109,7 @@ fn clamp_mm(v: f64, min: f64, max: f64) -> f64 {
     }
 }
 
-fn settings_to_media(settings: &PrintSettings) -> (String, f64, f64) {
+pub(crate) fn settings_to_media(settings: &PrintSettings) -> (String, f64, f64) {
     // Returns: (cups_media, width_mm, height_mm)
     let preset = settings.preset.to_lowercase();
 
@@ -131,174 +131,11 @@ fn settings_to_media(settings: &PrintSettings) -> (String, f64, f64) {
     }
 }
 
-fn is_pdf_printer(printer_name: &str) -> bool {
+pub(crate) fn is_pdf_printer(printer_name: &str) -> bool {
     let n = printer_name.to_lowercase();
     n.contains("pdf")
 }
 
-fn is_pdfwriter(printer_name: &str) -> bool {
-    printer_name.to_lowercase().contains("pdfwriter")
-}
-
-fn try_find_latest_pdfwriter_output() -> Option<String> {
-    // RWTS PDFwriter typically writes into: /private/var/spool/pdfwriter/<user>/
-    // We best-effort pick the most recently modified .pdf.
-    let user = std::env::var("USER").ok()?;
-    let dir = std::path::Path::new("/private/var/spool/pdfwriter").join(user);
-    let entries = std::fs::read_dir(&dir).ok()?;
-
-    let mut newest: Option<(std::time::SystemTime, std::path::PathBuf)> = None;
-    for entry in entries.flatten() {
-        let path = entry.path();
-        if path.extension().and_then(|e| e.to_str()).map(|e| e.eq_ignore_ascii_case("pdf")) != Some(true) {
-            continue;
-        }
-        let meta = match std::fs::metadata(&path) {
-            Ok(m) => m,
-            Err(_) => continue,
-        };
-        let modified = match meta.modified() {
-            Ok(t) => t,
-            Err(_) => continue,
-        };
-
-        match &newest {
-            None => newest = Some((modified, path)),
-            Some((best_time, _)) if modified > *best_time => newest = Some((modified, path)),
-            _ => {}
-        }
-    }
-
-    newest.map(|(_, p)| p.to_string_lossy().to_string())
-}
-
-fn try_find_latest_pdfwriter_output_since(since: SystemTime) -> Option<String> {
-    let user = std::env::var("USER").ok()?;
-    let dir = std::path::Path::new("/private/var/spool/pdfwriter").join(user);
-    let entries = std::fs::read_dir(&dir).ok()?;
-
-    let mut newest: Option<(std::time::SystemTime, std::path::PathBuf)> = None;
-    for entry in entries.flatten() {
-        let path = entry.path();
-        if path
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|e| e.eq_ignore_ascii_case("pdf"))
-            != Some(true)
-        {
-            continue;
-        }
-
-        let meta = match std::fs::metadata(&path) {
-            Ok(m) => m,
-            Err(_) => continue,
-        };
-        let modified = match meta.modified() {
-            Ok(t) => t,
-            Err(_) => continue,
-        };
-
-        // Must be newer than (or equal to) our start time.
-        if modified < since {
-            continue;
-        }
-
-        match &newest {
-            None => newest = Some((modified, path)),
-            Some((best_time, _)) if modified > *best_time => newest = Some((modified, path)),
-            _ => {}
-        }
-    }
-
-    newest.map(|(_, p)| p.to_string_lossy().to_string())
-}
-
-fn cups_job_key(printer_name: &str, job_id: i32) -> Option<String> {
-    if job_id <= 0 {
-        return None;
-    }
-    Some(format!("{}-{}", printer_name, job_id))
-}
-
-fn cups_job_seen_in_queue(printer_name: &str, job_id: i32) -> Result<bool, String> {
-    use std::process::Command;
-
-    let key = match cups_job_key(printer_name, job_id) {
-        Some(k) => k,
-        None => return Ok(true), // can't verify, don't block
-    };
-
-    let output = Command::new("lpstat")
-        .args(["-o", printer_name])
-        .output()
-        .map_err(|e| format!("Error executing lpstat: {}", e))?;
-
-    // If lpstat returns non-zero for an empty queue, treat as not seen.
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    Ok(stdout.contains(&key))
-}
-
-fn cups_job_seen_in_completed(printer_name: &str, job_id: i32) -> Result<bool, String> {
-    use std::process::Command;
-
-    let key = match cups_job_key(printer_name, job_id) {
-        Some(k) => k,
-        None => return Ok(true), // can't verify, don't block
-    };
-
-    let output = Command::new("lpstat")
-        .args(["-W", "completed"])
-        .output()
-        .map_err(|e| format!("Error executing lpstat: {}", e))?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    Ok(stdout.contains(&key))
-}
-
-async fn verify_cups_job_visible(
-    printer_name: &str,
-    job_id: i32,
-    timeout: Duration,
-) -> Result<(), String> {
-    // Best-effort: only validates that CUPS has *record* of the job (queued or completed).
-    // This avoids returning a false "success" when CUPS silently drops a job.
-    let key = match cups_job_key(printer_name, job_id) {
-        Some(k) => k,
-        None => return Ok(()),
-    };
-
-    let step = Duration::from_millis(250);
-    let mut waited = Duration::from_millis(0);
-
-    while waited < timeout {
-        let in_queue = match cups_job_seen_in_queue(printer_name, job_id) {
-            Ok(v) => v,
-            Err(_e) => {
-                return Ok(());
-            }
-        };
-
-        let in_completed = match cups_job_seen_in_completed(printer_name, job_id) {
-            Ok(v) => v,
-            Err(_e) => {
-                return Ok(());
-            }
-        };
-
-        if in_queue || in_completed {
-            return Ok(());
-        }
-
-        sleep(step).await;
-        waited += step;
-    }
-
-    Err(format!(
-        "Print job {} was submitted but not observed in CUPS queue/completed within {:?}",
-        key, timeout
-    ))
-}
-
 fn normalize_language(language: Option<String>) -> String {
     let raw = language.unwrap_or_else(|| "es".to_string());
     let lower = raw.trim().to_lowercase();
@@ -431,26 +268,6 @@ fn center_line(text: &str, width: usize) -> String {
     format!("{}{}", " ".repeat(pad), text)
 }
 
-async fn verify_pdfwriter_output_visible(since: SystemTime, timeout: Duration) -> Result<String, String> {
-    let step = Duration::from_millis(250);
-    let mut waited = Duration::from_millis(0);
-
-    while waited < timeout {
-        if let Some(out) = try_find_latest_pdfwriter_output_since(since)
-            .or_else(try_find_latest_pdfwriter_output)
-        {
-            return Ok(out);
-        }
-        sleep(step).await;
-        waited += step;
-    }
-
-    Err(format!(
-        "PDFwriter accepted the job, but no output PDF was detected in spool within {:?}",
-        timeout
-    ))
-}
-
 fn generate_test_page_pdf(
     width_mm: f64,
     height_mm: f64,
@@ -615,10 +432,29 @@ pub async fn login(
 ) -> Result<CommandResponse<AuthState>, String> {
     log::info!("Login attempt for: {}", email);
 
-    match graphql::login(&email, &password).await {
+    let api = state.read().await.api.clone();
+
+    match graphql::login(&api, &email, &password).await {
         Ok(response) => {
             // Get licenses after login
-            let licencias = graphql::get_licencias(&response.token).await.unwrap_or_default();
+            let licencias = graphql::get_licencias(&api, &response.token).await;
+            let verified_at = licencias.as_ref().ok().map(|_| chrono::Utc::now().timestamp());
+            let licencias = licencias.unwrap_or_default();
+            let signed_license = license::fetch_signed(&api, &response.token, Some(&email)).await;
+
+            // Identify this device; the backend enforces the license's device limit
+            match device::collect_info().await {
+                Ok(info) => match device::register(&api, &response.token, &info).await {
+                    Err(e) if e.is_device_limit() => {
+                        let mut app_state = state.write().await;
+                        app_state.add_log("ERROR", &format!("Login rejected for {}: {}", email, e));
+                        return Ok(CommandResponse::error(&e.to_string()));
+                    }
+                    Err(e) => log::warn!("Device registration failed: {}", e),
+                    Ok(()) => log::info!("Device {} registered", info.dispositivo_id),
+                },
+                Err(e) => log::warn!("Could not collect device info: {}", e),
+            }
 
             let mut app_state = state.write().await;
             app_state.auth = AuthState {
@@ -627,20 +463,31 @@ pub async fn login(
                 email: Some(email.clone()),
                 is_logged_in: true,
             };
-            app_state.licencias = licencias.clone();
+            app_state.licencias = licencias;
+            app_state.licenses_verified_at = verified_at;
+            app_state.signed_license = signed_license;
+            quota::align_period(&mut app_state);
+            license::apply_config(&mut app_state);
             app_state.add_log("INFO", &format!("Login successful for {}", email));
 
             // Save session to disk for next app start
-            if let Err(e) = persistence::save_session(&app_state.auth, &app_state.licencias) {
+            if let Err(e) = persistence::save_session(&app_state) {
                 log::warn!("Failed to save session: {}", e);
             }
+            let auth = app_state.auth.clone();
+            drop(app_state);
+
+            // Usage recorded by the backend, so a deleted quota.json does not reset it
+            if let Err(e) = quota::pull_usage(state.inner()).await {
+                log::info!("Print usage not fetched from server: {}", e);
+            }
 
-            Ok(CommandResponse::success(app_state.auth.clone()))
+            Ok(CommandResponse::success(auth))
         }
         Err(e) => {
             let mut app_state = state.write().await;
             app_state.add_log("ERROR", &format!("Login error: {}", e));
-            Ok(CommandResponse::error(&e))
+            Ok(CommandResponse::error(&e.to_string()))
         }
     }
 }
@@ -678,81 +525,44 @@ pub async fn get_printers(
     }
 }
 
-/// Imprimir PDF desde archivo - equivalente a /print
+/// Impresora por defecto efectiva para un tipo de documento
 #[tauri::command]
-pub async fn print_pdf(
-    file_path: String,
-    printer_name: String,
+pub async fn get_default_printer(
     state: State<'_, SharedAppState>,
-) -> Result<CommandResponse<String>, String> {
-    {
-        let mut app_state = state.write().await;
-
-        // Verify license
-        if !app_state.is_license_valid() {
-            app_state.add_log("ERROR", "License expired or invalid");
-            return Ok(CommandResponse::error("License expired or invalid"));
-        }
+    document_type: Option<String>,
+) -> Result<CommandResponse<DefaultPrinter>, String> {
+    let defaults = state.read().await.printer_defaults(document_type.as_deref());
 
-        // Verify print limit
-        if !app_state.can_print() {
-            app_state.add_log("ERROR", "Print limit reached");
-            return Ok(CommandResponse::error("Print limit reached"));
-        }
+    let resolved = tokio::task::spawn_blocking(move || printer::resolve_printer_with_source(None, &defaults))
+        .await
+        .map_err(|e| e.to_string())?;
 
-        app_state.add_log(
-            "INFO",
-            &format!("Printing {} on {}", file_path, printer_name),
-        );
+    match resolved {
+        Some((name, source)) => Ok(CommandResponse::success(DefaultPrinter { name, source })),
+        None => Ok(CommandResponse::error("No printers available")),
     }
+}
 
-    // Imprimir archivo
-    let since = SystemTime::now();
-
-    match printer::print_file(&file_path, &printer_name) {
-        Ok(job_id) => {
-            if is_pdfwriter(&printer_name) {
-                match verify_pdfwriter_output_visible(since, Duration::from_secs(8)).await {
-                    Ok(out) => {
-                        let mut app_state = state.write().await;
-                        app_state.add_log("INFO", &format!("PDFwriter output detected: {}", out));
-                    }
-                    Err(e) => {
-                        let mut app_state = state.write().await;
-                        app_state.add_log("ERROR", &format!("PDFwriter verification failed: {}", e));
-                        return Ok(CommandResponse::error(&e));
-                    }
-                }
-            } else if let Err(e) =
-                verify_cups_job_visible(&printer_name, job_id, Duration::from_secs(3)).await
-            {
-                let mut app_state = state.write().await;
-                app_state.add_log("ERROR", &format!("CUPS verification failed: {}", e));
-                return Ok(CommandResponse::error(&e));
-            }
-
-            // Send cut command (only for thermal printers, never for PDF virtual printers)
-            if !is_pdf_printer(&printer_name) {
-                if let Err(e) = printer::send_cut(&printer_name) {
-                    let mut app_state = state.write().await;
-                    app_state.add_log("WARN", &format!("Error sending cut: {}", e));
-                }
-            }
-
-            let mut app_state = state.write().await;
-            app_state.print_count += 1;
-            app_state.add_log("INFO", &format!("Print started. Job ID: {}", job_id));
-
-            Ok(CommandResponse::success("Print started".to_string()))
-        }
-        Err(e) => {
-            let mut app_state = state.write().await;
-            app_state.add_log("ERROR", &format!("Print error: {}", e));
-            Ok(CommandResponse::error(&e))
-        }
+/// Ejecutar un trabajo con el pipeline común y convertir el resultado
+async fn run_print_job(state: &SharedAppState, job: Job) -> Result<CommandResponse<String>, String> {
+    match pipeline::run(state, job).await {
+        Ok(outcome) => Ok(CommandResponse::success(outcome.message())),
+        Err(e) => Ok(CommandResponse::error(&e.message)),
     }
 }
 
+/// Imprimir PDF desde archivo - equivalente a /print
+#[tauri::command]
+pub async fn print_pdf(
+    file_path: String,
+    printer_name: String,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<String>, String> {
+    let job = Job::new(JobSource::App, Content::File(file_path.into()), SpoolFormat::Pdf)
+        .with_printer(Some(printer_name));
+    run_print_job(state.inner(), job).await
+}
+
 /// Imprimir PDF desde archivo con settings (media/tamaño)
 #[tauri::command]
 pub async fn print_pdf_with_settings(
@@ -761,75 +571,10 @@ pub async fn print_pdf_with_settings(
     settings: PrintSettings,
     state: State<'_, SharedAppState>,
 ) -> Result<CommandResponse<String>, String> {
-    {
-        let mut app_state = state.write().await;
-
-        if !app_state.is_license_valid() {
-            app_state.add_log("ERROR", "License expired or invalid");
-            return Ok(CommandResponse::error("License expired or invalid"));
-        }
-
-        if !app_state.can_print() {
-            app_state.add_log("ERROR", "Print limit reached");
-            return Ok(CommandResponse::error("Print limit reached"));
-        }
-
-        app_state.add_log(
-            "INFO",
-            &format!("Printing {} on {} (with settings)", file_path, printer_name),
-        );
-    }
-
-    let (media, _w, _h) = settings_to_media(&settings);
-
-    let print_result = if is_pdf_printer(&printer_name) {
-        // PDF virtual printers often ignore/reject custom media sizes.
-        printer::print_file(&file_path, &printer_name)
-    } else {
-        printer::print_file_with_media(&file_path, &printer_name, Some(&media))
-    };
-
-    let since = SystemTime::now();
-
-    match print_result {
-        Ok(job_id) => {
-            if is_pdfwriter(&printer_name) {
-                if let Err(e) = verify_pdfwriter_output_visible(since, Duration::from_secs(8)).await {
-                    let mut app_state = state.write().await;
-                    app_state.add_log("ERROR", &format!("PDFwriter verification failed: {}", e));
-                    return Ok(CommandResponse::error(&e));
-                }
-            } else if let Err(e) =
-                verify_cups_job_visible(&printer_name, job_id, Duration::from_secs(3)).await
-            {
-                let mut app_state = state.write().await;
-                app_state.add_log("ERROR", &format!("CUPS verification failed: {}", e));
-                return Ok(CommandResponse::error(&e));
-            }
-
-            // Cut is only meaningful for thermal printers.
-            if settings.preset.to_lowercase() == "thermal" && !is_pdf_printer(&printer_name) {
-                if let Err(e) = printer::send_cut(&printer_name) {
-                    let mut app_state = state.write().await;
-                    app_state.add_log("WARN", &format!("Error sending cut: {}", e));
-                }
-            }
-
-            let mut app_state = state.write().await;
-            app_state.print_count += 1;
-            app_state.add_log("INFO", &format!("Print started (with settings). Job ID: {}", job_id));
-
-            Ok(CommandResponse::success(format!(
-                "Print started. Job ID: {}",
-                job_id
-            )))
-        }
-        Err(e) => {
-            let mut app_state = state.write().await;
-            app_state.add_log("ERROR", &format!("Print error: {}", e));
-            Ok(CommandResponse::error(&e))
-        }
-    }
+    let job = Job::new(JobSource::App, Content::File(file_path.into()), SpoolFormat::Pdf)
+        .with_printer(Some(printer_name))
+        .with_paper(settings);
+    run_print_job(state.inner(), job).await
 }
 
 /// Imprimir PDF desde URL - equivalente a /printPDF
@@ -839,96 +584,8 @@ pub async fn print_pdf_from_url(
     printer_name: String,
     state: State<'_, SharedAppState>,
 ) -> Result<CommandResponse<String>, String> {
-    {
-        let mut app_state = state.write().await;
-
-        if !app_state.is_license_valid() {
-            app_state.add_log("ERROR", "License expired or invalid");
-            return Ok(CommandResponse::error("License expired or invalid"));
-        }
-
-        if !app_state.can_print() {
-            app_state.add_log("ERROR", "Print limit reached");
-            return Ok(CommandResponse::error("Print limit reached"));
-        }
-
-        app_state.add_log("INFO", "Downloading PDF");
-    }
-
-    // Download PDF
-    let pdf_data = match printer::download_pdf(&pdf_url).await {
-        Ok(data) => data,
-        Err(e) => {
-            let mut app_state = state.write().await;
-            app_state.add_log("ERROR", &format!("Error downloading PDF: {}", e));
-            return Ok(CommandResponse::error(&e));
-        }
-    };
-
-    // Save to temporary file (use a recognizable name for PDF virtual printers)
-    let mut temp_file = Builder::new()
-        .prefix("isiprint_url_pdf_")
-        .suffix(".pdf")
-        .tempfile()
-        .map_err(|e| format!("Error creating temp file: {}", e))?;
-
-    temp_file
-        .write_all(&pdf_data)
-        .map_err(|e| format!("Error writing temp file: {}", e))?;
-
-    // Persist the temp file so it's not deleted when temp_file goes out of scope
-    let temp_path = temp_file
-        .into_temp_path()
-        .keep()
-        .map_err(|e| format!("Error persisting temp file: {}", e))?;
-    
-    let temp_path_str = temp_path.to_string_lossy().to_string();
-
-    // Print
-    let since = SystemTime::now();
-
-    match printer::print_file(&temp_path_str, &printer_name) {
-        Ok(job_id) => {
-            if is_pdfwriter(&printer_name) {
-                if let Err(e) = verify_pdfwriter_output_visible(since, Duration::from_secs(8)).await {
-                    let mut app_state = state.write().await;
-                    app_state.add_log("ERROR", &format!("PDFwriter verification failed: {}", e));
-                    return Ok(CommandResponse::error(&e));
-                }
-            } else if let Err(e) =
-                verify_cups_job_visible(&printer_name, job_id, Duration::from_secs(3)).await
-            {
-                let mut app_state = state.write().await;
-                app_state.add_log("ERROR", &format!("CUPS verification failed: {}", e));
-                return Ok(CommandResponse::error(&e));
-            }
-
-            // Don't delete the temp file - let the system clean it up later
-            // Virtual printers like PDFwriter need time to process the file
-            
-            if let Err(e) = printer::send_cut(&printer_name) {
-                let mut app_state = state.write().await;
-                app_state.add_log("WARN", &format!("Error sending cut: {}", e));
-            }
-
-            let mut app_state = state.write().await;
-            app_state.print_count += 1;
-            app_state.add_log(
-                "INFO",
-                &format!("PDF printed from URL. Job ID: {}", job_id),
-            );
-
-            Ok(CommandResponse::success(
-                "PDF print started".to_string(),
-            ))
-        }
-        Err(e) => {
-            let _ = std::fs::remove_file(&temp_path);
-            let mut app_state = state.write().await;
-            app_state.add_log("ERROR", &format!("Error printing PDF: {}", e));
-            Ok(CommandResponse::error(&e))
-        }
-    }
+    let job = Job::new(JobSource::App, Content::Url(pdf_url), SpoolFormat::Pdf).with_printer(Some(printer_name));
+    run_print_job(state.inner(), job).await
 }
 
 /// Imprimir PDF desde URL con settings (media/tamaño)
@@ -939,103 +596,10 @@ pub async fn print_pdf_from_url_with_settings(
     settings: PrintSettings,
     state: State<'_, SharedAppState>,
 ) -> Result<CommandResponse<String>, String> {
-    {
-        let mut app_state = state.write().await;
-
-        if !app_state.is_license_valid() {
-            app_state.add_log("ERROR", "License expired or invalid");
-            return Ok(CommandResponse::error("License expired or invalid"));
-        }
-
-        if !app_state.can_print() {
-            app_state.add_log("ERROR", "Print limit reached");
-            return Ok(CommandResponse::error("Print limit reached"));
-        }
-
-        app_state.add_log("INFO", "Downloading PDF");
-    }
-
-    let pdf_data = match printer::download_pdf(&pdf_url).await {
-        Ok(data) => data,
-        Err(e) => {
-            let mut app_state = state.write().await;
-            app_state.add_log("ERROR", &format!("Error downloading PDF: {}", e));
-            return Ok(CommandResponse::error(&e));
-        }
-    };
-
-    let mut temp_file = Builder::new()
-        .prefix("isiprint_url_pdf_")
-        .suffix(".pdf")
-        .tempfile()
-        .map_err(|e| format!("Error creating temp file: {}", e))?;
-
-    temp_file
-        .write_all(&pdf_data)
-        .map_err(|e| format!("Error writing temp file: {}", e))?;
-
-    // Persist the temp file so it's not deleted when temp_file goes out of scope
-    let temp_path = temp_file
-        .into_temp_path()
-        .keep()
-        .map_err(|e| format!("Error persisting temp file: {}", e))?;
-    
-    let temp_path_str = temp_path.to_string_lossy().to_string();
-    let (media, _w, _h) = settings_to_media(&settings);
-
-    let print_result = if is_pdf_printer(&printer_name) {
-        printer::print_file(&temp_path_str, &printer_name)
-    } else {
-        printer::print_file_with_media(&temp_path_str, &printer_name, Some(&media))
-    };
-
-    let since = SystemTime::now();
-
-    match print_result {
-        Ok(job_id) => {
-            if is_pdfwriter(&printer_name) {
-                if let Err(e) = verify_pdfwriter_output_visible(since, Duration::from_secs(8)).await {
-                    let mut app_state = state.write().await;
-                    app_state.add_log("ERROR", &format!("PDFwriter verification failed: {}", e));
-                    return Ok(CommandResponse::error(&e));
-                }
-            } else if let Err(e) =
-                verify_cups_job_visible(&printer_name, job_id, Duration::from_secs(3)).await
-            {
-                let mut app_state = state.write().await;
-                app_state.add_log("ERROR", &format!("CUPS verification failed: {}", e));
-                return Ok(CommandResponse::error(&e));
-            }
-
-            // Don't delete the temp file - let the system clean it up later.
-            // Virtual printers like PDFwriter need time to process the file.
-
-            if settings.preset.to_lowercase() == "thermal" && !is_pdf_printer(&printer_name) {
-                if let Err(e) = printer::send_cut(&printer_name) {
-                    let mut app_state = state.write().await;
-                    app_state.add_log("WARN", &format!("Error sending cut: {}", e));
-                }
-            }
-
-            let mut app_state = state.write().await;
-            app_state.print_count += 1;
-            app_state.add_log(
-                "INFO",
-                &format!("PDF printed from URL (with settings). Job ID: {}", job_id),
-            );
-
-            Ok(CommandResponse::success(format!(
-                "PDF print started. Job ID: {}",
-                job_id
-            )))
-        }
-        Err(e) => {
-            let _ = std::fs::remove_file(&temp_path);
-            let mut app_state = state.write().await;
-            app_state.add_log("ERROR", &format!("Error printing PDF: {}", e));
-            Ok(CommandResponse::error(&e))
-        }
-    }
+    let job = Job::new(JobSource::App, Content::Url(pdf_url), SpoolFormat::Pdf)
+        .with_printer(Some(printer_name))
+        .with_paper(settings);
+    run_print_job(state.inner(), job).await
 }
 
 /// Imprimir una página de prueba (PDF generado) con settings
@@ -1046,24 +610,10 @@ pub async fn print_test_page(
     language: Option<String>,
     state: State<'_, SharedAppState>,
 ) -> Result<CommandResponse<String>, String> {
-    {
-        let mut app_state = state.write().await;
-
-        if !app_state.is_license_valid() {
-            app_state.add_log("ERROR", "License expired or invalid");
-            return Ok(CommandResponse::error("License expired or invalid"));
-        }
-
-        if !app_state.can_print() {
-            app_state.add_log("ERROR", "Print limit reached");
-            return Ok(CommandResponse::error("Print limit reached"));
-        }
-
-        app_state.add_log("INFO", &format!("Test print on {}", printer_name));
-    }
-
     // Detectar si es una impresora de red creada por nosotros (Network_Printer_IP_PORT)
-    if printer_name.starts_with("Network_Printer_") {
+    if printer_name.starts_with("Network_Printer_")
+        && pipeline::validate(state.inner(), SpoolFormat::Raw).await.is_ok()
+    {
         // Formato: Network_Printer_192_168_1_100_9100
         let parts: Vec<&str> = printer_name.split('_').collect();
         if parts.len() >= 4 {
@@ -1080,10 +630,14 @@ pub async fn print_test_page(
                 let raw_printer = crate::raw_printer::RawPrinter::new(&ip, port);
                 
                 // Intentar imprimir ticket de prueba ESC/POS
-                match raw_printer.print_test_receipt() {
+                if let Err(e) = quota::reserve(state.inner(), 1).await {
+                    return Ok(CommandResponse::error(&e));
+                }
+                let printed = raw_printer.print_test_receipt();
+                quota::settle(state.inner(), 1, printed.is_ok() as u32).await;
+                match printed {
                     Ok(_) => {
                         let mut app_state = state.write().await;
-                        app_state.print_count += 1;
                         app_state.add_log("INFO", &format!("RAW Test Print sent to {}:{}", ip, port));
                         return Ok(CommandResponse::success("RAW Test Print sent successfully".to_string()));
                     }
@@ -1099,6 +653,11 @@ pub async fn print_test_page(
 
     let (media, width_mm, height_mm) = settings_to_media(&settings);
 
+    // Without an explicit language, use the one configured in settings
+    let language = match language {
+        Some(l) => Some(l),
+        None => Some(state.read().await.settings.language.clone()),
+    };
     let lang = normalize_language(language);
     let pdf_data = generate_test_page_pdf(
         width_mm,
@@ -1109,106 +668,10 @@ pub async fn print_test_page(
         &lang,
     )?;
 
-    let mut temp_file = Builder::new()
-        .prefix("isiprint_test_page_")
-        .suffix(".pdf")
-        .tempfile()
-        .map_err(|e| format!("Error creating temp file: {}", e))?;
-
-    temp_file
-        .write_all(&pdf_data)
-        .map_err(|e| format!("Error writing temp file: {}", e))?;
-
-    // Persist the temp file so it's not deleted when temp_file goes out of scope
-    // This is critical for virtual printers (like PDF printers) that need time to read the file
-    let pdf_path = temp_file
-        .into_temp_path()
-        .keep()
-        .map_err(|e| format!("Error persisting temp file: {}", e))?;
-    
-    let pdf_path_str = pdf_path.to_string_lossy().to_string();
-    
-    // No debug logs here (avoid leaking temp paths)
-
-    // Capture a reference time so we can detect new PDFwriter outputs.
-    let pdfwriter_since = SystemTime::now();
-
-    let print_result = if is_pdf_printer(&printer_name) {
-        // PDF virtual printers often ignore/reject custom media sizes.
-        printer::print_file(&pdf_path_str, &printer_name)
-    } else {
-        printer::print_file_with_media(&pdf_path_str, &printer_name, Some(&media))
-    };
-
-    match print_result {
-        Ok(job_id) => {
-            // No debug logs here
-
-            // Generic verification for non-PDFwriter printers (avoid false-positive success)
-            if !is_pdfwriter(&printer_name) {
-                if let Err(e) =
-                    verify_cups_job_visible(&printer_name, job_id, Duration::from_secs(3)).await
-                {
-                    let mut app_state = state.write().await;
-                    app_state.add_log("ERROR", &format!("CUPS verification failed: {}", e));
-                    return Ok(CommandResponse::error(&e));
-                }
-            }
-            
-            // Don't delete the temp file - let the system clean it up later
-            // Virtual printers like PDFwriter need time to process the file
-            // The /tmp directory is cleaned automatically by the OS
-            
-            if settings.preset.to_lowercase() == "thermal" && !is_pdf_printer(&printer_name) {
-                if let Err(e) = printer::send_cut(&printer_name) {
-                    let mut app_state = state.write().await;
-                    app_state.add_log("WARN", &format!("Error sending cut: {}", e));
-                }
-            }
-
-            let mut app_state = state.write().await;
-            app_state.print_count += 1;
-            app_state.add_log("INFO", &format!("Test page printed successfully. Job ID: {}", job_id));
-
-            // For PDFwriter, don't report success unless we can actually observe an output PDF
-            // appear in the expected spool folder. Otherwise, return an error (no false positives).
-            if is_pdfwriter(&printer_name) {
-                match verify_pdfwriter_output_visible(pdfwriter_since, Duration::from_secs(8)).await {
-                    Ok(out) => {
-                        let _ = out; // verified, but don't leak path in response
-                        return Ok(CommandResponse::success(format!(
-                            "Test page printed. Job ID: {}",
-                            job_id
-                        )));
-                    }
-                    Err(e) => {
-                        let mut app_state = state.write().await;
-                        app_state.add_log(
-                            "ERROR",
-                            &format!(
-                                "PDFwriter job {} submitted but no output PDF detected",
-                                job_id
-                            ),
-                        );
-                        return Ok(CommandResponse::error(&e));
-                    }
-                }
-            }
-
-            Ok(CommandResponse::success(format!(
-                "Test page print started. Job ID: {}",
-                job_id
-            )))
-        }
-        Err(e) => {
-            // Clean up on error
-            let _ = std::fs::remove_file(&pdf_path);
-            
-            let mut app_state = state.write().await;
-            app_state.add_log("ERROR", &format!("Test print error: {}", e));
-            Ok(CommandResponse::error(&e))
-        }
-    }
+    let job = Job::new(JobSource::App, Content::Bytes(pdf_data), SpoolFormat::Pdf)
+        .with_printer(Some(printer_name))
+        .with_paper(settings);
+    run_print_job(state.inner(), job).await
 }
 
 /// Obtener trabajos de impresión - equivalente a /print_jobs
@@ -1280,29 +743,16 @@ pub async fn get_logs(state: State<'_, SharedAppState>) -> Result<Vec<LogEntry>,
     Ok(app_state.logs.iter().cloned().collect())
 }
 
-/// Validar dominio - equivalente a verificar_dominio en Python
+/// Validar dominio - equivalente a verificar_dominio en Python.
+/// Usa la misma lista de orígenes que el servidor HTTP.
 #[tauri::command]
-pub fn validate_domain(origin: String) -> Result<bool, String> {
-    if let Ok(parsed) = url::Url::parse(&origin) {
-        if let Some(host) = parsed.host_str() {
-            // Permitir localhost
-            if host == "localhost" || host == "127.0.0.1" {
-                return Ok(true);
-            }
-
-            // Verificar dominios permitidos con fnmatch
-            for pattern in DOMINIOS_PERMITIDOS {
-                if fnmatch_regex::glob_to_regex(pattern)
-                    .map(|re| re.is_match(host))
-                    .unwrap_or(false)
-                {
-                    return Ok(true);
-                }
-            }
-        }
-    }
-
-    Ok(false)
+pub async fn validate_domain(origin: String, state: State<'_, SharedAppState>) -> Result<bool, String> {
+    let app_state = state.read().await;
+    Ok(origins::is_known(
+        &app_state.settings.allowed_origins,
+        &app_state.license_config.allowed_origins,
+        &origin,
+    ))
 }
 
 /// Get authentication state
@@ -1337,20 +787,30 @@ pub async fn verify_session(state: State<'_, SharedAppState>) -> Result<CommandR
     };
     
     drop(app_state);
+
+    // Refresh ahead of time if the saved token is about to expire
+    if auth::needs_refresh(&token, chrono::Utc::now().timestamp()) {
+        if let Err(e) = auth::refresh_session(state.inner(), Some(&token)).await {
+            log::info!("Could not refresh saved token: {}", e);
+        }
+    }
     
-    // Try to get licenses with the saved token - if it works, token is valid
-    match graphql::get_licencias(&token).await {
-        Ok(licencias) => {
-            // Token is valid, update licenses (they might have changed)
+    // Try to get licenses with the saved token - if it works, token is valid.
+    // A rejected token is refreshed once with the stored refresh_token.
+    match license::refresh_licenses(state.inner()).await {
+        Ok(_) => {
             let mut app_state = state.write().await;
-            app_state.licencias = licencias.clone();
             app_state.add_log("INFO", "Session restored from saved data");
-            
-            // Update saved session with fresh license data
-            if let Err(e) = persistence::save_session(&app_state.auth, &app_state.licencias) {
-                log::warn!("Failed to update saved session: {}", e);
+            Ok(CommandResponse::success(app_state.auth.clone()))
+        }
+        Err(e) if e.is_unreachable() => {
+            // Backend down: keep the session and rely on the signed license cache
+            let mut app_state = state.write().await;
+            if let Err(grace) = app_state.check_license() {
+                app_state.add_log("ERROR", &format!("License server unreachable: {}", grace));
+                return Ok(CommandResponse::error(&grace));
             }
-            
+            app_state.add_log("WARN", &format!("License server unreachable, using cached license: {}", e));
             Ok(CommandResponse::success(app_state.auth.clone()))
         }
         Err(e) => {
@@ -1359,6 +819,9 @@ pub async fn verify_session(state: State<'_, SharedAppState>) -> Result<CommandR
             let mut app_state = state.write().await;
             app_state.auth = AuthState::default();
             app_state.licencias.clear();
+            app_state.licenses_verified_at = None;
+            app_state.signed_license = None;
+            app_state.license_config = LicenseConfig::default();
             app_state.add_log("INFO", "Session expired, please log in again");
             
             // Clear saved session
@@ -1375,6 +838,9 @@ pub async fn logout(state: State<'_, SharedAppState>) -> Result<CommandResponse<
     let mut app_state = state.write().await;
     app_state.auth = AuthState::default();
     app_state.licencias.clear();
+    app_state.licenses_verified_at = None;
+    app_state.signed_license = None;
+    app_state.license_config = LicenseConfig::default();
     app_state.add_log("INFO", "Session closed");
     
     // Clear saved session from disk
@@ -1385,6 +851,46 @@ pub async fn logout(state: State<'_, SharedAppState>) -> Result<CommandResponse<
     Ok(CommandResponse::success("Session closed".to_string()))
 }
 
+/// Get the backend API environment in use
+#[tauri::command]
+pub async fn get_api_config(state: State<'_, SharedAppState>) -> Result<ApiConfig, String> {
+    let app_state = state.read().await;
+    Ok(app_state.api.clone())
+}
+
+/// Select the backend API environment (sandbox, production or custom)
+#[tauri::command]
+pub async fn set_api_config(
+    environment: ApiEnvironment,
+    api_url: Option<String>,
+    shop: Option<String>,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<ApiConfig>, String> {
+    let api = match ApiConfig::for_environment(environment, api_url, shop) {
+        Ok(api) => api,
+        Err(e) => return Ok(CommandResponse::error(&e)),
+    };
+
+    let mut app_state = state.write().await;
+
+    // A token is only valid for the API that issued it
+    if app_state.auth.is_logged_in && app_state.api != api {
+        return Ok(CommandResponse::error("Log out before changing the API environment"));
+    }
+
+    if let Err(e) = persistence::save_api_config(&api) {
+        log::warn!("Failed to save API settings: {}", e);
+    }
+
+    app_state.add_log(
+        "INFO",
+        &format!("API environment set to {:?} ({}, shop {})", api.environment, api.api_url, api.shop),
+    );
+    app_state.api = api.clone();
+
+    Ok(CommandResponse::success(api))
+}
+
 // ==================== NETWORK DISCOVERY COMMANDS ====================
 
 /// Obtener la IP local del dispositivo
@@ -1412,6 +918,10 @@ pub async fn scan_network_printers(
     state: State<'_, SharedAppState>,
 ) -> Result<CommandResponse<Vec<crate::network_discovery::NetworkPrinter>>, String> {
     let mut app_state = state.write().await;
+    if !app_state.license_config.features.network_scan {
+        app_state.add_log("WARN", "Network scan disabled by license configuration");
+        return Ok(CommandResponse::error("Network scan is disabled by your license configuration"));
+    }
     app_state.add_log("INFO", "Starting network scan for printers...");
     drop(app_state);
 
@@ -1480,6 +990,63 @@ pub async fn add_network_printer(
     }
 }
 
+/// Sondear una dirección (IP/hostname, puerto y ruta opcionales) en todos los protocolos
+#[tauri::command]
+pub async fn probe_printer_address(
+    address: String,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<Vec<crate::network_discovery::ProtocolProbe>>, String> {
+    let mut app_state = state.write().await;
+    app_state.add_log("INFO", &format!("Probing printer address: {}", address));
+    drop(app_state);
+
+    match crate::network_discovery::probe_printer_address(&address).await {
+        Ok(probes) => {
+            let mut app_state = state.write().await;
+            let usable = probes.iter().filter(|p| p.is_usable()).count();
+            app_state.add_log(
+                "INFO",
+                &format!("Probe of {} complete. {} usable protocols", address, usable),
+            );
+            Ok(CommandResponse::success(probes))
+        }
+        Err(e) => {
+            let mut app_state = state.write().await;
+            app_state.add_log("ERROR", &format!("Error probing {}: {}", address, e));
+            Ok(CommandResponse::error(&e))
+        }
+    }
+}
+
+/// Agregar una impresora por dirección con un nombre de cola elegido por el usuario
+#[tauri::command]
+pub async fn add_printer_by_address(
+    address: String,
+    name: String,
+    protocol: Option<String>,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<crate::network_discovery::NetworkPrinter>, String> {
+    let mut app_state = state.write().await;
+    app_state.add_log("INFO", &format!("Adding printer {} at {}", name, address));
+    drop(app_state);
+
+    match crate::network_discovery::add_printer_by_address(&address, &name, protocol.as_deref()).await {
+        Ok((printer, message)) => {
+            let mut app_state = state.write().await;
+            app_state.add_log(
+                "INFO",
+                &format!("{} ({})", message, crate::network_discovery::printer_uri(&printer)),
+            );
+            Ok(CommandResponse::success(printer))
+        }
+        Err(e) => {
+            let mut app_state = state.write().await;
+            app_state.add_log("ERROR", &format!("Error adding printer: {}", e));
+            Ok(CommandResponse::error(&e))
+        }
+    }
+}
+
 /// Eliminar una impresora de red
 #[tauri::command]
 pub async fn remove_network_printer(
@@ -1503,6 +1070,392 @@ pub async fn remove_network_printer(
     }
 }
 
+/// Estado de salud e historial de las impresoras configuradas
+#[tauri::command]
+pub async fn get_printer_health(
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<HashMap<String, PrinterHealth>>, String> {
+    let app_state = state.read().await;
+    Ok(CommandResponse::success(app_state.printer_health.clone()))
+}
+
+/// Direcciones abiertas por el servidor HTTP local y errores al abrirlas
+#[tauri::command]
+pub async fn get_http_server_status(
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<HttpServerStatus>, String> {
+    let app_state = state.read().await;
+    Ok(CommandResponse::success(app_state.http_server.clone()))
+}
+
+/// Local settings (printer profiles, default printers, HTTP server, language)
+#[tauri::command]
+pub async fn get_settings(state: State<'_, SharedAppState>) -> Result<Settings, String> {
+    let app_state = state.read().await;
+    Ok(app_state.settings.clone())
+}
+
+/// Validate, persist and apply settings
+async fn store_settings(state: &SharedAppState, settings: Settings) -> CommandResponse<Settings> {
+    if let Err(e) = pools::check_installed_printers(&settings).await {
+        state.write().await.add_log("ERROR", &format!("Settings not saved: {}", e));
+        return CommandResponse::error(&e);
+    }
+
+    let mut app_state = state.write().await;
+    if let Err(e) = persistence::save_settings(&settings) {
+        app_state.add_log("ERROR", &format!("Settings not saved: {}", e));
+        return CommandResponse::error(&e);
+    }
+    if settings.http != app_state.settings.http {
+        app_state.add_log("INFO", "HTTP server settings changed, restart ISIPRINT to apply them");
+    }
+    app_state.settings = settings.clone();
+    app_state.add_log("INFO", "Settings saved");
+    CommandResponse::success(settings)
+}
+
+/// Replace all settings
+#[tauri::command]
+pub async fn update_settings(
+    settings: Settings,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<Settings>, String> {
+    let mut settings = settings;
+    settings.version = crate::settings::SETTINGS_VERSION;
+    Ok(store_settings(state.inner(), settings).await)
+}
+
+/// Create or replace a named printer profile
+#[tauri::command]
+pub async fn save_print_profile(
+    profile: PrintProfile,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<Settings>, String> {
+    let mut settings = state.read().await.settings.clone();
+    settings.upsert_profile(profile);
+    Ok(store_settings(state.inner(), settings).await)
+}
+
+/// Delete a named printer profile
+#[tauri::command]
+pub async fn delete_print_profile(
+    name: String,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<Settings>, String> {
+    let mut settings = state.read().await.settings.clone();
+    if !settings.remove_profile(&name) {
+        return Ok(CommandResponse::error(&format!("Profile {} not found", name)));
+    }
+    Ok(store_settings(state.inner(), settings).await)
+}
+
+/// Allow a web origin ("host" or "*.domain") or replace its permissions
+#[tauri::command]
+pub async fn save_allowed_origin(
+    pattern: String,
+    permissions: Vec<Permission>,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<Settings>, String> {
+    let mut settings = state.read().await.settings.clone();
+    settings.upsert_allowed_origin(AllowedOrigin::new(&pattern, permissions));
+    Ok(store_settings(state.inner(), settings).await)
+}
+
+/// Remove an allowed web origin
+#[tauri::command]
+pub async fn remove_allowed_origin(
+    pattern: String,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<Settings>, String> {
+    let mut settings = state.read().await.settings.clone();
+    if !settings.remove_allowed_origin(&pattern) {
+        return Ok(CommandResponse::error(&format!("Origin {} not found", pattern)));
+    }
+    Ok(store_settings(state.inner(), settings).await)
+}
+
+/// Unknown origins waiting for the user's approval
+#[tauri::command]
+pub async fn get_pending_origins(
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<Vec<PendingOrigin>>, String> {
+    let app_state = state.read().await;
+    Ok(CommandResponse::success(app_state.origin_approvals.pending()))
+}
+
+/// Answer the approval prompt of an unknown origin. "allow_always" and "deny"
+/// are saved in the settings; "allow_once" only lets the waiting requests through.
+#[tauri::command]
+pub async fn resolve_origin_approval(
+    origin: String,
+    choice: ApprovalChoice,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<Settings>, String> {
+    let (pending, mut settings) = {
+        let mut app_state = state.write().await;
+        let Some(pending) = app_state.origin_approvals.resolve(&origin, choice) else {
+            return Ok(CommandResponse::error(&format!(
+                "No pending approval for origin {}",
+                origin
+            )));
+        };
+        let (level, outcome) = match choice {
+            ApprovalChoice::AllowOnce => ("INFO", "allowed once"),
+            ApprovalChoice::AllowAlways => ("SUCCESS", "allowed"),
+            ApprovalChoice::Deny => ("WARN", "denied"),
+        };
+        app_state.add_log(level, &format!("Origin {} {}", pending.origin, outcome));
+        (pending, app_state.settings.clone())
+    };
+
+    match choice {
+        ApprovalChoice::AllowOnce => Ok(CommandResponse::success(settings)),
+        ApprovalChoice::AllowAlways => {
+            // Solo imprimir y consultar: configurar y limpiar se conceden desde la lista de orígenes
+            let permissions = Permission::DEFAULT.to_vec();
+            settings.upsert_allowed_origin(AllowedOrigin::new(&pending.origin, permissions));
+            Ok(store_settings(state.inner(), settings).await)
+        }
+        ApprovalChoice::Deny => {
+            settings.deny_origin(&pending.origin);
+            // También el resto del sitio, para que otro subdominio no vuelva a preguntar
+            if let Some(domain) = origins::registrable_domain(&pending.origin) {
+                settings.deny_origin(&format!("*.{}", domain));
+            }
+            Ok(store_settings(state.inner(), settings).await)
+        }
+    }
+}
+
+/// API key required from local clients that send no Origin (curl, scripts)
+#[tauri::command]
+pub async fn get_api_key(state: State<'_, SharedAppState>) -> Result<CommandResponse<String>, String> {
+    let app_state = state.read().await;
+    match &app_state.api_key {
+        Some(key) => Ok(CommandResponse::success(key.clone())),
+        None => Ok(CommandResponse::error("API key unavailable")),
+    }
+}
+
+/// Replace the API key; clients using the previous one stop working
+#[tauri::command]
+pub async fn regenerate_api_key(
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<String>, String> {
+    match persistence::regenerate_api_key() {
+        Ok(key) => {
+            let mut app_state = state.write().await;
+            app_state.api_key = Some(key.clone());
+            app_state.add_log("WARN", "API key regenerated");
+            Ok(CommandResponse::success(key))
+        }
+        Err(e) => Ok(CommandResponse::error(&e)),
+    }
+}
+
+/// Set (or clear) the default printer for a document type, or the general default
+#[tauri::command]
+pub async fn set_default_printer(
+    document_type: Option<String>,
+    printer_name: Option<String>,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<Settings>, String> {
+    let mut settings = state.read().await.settings.clone();
+    settings.set_default_printer(document_type.as_deref(), printer_name);
+    Ok(store_settings(state.inner(), settings).await)
+}
+
+/// Job history, newest first, filtered and paged
+#[tauri::command]
+pub async fn get_job_history(query: Option<HistoryQuery>) -> Result<CommandResponse<HistoryPage>, String> {
+    let query = query.unwrap_or_default();
+    let result = tokio::task::spawn_blocking(move || history::query(&query))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match result {
+        Ok(page) => Ok(CommandResponse::success(page)),
+        Err(e) => Ok(CommandResponse::error(&e)),
+    }
+}
+
+/// Export the filtered job history as CSV or JSON text
+#[tauri::command]
+pub async fn export_job_history(
+    query: Option<HistoryQuery>,
+    format: Option<ExportFormat>,
+) -> Result<CommandResponse<String>, String> {
+    let query = query.unwrap_or_default();
+    let format = format.unwrap_or_default();
+    let result = tokio::task::spawn_blocking(move || history::export(&query, format))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match result {
+        Ok(content) => Ok(CommandResponse::success(content)),
+        Err(e) => Ok(CommandResponse::error(&e)),
+    }
+}
+
+/// Jobs waiting in the retry queue
+#[tauri::command]
+pub async fn get_spooled_jobs() -> Result<CommandResponse<Vec<SpooledJob>>, String> {
+    match spool::list_jobs() {
+        Ok(jobs) => Ok(CommandResponse::success(jobs)),
+        Err(e) => Ok(CommandResponse::error(&e.to_string())),
+    }
+}
+
+/// Retry a queued job now
+#[tauri::command]
+pub async fn retry_spooled_job(
+    id: String,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<String>, String> {
+    match spool::retry_now(state.inner(), &id).await {
+        Ok(msg) => Ok(CommandResponse::success(msg)),
+        Err(e) => Ok(CommandResponse::error(&e.to_string())),
+    }
+}
+
+/// Send a queued job to another printer or pool
+#[tauri::command]
+pub async fn reroute_spooled_job(
+    id: String,
+    printer_name: String,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<SpooledJob>, String> {
+    match spool::reroute(state.inner(), &id, &printer_name).await {
+        Ok(job) => Ok(CommandResponse::success(job)),
+        Err(e) => Ok(CommandResponse::error(&e.to_string())),
+    }
+}
+
+/// Drop a queued job without printing it
+#[tauri::command]
+pub async fn discard_spooled_job(
+    id: String,
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<SpooledJob>, String> {
+    match spool::discard(state.inner(), &id).await {
+        Ok(job) => Ok(CommandResponse::success(job)),
+        Err(e) => Ok(CommandResponse::error(&e.to_string())),
+    }
+}
+
+/// Identity and inventory this device reports to the backend
+#[tauri::command]
+pub async fn get_device_info() -> Result<CommandResponse<DeviceInfo>, String> {
+    match device::collect_info().await {
+        Ok(info) => Ok(CommandResponse::success(info)),
+        Err(e) => Ok(CommandResponse::error(&e)),
+    }
+}
+
+/// Configuration pushed by the backend with the print license
+#[tauri::command]
+pub async fn get_license_config(state: State<'_, SharedAppState>) -> Result<LicenseConfig, String> {
+    let app_state = state.read().await;
+    Ok(app_state.license_config.clone())
+}
+
+/// Print quota usage for the current license period
+#[tauri::command]
+pub async fn get_quota_status(
+    state: State<'_, SharedAppState>,
+) -> Result<CommandResponse<QuotaStatus>, String> {
+    let app_state = state.read().await;
+    match app_state.quota_status() {
+        Some(status) => Ok(CommandResponse::success(status)),
+        None => Ok(CommandResponse::error("No print license found")),
+    }
+}
+
+/// Export a diagnostics bundle (logs, settings, printers and service state) as a zip.
+/// Tokens, keys and the session file are never included.
+#[tauri::command]
+pub async fn export_diagnostics(state: State<'_, SharedAppState>) -> Result<CommandResponse<String>, String> {
+    let summary = {
+        let app_state = state.read().await;
+        serde_json::json!({
+            "generated_at": Local::now().to_rfc3339(),
+            "app_version": env!("CARGO_PKG_VERSION"),
+            "os": std::env::consts::OS,
+            "arch": std::env::consts::ARCH,
+            "api": app_state.api,
+            "logged_in": app_state.auth.is_logged_in,
+            "settings": app_state.settings,
+            "http_server": app_state.http_server,
+            "license_config": app_state.license_config,
+            "quota": app_state.quota_status(),
+            "printer_health": app_state.printer_health,
+            "recent_logs": app_state.logs,
+        })
+    };
+
+    let result = tokio::task::spawn_blocking(move || {
+        let mut summary = summary;
+        summary["printers"] = serde_json::json!(printer::list_printers().unwrap_or_default());
+        summary["system_default_printer"] = serde_json::json!(printer::system_default_printer());
+        summary["spool"] = serde_json::json!(spool::list_jobs().unwrap_or_default());
+
+        let dir = dirs::download_dir()
+            .or_else(persistence::get_log_dir)
+            .ok_or("Could not find a folder for the diagnostics bundle")?;
+        let path = dir.join(format!("isiprint-diagnostics-{}.zip", Local::now().format("%Y%m%d-%H%M%S")));
+        logging::write_diagnostics_bundle(&path, &summary, &[])?;
+        Ok::<_, String>(path.to_string_lossy().to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    match result {
+        Ok(path) => {
+            state.write().await.add_log("INFO", &format!("Diagnostics bundle saved to {}", path));
+            Ok(CommandResponse::success(path))
+        }
+        Err(e) => Ok(CommandResponse::error(&e)),
+    }
+}
+
+/// Export the local HTTPS CA certificate for installation in the system or browser
+#[tauri::command]
+pub async fn export_https_ca(state: State<'_, SharedAppState>) -> Result<CommandResponse<String>, String> {
+    let (http, serving) = {
+        let app_state = state.read().await;
+        (app_state.settings.http.clone(), app_state.http_server.https.is_some())
+    };
+
+    let result = tokio::task::spawn_blocking(move || {
+        // Se genera si HTTPS todavía no se activó. Si ya está activo se exporta la CA
+        // del certificado en uso: volver a emitirlo la cambiaría.
+        let files = TlsFiles::open()?;
+        if !serving {
+            files.ensure(&tls::server_names(&http), chrono::Utc::now())?;
+        }
+
+        let dir = dirs::download_dir()
+            .or_else(persistence::get_tls_dir)
+            .ok_or("Could not find a folder for the certificate")?;
+        let path = dir.join("isiprint-local-ca.crt");
+        std::fs::copy(files.ca_path(), &path).map_err(|e| format!("Failed to export certificate: {}", e))?;
+        Ok::<_, String>(path.to_string_lossy().to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    match result {
+        Ok(path) => {
+            let mut app_state = state.write().await;
+            app_state.http_server.ca_reinstall = false;
+            app_state.add_log("INFO", &format!("Local CA certificate saved to {}", path));
+            Ok(CommandResponse::success(path))
+        }
+        Err(e) => Ok(CommandResponse::error(&e)),
+    }
+}
+
 // ==================== TESTS ====================
 
 #[cfg(test)]
@@ -1512,12 +1465,12 @@ mod tests {
     /// Test: Validación de dominios permitidos
     #[test]
     fn test_validate_domain_localhost() {
-        // localhost debe ser permitido
+        // localhost solo se permite en desarrollo
         let result = validate_domain_internal("http://localhost:1420");
-        assert!(result, "localhost debería estar permitido");
+        assert_eq!(result, cfg!(debug_assertions), "localhost solo en depuración");
 
         let result = validate_domain_internal("http://127.0.0.1:7777");
-        assert!(result, "127.0.0.1 debería estar permitido");
+        assert_eq!(result, cfg!(debug_assertions), "127.0.0.1 solo en depuración");
     }
 
     #[test]
@@ -1575,29 +1528,6 @@ mod tests {
 
     /// Función interna para tests (sin State de Tauri)
     fn validate_domain_internal(origin: &str) -> bool {
-        if let Ok(parsed) = url::Url::parse(origin) {
-            if let Some(host) = parsed.host_str() {
-                // Permitir localhost
-                if host == "localhost" || host == "127.0.0.1" {
-                    return true;
-                }
-
-                // Verificar dominios permitidos con fnmatch
-                for pattern in DOMINIOS_PERMITIDOS {
-                    // Coincidencia exacta primero
-                    if *pattern == host {
-                        return true;
-                    }
-                    // Luego wildcard
-                    if fnmatch_regex::glob_to_regex(pattern)
-                        .map(|re| re.is_match(host))
-                        .unwrap_or(false)
-                    {
-                        return true;
-                    }
-                }
-            }
-        }
-        false
+        origins::is_known(&origins::default_origins(), &[], origin)
     }
 }
@@ -0,0 +1,177 @@
+// Identidad de este equipo ante el backend: registro al iniciar sesión y latido periódico
+use crate::auth;
+use crate::graphql::{self, ApiConfig, GraphQLResult};
+use crate::persistence;
+use crate::printer;
+use crate::SharedAppState;
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Duration};
+
+/// Intervalo del latido
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Reintento cuando el backend no responde
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Datos del dispositivo enviados al registrarse y en cada latido
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    #[serde(rename = "dispositivoId")]
+    pub dispositivo_id: String,
+    pub nombre: String,
+    #[serde(rename = "sistemaOperativo")]
+    pub sistema_operativo: String,
+    pub version: String,
+    /// Impresoras instaladas en el equipo
+    pub impresoras: Vec<String>,
+}
+
+/// ID persistente del dispositivo
+pub fn device_id() -> Result<String, String> {
+    persistence::load_or_create_device_id()
+}
+
+/// Nombre visible del equipo (hostname)
+pub fn device_name() -> String {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| {
+            std::process::Command::new("hostname")
+                .output()
+                .ok()
+                .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "ISIPRINT".to_string())
+}
+
+/// Sistema operativo y arquitectura
+fn os_description() -> String {
+    format!("{} {}", std::env::consts::OS, std::env::consts::ARCH)
+}
+
+/// Reunir la información del dispositivo (incluye el inventario de impresoras)
+pub async fn collect_info() -> Result<DeviceInfo, String> {
+    let dispositivo_id = device_id()?;
+
+    let (nombre, impresoras) = tokio::task::spawn_blocking(|| {
+        let printers = printer::list_printers().unwrap_or_else(|e| {
+            log::warn!("Could not list printers for device inventory: {}", e);
+            Vec::new()
+        });
+        (device_name(), printers)
+    })
+    .await
+    .map_err(|e| format!("Failed to collect device info: {}", e))?;
+
+    Ok(DeviceInfo {
+        dispositivo_id,
+        nombre,
+        sistema_operativo: os_description(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        impresoras,
+    })
+}
+
+/// Registrar el dispositivo en la cuenta que acaba de iniciar sesión (solo en el login:
+/// el backend aplica ahí el límite de dispositivos). El latido reutiliza el ID guardado.
+pub async fn register(api: &ApiConfig, token: &str, device: &DeviceInfo) -> GraphQLResult<()> {
+    graphql::register_device(api, token, device).await
+}
+
+/// Enviar un latido con el inventario actual
+async fn heartbeat(state: &SharedAppState, device: &DeviceInfo) -> GraphQLResult<()> {
+    let api = state.read().await.api.clone();
+
+    auth::with_token_refresh(state, |token| {
+        let api = api.clone();
+        async move { graphql::device_heartbeat(&api, &token, device).await }
+    })
+    .await
+}
+
+/// Tarea de fondo: enviar latidos mientras haya sesión. El dispositivo ya quedó
+/// registrado al iniciar sesión; una sesión restaurada conserva el mismo ID.
+pub async fn run_heartbeat(state: SharedAppState) {
+    loop {
+        if !state.read().await.auth.is_logged_in {
+            sleep(RETRY_INTERVAL).await;
+            continue;
+        }
+
+        let device = match collect_info().await {
+            Ok(d) => d,
+            Err(e) => {
+                log::error!("Device heartbeat disabled: {}", e);
+                return;
+            }
+        };
+
+        match heartbeat(&state, &device).await {
+            Ok(()) => sleep(HEARTBEAT_INTERVAL).await,
+            Err(e) => {
+                if e.is_device_limit() {
+                    let mut app_state = state.write().await;
+                    app_state.add_log("ERROR", &format!("Device rejected by license: {}", e));
+                } else if !e.is_unreachable() {
+                    log::warn!("Device heartbeat failed: {}", e);
+                }
+                sleep(RETRY_INTERVAL).await;
+            }
+        }
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppState;
+    use crate::test_backend::TestBackend;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    fn test_device() -> DeviceInfo {
+        DeviceInfo {
+            dispositivo_id: "abc123".to_string(),
+            nombre: "CAJA-01".to_string(),
+            sistema_operativo: "linux x86_64".to_string(),
+            version: "4.0.0".to_string(),
+            impresoras: vec!["Caja".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_device_info_serialization() {
+        let json = serde_json::to_value(test_device()).unwrap();
+        assert_eq!(json["dispositivoId"], "abc123");
+        assert_eq!(json["sistemaOperativo"], "linux x86_64");
+        assert_eq!(json["impresoras"][0], "Caja");
+    }
+
+    #[tokio::test]
+    async fn test_register_sends_device_info() {
+        let backend = TestBackend::start(|operation, _| match operation {
+            "REGISTRAR_DISPOSITIVO" => serde_json::json!({ "data": { "registrarDispositivo": { "_id": "d1" } } }),
+            _ => serde_json::json!({
+                "errors": [{ "message": "Límite de dispositivos", "extensions": { "code": "DEVICE_LIMIT_EXCEEDED" } }]
+            }),
+        })
+        .await;
+
+        let mut app_state = AppState::default();
+        app_state.api = backend.api.clone();
+        app_state.auth.token = Some("test-token".to_string());
+        let state: SharedAppState = Arc::new(RwLock::new(app_state));
+
+        register(&backend.api, "test-token", &test_device()).await.unwrap();
+        let requests = backend.requests("REGISTRAR_DISPOSITIVO");
+        assert_eq!(requests[0].variables["input"]["nombre"], "CAJA-01");
+        assert_eq!(requests[0].variables["input"]["version"], "4.0.0");
+
+        let err = heartbeat(&state, &test_device()).await.unwrap_err();
+        assert!(err.is_device_limit());
+    }
+}
@@ -1,8 +1,160 @@
 // Módulo de integración con GraphQL
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+use crate::device::DeviceInfo;
+use crate::license::SignedLicense;
 use crate::state::LicenciaProducto;
 
-const API_URL: &str = "https://sandbox.isipass.net/api";
+/// Endpoints y shop por defecto de cada entorno
+pub const SANDBOX_API_URL: &str = "https://sandbox.isipass.net/api";
+pub const SANDBOX_SHOP: &str = "sandbox";
+pub const PRODUCTION_API_URL: &str = "https://api.isipass.net/api";
+
+/// Variables de entorno que sobreescriben la configuración guardada
+const ENV_API_ENVIRONMENT: &str = "ISIPRINT_API_ENV";
+const ENV_API_URL: &str = "ISIPRINT_API_URL";
+const ENV_SHOP: &str = "ISIPRINT_SHOP";
+
+/// Entorno del backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiEnvironment {
+    #[default]
+    Sandbox,
+    Production,
+    Custom,
+}
+
+impl ApiEnvironment {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "sandbox" => Some(ApiEnvironment::Sandbox),
+            "production" | "produccion" | "prod" => Some(ApiEnvironment::Production),
+            "custom" => Some(ApiEnvironment::Custom),
+            _ => None,
+        }
+    }
+}
+
+/// Endpoint GraphQL y shop usados para login y licencias
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiConfig {
+    pub environment: ApiEnvironment,
+    pub api_url: String,
+    pub shop: String,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            environment: ApiEnvironment::Sandbox,
+            api_url: SANDBOX_API_URL.to_string(),
+            shop: SANDBOX_SHOP.to_string(),
+        }
+    }
+}
+
+impl ApiConfig {
+    /// Construir la configuración de un entorno.
+    /// Sandbox y producción usan su URL fija; custom requiere `api_url`.
+    pub fn for_environment(
+        environment: ApiEnvironment,
+        api_url: Option<String>,
+        shop: Option<String>,
+    ) -> Result<Self, String> {
+        let shop = shop.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+
+        let config = match environment {
+            ApiEnvironment::Sandbox => Self {
+                environment,
+                api_url: SANDBOX_API_URL.to_string(),
+                shop: shop.unwrap_or_else(|| SANDBOX_SHOP.to_string()),
+            },
+            ApiEnvironment::Production => Self {
+                environment,
+                api_url: PRODUCTION_API_URL.to_string(),
+                shop: shop.ok_or("Shop required for production environment")?,
+            },
+            ApiEnvironment::Custom => Self {
+                environment,
+                api_url: api_url
+                    .map(|u| u.trim().to_string())
+                    .filter(|u| !u.is_empty())
+                    .ok_or("API URL required for custom environment")?,
+                shop: shop.ok_or("Shop required for custom environment")?,
+            },
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Validar URL y shop
+    pub fn validate(&self) -> Result<(), String> {
+        let parsed = url::Url::parse(&self.api_url).map_err(|e| format!("Invalid API URL: {}", e))?;
+        if parsed.scheme() != "https" && parsed.scheme() != "http" {
+            return Err(format!("Invalid API URL scheme: {}", parsed.scheme()));
+        }
+        if self.shop.trim().is_empty() {
+            return Err("Shop required".to_string());
+        }
+        Ok(())
+    }
+
+    /// Aplicar las variables de entorno ISIPRINT_API_ENV / ISIPRINT_API_URL / ISIPRINT_SHOP
+    pub fn with_env_overrides(self) -> Self {
+        Self::apply_overrides(
+            self,
+            std::env::var(ENV_API_ENVIRONMENT).ok(),
+            std::env::var(ENV_API_URL).ok(),
+            std::env::var(ENV_SHOP).ok(),
+        )
+    }
+
+    fn apply_overrides(
+        base: Self,
+        environment: Option<String>,
+        api_url: Option<String>,
+        shop: Option<String>,
+    ) -> Self {
+        if environment.is_none() && api_url.is_none() && shop.is_none() {
+            return base;
+        }
+
+        // Una URL sin entorno explícito implica entorno custom
+        let environment = environment
+            .as_deref()
+            .and_then(ApiEnvironment::parse)
+            .unwrap_or(if api_url.is_some() {
+                ApiEnvironment::Custom
+            } else {
+                base.environment
+            });
+
+        let api_url = api_url.or_else(|| Some(base.api_url.clone()));
+        let shop = shop.or_else(|| Some(base.shop.clone()));
+
+        match Self::for_environment(environment, api_url, shop) {
+            Ok(config) => config,
+            Err(e) => {
+                log::warn!("Ignoring invalid API environment variables: {}", e);
+                base
+            }
+        }
+    }
+}
+
+/// Timeouts del cliente GraphQL
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Códigos de `extensions.code` que indican token inválido o vencido
+const UNAUTHENTICATED_CODES: &[&str] = &["UNAUTHENTICATED", "UNAUTHORIZED", "TOKEN_EXPIRED", "FORBIDDEN"];
+
+/// Código del backend cuando la licencia alcanzó su límite de dispositivos
+const DEVICE_LIMIT_CODE: &str = "DEVICE_LIMIT_EXCEEDED";
 
 /// Respuesta del login
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,9 +171,170 @@ pub struct GraphQLResponse<T> {
     pub errors: Option<Vec<GraphQLError>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct GraphQLError {
     pub message: String,
+    #[serde(default)]
+    pub extensions: Option<GraphQLErrorExtensions>,
+}
+
+/// Extensiones estándar de un error GraphQL
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GraphQLErrorExtensions {
+    pub code: Option<String>,
+}
+
+/// Errores del cliente GraphQL
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum GraphQLClientError {
+    #[error("Connection error: {0}")]
+    Connection(String),
+    #[error("Request timed out")]
+    Timeout,
+    #[error("HTTP error: {0}")]
+    Http(u16),
+    #[error("Error parsing response: {0}")]
+    Parse(String),
+    #[error("{message}")]
+    GraphQL { message: String, code: Option<String> },
+    #[error("Empty {0} response")]
+    EmptyResponse(&'static str),
+}
+
+impl GraphQLClientError {
+    /// El backend rechazó el token (vencido o inválido)
+    pub fn is_unauthenticated(&self) -> bool {
+        match self {
+            GraphQLClientError::Http(status) => *status == 401,
+            GraphQLClientError::GraphQL { code: Some(code), .. } => {
+                UNAUTHENTICATED_CODES.contains(&code.to_uppercase().as_str())
+            }
+            _ => false,
+        }
+    }
+
+    /// La licencia ya tiene el máximo de dispositivos conectados
+    pub fn is_device_limit(&self) -> bool {
+        matches!(
+            self,
+            GraphQLClientError::GraphQL { code: Some(code), .. } if code.eq_ignore_ascii_case(DEVICE_LIMIT_CODE)
+        )
+    }
+
+    /// El backend no fue alcanzable (sin red, timeout o error del servidor)
+    pub fn is_unreachable(&self) -> bool {
+        match self {
+            GraphQLClientError::Connection(_) | GraphQLClientError::Timeout => true,
+            GraphQLClientError::Http(status) => *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl From<GraphQLError> for GraphQLClientError {
+    fn from(error: GraphQLError) -> Self {
+        GraphQLClientError::GraphQL {
+            message: error.message,
+            code: error.extensions.and_then(|e| e.code),
+        }
+    }
+}
+
+impl From<reqwest::Error> for GraphQLClientError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            GraphQLClientError::Timeout
+        } else {
+            GraphQLClientError::Connection(error.to_string())
+        }
+    }
+}
+
+pub type GraphQLResult<T> = Result<T, GraphQLClientError>;
+
+/// Cuerpo de una petición GraphQL
+#[derive(Debug, Serialize)]
+struct GraphQLRequest<'a, V: Serialize> {
+    query: &'a str,
+    #[serde(rename = "operationName")]
+    operation_name: &'a str,
+    variables: V,
+}
+
+/// Cliente HTTP compartido (pool de conexiones reutilizado entre peticiones)
+fn http_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// Cliente GraphQL tipado para un backend
+pub struct GraphQLClient<'a> {
+    api: &'a ApiConfig,
+    token: Option<&'a str>,
+}
+
+impl<'a> GraphQLClient<'a> {
+    pub fn new(api: &'a ApiConfig) -> Self {
+        Self { api, token: None }
+    }
+
+    /// Enviar el token como `Authorization: Bearer`
+    pub fn with_token(mut self, token: &'a str) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Ejecutar una operación con variables y deserializar `data`
+    pub async fn execute<V, T>(&self, operation_name: &str, query: &str, variables: V) -> GraphQLResult<T>
+    where
+        V: Serialize,
+        T: DeserializeOwned,
+    {
+        let mut request = http_client()
+            .post(&self.api.api_url)
+            .header("Content-Type", "application/json")
+            .json(&GraphQLRequest {
+                query,
+                operation_name,
+                variables,
+            });
+
+        if let Some(token) = self.token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+
+        parse_response(status.as_u16(), &body)
+    }
+}
+
+/// Interpretar el cuerpo de una respuesta GraphQL
+fn parse_response<T: DeserializeOwned>(status: u16, body: &[u8]) -> GraphQLResult<T> {
+    let result: GraphQLResponse<T> = match serde_json::from_slice(body) {
+        Ok(r) => r,
+        // Sin cuerpo GraphQL válido: reportar el status HTTP si es un error
+        Err(_) if !(200..300).contains(&status) => return Err(GraphQLClientError::Http(status)),
+        Err(e) => return Err(GraphQLClientError::Parse(e.to_string())),
+    };
+
+    if let Some(error) = result.errors.and_then(|errors| errors.into_iter().next()) {
+        return Err(error.into());
+    }
+
+    if !(200..300).contains(&status) {
+        return Err(GraphQLClientError::Http(status));
+    }
+
+    result.data.ok_or(GraphQLClientError::EmptyResponse("data"))
 }
 
 #[derive(Debug, Deserialize)]
@@ -29,89 +342,322 @@ pub struct LoginData {
     pub login: LoginResponse,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RefreshTokenData {
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: LoginResponse,
+}
+
+/// Consumo de impresiones registrado en el backend para una licencia
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrintUsage {
+    /// Impresiones consumidas en el período (todas las instalaciones)
+    pub usadas: i32,
+    #[serde(rename = "maximoConexiones")]
+    pub maximo_conexiones: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrintUsageData {
+    #[serde(rename = "registrarImpresiones")]
+    pub registrar_impresiones: PrintUsage,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrintUsageQueryData {
+    #[serde(rename = "consumoImpresiones")]
+    pub consumo_impresiones: PrintUsage,
+}
+
+fn default_copias() -> u32 {
+    1
+}
+
+/// Trabajo de impresión dirigido a este dispositivo desde el backend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemotePrintJob {
+    #[serde(rename = "_id")]
+    pub id: String,
+    /// "PDF" (url o contenido) o "RAW" (contenido)
+    pub formato: String,
+    pub impresora: Option<String>,
+    pub url: Option<String>,
+    /// Documento en base64
+    pub contenido: Option<String>,
+    #[serde(default = "default_copias")]
+    pub copias: u32,
+}
+
+/// Estado final reportado para un trabajo remoto
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RemoteJobStatus {
+    #[serde(rename = "COMPLETADO")]
+    Completed,
+    #[serde(rename = "FALLIDO")]
+    Failed,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterDeviceData {
+    #[serde(rename = "registrarDispositivo")]
+    pub registrar_dispositivo: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeartbeatData {
+    #[serde(rename = "latidoDispositivo")]
+    pub latido_dispositivo: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PendingJobsData {
+    #[serde(rename = "trabajosImpresionPendientes")]
+    pub trabajos_impresion_pendientes: Vec<RemotePrintJob>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AckJobData {
+    #[serde(rename = "confirmarTrabajoImpresion")]
+    pub confirmar_trabajo_impresion: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LicenciaFirmadaData {
+    #[serde(rename = "licenciaFirmada")]
+    pub licencia_firmada: SignedLicense,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LicenciaData {
     #[serde(rename = "licenciaProductoListado")]
     pub licencia_producto_listado: Vec<LicenciaProducto>,
 }
 
-/// Realizar login vía GraphQL
-pub async fn login(email: &str, password: &str) -> Result<LoginResponse, String> {
-    let query = format!(
-        r#"mutation LOGIN {{
-            login(shop: "sandbox", email: "{}", password: "{}") {{
-                token
-                refreshToken
-            }}
-        }}"#,
-        email, password
-    );
-
-    let client = reqwest::Client::new();
-    let response = client
-        .post(API_URL)
-        .header("Content-Type", "application/json")
-        .json(&serde_json::json!({ "query": query }))
-        .send()
-        .await
-        .map_err(|e| format!("Connection error: {}", e))?;
-
-    let result: GraphQLResponse<LoginData> = response
-        .json()
-        .await
-        .map_err(|e| format!("Error parsing response: {}", e))?;
-
-    if let Some(errors) = result.errors {
-        if !errors.is_empty() {
-            return Err(errors[0].message.clone());
-        }
+const LOGIN_MUTATION: &str = r#"mutation LOGIN($shop: String!, $email: String!, $password: String!) {
+    login(shop: $shop, email: $email, password: $password) {
+        token
+        refreshToken
+    }
+}"#;
+
+const REFRESH_TOKEN_MUTATION: &str = r#"mutation REFRESH_TOKEN($refreshToken: String!) {
+    refreshToken(refreshToken: $refreshToken) {
+        token
+        refreshToken
+    }
+}"#;
+
+const LICENCIAS_QUERY: &str = r#"query LICENCIA_PRODUCTOS {
+    licenciaProductoListado {
+        _id
+        tipoProducto
+        maximoConexiones
+        fechaVencimiento
+        delegado
+        configuracion
+        state
+    }
+}"#;
+
+const LICENCIA_FIRMADA_QUERY: &str = r#"query LICENCIA_FIRMADA {
+    licenciaFirmada {
+        payload
+        firma
+    }
+}"#;
+
+const REGISTRAR_IMPRESIONES_MUTATION: &str = r#"mutation REGISTRAR_IMPRESIONES($licenciaId: ID!, $cantidad: Int!) {
+    registrarImpresiones(licenciaId: $licenciaId, cantidad: $cantidad) {
+        usadas
+        maximoConexiones
+    }
+}"#;
+
+const CONSUMO_IMPRESIONES_QUERY: &str = r#"query CONSUMO_IMPRESIONES($licenciaId: ID!) {
+    consumoImpresiones(licenciaId: $licenciaId) {
+        usadas
+        maximoConexiones
+    }
+}"#;
+
+const REGISTRAR_DISPOSITIVO_MUTATION: &str = r#"mutation REGISTRAR_DISPOSITIVO($input: DispositivoInput!) {
+    registrarDispositivo(input: $input) {
+        _id
+    }
+}"#;
+
+const LATIDO_DISPOSITIVO_MUTATION: &str = r#"mutation LATIDO_DISPOSITIVO($input: DispositivoInput!) {
+    latidoDispositivo(input: $input) {
+        _id
+    }
+}"#;
+
+const TRABAJOS_PENDIENTES_QUERY: &str = r#"query TRABAJOS_IMPRESION_PENDIENTES($dispositivoId: String!) {
+    trabajosImpresionPendientes(dispositivoId: $dispositivoId) {
+        _id
+        formato
+        impresora
+        url
+        contenido
+        copias
+    }
+}"#;
+
+const CONFIRMAR_TRABAJO_MUTATION: &str = r#"mutation CONFIRMAR_TRABAJO_IMPRESION($id: ID!, $dispositivoId: String!, $estado: String!, $mensaje: String) {
+    confirmarTrabajoImpresion(id: $id, dispositivoId: $dispositivoId, estado: $estado, mensaje: $mensaje) {
+        _id
     }
+}"#;
 
-    result
-        .data
-        .map(|d| d.login)
-        .ok_or_else(|| "Empty login response".to_string())
+/// Realizar login vía GraphQL
+pub async fn login(api: &ApiConfig, email: &str, password: &str) -> GraphQLResult<LoginResponse> {
+    let data: LoginData = GraphQLClient::new(api)
+        .execute(
+            "LOGIN",
+            LOGIN_MUTATION,
+            serde_json::json!({
+                "shop": api.shop,
+                "email": email,
+                "password": password,
+            }),
+        )
+        .await?;
+
+    Ok(data.login)
+}
+
+/// Obtener un nuevo par token/refreshToken a partir del refresh token
+pub async fn refresh_token(api: &ApiConfig, refresh_token: &str) -> GraphQLResult<LoginResponse> {
+    let data: RefreshTokenData = GraphQLClient::new(api)
+        .execute(
+            "REFRESH_TOKEN",
+            REFRESH_TOKEN_MUTATION,
+            serde_json::json!({ "refreshToken": refresh_token }),
+        )
+        .await?;
+
+    Ok(data.refresh_token)
 }
 
 /// Obtener licencias del usuario
-pub async fn get_licencias(token: &str) -> Result<Vec<LicenciaProducto>, String> {
-    let query = r#"query LICENCIA_PRODUCTOS {
-        licenciaProductoListado {
-            _id
-            tipoProducto
-            maximoConexiones
-            fechaVencimiento
-            delegado
-            configuracion
-            state
-        }
-    }"#;
-
-    let client = reqwest::Client::new();
-    let response = client
-        .post(API_URL)
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", token))
-        .json(&serde_json::json!({ "query": query }))
-        .send()
-        .await
-        .map_err(|e| format!("Connection error: {}", e))?;
-
-    let result: GraphQLResponse<LicenciaData> = response
-        .json()
-        .await
-        .map_err(|e| format!("Error parsing response: {}", e))?;
-
-    if let Some(errors) = result.errors {
-        if !errors.is_empty() {
-            return Err(errors[0].message.clone());
-        }
-    }
+pub async fn get_licencias(api: &ApiConfig, token: &str) -> GraphQLResult<Vec<LicenciaProducto>> {
+    let data: LicenciaData = GraphQLClient::new(api)
+        .with_token(token)
+        .execute("LICENCIA_PRODUCTOS", LICENCIAS_QUERY, serde_json::json!({}))
+        .await?;
 
-    result
-        .data
-        .map(|d| d.licencia_producto_listado)
-        .ok_or_else(|| "Empty licenses response".to_string())
+    Ok(data.licencia_producto_listado)
+}
+
+/// Obtener las licencias firmadas por el servidor (para el uso sin conexión)
+pub async fn get_signed_license(api: &ApiConfig, token: &str) -> GraphQLResult<SignedLicense> {
+    let data: LicenciaFirmadaData = GraphQLClient::new(api)
+        .with_token(token)
+        .execute("LICENCIA_FIRMADA", LICENCIA_FIRMADA_QUERY, serde_json::json!({}))
+        .await?;
+
+    Ok(data.licencia_firmada)
+}
+
+/// Reportar impresiones realizadas; devuelve el consumo total del período
+pub async fn report_print_usage(
+    api: &ApiConfig,
+    token: &str,
+    licencia_id: &str,
+    cantidad: i32,
+) -> GraphQLResult<PrintUsage> {
+    let data: PrintUsageData = GraphQLClient::new(api)
+        .with_token(token)
+        .execute(
+            "REGISTRAR_IMPRESIONES",
+            REGISTRAR_IMPRESIONES_MUTATION,
+            serde_json::json!({ "licenciaId": licencia_id, "cantidad": cantidad }),
+        )
+        .await?;
+
+    Ok(data.registrar_impresiones)
+}
+
+/// Consumo del período registrado en el backend (todas las instalaciones)
+pub async fn get_print_usage(api: &ApiConfig, token: &str, licencia_id: &str) -> GraphQLResult<PrintUsage> {
+    let data: PrintUsageQueryData = GraphQLClient::new(api)
+        .with_token(token)
+        .execute(
+            "CONSUMO_IMPRESIONES",
+            CONSUMO_IMPRESIONES_QUERY,
+            serde_json::json!({ "licenciaId": licencia_id }),
+        )
+        .await?;
+
+    Ok(data.consumo_impresiones)
+}
+
+/// Registrar este equipo como dispositivo de impresión de la cuenta
+pub async fn register_device(api: &ApiConfig, token: &str, device: &DeviceInfo) -> GraphQLResult<()> {
+    let _: RegisterDeviceData = GraphQLClient::new(api)
+        .with_token(token)
+        .execute(
+            "REGISTRAR_DISPOSITIVO",
+            REGISTRAR_DISPOSITIVO_MUTATION,
+            serde_json::json!({ "input": device }),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Informar que el dispositivo sigue en línea (con su inventario actual)
+pub async fn device_heartbeat(api: &ApiConfig, token: &str, device: &DeviceInfo) -> GraphQLResult<()> {
+    let _: HeartbeatData = GraphQLClient::new(api)
+        .with_token(token)
+        .execute(
+            "LATIDO_DISPOSITIVO",
+            LATIDO_DISPOSITIVO_MUTATION,
+            serde_json::json!({ "input": device }),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Obtener los trabajos de impresión pendientes para este dispositivo
+pub async fn pending_print_jobs(api: &ApiConfig, token: &str, device_id: &str) -> GraphQLResult<Vec<RemotePrintJob>> {
+    let data: PendingJobsData = GraphQLClient::new(api)
+        .with_token(token)
+        .execute(
+            "TRABAJOS_IMPRESION_PENDIENTES",
+            TRABAJOS_PENDIENTES_QUERY,
+            serde_json::json!({ "dispositivoId": device_id }),
+        )
+        .await?;
+
+    Ok(data.trabajos_impresion_pendientes)
+}
+
+/// Confirmar el estado final de un trabajo remoto
+pub async fn ack_print_job(
+    api: &ApiConfig,
+    token: &str,
+    device_id: &str,
+    job_id: &str,
+    status: RemoteJobStatus,
+    message: Option<&str>,
+) -> GraphQLResult<()> {
+    let _: AckJobData = GraphQLClient::new(api)
+        .with_token(token)
+        .execute(
+            "CONFIRMAR_TRABAJO_IMPRESION",
+            CONFIRMAR_TRABAJO_MUTATION,
+            serde_json::json!({
+                "id": job_id,
+                "dispositivoId": device_id,
+                "estado": status,
+                "mensaje": message,
+            }),
+        )
+        .await?;
+
+    Ok(())
 }
 
 // ==================== TESTS ====================
@@ -122,7 +668,53 @@ mod tests {
 
     #[test]
     fn test_api_url_is_correct() {
-        assert_eq!(API_URL, "https://sandbox.isipass.net/api");
+        let api = ApiConfig::default();
+        assert_eq!(api.environment, ApiEnvironment::Sandbox);
+        assert_eq!(api.api_url, "https://sandbox.isipass.net/api");
+        assert_eq!(api.shop, "sandbox");
+    }
+
+    #[test]
+    fn test_api_config_for_environment() {
+        let prod = ApiConfig::for_environment(ApiEnvironment::Production, None, Some("tienda".to_string())).unwrap();
+        assert_eq!(prod.api_url, PRODUCTION_API_URL);
+        assert_eq!(prod.shop, "tienda");
+
+        assert!(ApiConfig::for_environment(ApiEnvironment::Production, None, None).is_err());
+        assert!(ApiConfig::for_environment(ApiEnvironment::Custom, None, Some("x".to_string())).is_err());
+        assert!(ApiConfig::for_environment(
+            ApiEnvironment::Custom,
+            Some("ftp://api.example.com".to_string()),
+            Some("x".to_string())
+        )
+        .is_err());
+
+        let custom = ApiConfig::for_environment(
+            ApiEnvironment::Custom,
+            Some("https://api.example.com/graphql".to_string()),
+            Some("demo".to_string()),
+        )
+        .unwrap();
+        assert_eq!(custom.api_url, "https://api.example.com/graphql");
+    }
+
+    #[test]
+    fn test_api_config_overrides() {
+        let base = ApiConfig::default();
+        assert_eq!(ApiConfig::apply_overrides(base.clone(), None, None, None), base);
+
+        let custom = ApiConfig::apply_overrides(
+            base.clone(),
+            None,
+            Some("https://staging.example.com/api".to_string()),
+            Some("staging".to_string()),
+        );
+        assert_eq!(custom.environment, ApiEnvironment::Custom);
+        assert_eq!(custom.shop, "staging");
+
+        // Producción sin shop es inválido: se conserva la base
+        let invalid = ApiConfig::apply_overrides(base.clone(), Some("production".to_string()), None, Some(" ".to_string()));
+        assert_eq!(invalid, base);
     }
 
     #[test]
@@ -200,10 +792,98 @@ mod tests {
         assert_eq!(lic.maximo_conexiones, 100);
     }
 
+    #[test]
+    fn test_graphql_error_extensions() {
+        let json = r#"{
+            "data": null,
+            "errors": [
+                {"message": "jwt expired", "extensions": {"code": "UNAUTHENTICATED"}}
+            ]
+        }"#;
+
+        let err = parse_response::<LoginData>(200, json.as_bytes()).unwrap_err();
+        assert!(err.is_unauthenticated());
+        assert_eq!(err.to_string(), "jwt expired");
+    }
+
+    #[test]
+    fn test_parse_response_http_errors() {
+        let err = parse_response::<LoginData>(401, b"Unauthorized").unwrap_err();
+        assert!(matches!(err, GraphQLClientError::Http(401)));
+        assert!(err.is_unauthenticated());
+
+        let err = parse_response::<LoginData>(502, b"<html>Bad gateway</html>").unwrap_err();
+        assert!(err.is_unreachable());
+
+        let err = parse_response::<LoginData>(200, br#"{"data": null}"#).unwrap_err();
+        assert!(matches!(err, GraphQLClientError::EmptyResponse(_)));
+    }
+
+    #[test]
+    fn test_request_uses_variables() {
+        // Las credenciales viajan como variables, nunca dentro del texto de la query
+        let password = r#"pa"ss\word"#;
+        let request = GraphQLRequest {
+            query: LOGIN_MUTATION,
+            operation_name: "LOGIN",
+            variables: serde_json::json!({ "shop": "sandbox", "email": "a@b.com", "password": password }),
+        };
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["operationName"], "LOGIN");
+        assert_eq!(json["variables"]["password"], password);
+        assert!(!json["query"].as_str().unwrap().contains(password));
+    }
+
+    #[test]
+    fn test_refresh_token_data_deserialization() {
+        let json = r#"{"refreshToken": {"token": "new", "refreshToken": "new-refresh"}}"#;
+        let data: RefreshTokenData = serde_json::from_str(json).unwrap();
+        assert_eq!(data.refresh_token.token, "new");
+        assert_eq!(data.refresh_token.refresh_token, "new-refresh");
+    }
+
+    #[test]
+    fn test_print_usage_data_deserialization() {
+        let json = r#"{"registrarImpresiones": {"usadas": 42, "maximoConexiones": 100}}"#;
+        let data: PrintUsageData = serde_json::from_str(json).unwrap();
+        assert_eq!(data.registrar_impresiones.usadas, 42);
+        assert_eq!(data.registrar_impresiones.maximo_conexiones, 100);
+
+        let json = r#"{"consumoImpresiones": {"usadas": 7, "maximoConexiones": 100}}"#;
+        let data: PrintUsageQueryData = serde_json::from_str(json).unwrap();
+        assert_eq!(data.consumo_impresiones.usadas, 7);
+    }
+
+    #[test]
+    fn test_pending_jobs_deserialization() {
+        let json = r#"{"trabajosImpresionPendientes": [
+            {"_id": "job1", "formato": "PDF", "impresora": null, "url": "https://x/a.pdf", "contenido": null},
+            {"_id": "job2", "formato": "RAW", "impresora": "Caja", "url": null, "contenido": "SG9sYQ==", "copias": 2}
+        ]}"#;
+        let data: PendingJobsData = serde_json::from_str(json).unwrap();
+        let jobs = data.trabajos_impresion_pendientes;
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].copias, 1);
+        assert_eq!(jobs[1].impresora.as_deref(), Some("Caja"));
+        assert_eq!(serde_json::to_value(RemoteJobStatus::Failed).unwrap(), "FALLIDO");
+    }
+
+    #[test]
+    fn test_device_limit_error() {
+        let limit = GraphQLClientError::GraphQL {
+            message: "Límite de dispositivos alcanzado".to_string(),
+            code: Some("DEVICE_LIMIT_EXCEEDED".to_string()),
+        };
+        assert!(limit.is_device_limit());
+        assert!(!limit.is_unauthenticated());
+        assert!(!GraphQLClientError::Timeout.is_device_limit());
+    }
+
     #[tokio::test]
     async fn test_login_with_invalid_credentials() {
         // Test con credenciales inválidas - debería retornar error del servidor
-        let result = login("invalid@email.com", "wrongpassword").await;
+        let result = login(&ApiConfig::default(), "invalid@email.com", "wrongpassword").await;
         
         // El servidor debería responder (aunque sea con error)
         // Si falla la conexión, también es un resultado válido para el test
@@ -216,7 +896,7 @@ mod tests {
             Err(e) => {
                 println!("Error esperado: {}", e);
                 // Verificar que el error es legible
-                assert!(!e.is_empty());
+                assert!(!e.to_string().is_empty());
             }
         }
     }
@@ -224,7 +904,7 @@ mod tests {
     #[tokio::test]
     async fn test_get_licencias_with_invalid_token() {
         // Test con token inválido
-        let result = get_licencias("invalid-token-12345").await;
+        let result = get_licencias(&ApiConfig::default(), "invalid-token-12345").await;
         
         match result {
             Ok(licencias) => {
@@ -233,7 +913,7 @@ mod tests {
             }
             Err(e) => {
                 println!("Error esperado: {}", e);
-                assert!(!e.is_empty());
+                assert!(!e.to_string().is_empty());
             }
         }
     }
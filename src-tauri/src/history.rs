@@ -0,0 +1,510 @@
+// Historial persistente de trabajos de impresión (JSON lines) con búsqueda y exportación
+use crate::persistence;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Registros conservados al compactar el archivo
+const MAX_HISTORY: usize = 20_000;
+
+/// Tamaño de página por defecto y máximo de las consultas
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+/// Por dónde llegó el trabajo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobSource {
+    /// Comandos de la aplicación de escritorio
+    App,
+    Http,
+    Remote,
+    Spool,
+}
+
+/// Resultado del trabajo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobResult {
+    Printed,
+    Failed,
+    /// No se pudo imprimir y quedó en la cola de reintentos
+    Queued,
+    /// Descartado desde la cola de reintentos
+    Discarded,
+}
+
+/// Registro de un trabajo
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub id: String,
+    /// RFC 3339, hora local
+    pub timestamp: String,
+    pub source: JobSource,
+    pub origin: Option<String>,
+    pub printer: String,
+    pub document_type: Option<String>,
+    /// pdf o raw
+    pub format: String,
+    pub pages: Option<u32>,
+    pub copies: u32,
+    pub size_bytes: u64,
+    pub duration_ms: u64,
+    pub result: JobResult,
+    pub error: Option<String>,
+}
+
+/// Datos de un trabajo en curso; `finish` produce el registro
+#[derive(Debug, Clone)]
+pub struct JobContext {
+    pub source: JobSource,
+    pub origin: Option<String>,
+    pub document_type: Option<String>,
+    pub format: String,
+    pub pages: Option<u32>,
+    pub copies: u32,
+    pub size_bytes: u64,
+    started: Instant,
+}
+
+impl JobContext {
+    pub fn new(source: JobSource, format: &str, data: &[u8], copies: u32) -> Self {
+        Self {
+            source,
+            origin: None,
+            document_type: None,
+            format: format.to_string(),
+            pages: if format == "pdf" { count_pdf_pages(data) } else { None },
+            copies,
+            size_bytes: data.len() as u64,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn with_request(mut self, origin: Option<String>, document_type: Option<String>) -> Self {
+        self.origin = origin;
+        self.document_type = document_type;
+        self
+    }
+
+    pub fn finish(&self, printer: &str, result: JobResult, error: Option<String>) -> JobRecord {
+        JobRecord {
+            id: format!("{:x}-{:04x}", chrono::Utc::now().timestamp_millis(), rand::random::<u16>()),
+            timestamp: chrono::Local::now().to_rfc3339(),
+            source: self.source,
+            origin: self.origin.clone(),
+            printer: printer.to_string(),
+            document_type: self.document_type.clone(),
+            format: self.format.clone(),
+            pages: self.pages,
+            copies: self.copies,
+            size_bytes: self.size_bytes,
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            result,
+            error,
+        }
+    }
+}
+
+/// Páginas de un PDF contando los objetos `/Type /Page` (sin leer la estructura completa)
+pub fn count_pdf_pages(data: &[u8]) -> Option<u32> {
+    if !data.starts_with(b"%PDF") {
+        return None;
+    }
+
+    let mut count = 0;
+    let mut i = 0;
+    while let Some(pos) = find(&data[i..], b"/Type") {
+        i += pos + b"/Type".len();
+        // Saltar espacios entre /Type y el valor
+        while i < data.len() && data[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if data[i..].starts_with(b"/Page") {
+            let next = data.get(i + b"/Page".len()).copied().unwrap_or(b' ');
+            if !next.is_ascii_alphanumeric() {
+                count += 1;
+            }
+        }
+    }
+
+    (count > 0).then_some(count)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Filtros y página de una consulta
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HistoryQuery {
+    /// Desde esta fecha (incluida), YYYY-MM-DD
+    #[serde(default)]
+    pub from: Option<NaiveDate>,
+    /// Hasta esta fecha (incluida), YYYY-MM-DD
+    #[serde(default)]
+    pub to: Option<NaiveDate>,
+    #[serde(default)]
+    pub printer: Option<String>,
+    #[serde(default)]
+    pub result: Option<JobResult>,
+    /// Página (desde 1)
+    #[serde(default)]
+    pub page: Option<usize>,
+    #[serde(default)]
+    pub page_size: Option<usize>,
+}
+
+impl HistoryQuery {
+    fn matches(&self, record: &JobRecord) -> bool {
+        let date = chrono::DateTime::parse_from_rfc3339(&record.timestamp)
+            .ok()
+            .map(|t| t.date_naive());
+
+        let date_ok = match (date, self.from, self.to) {
+            (_, None, None) => true,
+            (None, _, _) => false,
+            (Some(d), from, to) => from.map_or(true, |f| d >= f) && to.map_or(true, |t| d <= t),
+        };
+
+        let printer_ok = self
+            .printer
+            .as_deref()
+            .filter(|p| !p.is_empty())
+            .map_or(true, |p| record.printer.eq_ignore_ascii_case(p));
+
+        let result_ok = self.result.map_or(true, |r| record.result == r);
+
+        date_ok && printer_ok && result_ok
+    }
+}
+
+/// Resultado paginado (más recientes primero)
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryPage {
+    pub total: usize,
+    pub page: usize,
+    pub page_size: usize,
+    pub jobs: Vec<JobRecord>,
+}
+
+/// Formato de exportación
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+/// Archivo de historial
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    /// Historial en el directorio de datos de ISIPRINT
+    pub fn open() -> Result<Self, String> {
+        let path = persistence::get_history_path().ok_or("Could not determine history path")?;
+        if let Some(parent) = path.parent() {
+            persistence::create_private_dir(parent)?;
+        }
+        Ok(Self::at(path))
+    }
+
+    pub fn at(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn append(&self, record: &JobRecord) -> Result<(), String> {
+        let line = serde_json::to_string(record).map_err(|e| format!("Failed to serialize job: {}", e))?;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open job history: {}", e))?;
+        writeln!(file, "{}", line).map_err(|e| format!("Failed to write job history: {}", e))
+    }
+
+    /// Todos los registros, del más antiguo al más nuevo (se ignoran líneas dañadas)
+    pub fn load(&self) -> Vec<JobRecord> {
+        let Ok(file) = fs::File::open(&self.path) else {
+            return Vec::new();
+        };
+        BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect()
+    }
+
+    /// Registros que cumplen los filtros, más recientes primero
+    pub fn filter(&self, query: &HistoryQuery) -> Vec<JobRecord> {
+        let mut jobs: Vec<JobRecord> = self.load().into_iter().filter(|r| query.matches(r)).collect();
+        jobs.reverse();
+        jobs
+    }
+
+    pub fn query(&self, query: &HistoryQuery) -> HistoryPage {
+        let jobs = self.filter(query);
+        let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        let page = query.page.unwrap_or(1).max(1);
+
+        HistoryPage {
+            total: jobs.len(),
+            page,
+            page_size,
+            jobs: jobs.into_iter().skip((page - 1) * page_size).take(page_size).collect(),
+        }
+    }
+
+    /// Conservar solo los últimos `max` registros
+    pub fn compact(&self, max: usize) -> Result<(), String> {
+        let records = self.load();
+        if records.len() <= max {
+            return Ok(());
+        }
+
+        let mut contents = String::new();
+        for record in &records[records.len() - max..] {
+            let line = serde_json::to_string(record).map_err(|e| format!("Failed to serialize job: {}", e))?;
+            contents.push_str(&line);
+            contents.push('\n');
+        }
+
+        let tmp = self.path.with_extension("jsonl.tmp");
+        fs::write(&tmp, contents).map_err(|e| format!("Failed to compact job history: {}", e))?;
+        fs::rename(&tmp, &self.path).map_err(|e| format!("Failed to compact job history: {}", e))
+    }
+
+    /// Borrar todos los registros
+    pub fn clear(&self) -> Result<(), String> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(format!("Failed to clear job history: {}", e))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    // Las hojas de cálculo ejecutan como fórmula lo que empieza con = + - @ (o tab/CR):
+    // el origen, la impresora y el error vienen de peticiones externas
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn as_str<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Exportar registros a CSV (con encabezado)
+pub fn to_csv(jobs: &[JobRecord]) -> String {
+    let mut out = String::from(
+        "id,timestamp,source,origin,printer,document_type,format,pages,copies,size_bytes,duration_ms,result,error\n",
+    );
+    for job in jobs {
+        let fields = [
+            job.id.clone(),
+            job.timestamp.clone(),
+            as_str(&job.source),
+            job.origin.clone().unwrap_or_default(),
+            job.printer.clone(),
+            job.document_type.clone().unwrap_or_default(),
+            job.format.clone(),
+            job.pages.map(|p| p.to_string()).unwrap_or_default(),
+            job.copies.to_string(),
+            job.size_bytes.to_string(),
+            job.duration_ms.to_string(),
+            as_str(&job.result),
+            job.error.clone().unwrap_or_default(),
+        ];
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&line.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Exportar los registros filtrados (sin paginar)
+pub fn export(query: &HistoryQuery, format: ExportFormat) -> Result<String, String> {
+    let jobs = History::open()?.filter(query);
+    match format {
+        ExportFormat::Csv => Ok(to_csv(&jobs)),
+        ExportFormat::Json => {
+            serde_json::to_string_pretty(&jobs).map_err(|e| format!("Failed to export history: {}", e))
+        }
+    }
+}
+
+/// Consultar el historial
+pub fn query(query: &HistoryQuery) -> Result<HistoryPage, String> {
+    Ok(History::open()?.query(query))
+}
+
+/// Vaciar el historial
+pub fn clear() -> Result<(), String> {
+    History::open()?.clear()
+}
+
+/// Agregar un registro sin bloquear el runtime
+pub async fn record(record: JobRecord) {
+    let result = tokio::task::spawn_blocking(move || History::open()?.append(&record)).await;
+    if let Ok(Err(e)) = result {
+        log::warn!("Job not saved to history: {}", e);
+    }
+}
+
+/// Recortar el historial al iniciar
+pub fn compact() {
+    if let Err(e) = History::open().and_then(|h| h.compact(MAX_HISTORY)) {
+        log::warn!("Failed to compact job history: {}", e);
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(timestamp: &str, printer: &str, result: JobResult) -> JobRecord {
+        JobRecord {
+            id: format!("{}-{}", timestamp, printer),
+            timestamp: timestamp.to_string(),
+            source: JobSource::Http,
+            origin: Some("https://caja.isipass.net".to_string()),
+            printer: printer.to_string(),
+            document_type: Some("factura".to_string()),
+            format: "pdf".to_string(),
+            pages: Some(2),
+            copies: 1,
+            size_bytes: 1024,
+            duration_ms: 350,
+            result,
+            error: None,
+        }
+    }
+
+    fn history_with(records: &[JobRecord]) -> (tempfile::TempDir, History) {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::at(dir.path().join("history.jsonl"));
+        for r in records {
+            history.append(r).unwrap();
+        }
+        (dir, history)
+    }
+
+    #[test]
+    fn test_count_pdf_pages() {
+        let pdf = b"%PDF-1.4\n1 0 obj << /Type /Pages /Count 2 >>\n2 0 obj << /Type /Page >>\n3 0 obj <</Type/Page/Parent 1 0 R>>";
+        assert_eq!(count_pdf_pages(pdf), Some(2));
+        assert_eq!(count_pdf_pages(b"ESC/POS"), None);
+    }
+
+    #[test]
+    fn test_query_filters_and_pages() {
+        let (_dir, history) = history_with(&[
+            record("2026-03-01T10:00:00-04:00", "Caja", JobResult::Printed),
+            record("2026-03-02T10:00:00-04:00", "Cocina", JobResult::Failed),
+            record("2026-03-03T10:00:00-04:00", "Caja", JobResult::Printed),
+            record("2026-03-04T10:00:00-04:00", "caja", JobResult::Queued),
+        ]);
+
+        let all = history.query(&HistoryQuery::default());
+        assert_eq!(all.total, 4);
+        assert_eq!(all.jobs[0].timestamp, "2026-03-04T10:00:00-04:00");
+
+        let caja = history.query(&HistoryQuery {
+            printer: Some("CAJA".to_string()),
+            from: NaiveDate::from_ymd_opt(2026, 3, 2),
+            to: NaiveDate::from_ymd_opt(2026, 3, 3),
+            ..Default::default()
+        });
+        assert_eq!(caja.total, 1);
+
+        let page = history.query(&HistoryQuery {
+            page: Some(2),
+            page_size: Some(3),
+            ..Default::default()
+        });
+        assert_eq!(page.jobs.len(), 1);
+        assert_eq!(page.jobs[0].printer, "Caja");
+
+        let failed = history.query(&HistoryQuery {
+            result: Some(JobResult::Failed),
+            ..Default::default()
+        });
+        assert_eq!(failed.jobs[0].printer, "Cocina");
+    }
+
+    #[test]
+    fn test_compact_keeps_latest() {
+        let (_dir, history) = history_with(&[
+            record("2026-03-01T10:00:00-04:00", "A", JobResult::Printed),
+            record("2026-03-02T10:00:00-04:00", "B", JobResult::Printed),
+            record("2026-03-03T10:00:00-04:00", "C", JobResult::Printed),
+        ]);
+
+        history.compact(2).unwrap();
+        let printers: Vec<String> = history.load().into_iter().map(|r| r.printer).collect();
+        assert_eq!(printers, vec!["B", "C"]);
+    }
+
+    #[test]
+    fn test_clear_empties_history() {
+        let (_dir, history) = history_with(&[
+            record("2026-03-01T10:00:00-04:00", "A", JobResult::Printed),
+            record("2026-03-02T10:00:00-04:00", "B", JobResult::Failed),
+        ]);
+
+        history.clear().unwrap();
+        assert_eq!(history.query(&HistoryQuery::default()).total, 0);
+
+        // Vaciar un historial inexistente no es un error
+        history.clear().unwrap();
+        history.append(&record("2026-03-03T10:00:00-04:00", "C", JobResult::Printed)).unwrap();
+        assert_eq!(history.query(&HistoryQuery::default()).total, 1);
+    }
+
+    #[test]
+    fn test_csv_export_escapes_fields() {
+        let mut job = record("2026-03-01T10:00:00-04:00", "Caja, principal", JobResult::Failed);
+        job.error = Some("Printer \"Caja\" offline".to_string());
+
+        let csv = to_csv(&[job]);
+        let mut lines = csv.lines();
+        assert!(lines.next().unwrap().starts_with("id,timestamp,source"));
+        let row = lines.next().unwrap();
+        assert!(row.contains(",\"Caja, principal\","));
+        assert!(row.ends_with(",failed,\"Printer \"\"Caja\"\" offline\""));
+        assert!(row.contains(",http,"));
+    }
+
+    #[test]
+    fn test_csv_export_neutralizes_formulas() {
+        assert_eq!(csv_field("=HYPERLINK(\"http://x\")"), "\"'=HYPERLINK(\"\"http://x\"\")\"");
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-1"), "'-1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("\tcmd"), "'\tcmd");
+        assert_eq!(csv_field("\rcmd"), "\"'\rcmd\"");
+        assert_eq!(csv_field("Caja"), "Caja");
+        assert_eq!(csv_field(""), "");
+    }
+}
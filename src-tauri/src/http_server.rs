@@ -1,83 +1,354 @@
 use axum::{
-    extract::State,
+    extract::{Path, Query, Request, State},
     http::{header, HeaderMap, Method, StatusCode},
-    response::{IntoResponse, Json},
-    routing::{get, post},
+    middleware::{self, Next},
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, post},
     Router,
 };
 use axum_extra::extract::Multipart;
+use axum_server::tls_rustls::RustlsConfig;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::printer;
-use crate::state::{AppState, LogEntry};
+use crate::approvals::{self, API_KEY_HEADER, APPROVAL_TIMEOUT};
+use crate::history::{self, ExportFormat, HistoryQuery, JobSource};
+use crate::logging;
+use crate::network_discovery;
+use crate::origins::{self, AllowedOrigin, Decision, Permission};
+use crate::pipeline::{self, Content, ErrorKind, Job, Outcome, PipelineError};
+use crate::pools::PrinterPool;
+use crate::printer::{self, PrinterSource};
+use crate::settings::{HttpSettings, Settings, SETTINGS_VERSION};
+use crate::spool::{self, SpoolError, SpoolFormat, SpooledJob};
+use crate::state::AppState;
+use crate::tls::{self, Issued, TlsFiles};
 
 /// Estado compartido del servidor HTTP
 pub struct HttpServerState {
     pub app_state: Arc<RwLock<AppState>>,
+    /// La escucha es accesible desde la red: toda petición necesita la clave de API
+    pub require_api_key: bool,
 }
 
-/// Dominios permitidos para CORS
-const DOMINIOS_PERMITIDOS: &[&str] = &[
-    "localhost",
-    "127.0.0.1",
-    "*.integrate.com.bo",
-    "*.isipass.net",
-    "*.adeabordo.com",
-    "*.adeabordo.com.bo",
-    "*.adesite.net",
-    "*.isipass.app",
-    "*.pruebas.isipass.net",
-];
-
-/// Verifica si un origen está permitido
-fn is_origin_allowed(origin: &str) -> bool {
-    // Origen vacío se permite (para peticiones directas, curl, Postman, etc.)
-    if origin.is_empty() {
-        return true;
-    }
-
-    // Extraer el hostname del origen
-    let host = if let Some(stripped) = origin.strip_prefix("https://") {
-        stripped.split('/').next().unwrap_or("")
-    } else if let Some(stripped) = origin.strip_prefix("http://") {
-        stripped.split('/').next().unwrap_or("")
-    } else {
-        origin
-    };
+/// Estado de las escuchas del servidor HTTP, visible en la interfaz y en `GET /`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HttpServerStatus {
+    /// Direcciones en las que se escucha
+    pub listening: Vec<String>,
+    /// Puerto de la API local: el configurado o el de respaldo
+    pub port: Option<u16>,
+    /// El puerto configurado estaba ocupado y se usa el de respaldo
+    pub fallback: bool,
+    /// Dirección HTTPS con el certificado local, si está activada
+    pub https: Option<String>,
+    /// Se generó una CA local nueva: hay que exportarla e instalarla otra vez
+    pub ca_reinstall: bool,
+    /// Direcciones accesibles desde la red (exigen la clave de API aunque haya Origin)
+    pub exposed: Vec<String>,
+    /// Direcciones que no se pudieron abrir
+    pub errors: Vec<String>,
+}
+
+/// Origen de la petición (Origin o, si falta, Referer)
+fn request_origin(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::ORIGIN)
+        .or_else(|| headers.get(header::REFERER))
+        .and_then(|v| v.to_str().ok())
+        .filter(|o| !o.is_empty())
+}
+
+/// Solo las escuchas en una dirección de loopback quedan fuera del alcance de la red
+fn is_exposed(address: &std::net::SocketAddr) -> bool {
+    !address.ip().is_loopback()
+}
+
+/// Comprobar la clave de API local (cabecera `X-API-Key`)
+async fn verify_api_key(
+    state: &Arc<HttpServerState>,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, &'static str)> {
+    let provided = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok());
+    let app = state.app_state.read().await;
+    match &app.api_key {
+        Some(key) if approvals::api_key_matches(key, provided) => Ok(()),
+        _ => Err((StatusCode::UNAUTHORIZED, "API key required")),
+    }
+}
+
+/// Middleware to verify origin and its permission for the endpoint.
+/// Unknown origins wait for the user's approval; requests without an origin
+/// (curl, scripts) need the local API key. On listeners reachable from the
+/// network the API key is required for every request, whatever its origin.
+async fn verify_origin(
+    state: &Arc<HttpServerState>,
+    headers: &HeaderMap,
+    permission: Permission,
+) -> Result<(), (StatusCode, &'static str)> {
+    if state.require_api_key {
+        verify_api_key(state, headers).await.inspect_err(|_| {
+            log::warn!("Request on a network listener rejected: missing or invalid API key");
+        })?;
+    }
 
-    // Quitar el puerto si existe
-    let host_without_port = host.split(':').next().unwrap_or(host);
+    let Some(origin) = request_origin(headers) else {
+        return verify_api_key(state, headers).await.inspect_err(|_| {
+            log::warn!("Request without origin rejected: missing or invalid API key");
+        });
+    };
 
-    for pattern in DOMINIOS_PERMITIDOS {
-        if pattern.starts_with("*.") {
-            // Patrón con wildcard
-            let suffix = &pattern[1..]; // ".integrate.com.bo"
-            if host_without_port.ends_with(suffix) || host_without_port == &pattern[2..] {
-                return true;
+    {
+        let app = state.app_state.read().await;
+        match origins::check(
+            &app.settings.allowed_origins,
+            &app.license_config.allowed_origins,
+            origin,
+            permission,
+        ) {
+            Decision::Allowed => return Ok(()),
+            Decision::Forbidden => {
+                log::warn!("Origin {} not allowed to {}", origin, permission.as_str());
+                return Err((StatusCode::FORBIDDEN, "Origin not allowed for this operation"));
+            }
+            Decision::Unknown if approvals::is_denied(&app.settings.denied_origins, origin) => {
+                log::warn!("Denied origin: {}", origin);
+                return Err((StatusCode::FORBIDDEN, "Unauthorized origin"));
             }
-        } else if host_without_port == *pattern {
-            return true;
+            Decision::Unknown => {}
+        }
+    }
+
+    // Solo se pregunta por sitios http(s): "null", "file://" y similares no se pueden aprobar
+    if origins::web_host(origin).is_none() {
+        log::warn!("Origin {} cannot be approved", origin);
+        return Err((StatusCode::FORBIDDEN, "Unauthorized origin"));
+    }
+
+    // La aprobación solo concede imprimir y consultar: el resto se configura en la aplicación
+    if !Permission::DEFAULT.contains(&permission) {
+        log::warn!("Unknown origin {} not allowed to {}", origin, permission.as_str());
+        return Err((StatusCode::FORBIDDEN, "Origin not allowed for this operation"));
+    }
+
+    // Origen desconocido: preguntar al usuario y esperar su respuesta
+    let Some(approval) = state
+        .app_state
+        .write()
+        .await
+        .origin_approvals
+        .request(origin, permission)
+    else {
+        log::warn!("Too many origins waiting for approval, {} rejected", origin);
+        return Err((StatusCode::TOO_MANY_REQUESTS, "Too many origins waiting for approval"));
+    };
+    match tokio::time::timeout(APPROVAL_TIMEOUT, approval).await {
+        Ok(Ok(choice)) if choice.allows() => Ok(()),
+        Ok(Ok(_)) => {
+            log::warn!("Origin {} denied by the user", origin);
+            Err((StatusCode::FORBIDDEN, "Unauthorized origin"))
+        }
+        _ => {
+            state.app_state.write().await.origin_approvals.prune(origin);
+            log::warn!("Origin {} was not approved in time", origin);
+            Err((StatusCode::FORBIDDEN, "Origin approval pending"))
         }
     }
-    false
 }
 
-/// Middleware to verify origin
-fn verify_origin(headers: &HeaderMap) -> Result<(), (StatusCode, &'static str)> {
+/// Cabecera con el ID de la petición (se respeta el del cliente si es válido)
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// ID de petición recibido del cliente, si es razonable para los logs
+fn incoming_request_id(headers: &HeaderMap) -> Option<String> {
+    let id = headers.get(REQUEST_ID_HEADER)?.to_str().ok()?.trim();
+    let valid = !id.is_empty()
+        && id.len() <= 64
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| id.to_string())
+}
+
+/// Middleware: asigna un ID a cada petición, lo expone en la respuesta y
+/// lo adjunta a todos los logs que se emitan mientras se procesa
+async fn assign_request_id(request: Request, next: Next) -> Response {
+    let id = incoming_request_id(request.headers()).unwrap_or_else(logging::new_request_id);
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let started = std::time::Instant::now();
+
+    let mut response = logging::REQUEST_ID
+        .scope(id.clone(), async move {
+            let response = next.run(request).await;
+            log::info!(
+                "{} {} {} ({} ms)",
+                method,
+                path,
+                response.status().as_u16(),
+                started.elapsed().as_millis()
+            );
+            response
+        })
+        .await;
+
+    if let Ok(value) = header::HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Cabeceras del preflight de Private Network Access (Chromium)
+const REQUEST_PRIVATE_NETWORK: &str = "access-control-request-private-network";
+const ALLOW_PRIVATE_NETWORK: &str = "access-control-allow-private-network";
+
+/// Preflight que pide acceso a la red privada: `None` si no lo es,
+/// si no indica si el origen puede acceder. Solo se niega a los orígenes
+/// rechazados; los desconocidos pasan y su petición espera la aprobación.
+fn private_network_preflight(
+    method: &Method,
+    headers: &HeaderMap,
+    allowed: &[AllowedOrigin],
+    license_origins: &[String],
+    denied: &[String],
+) -> Option<bool> {
+    let requested = headers
+        .get(REQUEST_PRIVATE_NETWORK)
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"true"));
+    if method != Method::OPTIONS || !requested {
+        return None;
+    }
+
     let origin = headers
         .get(header::ORIGIN)
-        .or_else(|| headers.get(header::REFERER))
         .and_then(|v| v.to_str().ok())
         .unwrap_or("");
+    let known = origins::is_known(allowed, license_origins, origin);
+    let approvable = origins::web_host(origin).is_some() && !approvals::is_denied(denied, origin);
+    Some(known || approvable)
+}
+
+/// Middleware: Private Network Access. Los navegadores Chromium exigen
+/// `Access-Control-Allow-Private-Network: true` en el preflight para que un
+/// sitio público llame a localhost; se concede a todo origen no rechazado.
+async fn allow_private_network(
+    State(state): State<Arc<HttpServerState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let allowed = {
+        let app = state.app_state.read().await;
+        private_network_preflight(
+            request.method(),
+            request.headers(),
+            &app.settings.allowed_origins,
+            &app.license_config.allowed_origins,
+            &app.settings.denied_origins,
+        )
+    };
+    let origin = request
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+
+    let mut response = next.run(request).await;
+    match allowed {
+        Some(true) => {
+            let headers = response.headers_mut();
+            headers.insert(ALLOW_PRIVATE_NETWORK, header::HeaderValue::from_static("true"));
+            headers.append(header::VARY, header::HeaderValue::from_static(REQUEST_PRIVATE_NETWORK));
+        }
+        Some(false) => log::warn!("Private network access denied for origin: {}", origin),
+        None => {}
+    }
+    response
+}
+
+/// Middleware: endpoints deshabilitados por la configuración de la licencia
+async fn verify_endpoint(
+    State(state): State<Arc<HttpServerState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    let allowed = path == "/"
+        || state
+            .app_state
+            .read()
+            .await
+            .license_config
+            .is_endpoint_allowed(&path);
+
+    if !allowed {
+        log::warn!("Endpoint disabled by license configuration: {}", path);
+        return (
+            StatusCode::FORBIDDEN,
+            Json(PrintResponse {
+                success: false,
+                message: format!("Endpoint {} is disabled by your license configuration", path),
+            }),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Impresora a usar cuando la petición no indica una
+async fn resolve_printer(
+    state: &Arc<HttpServerState>,
+    requested: Option<String>,
+    document_type: Option<&str>,
+) -> Option<String> {
+    // Por defecto de settings.json (por tipo de documento) o de la licencia
+    let defaults = state.app_state.read().await.printer_defaults(document_type);
+    printer::resolve_printer(requested, &defaults)
+}
+
+/// Código HTTP para cada etapa en la que falla un trabajo
+fn error_status(kind: ErrorKind) -> StatusCode {
+    match kind {
+        ErrorKind::License => StatusCode::FORBIDDEN,
+        ErrorKind::Quota => StatusCode::TOO_MANY_REQUESTS,
+        ErrorKind::Invalid | ErrorKind::NoPrinter => StatusCode::BAD_REQUEST,
+        ErrorKind::Print | ErrorKind::Verification | ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
 
-    if origin.is_empty() || is_origin_allowed(origin) {
-        Ok(())
-    } else {
-        log::warn!("Unauthorized origin: {}", origin);
-        Err((StatusCode::FORBIDDEN, "Unauthorized origin"))
+/// Respuesta HTTP para el resultado del pipeline de impresión
+fn pipeline_response(result: Result<Outcome, PipelineError>) -> Response {
+    match result {
+        Ok(outcome) => {
+            let message = outcome.message();
+            match outcome {
+                Outcome::Printed { .. } => Json(PrintResponse {
+                    success: true,
+                    message,
+                })
+                .into_response(),
+                // 202: no se imprimió (todavía); el trabajo quedó en la cola de reintentos
+                Outcome::Queued { id, .. } => (
+                    StatusCode::ACCEPTED,
+                    Json(QueuedResponse {
+                        success: false,
+                        queued: true,
+                        id,
+                        message,
+                    }),
+                )
+                    .into_response(),
+            }
+        }
+        Err(e) => (
+            error_status(e.kind),
+            Json(PrintResponse {
+                success: false,
+                message: e.message,
+            }),
+        )
+            .into_response(),
     }
 }
 
@@ -104,17 +375,19 @@ async fn verify_auth(state: &Arc<HttpServerState>) -> Result<(), (StatusCode, Js
             }),
         ));
     }
-    
-    Ok(())
-}
 
-/// Crea un LogEntry con timestamp actual
-fn create_log_entry(level: &str, message: String) -> LogEntry {
-    LogEntry {
-        timestamp: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-        level: level.to_string(),
-        message,
+    // Offline grace period exhausted without re-verifying with the backend
+    if let Err(e) = app.check_license() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(PrintResponse {
+                success: false,
+                message: e,
+            }),
+        ));
     }
+    
+    Ok(())
 }
 
 // ============ Request/Response types ============
@@ -125,19 +398,49 @@ pub struct PrintRequest {
     pub printer: Option<String>,
     #[serde(alias = "contenido")]
     pub content: Option<String>,
-    #[serde(default = "default_copies")]
-    pub copies: u32,
+    /// Si falta se usa la de la regla de enrutamiento (o 1)
+    #[serde(default, alias = "copias")]
+    pub copies: Option<u32>,
+    #[serde(default, alias = "documentType", alias = "tipoDocumento")]
+    pub document_type: Option<String>,
 }
 
-fn default_copies() -> u32 { 1 }
-
 #[derive(Debug, Deserialize)]
 pub struct PrintPdfRequest {
     #[serde(alias = "impresora")]
     pub printer: Option<String>,
     pub url: Option<String>,
-    #[serde(default = "default_copies")]
-    pub copies: u32,
+    #[serde(default, alias = "copias")]
+    pub copies: Option<u32>,
+    #[serde(default, alias = "documentType", alias = "tipoDocumento")]
+    pub document_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProbePrinterRequest {
+    #[serde(alias = "direccion")]
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddPrinterRequest {
+    #[serde(alias = "direccion")]
+    pub address: Option<String>,
+    #[serde(alias = "nombre")]
+    pub name: Option<String>,
+    #[serde(alias = "protocolo")]
+    pub protocol: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RerouteRequest {
+    #[serde(alias = "impresora")]
+    pub printer: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SpoolResponse {
+    pub jobs: Vec<SpooledJob>,
 }
 
 #[derive(Debug, Serialize)]
@@ -146,9 +449,30 @@ pub struct PrintResponse {
     pub message: String,
 }
 
+/// Trabajo no impreso que quedó en la cola de reintentos
+#[derive(Debug, Serialize)]
+pub struct QueuedResponse {
+    pub success: bool,
+    pub queued: bool,
+    /// ID en la cola (para /spool/{id})
+    pub id: String,
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrintersQuery {
+    #[serde(default, alias = "documentType", alias = "tipoDocumento")]
+    pub document_type: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct PrintersResponse {
     pub printers: Vec<PrinterInfo>,
+    /// Impresora usada cuando la petición no indica una
+    pub default_printer: Option<String>,
+    pub default_source: Option<PrinterSource>,
+    /// Grupos de impresoras utilizables como destino
+    pub pools: Vec<PrinterPool>,
 }
 
 #[derive(Debug, Serialize)]
@@ -156,38 +480,32 @@ pub struct PrinterInfo {
     pub name: String,
     #[serde(rename = "default")]
     pub is_default: bool,
-}
-
-#[derive(Debug, Serialize)]
-pub struct PrintJobsResponse {
-    pub jobs: Vec<PrintJobInfo>,
-}
-
-#[derive(Debug, Serialize)]
-pub struct PrintJobInfo {
-    pub id: String,
-    pub timestamp: String,
-    pub status: String,
-    pub message: String,
+    /// Por defecto del sistema operativo
+    pub system_default: bool,
 }
 
 // ============ Route handlers ============
 
 /// GET / - Estado del servidor
-async fn index() -> impl IntoResponse {
+async fn index(State(state): State<Arc<HttpServerState>>) -> impl IntoResponse {
+    let server = state.app_state.read().await.http_server.clone();
     Json(serde_json::json!({
         "status": "ok",
         "app": "ISIPRINT Client",
-        "version": "1.0.0"
+        "version": "1.0.0",
+        "port": server.port,
+        "fallback": server.fallback,
+        "listening": server.listening
     }))
 }
 
-/// GET /printers - List available printers
+/// GET /printers - List available printers and the effective default
 async fn get_printers(
     headers: HeaderMap,
     State(state): State<Arc<HttpServerState>>,
+    Query(query): Query<PrintersQuery>,
 ) -> impl IntoResponse {
-    if let Err(e) = verify_origin(&headers) {
+    if let Err(e) = verify_origin(&state, &headers, Permission::ListPrinters).await {
         return e.into_response();
     }
 
@@ -196,27 +514,40 @@ async fn get_printers(
         return e.into_response();
     }
 
+    let (defaults, pools) = {
+        let app = state.app_state.read().await;
+        (
+            app.printer_defaults(query.document_type.as_deref()),
+            app.settings.printer_pools.clone(),
+        )
+    };
+
     match printer::list_printers() {
         Ok(printers) => {
+            let system_default = printer::system_default_printer();
+            let installed = printers.clone();
+            let default = printer::choose_printer(None, &defaults, || system_default.clone(), || installed);
+
             let printer_list: Vec<PrinterInfo> = printers
                 .into_iter()
-                .enumerate()
-                .map(|(i, name)| PrinterInfo {
+                .map(|name| PrinterInfo {
+                    is_default: default.as_ref().is_some_and(|(d, _)| *d == name),
+                    system_default: system_default.as_deref() == Some(name.as_str()),
                     name,
-                    is_default: i == 0, // First printer as default
                 })
                 .collect();
 
             // Log
-            let log_entry = create_log_entry("info", format!("Listed {} printers", printer_list.len()));
-            if let Ok(mut app) = state.app_state.try_write() {
-                if app.logs.len() >= 100 {
-                    app.logs.pop_front();
-                }
-                app.logs.push_back(log_entry);
-            }
-
-            Json(PrintersResponse { printers: printer_list }).into_response()
+            state.app_state.write().await.add_log("INFO", &format!("Listed {} printers", printer_list.len()));
+
+            let (default_printer, default_source) = default.unzip();
+            Json(PrintersResponse {
+                printers: printer_list,
+                default_printer,
+                default_source,
+                pools,
+            })
+            .into_response()
         }
         Err(e) => {
             log::error!("Error listing printers: {}", e);
@@ -238,7 +569,32 @@ async fn print_raw(
     State(state): State<Arc<HttpServerState>>,
     Json(payload): Json<PrintRequest>,
 ) -> impl IntoResponse {
-    if let Err(e) = verify_origin(&headers) {
+    if let Err(e) = verify_origin(&state, &headers, Permission::Print).await {
+        return e.into_response();
+    }
+
+    // Verify authentication
+    if let Err(e) = verify_auth(&state).await {
+        return e.into_response();
+    }
+
+    let content = payload.content.unwrap_or_default().into_bytes();
+    let job = Job::new(JobSource::Http, Content::Bytes(content), SpoolFormat::Raw)
+        .with_printer(payload.printer)
+        .with_copies(payload.copies)
+        .with_request(request_origin(&headers).map(str::to_string), payload.document_type)
+        .spool_on_failure();
+
+    pipeline_response(pipeline::run(&state.app_state, job).await)
+}
+
+/// POST /printPDF - Download and print a PDF from URL
+async fn print_pdf(
+    headers: HeaderMap,
+    State(state): State<Arc<HttpServerState>>,
+    Json(payload): Json<PrintPdfRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = verify_origin(&state, &headers, Permission::Print).await {
         return e.into_response();
     }
 
@@ -247,495 +603,344 @@ async fn print_raw(
         return e.into_response();
     }
 
-    let content = match payload.content {
-        Some(c) if !c.is_empty() => c,
+    let url = match payload.url {
+        Some(u) if !u.is_empty() => u,
         _ => {
             return (
                 StatusCode::BAD_REQUEST,
                 Json(PrintResponse {
                     success: false,
-                    message: "Content required".to_string(),
+                    message: "URL required".to_string(),
                 }),
             )
                 .into_response()
         }
     };
 
-    let printer_name = match payload.printer {
-        Some(p) if !p.is_empty() => p,
-        _ => {
-            // Get default printer
-            match printer::list_printers() {
-                Ok(printers) if !printers.is_empty() => printers[0].clone(),
-                _ => {
-                    return (
-                        StatusCode::BAD_REQUEST,
-                        Json(PrintResponse {
-                            success: false,
-                            message: "No printers available".to_string(),
-                        }),
-                    )
-                        .into_response()
+    let job = Job::new(JobSource::Http, Content::Url(url), SpoolFormat::Pdf)
+        .with_printer(payload.printer)
+        .with_copies(payload.copies)
+        .with_request(request_origin(&headers).map(str::to_string), payload.document_type)
+        .spool_on_failure();
+
+    pipeline_response(pipeline::run(&state.app_state, job).await)
+}
+
+/// POST /printPDF/upload with multipart (file uploaded directly)
+async fn print_pdf_multipart(
+    headers: HeaderMap,
+    State(state): State<Arc<HttpServerState>>,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    if let Err(e) = verify_origin(&state, &headers, Permission::Print).await {
+        return e.into_response();
+    }
+
+    // Verify authentication
+    if let Err(e) = verify_auth(&state).await {
+        return e.into_response();
+    }
+
+    let mut pdf_data: Option<Vec<u8>> = None;
+    let mut printer_name: Option<String> = None;
+    let mut copies: Option<u32> = None;
+    let mut document_type: Option<String> = None;
+
+    while let Ok(Some(field)) = multipart.next_field().await {
+        let name = field.name().unwrap_or("").to_string();
+        match name.as_str() {
+            "file" | "pdf" => {
+                if let Ok(data) = field.bytes().await {
+                    pdf_data = Some(data.to_vec());
+                }
+            }
+            "impresora" | "printer" => {
+                if let Ok(text) = field.text().await {
+                    printer_name = Some(text);
                 }
             }
+            "copias" | "copies" => {
+                if let Ok(text) = field.text().await {
+                    copies = text.trim().parse().ok().or(copies);
+                }
+            }
+            "document_type" | "documentType" | "tipo_documento" => {
+                if let Ok(text) = field.text().await {
+                    document_type = Some(text);
+                }
+            }
+            _ => {}
         }
-    };
-
-    // Create temp file with content
-    use std::io::Write;
-    use tempfile::NamedTempFile;
+    }
 
-    let temp_result = NamedTempFile::new();
-    let mut temp_file = match temp_result {
-        Ok(f) => f,
-        Err(e) => {
+    let data = match pdf_data {
+        Some(d) if !d.is_empty() => d,
+        _ => {
             return (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_REQUEST,
                 Json(PrintResponse {
                     success: false,
-                    message: format!("Error creating temp file: {}", e),
+                    message: "PDF file required".to_string(),
                 }),
             )
                 .into_response()
         }
     };
 
-    if let Err(e) = temp_file.write_all(content.as_bytes()) {
-        return (
+    let job = Job::new(JobSource::Http, Content::Bytes(data), SpoolFormat::Pdf)
+        .with_printer(printer_name)
+        .with_copies(copies)
+        .with_request(request_origin(&headers).map(str::to_string), document_type)
+        .spool_on_failure();
+
+    pipeline_response(pipeline::run(&state.app_state, job).await)
+}
+
+/// GET /print_jobs - Job history, newest first (filters: from, to, printer, result; paging: page, page_size)
+async fn get_print_jobs(
+    headers: HeaderMap,
+    State(state): State<Arc<HttpServerState>>,
+    Query(query): Query<HistoryQuery>,
+) -> impl IntoResponse {
+    if let Err(e) = verify_origin(&state, &headers, Permission::ListPrinters).await {
+        return e.into_response();
+    }
+
+    // Verify authentication
+    if let Err(e) = verify_auth(&state).await {
+        return e.into_response();
+    }
+
+    let result = tokio::task::spawn_blocking(move || history::query(&query))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+
+    match result {
+        Ok(page) => Json(page).into_response(),
+        Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(PrintResponse {
                 success: false,
-                message: format!("Error writing content: {}", e),
+                message: e,
             }),
         )
-            .into_response();
+            .into_response(),
     }
+}
 
-    // Persist the temp file so it's not deleted when temp_file goes out of scope
-    let path = match temp_file.into_temp_path().keep() {
-        Ok(p) => p,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(PrintResponse {
-                    success: false,
-                    message: format!("Error persisting temp file: {}", e),
-                }),
-            )
-                .into_response();
-        }
-    };
-    let path_str = path.to_string_lossy().to_string();
-
-    match printer::print_file(&path_str, &printer_name) {
-        Ok(job_id) => {
-            // Don't delete the temp file - let the system clean it up later
-            // Virtual printers like PDFwriter need time to process the file
-            
-            let msg = format!("Printed successfully. Job ID: {}", job_id);
-            let log_entry = create_log_entry("success", format!("RAW print: {}", msg));
-            if let Ok(mut app) = state.app_state.try_write() {
-                if app.logs.len() >= 100 {
-                    app.logs.pop_front();
-                }
-                app.logs.push_back(log_entry);
-            }
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
 
-            Json(PrintResponse {
-                success: true,
-                message: msg,
-            })
-            .into_response()
-        }
-        Err(e) => {
-            let _ = std::fs::remove_file(&path);
-            
-            let log_entry = create_log_entry("error", format!("RAW print error: {}", e));
-            if let Ok(mut app) = state.app_state.try_write() {
-                if app.logs.len() >= 100 {
-                    app.logs.pop_front();
-                }
-                app.logs.push_back(log_entry);
-            }
+/// GET /print_jobs/export - Filtered job history as CSV or JSON (format=csv|json)
+async fn export_print_jobs(
+    headers: HeaderMap,
+    State(state): State<Arc<HttpServerState>>,
+    Query(query): Query<HistoryQuery>,
+    Query(export): Query<ExportQuery>,
+) -> impl IntoResponse {
+    if let Err(e) = verify_origin(&state, &headers, Permission::ListPrinters).await {
+        return e.into_response();
+    }
+
+    if let Err(e) = verify_auth(&state).await {
+        return e.into_response();
+    }
 
+    let format = export.format;
+    let result = tokio::task::spawn_blocking(move || history::export(&query, format))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+
+    match result {
+        Ok(body) => {
+            let (content_type, extension) = match format {
+                ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+                ExportFormat::Json => ("application/json", "json"),
+            };
+            let disposition = format!("attachment; filename=\"isiprint-jobs.{}\"", extension);
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(PrintResponse {
-                    success: false,
-                    message: e,
-                }),
+                [(header::CONTENT_TYPE, content_type.to_string()), (header::CONTENT_DISPOSITION, disposition)],
+                body,
             )
                 .into_response()
         }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(PrintResponse {
+                success: false,
+                message: e,
+            }),
+        )
+            .into_response(),
     }
 }
 
-/// POST /printPDF - Download and print a PDF from URL
-async fn print_pdf(
+/// GET /settings - Local settings (profiles, default printers, HTTP server, language)
+async fn get_settings(
     headers: HeaderMap,
     State(state): State<Arc<HttpServerState>>,
-    Json(payload): Json<PrintPdfRequest>,
 ) -> impl IntoResponse {
-    if let Err(e) = verify_origin(&headers) {
+    if let Err(e) = verify_origin(&state, &headers, Permission::Configure).await {
         return e.into_response();
     }
 
-    // Verify authentication
     if let Err(e) = verify_auth(&state).await {
         return e.into_response();
     }
 
-    let url = match payload.url {
-        Some(u) if !u.is_empty() => u,
-        _ => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(PrintResponse {
-                    success: false,
-                    message: "URL required".to_string(),
-                }),
-            )
-                .into_response()
-        }
-    };
-
-    let printer_name = match payload.printer {
-        Some(p) if !p.is_empty() => p,
-        _ => {
-            match printer::list_printers() {
-                Ok(printers) if !printers.is_empty() => printers[0].clone(),
-                _ => {
-                    return (
-                        StatusCode::BAD_REQUEST,
-                        Json(PrintResponse {
-                            success: false,
-                            message: "No printers available".to_string(),
-                        }),
-                    )
-                        .into_response()
-                }
-            }
-        }
-    };
-
-    // Download PDF
-    let client = reqwest::Client::new();
-    let response = match client.get(&url).send().await {
-        Ok(r) => r,
-        Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(PrintResponse {
-                    success: false,
-                    message: format!("Error downloading PDF: {}", e),
-                }),
-            )
-                .into_response()
-        }
-    };
-
-    let pdf_bytes = match response.bytes().await {
-        Ok(b) => b,
-        Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(PrintResponse {
-                    success: false,
-                    message: format!("Error reading PDF: {}", e),
-                }),
-            )
-                .into_response()
-        }
-    };
+    let app = state.app_state.read().await;
+    Json(app.settings.clone()).into_response()
+}
 
-    // Save to temporary file
-    use tempfile::Builder;
+/// POST /settings - Replace local settings. Allowed and denied origins and the
+/// HTTP server addresses are kept: they can only be changed from the app.
+async fn update_settings(
+    headers: HeaderMap,
+    State(state): State<Arc<HttpServerState>>,
+    Json(mut settings): Json<Settings>,
+) -> impl IntoResponse {
+    if let Err(e) = verify_origin(&state, &headers, Permission::Configure).await {
+        return e.into_response();
+    }
 
-    let temp_file = match Builder::new()
-        .prefix("isiprint_http_pdf_")
-        .suffix(".pdf")
-        .tempfile()
-    {
-        Ok(f) => f,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(PrintResponse {
-                    success: false,
-                    message: format!("Error creating temp file: {}", e),
-                }),
-            )
-                .into_response()
-        }
-    };
+    if let Err(e) = verify_auth(&state).await {
+        return e.into_response();
+    }
 
-    // Persist the temp file so it's not deleted when temp_file goes out of scope
-    let path = match temp_file.into_temp_path().keep() {
-        Ok(p) => p,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(PrintResponse {
-                    success: false,
-                    message: format!("Error persisting temp file: {}", e),
-                }),
-            )
-                .into_response();
-        }
+    settings.version = SETTINGS_VERSION;
+    settings.keep_access_settings(&state.app_state.read().await.settings);
+    let saved = match crate::pools::check_installed_printers(&settings).await {
+        Ok(()) => crate::persistence::save_settings(&settings),
+        Err(e) => Err(e),
     };
-    let path_str = path.to_string_lossy().to_string();
-    
-    if let Err(e) = std::fs::write(&path, &pdf_bytes) {
-        let _ = std::fs::remove_file(&path);
+    if let Err(e) = saved {
         return (
-            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::BAD_REQUEST,
             Json(PrintResponse {
                 success: false,
-                message: format!("Error saving PDF: {}", e),
+                message: e,
             }),
         )
             .into_response();
     }
 
-    match printer::print_file(&path_str, &printer_name) {
-        Ok(job_id) => {
-            // Don't delete the temp file - let the system clean it up later
-            // Virtual printers like PDFwriter need time to process the file
-            
-            let msg = format!("PDF printed successfully. Job ID: {}", job_id);
-            let log_entry = create_log_entry("success", format!("PDF print: {}", msg));
-            if let Ok(mut app) = state.app_state.try_write() {
-                if app.logs.len() >= 100 {
-                    app.logs.pop_front();
-                }
-                app.logs.push_back(log_entry);
-            }
-
-            Json(PrintResponse {
-                success: true,
-                message: msg,
-            })
-            .into_response()
-        }
-        Err(e) => {
-            let _ = std::fs::remove_file(&path);
-            
-            let log_entry = create_log_entry("error", format!("PDF print error: {}", e));
-            if let Ok(mut app) = state.app_state.try_write() {
-                if app.logs.len() >= 100 {
-                    app.logs.pop_front();
-                }
-                app.logs.push_back(log_entry);
-            }
+    let mut app = state.app_state.write().await;
+    app.settings = settings.clone();
+    app.add_log("INFO", "Settings updated via HTTP");
+    Json(settings).into_response()
+}
 
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(PrintResponse {
-                    success: false,
-                    message: e,
-                }),
-            )
-                .into_response()
-        }
-    }
+/// Respuesta de error de las rutas de la cola de reintentos
+fn spool_error(error: SpoolError) -> Response {
+    let status = match &error {
+        SpoolError::NotFound(_) => StatusCode::NOT_FOUND,
+        SpoolError::Delivery(e) if e.kind == ErrorKind::Print => StatusCode::SERVICE_UNAVAILABLE,
+        SpoolError::Delivery(e) => error_status(e.kind),
+        SpoolError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (
+        status,
+        Json(PrintResponse {
+            success: false,
+            message: error.to_string(),
+        }),
+    )
+        .into_response()
 }
 
-/// POST /printPDF/upload with multipart (file uploaded directly)
-async fn print_pdf_multipart(
+/// GET /spool - Jobs waiting in the retry queue
+async fn get_spooled_jobs(
     headers: HeaderMap,
     State(state): State<Arc<HttpServerState>>,
-    mut multipart: Multipart,
 ) -> impl IntoResponse {
-    if let Err(e) = verify_origin(&headers) {
+    if let Err(e) = verify_origin(&state, &headers, Permission::ListPrinters).await {
         return e.into_response();
     }
 
-    // Verify authentication
     if let Err(e) = verify_auth(&state).await {
         return e.into_response();
     }
 
-    let mut pdf_data: Option<Vec<u8>> = None;
-    let mut printer_name: Option<String> = None;
-
-    while let Ok(Some(field)) = multipart.next_field().await {
-        let name = field.name().unwrap_or("").to_string();
-        match name.as_str() {
-            "file" | "pdf" => {
-                if let Ok(data) = field.bytes().await {
-                    pdf_data = Some(data.to_vec());
-                }
-            }
-            "impresora" | "printer" => {
-                if let Ok(text) = field.text().await {
-                    printer_name = Some(text);
-                }
-            }
-            _ => {}
-        }
+    match spool::list_jobs() {
+        Ok(jobs) => Json(SpoolResponse { jobs }).into_response(),
+        Err(e) => spool_error(e),
     }
+}
 
-    let data = match pdf_data {
-        Some(d) if !d.is_empty() => d,
-        _ => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(PrintResponse {
-                    success: false,
-                    message: "PDF file required".to_string(),
-                }),
-            )
-                .into_response()
-        }
-    };
-
-    let printer = match printer_name {
-        Some(p) if !p.is_empty() => p,
-        _ => {
-            match printer::list_printers() {
-                Ok(printers) if !printers.is_empty() => printers[0].clone(),
-                _ => {
-                    return (
-                        StatusCode::BAD_REQUEST,
-                        Json(PrintResponse {
-                            success: false,
-                            message: "No printers available".to_string(),
-                        }),
-                    )
-                        .into_response()
-                }
-            }
-        }
-    };
-
-    // Save to temporary file
-    use tempfile::Builder;
+/// POST /spool/{id}/retry - Retry a queued job now
+async fn retry_spooled_job(
+    headers: HeaderMap,
+    State(state): State<Arc<HttpServerState>>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    if let Err(e) = verify_origin(&state, &headers, Permission::Print).await {
+        return e.into_response();
+    }
 
-    let temp_file = match Builder::new()
-        .prefix("isiprint_http_pdf_")
-        .suffix(".pdf")
-        .tempfile()
-    {
-        Ok(f) => f,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(PrintResponse {
-                    success: false,
-                    message: format!("Error creating temp file: {}", e),
-                }),
-            )
-                .into_response()
-        }
-    };
+    if let Err(e) = verify_auth(&state).await {
+        return e.into_response();
+    }
 
-    // Persist the temp file so it's not deleted when temp_file goes out of scope
-    let path = match temp_file.into_temp_path().keep() {
-        Ok(p) => p,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(PrintResponse {
-                    success: false,
-                    message: format!("Error persisting temp file: {}", e),
-                }),
-            )
-                .into_response();
-        }
-    };
-    let path_str = path.to_string_lossy().to_string();
-    
-    if let Err(e) = std::fs::write(&path, &data) {
-        let _ = std::fs::remove_file(&path);
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(PrintResponse {
-                success: false,
-                message: format!("Error saving PDF: {}", e),
-            }),
-        )
-            .into_response();
+    match spool::retry_now(&state.app_state, &id).await {
+        Ok(message) => Json(PrintResponse {
+            success: true,
+            message,
+        })
+        .into_response(),
+        Err(e) => spool_error(e),
     }
+}
 
-    match printer::print_file(&path_str, &printer) {
-        Ok(job_id) => {
-            // Don't delete the temp file - let the system clean it up later
-            // Virtual printers like PDFwriter need time to process the file
-            
-            let msg = format!("Multipart PDF printed. Job ID: {}", job_id);
-            let log_entry = create_log_entry("success", format!("Multipart PDF print: {}", msg));
-            if let Ok(mut app) = state.app_state.try_write() {
-                if app.logs.len() >= 100 {
-                    app.logs.pop_front();
-                }
-                app.logs.push_back(log_entry);
-            }
+/// POST /spool/{id}/reroute - Send a queued job to another printer or pool
+async fn reroute_spooled_job(
+    headers: HeaderMap,
+    State(state): State<Arc<HttpServerState>>,
+    Path(id): Path<String>,
+    Json(payload): Json<RerouteRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = verify_origin(&state, &headers, Permission::Print).await {
+        return e.into_response();
+    }
 
-            Json(PrintResponse {
-                success: true,
-                message: msg,
-            })
-            .into_response()
-        }
-        Err(e) => {
-            let _ = std::fs::remove_file(&path);
-            
-            let log_entry = create_log_entry("error", format!("Multipart PDF print error: {}", e));
-            if let Ok(mut app) = state.app_state.try_write() {
-                if app.logs.len() >= 100 {
-                    app.logs.pop_front();
-                }
-                app.logs.push_back(log_entry);
-            }
+    if let Err(e) = verify_auth(&state).await {
+        return e.into_response();
+    }
 
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(PrintResponse {
-                    success: false,
-                    message: e,
-                }),
-            )
-                .into_response()
-        }
+    match spool::reroute(&state.app_state, &id, &payload.printer).await {
+        Ok(job) => Json(job).into_response(),
+        Err(e) => spool_error(e),
     }
 }
 
-/// GET /print_jobs - List print jobs (recent logs)
-async fn get_print_jobs(
+/// DELETE /spool/{id} - Discard a queued job
+async fn discard_spooled_job(
     headers: HeaderMap,
     State(state): State<Arc<HttpServerState>>,
+    Path(id): Path<String>,
 ) -> impl IntoResponse {
-    if let Err(e) = verify_origin(&headers) {
+    if let Err(e) = verify_origin(&state, &headers, Permission::ClearJobs).await {
         return e.into_response();
     }
 
-    // Verify authentication
     if let Err(e) = verify_auth(&state).await {
         return e.into_response();
     }
 
-    let app = state.app_state.read().await;
-    let jobs: Vec<PrintJobInfo> = app.logs
-        .iter()
-        .filter(|log| log.message.to_lowercase().contains("print") || log.message.to_lowercase().contains("printed"))
-        .enumerate()
-        .map(|(i, log)| PrintJobInfo {
-            id: format!("{}", i),
-            timestamp: log.timestamp.clone(),
-            status: log.level.clone(),
-            message: log.message.clone(),
-        })
-        .collect();
-
-    Json(PrintJobsResponse { jobs }).into_response()
+    match spool::discard(&state.app_state, &id).await {
+        Ok(job) => Json(job).into_response(),
+        Err(e) => spool_error(e),
+    }
 }
 
-/// POST /clear_jobs - Clear print jobs
+/// POST /clear_jobs - Clear the job history, logs and system queue
 async fn clear_print_jobs(
     headers: HeaderMap,
     State(state): State<Arc<HttpServerState>>,
 ) -> impl IntoResponse {
-    if let Err(e) = verify_origin(&headers) {
+    if let Err(e) = verify_origin(&state, &headers, Permission::ClearJobs).await {
         return e.into_response();
     }
 
@@ -744,6 +949,20 @@ async fn clear_print_jobs(
         return e.into_response();
     }
 
+    let cleared = tokio::task::spawn_blocking(history::clear)
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    if let Err(e) = cleared {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(PrintResponse {
+                success: false,
+                message: e,
+            }),
+        )
+            .into_response();
+    }
+
     {
         let mut app = state.app_state.write().await;
         app.logs.clear();
@@ -765,7 +984,7 @@ async fn send_cut(
     State(state): State<Arc<HttpServerState>>,
     Json(payload): Json<PrintRequest>,
 ) -> impl IntoResponse {
-    if let Err(e) = verify_origin(&headers) {
+    if let Err(e) = verify_origin(&state, &headers, Permission::Print).await {
         return e.into_response();
     }
 
@@ -774,34 +993,23 @@ async fn send_cut(
         return e.into_response();
     }
 
-    let printer_name = match payload.printer {
-        Some(p) if !p.is_empty() => p,
-        _ => {
-            match printer::list_printers() {
-                Ok(printers) if !printers.is_empty() => printers[0].clone(),
-                _ => {
-                    return (
-                        StatusCode::BAD_REQUEST,
-                        Json(PrintResponse {
-                            success: false,
-                            message: "No printers available".to_string(),
-                        }),
-                    )
-                        .into_response()
-                }
-            }
+    let printer_name = match resolve_printer(&state, payload.printer, payload.document_type.as_deref()).await {
+        Some(p) => p,
+        None => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(PrintResponse {
+                    success: false,
+                    message: "No printers available".to_string(),
+                }),
+            )
+                .into_response()
         }
     };
 
     match printer::send_cut(&printer_name) {
         Ok(()) => {
-            let log_entry = create_log_entry("success", "Cut command sent".to_string());
-            if let Ok(mut app) = state.app_state.try_write() {
-                if app.logs.len() >= 100 {
-                    app.logs.pop_front();
-                }
-                app.logs.push_back(log_entry);
-            }
+            state.app_state.write().await.add_log("SUCCESS", "Cut command sent");
 
             Json(PrintResponse {
                 success: true,
@@ -822,42 +1030,342 @@ async fn send_cut(
     }
 }
 
-/// Inicia el servidor HTTP en el puerto 7777
+/// POST /printers/probe - Probe raw, IPP/IPPS, LPD and HTTP on a host.
+/// Needs the same permission as adding a printer and only reaches printer ports.
+async fn probe_printer(
+    headers: HeaderMap,
+    State(state): State<Arc<HttpServerState>>,
+    Json(payload): Json<ProbePrinterRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = verify_origin(&state, &headers, Permission::Configure).await {
+        return e.into_response();
+    }
+
+    // Verify authentication
+    if let Err(e) = verify_auth(&state).await {
+        return e.into_response();
+    }
+
+    let address = match payload.address {
+        Some(a) if !a.trim().is_empty() => a,
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(PrintResponse {
+                    success: false,
+                    message: "Address required".to_string(),
+                }),
+            )
+                .into_response()
+        }
+    };
+    if let Err(e) = network_discovery::check_printer_port(&address) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(PrintResponse {
+                success: false,
+                message: e,
+            }),
+        )
+            .into_response();
+    }
+
+    match network_discovery::probe_printer_address(&address).await {
+        Ok(probes) => Json(serde_json::json!({ "probes": probes })).into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(PrintResponse {
+                success: false,
+                message: e,
+            }),
+        )
+            .into_response(),
+    }
+}
+
+/// POST /printers/add - Probe an address and register it as a printer queue
+async fn add_printer(
+    headers: HeaderMap,
+    State(state): State<Arc<HttpServerState>>,
+    Json(payload): Json<AddPrinterRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = verify_origin(&state, &headers, Permission::Configure).await {
+        return e.into_response();
+    }
+
+    // Verify authentication
+    if let Err(e) = verify_auth(&state).await {
+        return e.into_response();
+    }
+
+    let (address, name) = match (payload.address, payload.name) {
+        (Some(a), Some(n)) if !a.trim().is_empty() && !n.trim().is_empty() => (a, n),
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(PrintResponse {
+                    success: false,
+                    message: "Address and name required".to_string(),
+                }),
+            )
+                .into_response()
+        }
+    };
+    if let Err(e) = network_discovery::check_printer_port(&address) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(PrintResponse {
+                success: false,
+                message: e,
+            }),
+        )
+            .into_response();
+    }
+
+    match network_discovery::add_printer_by_address(&address, &name, payload.protocol.as_deref()).await {
+        Ok((added, message)) => {
+            state.app_state.write().await.add_log("SUCCESS", &format!("{} ({})", message, network_discovery::printer_uri(&added)));
+
+            Json(serde_json::json!({
+                "success": true,
+                "message": message,
+                "printer": added,
+            }))
+            .into_response()
+        }
+        Err(e) => {
+            state.app_state.write().await.add_log("ERROR", &format!("Add printer error: {}", e));
+
+            (
+                StatusCode::BAD_REQUEST,
+                Json(PrintResponse {
+                    success: false,
+                    message: e,
+                }),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Inicia el servidor HTTP en las direcciones configuradas
 pub async fn start_http_server(app_state: Arc<RwLock<AppState>>) {
-    let state = Arc::new(HttpServerState { app_state });
+    // Direcciones configuradas en settings.json (por defecto 127.0.0.1:7777, respaldo 7778)
+    let http = app_state.read().await.settings.http.clone();
+
+    let (listeners, mut status) = bind_listeners(&http).await;
+    let https = match http.https_port {
+        Some(port) => match bind_https(&http, port).await {
+            Ok(https) => {
+                status.ca_reinstall = https.issued == Issued::Replaced;
+                if let Ok(address) = https.listener.local_addr() {
+                    status.https = Some(address.to_string());
+                    if is_exposed(&address) {
+                        status.exposed.push(address.to_string());
+                    }
+                }
+                Some(https)
+            }
+            Err(e) => {
+                status.errors.push(e);
+                None
+            }
+        },
+        None => None,
+    };
+    {
+        let mut app_state = app_state.write().await;
+        for error in &status.errors {
+            app_state.add_log("ERROR", error);
+        }
+        if status.fallback {
+            app_state.add_log(
+                "WARN",
+                &format!(
+                    "Port {} is in use, local API available on fallback port {}",
+                    http.port,
+                    status.port.unwrap_or_default()
+                ),
+            );
+        }
+        if status.ca_reinstall {
+            app_state.add_log(
+                "WARN",
+                "HTTPS certificate renewed with a new local CA: export and install it again",
+            );
+        }
+        if !status.exposed.is_empty() {
+            app_state.add_log(
+                "WARN",
+                &format!(
+                    "Local API reachable from the network at {}: every request needs the API key",
+                    status.exposed.join(", ")
+                ),
+            );
+        }
+        if listeners.is_empty() && https.is_none() {
+            app_state.add_log("ERROR", "HTTP server could not start: no address available");
+        }
+        app_state.http_server = status.clone();
+    }
+
+    for address in &status.listening {
+        log::info!("HTTP server started at http://{}", address);
+        println!("ISIPRINT HTTP Server running at http://{}", address);
+    }
+
+    let mut servers = tokio::task::JoinSet::new();
+    for listener in listeners {
+        let exposed = listener.local_addr().map_or(true, |a| is_exposed(&a));
+        let app = router(app_state.clone(), exposed);
+        servers.spawn(async move { axum::serve(listener, app).await });
+    }
+    if let Some(HttpsListener {
+        listener,
+        config,
+        files,
+        names,
+        ..
+    }) = https
+    {
+        log::info!(
+            "HTTPS server started at https://{}",
+            status.https.as_deref().unwrap_or_default()
+        );
+        tokio::spawn(tls::run_renewal(files, names, config.clone(), app_state.clone()));
+        let exposed = listener.local_addr().map_or(true, |a| is_exposed(&a));
+        let app = router(app_state.clone(), exposed);
+        servers.spawn(async move {
+            axum_server::from_tcp_rustls(listener, config)
+                .serve(app.into_make_service())
+                .await
+        });
+    }
+    while let Some(result) = servers.join_next().await {
+        if let Ok(Err(e)) = result {
+            log::error!("Error en servidor HTTP: {}", e);
+        }
+    }
+}
 
-    // Configurar CORS permisivo (la validación se hace en cada endpoint)
+/// Rutas de la API para una escucha (`exposed` si es accesible desde la red)
+fn router(app_state: Arc<RwLock<AppState>>, exposed: bool) -> Router {
+    let state = Arc::new(HttpServerState {
+        app_state,
+        require_api_key: exposed,
+    });
+
+    // Configurar CORS permisivo (la validación se hace en cada endpoint y,
+    // para Private Network Access, en `allow_private_network`)
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
-        .allow_headers(Any);
+        .allow_headers(Any)
+        .expose_headers([header::HeaderName::from_static(REQUEST_ID_HEADER)]);
 
-    let app = Router::new()
+    Router::new()
         .route("/", get(index))
         .route("/printers", get(get_printers))
+        .route("/printers/probe", post(probe_printer))
+        .route("/printers/add", post(add_printer))
         .route("/print", post(print_raw))
         .route("/printPDF", post(print_pdf))
         .route("/printPDF/upload", post(print_pdf_multipart))
         .route("/print_jobs", get(get_print_jobs))
+        .route("/print_jobs/export", get(export_print_jobs))
         .route("/clear_jobs", post(clear_print_jobs))
         .route("/cut", post(send_cut))
+        .route("/settings", get(get_settings).post(update_settings))
+        .route("/spool", get(get_spooled_jobs))
+        .route("/spool/{id}", delete(discard_spooled_job))
+        .route("/spool/{id}/retry", post(retry_spooled_job))
+        .route("/spool/{id}/reroute", post(reroute_spooled_job))
+        .layer(middleware::from_fn_with_state(state.clone(), verify_endpoint))
+        .layer(middleware::from_fn(assign_request_id))
         .layer(cors)
-        .with_state(state);
+        .layer(middleware::from_fn_with_state(state.clone(), allow_private_network))
+        .with_state(state)
+}
 
-    let listener = match tokio::net::TcpListener::bind("127.0.0.1:7777").await {
-        Ok(l) => l,
-        Err(e) => {
-            log::error!("Error binding to port 7777: {}", e);
-            return;
+/// Escucha HTTPS lista para servir
+struct HttpsListener {
+    listener: std::net::TcpListener,
+    config: RustlsConfig,
+    files: TlsFiles,
+    names: Vec<String>,
+    issued: Issued,
+}
+
+/// Abrir la escucha HTTPS con el certificado local (generado o renovado si hace falta)
+async fn bind_https(http: &HttpSettings, port: u16) -> Result<HttpsListener, String> {
+    let names = tls::server_names(http);
+    let (files, issued) = tokio::task::spawn_blocking({
+        let names = names.clone();
+        move || {
+            let files = TlsFiles::open()?;
+            let issued = files.ensure(&names, chrono::Utc::now())?;
+            Ok::<_, String>((files, issued))
         }
-    };
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    let config = tls::load_config(&files).await?;
+    let listener = tokio::net::TcpListener::bind((http.host.as_str(), port))
+        .await
+        .and_then(|l| l.into_std())
+        .map_err(|e| format!("Could not listen on {}:{} (HTTPS): {}", http.host, port, e))?;
+    Ok(HttpsListener {
+        listener,
+        config,
+        files,
+        names,
+        issued,
+    })
+}
 
-    log::info!("HTTP server started at http://127.0.0.1:7777");
-    println!("ISIPRINT HTTP Server running at http://127.0.0.1:7777");
+/// Abrir el puerto principal (o el de respaldo si está ocupado) y las escuchas adicionales
+async fn bind_listeners(http: &HttpSettings) -> (Vec<tokio::net::TcpListener>, HttpServerStatus) {
+    let mut listeners = Vec::new();
+    let mut status = HttpServerStatus::default();
 
-    if let Err(e) = axum::serve(listener, app).await {
-        log::error!("Error en servidor HTTP: {}", e);
+    let mut ports = vec![(http.port, false)];
+    if let Some(port) = http.fallback_port {
+        ports.push((port, true));
     }
+    for (port, fallback) in ports {
+        match tokio::net::TcpListener::bind((http.host.as_str(), port)).await {
+            Ok(listener) => {
+                status.port = listener.local_addr().ok().map(|a| a.port());
+                status.fallback = fallback;
+                listeners.push(listener);
+                break;
+            }
+            Err(e) => status
+                .errors
+                .push(format!("Could not listen on {}:{}: {}", http.host, port, e)),
+        }
+    }
+
+    for extra in &http.listeners {
+        match tokio::net::TcpListener::bind((extra.host.as_str(), extra.port)).await {
+            Ok(listener) => listeners.push(listener),
+            Err(e) => status.errors.push(format!(
+                "Could not listen on {}:{}: {}",
+                extra.host, extra.port, e
+            )),
+        }
+    }
+
+    let addresses: Vec<std::net::SocketAddr> =
+        listeners.iter().filter_map(|l| l.local_addr().ok()).collect();
+    status.listening = addresses.iter().map(|a| a.to_string()).collect();
+    status.exposed = addresses
+        .iter()
+        .filter(|a| is_exposed(a))
+        .map(|a| a.to_string())
+        .collect();
+    (listeners, status)
 }
 
 // ============ Tests ============
@@ -866,12 +1374,22 @@ pub async fn start_http_server(app_state: Arc<RwLock<AppState>>) {
 mod tests {
     use super::*;
 
+    /// Origen permitido para imprimir con la lista por defecto
+    fn is_origin_allowed(origin: &str) -> bool {
+        is_origin_allowed_with(origin, &[])
+    }
+
+    fn is_origin_allowed_with(origin: &str, extra: &[String]) -> bool {
+        origins::check(&origins::default_origins(), extra, origin, Permission::Print)
+            == Decision::Allowed
+    }
+
     #[test]
-    fn test_origin_localhost_allowed() {
-        assert!(is_origin_allowed("http://localhost"));
-        assert!(is_origin_allowed("http://localhost:3000"));
-        assert!(is_origin_allowed("http://127.0.0.1"));
-        assert!(is_origin_allowed("http://127.0.0.1:8080"));
+    fn test_origin_localhost_only_in_debug() {
+        // Loopback es el servidor de desarrollo: en producción necesita la clave de API
+        for origin in ["http://localhost", "http://localhost:3000", "http://127.0.0.1:8080"] {
+            assert_eq!(is_origin_allowed(origin), cfg!(debug_assertions), "{}", origin);
+        }
     }
 
     #[test]
@@ -887,6 +1405,18 @@ mod tests {
         assert!(is_origin_allowed("https://integrate.com.bo"));
     }
 
+    #[test]
+    fn test_incoming_request_id() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(incoming_request_id(&headers), None);
+
+        headers.insert(REQUEST_ID_HEADER, "pos-42_a".parse().unwrap());
+        assert_eq!(incoming_request_id(&headers).as_deref(), Some("pos-42_a"));
+
+        headers.insert(REQUEST_ID_HEADER, "bad id\"".parse().unwrap());
+        assert_eq!(incoming_request_id(&headers), None);
+    }
+
     #[test]
     fn test_origin_malicious_rejected() {
         assert!(!is_origin_allowed("https://evil.com"));
@@ -901,8 +1431,150 @@ mod tests {
     }
 
     #[test]
-    fn test_origin_empty_allowed() {
-        // Origen vacío se permite (para peticiones directas, curl, etc.)
-        assert!(is_origin_allowed(""));
+    fn test_origin_from_license_config_allowed() {
+        let extra = vec!["*.mitienda.com".to_string(), "kiosko.local".to_string()];
+        assert!(is_origin_allowed_with("https://pos.mitienda.com", &extra));
+        assert!(is_origin_allowed_with("http://kiosko.local:8080", &extra));
+        assert!(is_origin_allowed_with("https://app.isipass.net", &extra));
+        assert!(!is_origin_allowed_with("https://pos.mitienda.com", &[]));
+        assert!(!is_origin_allowed_with("https://evil.com", &extra));
+    }
+
+    #[test]
+    fn test_origin_empty_needs_api_key() {
+        // Sin origen (curl, scripts) no hay permiso por lista: se pide la clave de API
+        assert!(!is_origin_allowed(""));
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(request_origin(&headers), None);
+        headers.insert(header::ORIGIN, "".parse().unwrap());
+        assert_eq!(request_origin(&headers), None);
+        headers.insert(header::REFERER, "https://app.isipass.net/caja".parse().unwrap());
+        assert_eq!(request_origin(&headers), Some("https://app.isipass.net/caja"));
+    }
+
+    #[tokio::test]
+    async fn test_bind_falls_back_when_port_taken() {
+        let taken = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let http = HttpSettings {
+            host: "127.0.0.1".to_string(),
+            port: taken.local_addr().unwrap().port(),
+            // Puerto libre elegido por el sistema
+            fallback_port: Some(0),
+            listeners: vec![crate::settings::HttpListener {
+                host: "127.0.0.1".to_string(),
+                port: 0,
+            }],
+            https_port: None,
+        };
+
+        let (listeners, status) = bind_listeners(&http).await;
+        assert_eq!(listeners.len(), 2);
+        assert!(status.fallback);
+        assert_ne!(status.port, Some(http.port));
+        assert_eq!(status.errors.len(), 1);
+        assert_eq!(status.listening.len(), 2);
+
+        let http = HttpSettings {
+            fallback_port: None,
+            listeners: Vec::new(),
+            ..http
+        };
+        let (listeners, status) = bind_listeners(&http).await;
+        assert!(listeners.is_empty());
+        assert_eq!(status.port, None);
+        assert_eq!(status.errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_network_listener_requires_api_key_with_origin() {
+        let app_state = AppState {
+            api_key: Some("clave-local".to_string()),
+            ..AppState::default()
+        };
+        let state = Arc::new(HttpServerState {
+            app_state: Arc::new(RwLock::new(app_state)),
+            require_api_key: true,
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ORIGIN, "https://app.isipass.net".parse().unwrap());
+
+        let result = verify_origin(&state, &headers, Permission::Print).await;
+        assert_eq!(result.unwrap_err().0, StatusCode::UNAUTHORIZED);
+
+        headers.insert(API_KEY_HEADER, "clave-local".parse().unwrap());
+        assert!(verify_origin(&state, &headers, Permission::Print).await.is_ok());
+
+        assert!(is_exposed(&"0.0.0.0:7777".parse().unwrap()));
+        assert!(is_exposed(&"192.168.1.20:7777".parse().unwrap()));
+        assert!(!is_exposed(&"127.0.0.1:7777".parse().unwrap()));
+        assert!(!is_exposed(&"[::1]:7777".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_opaque_origins_are_rejected_without_prompt() {
+        let state = Arc::new(HttpServerState {
+            app_state: Arc::new(RwLock::new(AppState::default())),
+            require_api_key: false,
+        });
+
+        for origin in ["null", "file://"] {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ORIGIN, origin.parse().unwrap());
+            let result = verify_origin(&state, &headers, Permission::Print).await;
+            assert_eq!(result.unwrap_err().0, StatusCode::FORBIDDEN);
+        }
+        assert!(state.app_state.read().await.origin_approvals.pending().is_empty());
+    }
+
+    #[test]
+    fn test_private_network_preflight() {
+        let preflight = |origin: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ORIGIN, origin.parse().unwrap());
+            headers.insert(REQUEST_PRIVATE_NETWORK, "true".parse().unwrap());
+            headers
+        };
+        let defaults = origins::default_origins();
+        let denied = vec!["evil.com".to_string()];
+        let check = |headers: &HeaderMap| {
+            private_network_preflight(&Method::OPTIONS, headers, &defaults, &[], &denied)
+        };
+
+        let allowed = preflight("https://app.isipass.net");
+        assert_eq!(check(&allowed), Some(true));
+        assert_eq!(
+            private_network_preflight(&Method::POST, &allowed, &defaults, &[], &denied),
+            None
+        );
+        assert_eq!(check(&preflight("https://evil.com")), Some(false));
+        // Desconocido: pasa el preflight y la petición espera la aprobación
+        assert_eq!(check(&preflight("https://pos.mitienda.com")), Some(true));
+        // Sin un sitio http(s) no hay a quién pedir aprobación
+        assert_eq!(check(&preflight("null")), Some(false));
+        let mut anonymous = HeaderMap::new();
+        anonymous.insert(REQUEST_PRIVATE_NETWORK, "true".parse().unwrap());
+        assert_eq!(check(&anonymous), Some(false));
+
+        // Preflight CORS común: no se agrega la cabecera
+        let mut plain = HeaderMap::new();
+        plain.insert(header::ORIGIN, "https://app.isipass.net".parse().unwrap());
+        assert_eq!(check(&plain), None);
+    }
+
+    #[test]
+    fn test_origin_permissions() {
+        let mut allowed = origins::default_origins();
+        allowed.push(AllowedOrigin::new("kiosko.isipass.net", vec![Permission::Print]));
+        let kiosk = "https://kiosko.isipass.net";
+
+        assert_eq!(
+            origins::check(&allowed, &[], kiosk, Permission::Print),
+            Decision::Allowed
+        );
+        assert_eq!(
+            origins::check(&allowed, &[], kiosk, Permission::ClearJobs),
+            Decision::Forbidden
+        );
     }
 }
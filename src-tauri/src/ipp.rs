@@ -0,0 +1,270 @@
+// Cliente IPP mínimo (Get-Printer-Attributes) usado para validar colas y consultar estado
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Operación IPP Get-Printer-Attributes (RFC 8011)
+const OP_GET_PRINTER_ATTRIBUTES: u16 = 0x000B;
+
+/// Delimitadores de grupos de atributos
+const TAG_OPERATION_ATTRIBUTES: u8 = 0x01;
+const TAG_END_OF_ATTRIBUTES: u8 = 0x03;
+
+/// Tags de valores usados en la petición/respuesta
+const TAG_INTEGER: u8 = 0x21;
+const TAG_ENUM: u8 = 0x23;
+const TAG_KEYWORD: u8 = 0x44;
+const TAG_URI: u8 = 0x45;
+const TAG_CHARSET: u8 = 0x47;
+const TAG_NATURAL_LANGUAGE: u8 = 0x48;
+
+/// Atributos que pedimos a la impresora
+const REQUESTED_ATTRIBUTES: &[&str] = &[
+    "printer-state",
+    "printer-state-message",
+    "printer-make-and-model",
+    "printer-info",
+];
+
+/// Estado reportado por `printer-state`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrinterState {
+    Idle,
+    Processing,
+    Stopped,
+    Unknown,
+}
+
+impl PrinterState {
+    fn from_enum(value: i32) -> Self {
+        match value {
+            3 => PrinterState::Idle,
+            4 => PrinterState::Processing,
+            5 => PrinterState::Stopped,
+            _ => PrinterState::Unknown,
+        }
+    }
+}
+
+/// Atributos relevantes de la respuesta Get-Printer-Attributes
+#[derive(Debug, Clone)]
+pub struct PrinterAttributes {
+    pub state: PrinterState,
+    pub state_message: Option<String>,
+    pub make_and_model: Option<String>,
+    pub info: Option<String>,
+}
+
+fn push_attribute(buf: &mut Vec<u8>, tag: u8, name: &str, value: &[u8]) {
+    buf.push(tag);
+    buf.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    buf.extend_from_slice(name.as_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buf.extend_from_slice(value);
+}
+
+/// Construir una petición Get-Printer-Attributes (IPP/1.1)
+pub fn build_get_printer_attributes(printer_uri: &str, request_id: u32) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&[0x01, 0x01]);
+    buf.extend_from_slice(&OP_GET_PRINTER_ATTRIBUTES.to_be_bytes());
+    buf.extend_from_slice(&request_id.to_be_bytes());
+
+    buf.push(TAG_OPERATION_ATTRIBUTES);
+    push_attribute(&mut buf, TAG_CHARSET, "attributes-charset", b"utf-8");
+    push_attribute(&mut buf, TAG_NATURAL_LANGUAGE, "attributes-natural-language", b"en");
+    push_attribute(&mut buf, TAG_URI, "printer-uri", printer_uri.as_bytes());
+
+    for (i, attr) in REQUESTED_ATTRIBUTES.iter().enumerate() {
+        // Los valores adicionales de un atributo multi-valor llevan nombre vacío
+        let name = if i == 0 { "requested-attributes" } else { "" };
+        push_attribute(&mut buf, TAG_KEYWORD, name, attr.as_bytes());
+    }
+
+    buf.push(TAG_END_OF_ATTRIBUTES);
+    buf
+}
+
+fn read_u16(body: &[u8], pos: usize) -> Result<usize, String> {
+    body.get(pos..pos + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+        .ok_or_else(|| "Truncated IPP response".to_string())
+}
+
+/// Parsear la respuesta Get-Printer-Attributes
+pub fn parse_get_printer_attributes(body: &[u8]) -> Result<PrinterAttributes, String> {
+    if body.len() < 8 {
+        return Err("Truncated IPP response".to_string());
+    }
+
+    let status = u16::from_be_bytes([body[2], body[3]]);
+    if status >= 0x0100 {
+        return Err(format!("IPP error status 0x{:04x}", status));
+    }
+
+    // Solo guardamos el primer valor de cada atributo
+    let mut values: HashMap<String, (u8, Vec<u8>)> = HashMap::new();
+    let mut pos = 8;
+
+    while pos < body.len() {
+        let tag = body[pos];
+        pos += 1;
+
+        if tag == TAG_END_OF_ATTRIBUTES {
+            break;
+        }
+        if tag <= 0x0F {
+            // Delimitador de grupo
+            continue;
+        }
+
+        let name_len = read_u16(body, pos)?;
+        pos += 2;
+        let name = body
+            .get(pos..pos + name_len)
+            .ok_or_else(|| "Truncated IPP response".to_string())?;
+        pos += name_len;
+
+        let value_len = read_u16(body, pos)?;
+        pos += 2;
+        let value = body
+            .get(pos..pos + value_len)
+            .ok_or_else(|| "Truncated IPP response".to_string())?;
+        pos += value_len;
+
+        if name_len > 0 {
+            values
+                .entry(String::from_utf8_lossy(name).to_string())
+                .or_insert((tag, value.to_vec()));
+        }
+    }
+
+    let text = |name: &str| {
+        values
+            .get(name)
+            .map(|(_, v)| String::from_utf8_lossy(v).to_string())
+            .filter(|s| !s.is_empty())
+    };
+
+    let state = match values.get("printer-state") {
+        Some((tag, v)) if (*tag == TAG_ENUM || *tag == TAG_INTEGER) && v.len() == 4 => {
+            PrinterState::from_enum(i32::from_be_bytes([v[0], v[1], v[2], v[3]]))
+        }
+        _ => PrinterState::Unknown,
+    };
+
+    Ok(PrinterAttributes {
+        state,
+        state_message: text("printer-state-message"),
+        make_and_model: text("printer-make-and-model"),
+        info: text("printer-info"),
+    })
+}
+
+/// Convertir un URI ipp:// o ipps:// en la URL HTTP equivalente
+pub fn ipp_uri_to_http_url(printer_uri: &str) -> Result<String, String> {
+    let parsed = url::Url::parse(printer_uri).map_err(|e| format!("Invalid IPP URI: {}", e))?;
+    let scheme = match parsed.scheme() {
+        "ipp" | "http" => "http",
+        "ipps" | "https" => "https",
+        other => return Err(format!("Unsupported IPP scheme: {}", other)),
+    };
+    let host = parsed.host_str().ok_or("IPP URI without host")?;
+    let port = parsed.port_or_known_default().unwrap_or(631);
+
+    Ok(format!("{}://{}:{}{}", scheme, host, port, parsed.path()))
+}
+
+/// Consultar los atributos de una impresora vía IPP
+pub async fn get_printer_attributes(
+    printer_uri: &str,
+    timeout: Duration,
+) -> Result<PrinterAttributes, String> {
+    let http_url = ipp_uri_to_http_url(printer_uri)?;
+
+    // Las impresoras con IPPS suelen usar certificados autofirmados
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .danger_accept_invalid_certs(true)
+        .build()
+        .map_err(|e| format!("Error creating HTTP client: {}", e))?;
+
+    let response = client
+        .post(&http_url)
+        .header("Content-Type", "application/ipp")
+        .body(build_get_printer_attributes(printer_uri, 1))
+        .send()
+        .await
+        .map_err(|e| format!("Connection error: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("HTTP error: {}", response.status()));
+    }
+
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Error reading IPP response: {}", e))?;
+
+    parse_get_printer_attributes(&body)
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_request_header() {
+        let req = build_get_printer_attributes("ipp://192.168.1.50/ipp/print", 7);
+        assert_eq!(&req[0..2], &[0x01, 0x01]);
+        assert_eq!(&req[2..4], &[0x00, 0x0B]);
+        assert_eq!(&req[4..8], &7u32.to_be_bytes());
+        assert_eq!(req[8], TAG_OPERATION_ATTRIBUTES);
+        assert_eq!(*req.last().unwrap(), TAG_END_OF_ATTRIBUTES);
+    }
+
+    #[test]
+    fn test_parse_response_with_state() {
+        let mut body = vec![0x01, 0x01, 0x00, 0x00, 0, 0, 0, 1];
+        body.push(TAG_OPERATION_ATTRIBUTES);
+        push_attribute(&mut body, TAG_CHARSET, "attributes-charset", b"utf-8");
+        body.push(0x04); // printer-attributes-tag
+        push_attribute(&mut body, TAG_ENUM, "printer-state", &5i32.to_be_bytes());
+        push_attribute(&mut body, 0x41, "printer-make-and-model", b"EPSON TM-T20III");
+        body.push(TAG_END_OF_ATTRIBUTES);
+
+        let attrs = parse_get_printer_attributes(&body).unwrap();
+        assert_eq!(attrs.state, PrinterState::Stopped);
+        assert_eq!(attrs.make_and_model.as_deref(), Some("EPSON TM-T20III"));
+        assert!(attrs.state_message.is_none());
+    }
+
+    #[test]
+    fn test_parse_response_error_status() {
+        // client-error-not-found
+        let body = vec![0x01, 0x01, 0x04, 0x06, 0, 0, 0, 1, TAG_END_OF_ATTRIBUTES];
+        let result = parse_get_printer_attributes(&body);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("0x0406"));
+    }
+
+    #[test]
+    fn test_parse_response_truncated() {
+        assert!(parse_get_printer_attributes(&[0x01, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_ipp_uri_to_http_url() {
+        assert_eq!(
+            ipp_uri_to_http_url("ipp://10.0.0.5/ipp/print").unwrap(),
+            "http://10.0.0.5:631/ipp/print"
+        );
+        assert_eq!(
+            ipp_uri_to_http_url("ipps://printer.local:443/ipp/print").unwrap(),
+            "https://printer.local:443/ipp/print"
+        );
+        assert!(ipp_uri_to_http_url("socket://10.0.0.5:9100").is_err());
+    }
+}
@@ -0,0 +1,222 @@
+// Archivos temporales de los trabajos: directorio propio, borrado al terminar y limpieza de restos
+use crate::persistence;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Prefijo de los archivos de trabajo (también el de versiones anteriores en el temp del sistema)
+const FILE_PREFIX: &str = "isiprint_";
+
+/// Tiempo que se conservan los archivos leídos después por impresoras PDF virtuales
+pub const RETAIN_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Intervalo del barrido periódico
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Archivos de trabajos en curso: el barrido nunca los toca
+static IN_USE: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+
+fn mark_in_use(path: &Path, in_use: bool) {
+    if let Ok(mut set) = IN_USE.lock() {
+        if in_use {
+            set.insert(path.to_path_buf());
+        } else {
+            set.remove(path);
+        }
+    }
+}
+
+fn is_in_use(path: &Path) -> bool {
+    IN_USE.lock().map(|set| set.contains(path)).unwrap_or(false)
+}
+
+/// Archivo de un trabajo. Se elimina al soltarlo, salvo que se use `retain`.
+#[derive(Debug)]
+pub struct JobFile {
+    path: PathBuf,
+    retain: bool,
+}
+
+impl JobFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Conservar el archivo hasta que lo elimine el barrido (`RETAIN_TTL`)
+    pub fn retain(mut self) {
+        self.retain = true;
+    }
+}
+
+impl Drop for JobFile {
+    fn drop(&mut self) {
+        mark_in_use(&self.path, false);
+        if !self.retain {
+            if let Err(e) = fs::remove_file(&self.path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Could not delete job file {}: {}", self.path.display(), e);
+                }
+            }
+        }
+    }
+}
+
+/// Directorio de archivos de trabajo
+#[derive(Debug, Clone)]
+pub struct JobFiles {
+    dir: PathBuf,
+}
+
+impl JobFiles {
+    pub fn open() -> Result<Self, String> {
+        let dir = persistence::get_jobs_dir().ok_or("Could not find data directory")?;
+        Self::at(dir)
+    }
+
+    pub fn at(dir: PathBuf) -> Result<Self, String> {
+        persistence::create_private_dir(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Guardar el contenido de un trabajo (`extension` sin punto)
+    pub fn create(&self, data: &[u8], extension: &str) -> Result<JobFile, String> {
+        let name = format!(
+            "{}job_{:x}_{:08x}.{}",
+            FILE_PREFIX,
+            chrono::Utc::now().timestamp_millis(),
+            rand::random::<u32>(),
+            extension
+        );
+        let path = self.dir.join(name);
+        mark_in_use(&path, true);
+
+        let file = JobFile {
+            path,
+            retain: false,
+        };
+        fs::write(&file.path, data).map_err(|e| format!("Error creating job file: {}", e))?;
+        Ok(file)
+    }
+
+    /// Eliminar los archivos más antiguos que `ttl` que no estén en uso.
+    /// Retorna cuántos se eliminaron.
+    pub fn sweep(&self, ttl: Duration, now: SystemTime) -> usize {
+        sweep_dir(&self.dir, ttl, now)
+    }
+}
+
+/// Eliminar los archivos `isiprint_*` de `dir` más antiguos que `ttl`
+fn sweep_dir(dir: &Path, ttl: Duration, now: SystemTime) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_job_file = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with(FILE_PREFIX));
+        if !is_job_file || !path.is_file() || is_in_use(&path) {
+            continue;
+        }
+
+        let age = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .unwrap_or_default();
+        if age >= ttl && fs::remove_file(&path).is_ok() {
+            removed += 1;
+        }
+    }
+    removed
+}
+
+/// Limpieza al iniciar: ningún trabajo está en curso, así que se elimina todo lo
+/// que quedó en el directorio de trabajos y los `isiprint_*` que versiones
+/// anteriores dejaban en el temp del sistema
+pub fn startup_sweep() {
+    let mut removed = match JobFiles::open() {
+        Ok(files) => files.sweep(Duration::ZERO, SystemTime::now()),
+        Err(e) => {
+            log::warn!("Job files directory unavailable: {}", e);
+            0
+        }
+    };
+    removed += sweep_dir(&std::env::temp_dir(), Duration::ZERO, SystemTime::now());
+
+    if removed > 0 {
+        log::info!("Removed {} stale job files", removed);
+    }
+}
+
+/// Barrido periódico de los archivos conservados para impresoras PDF virtuales
+pub async fn run() {
+    loop {
+        tokio::time::sleep(SWEEP_INTERVAL).await;
+        let removed = tokio::task::spawn_blocking(|| {
+            JobFiles::open()
+                .map(|files| files.sweep(RETAIN_TTL, SystemTime::now()))
+                .unwrap_or(0)
+        })
+        .await
+        .unwrap_or(0);
+
+        if removed > 0 {
+            log::info!("Removed {} expired job files", removed);
+        }
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_file_deleted_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = JobFiles::at(dir.path().join("jobs")).unwrap();
+
+        let file = files.create(b"%PDF-1.4", "pdf").unwrap();
+        let path = file.path().to_path_buf();
+        assert!(path.exists());
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("isiprint_job_"));
+
+        drop(file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_sweep_skips_files_in_use_and_recent() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = JobFiles::at(dir.path().to_path_buf()).unwrap();
+
+        let active = files.create(b"active", "bin").unwrap();
+        let retained = files.create(b"retained", "pdf").unwrap();
+        let retained_path = retained.path().to_path_buf();
+        retained.retain();
+        fs::write(dir.path().join("other.txt"), b"not ours").unwrap();
+
+        // Recientes: no se eliminan
+        assert_eq!(files.sweep(RETAIN_TTL, SystemTime::now()), 0);
+
+        // Vencidos: solo el conservado, no el que está en uso ni archivos ajenos
+        let later = SystemTime::now() + RETAIN_TTL + Duration::from_secs(1);
+        assert_eq!(files.sweep(RETAIN_TTL, later), 1);
+        assert!(!retained_path.exists());
+        assert!(active.path().exists());
+        assert!(dir.path().join("other.txt").exists());
+    }
+}
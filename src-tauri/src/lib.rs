@@ -1,12 +1,33 @@
 // Módulos del proyecto
+mod approvals;
+mod auth;
 mod commands;
+mod device;
 mod graphql;
+mod history;
 mod http_server;
+mod ipp;
+mod job_files;
+mod license;
+mod logging;
 mod network_discovery;
+mod origins;
 mod persistence;
+mod pipeline;
+mod pools;
 mod printer;
+mod printer_health;
+mod quota;
 mod raw_printer;
+mod remote_jobs;
+mod routing;
+mod secrets;
+mod settings;
+mod spool;
 mod state;
+mod tls;
+#[cfg(test)]
+mod test_backend;
 
 use state::AppState;
 use std::sync::Arc;
@@ -22,23 +43,91 @@ use tokio::sync::RwLock;
 /// Estado compartido envuelto en Arc<RwLock> para ser accesible desde Tauri y HTTP server
 pub type SharedAppState = Arc<RwLock<AppState>>;
 
+/// Identificador del tray icon (para actualizarlo desde otros módulos)
+pub const TRAY_ID: &str = "main";
+
+/// Icono del proyecto usado en el tray
+pub const TRAY_ICON: &[u8] = include_bytes!("../icons/icon.png");
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // Initialize logging
-    env_logger::init();
+    logging::init();
+
+    // Saved environment, with environment variables taking precedence
+    let api = persistence::load_api_config()
+        .unwrap_or_default()
+        .with_env_overrides();
+    log::info!(
+        "Using {:?} API at {} (shop: {})",
+        api.environment,
+        api.api_url,
+        api.shop
+    );
 
     // Try to load saved session
-    let initial_state = if let Some(saved_session) = persistence::load_session() {
-        log::info!("Found saved session for: {:?}", saved_session.auth.email);
-        let mut state = AppState::default();
-        state.auth = saved_session.auth;
-        state.licencias = saved_session.licencias;
-        state
-    } else {
-        log::info!("No saved session, starting fresh");
-        AppState::default()
+    let mut initial_state = match persistence::load_session() {
+        // The token belongs to the API that issued it: a session from another API is not restored
+        Some(saved_session) if saved_session.api != api => {
+            log::warn!(
+                "Saved session was issued by {} but the configured API is {}, login required",
+                saved_session.api.api_url,
+                api.api_url
+            );
+            AppState::default()
+        }
+        Some(saved_session) => {
+            log::info!("Found saved session for: {:?}", saved_session.auth.email);
+            let mut state = AppState::default();
+            state.auth = saved_session.auth;
+            // Only licenses signed by the server are trusted, as of the time the server signed them
+            let verified = saved_session.signed_license.as_ref().and_then(|signed| {
+                license::public_key().and_then(|key| signed.verify(&key, state.auth.email.as_deref()))
+            });
+            match verified {
+                Some(payload) => {
+                    state.licencias = payload.licencias;
+                    state.licenses_verified_at = Some(payload.issued_at);
+                    state.signed_license = saved_session.signed_license;
+                }
+                None if saved_session.signed_license.is_some() => {
+                    log::warn!("Saved license signature is invalid, re-verification required")
+                }
+                None => log::info!("No signed license saved, re-verification required"),
+            }
+            state
+        }
+        None => {
+            log::info!("No saved session, starting fresh");
+            AppState::default()
+        }
+    };
+    initial_state.api = api;
+
+    initial_state.settings = persistence::load_settings();
+
+    // HTTP callers without an origin (curl, scripts) must send this key
+    initial_state.api_key = match persistence::load_or_create_api_key() {
+        Ok(key) => Some(key),
+        Err(e) => {
+            log::error!("Local API key unavailable: {}", e);
+            None
+        }
     };
 
+    // Keep the job history file bounded
+    history::compact();
+
+    // Job files left behind by a previous run (including legacy files in the system temp dir)
+    job_files::startup_sweep();
+
+    // Print quota persists across restarts; a new license period starts from zero
+    if let Some(saved_quota) = persistence::load_quota() {
+        initial_state.quota = saved_quota;
+    }
+    quota::align_period(&mut initial_state);
+    license::apply_config(&mut initial_state);
+
     // Shared state for the whole application
     let shared_state: SharedAppState = Arc::new(RwLock::new(initial_state));
     let http_state = shared_state.clone();
@@ -81,10 +170,10 @@ pub fn run() {
             let menu = Menu::with_items(app, &[&show_item, &printers_item, &separator, &quit_item])?;
 
             // Usar el icono del proyecto (no el default)
-            let tray_icon = Image::from_bytes(include_bytes!("../icons/icon.png"))?;
+            let tray_icon = Image::from_bytes(TRAY_ICON)?;
 
             // Crear el tray icon
-            let _tray = TrayIconBuilder::new()
+            let _tray = TrayIconBuilder::with_id(TRAY_ID)
                 .icon(tray_icon.into())
                 .menu(&menu)
                 // Click izquierdo solo abre el menú, no la ventana (en todos los OS)
@@ -116,6 +205,40 @@ pub fn run() {
             if let Some(state) = app.try_state::<SharedAppState>() {
                 let mut app_state = state.blocking_write();
                 app_state.add_log("INFO", "ISIPRINT started successfully");
+                drop(app_state);
+
+                // Monitor de salud de impresoras en segundo plano
+                tauri::async_runtime::spawn(printer_health::run(
+                    app.handle().clone(),
+                    state.inner().clone(),
+                ));
+
+                // Renovación proactiva del token de acceso
+                tauri::async_runtime::spawn(auth::run_refresh_loop(state.inner().clone()));
+
+                // Re-verificación periódica de licencias (período de gracia sin conexión)
+                tauri::async_runtime::spawn(license::run_verification_loop(state.inner().clone()));
+
+                // Reporte al backend de las impresiones pendientes
+                tauri::async_runtime::spawn(quota::run_sync_loop(state.inner().clone()));
+
+                // Registro del dispositivo y latido con el inventario de impresoras
+                tauri::async_runtime::spawn(device::run_heartbeat(state.inner().clone()));
+
+                // Trabajos de impresión enviados desde el backend a este dispositivo
+                tauri::async_runtime::spawn(remote_jobs::run(state.inner().clone()));
+
+                // Reintentos de los trabajos que quedaron en cola
+                tauri::async_runtime::spawn(spool::run(state.inner().clone()));
+
+                // Limpieza de los archivos conservados para impresoras PDF virtuales
+                tauri::async_runtime::spawn(job_files::run());
+
+                // Aprobación de orígenes desconocidos desde la ventana principal
+                tauri::async_runtime::spawn(approvals::run(
+                    app.handle().clone(),
+                    state.inner().clone(),
+                ));
             }
 
             Ok(())
@@ -124,6 +247,7 @@ pub fn run() {
             commands::login,
             commands::get_licencias,
             commands::get_printers,
+            commands::get_default_printer,
             commands::print_pdf,
             commands::print_pdf_from_url,
             commands::print_pdf_with_settings,
@@ -133,6 +257,7 @@ pub fn run() {
             commands::clear_print_jobs,
             commands::send_cut_command,
             commands::get_logs,
+            commands::export_diagnostics,
             commands::validate_domain,
             commands::get_auth_state,
             commands::verify_session,
@@ -142,6 +267,33 @@ pub fn run() {
             commands::scan_network_printers,
             commands::add_network_printer,
             commands::remove_network_printer,
+            commands::probe_printer_address,
+            commands::add_printer_by_address,
+            commands::get_printer_health,
+            commands::get_http_server_status,
+            commands::export_https_ca,
+            commands::get_quota_status,
+            commands::get_license_config,
+            commands::get_device_info,
+            commands::get_settings,
+            commands::update_settings,
+            commands::save_print_profile,
+            commands::delete_print_profile,
+            commands::set_default_printer,
+            commands::save_allowed_origin,
+            commands::remove_allowed_origin,
+            commands::get_pending_origins,
+            commands::resolve_origin_approval,
+            commands::get_api_key,
+            commands::regenerate_api_key,
+            commands::get_job_history,
+            commands::export_job_history,
+            commands::get_spooled_jobs,
+            commands::retry_spooled_job,
+            commands::reroute_spooled_job,
+            commands::discard_spooled_job,
+            commands::get_api_config,
+            commands::set_api_config,
         ])
         .on_window_event(|window, event| {
             // Al cerrar la ventana, solo ocultarla (no cerrar la app)
@@ -0,0 +1,472 @@
+// Licencias firmadas por el servidor y período de gracia sin conexión
+use crate::auth;
+use crate::graphql::{self, GraphQLResult};
+use crate::persistence;
+use crate::quota;
+use crate::state::{AppState, LicenciaProducto};
+use crate::SharedAppState;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Duration};
+
+/// Período de gracia por defecto sin poder contactar al backend
+pub const DEFAULT_GRACE_HOURS: i64 = 72;
+
+/// Máximo configurable: la variable de entorno no puede extender el uso sin conexión indefinidamente
+pub const MAX_GRACE_HOURS: i64 = 7 * 24;
+
+/// Variable de entorno para configurar el período de gracia
+const ENV_GRACE_HOURS: &str = "ISIPRINT_LICENSE_GRACE_HOURS";
+
+/// Tolerancia para relojes levemente adelantados/atrasados
+const CLOCK_SKEW_SECS: i64 = 5 * 60;
+
+/// Intervalo de re-verificación en segundo plano
+const VERIFY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Clave pública Ed25519 (base64) con la que el backend firma las licencias,
+/// incluida al compilar. Obligatoria en release: sin ella no se confía en ninguna
+/// caché y se pierde el periodo de gracia sin conexión.
+#[cfg(not(debug_assertions))]
+const LICENSE_PUBLIC_KEY: Option<&str> = Some(env!(
+    "ISIPRINT_LICENSE_PUBLIC_KEY",
+    "ISIPRINT_LICENSE_PUBLIC_KEY must be set for release builds (base64 Ed25519 public key)"
+));
+
+/// En desarrollo es opcional: sin ella se exige conexión para validar licencias
+#[cfg(debug_assertions)]
+const LICENSE_PUBLIC_KEY: Option<&str> = option_env!("ISIPRINT_LICENSE_PUBLIC_KEY");
+
+/// Licencias firmadas por el backend (`licenciaFirmada`), tal como se guardan en la sesión
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedLicense {
+    /// JSON de `LicensePayload` en base64
+    pub payload: String,
+    /// Firma Ed25519 del payload (bytes decodificados) en base64
+    #[serde(alias = "firma")]
+    pub signature: String,
+}
+
+/// Contenido firmado por el backend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LicensePayload {
+    #[serde(alias = "correo")]
+    pub email: String,
+    pub licencias: Vec<LicenciaProducto>,
+    /// Momento de la firma según el servidor (segundos Unix)
+    #[serde(alias = "emitidaEn", alias = "issuedAt")]
+    pub issued_at: i64,
+}
+
+impl SignedLicense {
+    /// Verificar la firma del servidor y que las licencias sean de la cuenta
+    pub fn verify(&self, key: &VerifyingKey, email: Option<&str>) -> Option<LicensePayload> {
+        let payload = STANDARD.decode(&self.payload).ok()?;
+        let signature = STANDARD.decode(&self.signature).ok()?;
+        let signature = Signature::from_slice(&signature).ok()?;
+        key.verify_strict(&payload, &signature).ok()?;
+
+        let payload: LicensePayload = serde_json::from_slice(&payload).ok()?;
+        let same_account = email.is_some_and(|e| e.eq_ignore_ascii_case(&payload.email));
+        same_account.then_some(payload)
+    }
+}
+
+/// Clave pública del backend incluida en el binario
+pub fn public_key() -> Option<VerifyingKey> {
+    parse_public_key(LICENSE_PUBLIC_KEY?)
+}
+
+fn parse_public_key(encoded: &str) -> Option<VerifyingKey> {
+    let bytes: [u8; 32] = STANDARD.decode(encoded.trim()).ok()?.try_into().ok()?;
+    VerifyingKey::from_bytes(&bytes).ok()
+}
+
+/// Pedir las licencias firmadas al backend; solo se conservan si la firma es válida
+pub async fn fetch_signed(
+    api: &graphql::ApiConfig,
+    token: &str,
+    email: Option<&str>,
+) -> Option<SignedLicense> {
+    let Some(key) = public_key() else {
+        log::warn!("No license public key in this build, offline use requires a connection");
+        return None;
+    };
+
+    match graphql::get_signed_license(api, token).await {
+        Ok(signed) if signed.verify(&key, email).is_some() => Some(signed),
+        Ok(_) => {
+            log::warn!("Signed license from the server failed verification");
+            None
+        }
+        Err(e) => {
+            log::warn!("Could not fetch signed license: {}", e);
+            None
+        }
+    }
+}
+
+/// Funciones que el administrador puede deshabilitar desde el backend
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeatureToggles {
+    #[serde(alias = "rawPrinting", alias = "impresionRaw")]
+    pub raw_printing: bool,
+    #[serde(alias = "networkScan", alias = "escaneoRed")]
+    pub network_scan: bool,
+}
+
+impl Default for FeatureToggles {
+    fn default() -> Self {
+        Self {
+            raw_printing: true,
+            network_scan: true,
+        }
+    }
+}
+
+fn default_preset() -> String {
+    "thermal".to_string()
+}
+
+/// Perfil de impresión predefinido por el administrador
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrinterProfile {
+    #[serde(alias = "nombre")]
+    pub name: String,
+    #[serde(default, alias = "impresora")]
+    pub printer: Option<String>,
+    #[serde(default = "default_preset")]
+    pub preset: String,
+    #[serde(default, alias = "widthMm", alias = "anchoMm")]
+    pub width_mm: Option<f64>,
+    #[serde(default, alias = "heightMm", alias = "altoMm")]
+    pub height_mm: Option<f64>,
+}
+
+impl PrinterProfile {
+    /// Medidas positivas: `lp` no recibe un tamaño de papel inválido
+    fn validate(&self) -> Result<(), String> {
+        for size in [self.width_mm, self.height_mm].into_iter().flatten() {
+            if !size.is_finite() || size <= 0.0 {
+                return Err(format!("Printer profile '{}' has an invalid paper size", self.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Configuración centralizada enviada en `LicenciaProducto.configuracion` (JSON)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LicenseConfig {
+    /// Orígenes adicionales a los dominios permitidos por defecto
+    #[serde(alias = "allowedOrigins", alias = "origenesPermitidos")]
+    pub allowed_origins: Vec<String>,
+    #[serde(alias = "defaultPrinter", alias = "impresoraPorDefecto")]
+    pub default_printer: Option<String>,
+    #[serde(alias = "printerProfiles", alias = "perfiles")]
+    pub printer_profiles: Vec<PrinterProfile>,
+    #[serde(alias = "maxCopies", alias = "maxCopias")]
+    pub max_copies: Option<u32>,
+    /// Endpoints HTTP habilitados (None: todos)
+    #[serde(alias = "allowedEndpoints", alias = "endpointsPermitidos")]
+    pub allowed_endpoints: Option<Vec<String>>,
+    #[serde(alias = "funciones")]
+    pub features: FeatureToggles,
+}
+
+impl LicenseConfig {
+    /// Interpretar la configuración de la licencia; vacía equivale a la configuración por defecto
+    pub fn parse(raw: Option<&str>) -> Result<Self, String> {
+        match raw.map(str::trim) {
+            None | Some("") | Some("null") => Ok(Self::default()),
+            Some(json) => {
+                let config: Self = serde_json::from_str(json)
+                    .map_err(|e| format!("Invalid license configuration: {}", e))?;
+                for profile in &config.printer_profiles {
+                    profile
+                        .validate()
+                        .map_err(|e| format!("Invalid license configuration: {}", e))?;
+                }
+                Ok(config)
+            }
+        }
+    }
+
+    /// Perfil del administrador para una impresora (el primero que la nombra)
+    pub fn profile_for(&self, printer: &str) -> Option<&PrinterProfile> {
+        self.printer_profiles.iter().find(|p| {
+            p.printer
+                .as_deref()
+                .is_some_and(|name| name.eq_ignore_ascii_case(printer))
+        })
+    }
+
+    /// Verificar si un endpoint HTTP está habilitado (acepta rutas con o sin "/")
+    pub fn is_endpoint_allowed(&self, path: &str) -> bool {
+        match &self.allowed_endpoints {
+            None => true,
+            Some(endpoints) => endpoints
+                .iter()
+                .any(|e| e.trim_start_matches('/') == path.trim_start_matches('/')),
+        }
+    }
+
+    /// Verificar la cantidad de copias solicitada
+    pub fn check_copies(&self, copies: u32) -> Result<(), String> {
+        match self.max_copies {
+            Some(max) if copies > max => Err(format!(
+                "Too many copies requested ({}), the maximum allowed is {}",
+                copies, max
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Aplicar la configuración de la licencia de impresión actual
+pub fn apply_config(app_state: &mut AppState) {
+    let raw = app_state
+        .print_license()
+        .and_then(|l| l.configuracion.clone());
+
+    match LicenseConfig::parse(raw.as_deref()) {
+        Ok(config) => {
+            if config != app_state.license_config {
+                app_state.add_log("INFO", "License configuration applied");
+            }
+            app_state.license_config = config;
+        }
+        Err(e) => {
+            // Se mantiene la configuración anterior
+            app_state.add_log("WARN", &e);
+        }
+    }
+}
+
+/// Período de gracia configurado, en segundos
+pub fn grace_period_secs() -> i64 {
+    grace_hours(std::env::var(ENV_GRACE_HOURS).ok().as_deref()) * 3600
+}
+
+/// Horas de gracia: el valor configurado, limitado a `MAX_GRACE_HOURS`
+fn grace_hours(value: Option<&str>) -> i64 {
+    value
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|h| *h >= 0)
+        .unwrap_or(DEFAULT_GRACE_HOURS)
+        .min(MAX_GRACE_HOURS)
+}
+
+/// Verificar si una verificación sigue dentro del período de gracia.
+/// Un reloj atrasado respecto a la verificación se trata como manipulado.
+pub fn is_within_grace(verified_at: Option<i64>, now: i64, grace_secs: i64) -> bool {
+    match verified_at {
+        Some(t) => now >= t - CLOCK_SKEW_SECS && now - t <= grace_secs,
+        None => false,
+    }
+}
+
+/// Volver a consultar las licencias al backend, actualizar el estado y la caché
+pub async fn refresh_licenses(state: &SharedAppState) -> GraphQLResult<Vec<LicenciaProducto>> {
+    let api = state.read().await.api.clone();
+
+    let licencias = auth::with_token_refresh(state, |token| {
+        let api = api.clone();
+        async move { graphql::get_licencias(&api, &token).await }
+    })
+    .await?;
+
+    let (token, email) = {
+        let app_state = state.read().await;
+        (app_state.auth.token.clone(), app_state.auth.email.clone())
+    };
+    let signed = match token {
+        Some(token) => fetch_signed(&api, &token, email.as_deref()).await,
+        None => None,
+    };
+
+    let mut app_state = state.write().await;
+    app_state.licencias = licencias.clone();
+    app_state.licenses_verified_at = Some(chrono::Utc::now().timestamp());
+    app_state.signed_license = signed;
+    quota::align_period(&mut app_state);
+    apply_config(&mut app_state);
+
+    if let Err(e) = persistence::save_session(&app_state) {
+        log::warn!("Failed to update saved session: {}", e);
+    }
+    drop(app_state);
+
+    // Consumo del período según el backend (no depende de quota.json)
+    if let Err(e) = quota::pull_usage(state).await {
+        log::info!("Print usage not fetched from server: {}", e);
+    }
+
+    Ok(licencias)
+}
+
+/// Tarea de fondo: re-verificar las licencias para no agotar el período de gracia
+pub async fn run_verification_loop(state: SharedAppState) {
+    loop {
+        sleep(VERIFY_INTERVAL).await;
+
+        if !state.read().await.auth.is_logged_in {
+            continue;
+        }
+
+        match refresh_licenses(&state).await {
+            Ok(_) => log::info!("Licenses re-verified with backend"),
+            Err(e) if e.is_unreachable() => {
+                let mut app_state = state.write().await;
+                app_state.add_log("WARN", &format!("License server unreachable, using cached license: {}", e));
+            }
+            Err(e) => log::warn!("License re-verification failed: {}", e),
+        }
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_license(fecha_vencimiento: &str) -> LicenciaProducto {
+        LicenciaProducto {
+            _id: "lic1".to_string(),
+            tipo_producto: "IMPRESION".to_string(),
+            maximo_conexiones: 10,
+            fecha_vencimiento: fecha_vencimiento.to_string(),
+            delegado: false,
+            configuracion: None,
+            state: "ACTIVADO".to_string(),
+        }
+    }
+
+    fn signed(key: &ed25519_dalek::SigningKey, payload: &LicensePayload) -> SignedLicense {
+        use ed25519_dalek::Signer;
+
+        let bytes = serde_json::to_vec(payload).unwrap();
+        SignedLicense {
+            payload: STANDARD.encode(&bytes),
+            signature: STANDARD.encode(key.sign(&bytes).to_bytes()),
+        }
+    }
+
+    fn payload(fecha_vencimiento: &str) -> LicensePayload {
+        LicensePayload {
+            email: "a@b.com".to_string(),
+            licencias: vec![test_license(fecha_vencimiento)],
+            issued_at: 1_800_000_000,
+        }
+    }
+
+    #[test]
+    fn test_embedded_public_key_is_valid() {
+        // Una clave mal copiada en el entorno de compilación no debe pasar inadvertida
+        if let Some(key) = LICENSE_PUBLIC_KEY {
+            assert!(parse_public_key(key).is_some(), "invalid ISIPRINT_LICENSE_PUBLIC_KEY");
+        }
+    }
+
+    #[test]
+    fn test_signed_license_verifies() {
+        let server = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let key = parse_public_key(&STANDARD.encode(server.verifying_key().to_bytes())).unwrap();
+        let license = signed(&server, &payload("01/01/2030 00:00:00"));
+
+        let json = serde_json::to_string(&license).unwrap();
+        let parsed: SignedLicense = serde_json::from_str(&json).unwrap();
+        let verified = parsed.verify(&key, Some("A@b.com")).unwrap();
+        assert_eq!(verified.issued_at, 1_800_000_000);
+        assert_eq!(verified.licencias.len(), 1);
+    }
+
+    #[test]
+    fn test_signed_license_tampering_detected() {
+        let server = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let key = server.verifying_key();
+        let license = signed(&server, &payload("01/01/2025 00:00:00"));
+
+        // Extender la fecha de vencimiento sin la clave del servidor
+        let mut extended = license.clone();
+        extended.payload = STANDARD.encode(serde_json::to_vec(&payload("01/01/2099 00:00:00")).unwrap());
+        assert!(extended.verify(&key, Some("a@b.com")).is_none());
+
+        // Firmada con otra clave (p. ej. una generada localmente)
+        let local = ed25519_dalek::SigningKey::from_bytes(&[9; 32]);
+        assert!(signed(&local, &payload("01/01/2099 00:00:00"))
+            .verify(&key, Some("a@b.com"))
+            .is_none());
+
+        // Otra cuenta
+        assert!(license.verify(&key, Some("otro@b.com")).is_none());
+        assert!(license.verify(&key, None).is_none());
+    }
+
+    #[test]
+    fn test_grace_hours_clamped() {
+        assert_eq!(grace_hours(None), DEFAULT_GRACE_HOURS);
+        assert_eq!(grace_hours(Some("24")), 24);
+        assert_eq!(grace_hours(Some("-1")), DEFAULT_GRACE_HOURS);
+        assert_eq!(grace_hours(Some("100000")), MAX_GRACE_HOURS);
+    }
+
+    #[test]
+    fn test_license_config_parse() {
+        assert_eq!(LicenseConfig::parse(None).unwrap(), LicenseConfig::default());
+        assert_eq!(LicenseConfig::parse(Some("  ")).unwrap(), LicenseConfig::default());
+        assert!(LicenseConfig::parse(Some("{not json")).is_err());
+
+        let json = r#"{
+            "origenesPermitidos": ["*.mitienda.com"],
+            "impresoraPorDefecto": "Caja",
+            "perfiles": [{"nombre": "Ticket", "impresora": "Caja", "anchoMm": 80}],
+            "maxCopias": 3,
+            "endpointsPermitidos": ["/print", "printPDF"],
+            "funciones": {"escaneoRed": false}
+        }"#;
+        let config = LicenseConfig::parse(Some(json)).unwrap();
+        assert_eq!(config.allowed_origins, vec!["*.mitienda.com"]);
+        assert_eq!(config.default_printer.as_deref(), Some("Caja"));
+        assert_eq!(config.printer_profiles[0].preset, "thermal");
+        assert_eq!(config.printer_profiles[0].width_mm, Some(80.0));
+        assert!(config.features.raw_printing);
+        assert!(!config.features.network_scan);
+
+        assert!(config.is_endpoint_allowed("/print"));
+        assert!(config.is_endpoint_allowed("/printPDF"));
+        assert!(!config.is_endpoint_allowed("/cut"));
+        assert!(config.check_copies(3).is_ok());
+        assert!(config.check_copies(4).is_err());
+
+        assert_eq!(config.profile_for("caja").map(|p| p.name.as_str()), Some("Ticket"));
+        assert!(config.profile_for("Cocina").is_none());
+        assert!(LicenseConfig::parse(Some(r#"{"perfiles": [{"nombre": "X", "anchoMm": 0}]}"#)).is_err());
+    }
+
+    #[test]
+    fn test_license_config_defaults_allow_everything() {
+        let config = LicenseConfig::default();
+        assert!(config.is_endpoint_allowed("/cut"));
+        assert!(config.check_copies(100).is_ok());
+        assert!(config.features.raw_printing && config.features.network_scan);
+    }
+
+    #[test]
+    fn test_is_within_grace() {
+        let grace = 72 * 3600;
+        let verified = 1_800_000_000;
+
+        assert!(is_within_grace(Some(verified), verified + 3600, grace));
+        assert!(is_within_grace(Some(verified), verified + grace, grace));
+        assert!(!is_within_grace(Some(verified), verified + grace + 1, grace));
+        // Reloj atrasado más allá de la tolerancia
+        assert!(!is_within_grace(Some(verified), verified - 86_400, grace));
+        assert!(!is_within_grace(None, verified, grace));
+    }
+}
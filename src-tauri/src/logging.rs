@@ -0,0 +1,404 @@
+// Registro de la aplicación: archivos JSON lines rotativos, filtros por módulo e IDs de petición
+use crate::persistence;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Nombre del archivo de log actual (los rotados terminan en .1, .2, …)
+pub const LOG_FILE_NAME: &str = "isiprint.log";
+
+/// Tamaño máximo de cada archivo y cantidad de archivos rotados
+const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+const MAX_ROTATED_FILES: usize = 5;
+
+/// Variable de entorno con los filtros ("info,print_client_lib::http_server=debug")
+const FILTER_ENV: &str = "ISIPRINT_LOG";
+
+tokio::task_local! {
+    /// ID de la petición HTTP en curso
+    pub static REQUEST_ID: String;
+}
+
+/// ID de la petición en curso, si la hay
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Nuevo ID de petición (12 caracteres hex)
+pub fn new_request_id() -> String {
+    format!("{:012x}", rand::random::<u64>() & 0xffff_ffff_ffff)
+}
+
+/// Línea del archivo de log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogLine {
+    pub timestamp: String,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// Filtros de nivel: uno general y otros por prefijo de módulo
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filters {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Filters {
+    /// Parsear "nivel,modulo=nivel,…"; las entradas inválidas se ignoran
+    pub fn parse(spec: &str) -> Self {
+        let mut filters = Self {
+            default: LevelFilter::Info,
+            targets: Vec::new(),
+        };
+
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some((target, level)) => {
+                    if let Ok(level) = level.trim().parse() {
+                        filters.targets.push((target.trim().to_string(), level));
+                    }
+                }
+                None => {
+                    if let Ok(level) = part.parse() {
+                        filters.default = level;
+                    }
+                }
+            }
+        }
+
+        // El prefijo más largo gana
+        filters.targets.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        filters
+    }
+
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .find(|(prefix, _)| target == prefix || target.starts_with(&format!("{}::", prefix)))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    pub fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, std::cmp::Ord::max)
+    }
+}
+
+/// Archivo de log con rotación por tamaño
+pub struct RotatingFile {
+    path: PathBuf,
+    file: Option<File>,
+    size: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    pub fn new(path: PathBuf, max_bytes: u64, max_files: usize) -> Self {
+        Self {
+            path,
+            file: None,
+            size: 0,
+            max_bytes,
+            max_files,
+        }
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    /// isiprint.log → .1 → .2 …; el más antiguo se elimina
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file = None;
+        let _ = fs::remove_file(self.rotated_path(self.max_files));
+        for index in (1..self.max_files).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        if self.path.exists() {
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+
+    pub fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.file.is_none() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            self.size = file.metadata().map(|m| m.len()).unwrap_or(0);
+            self.file = Some(file);
+        }
+        if self.size > 0 && self.size + len > self.max_bytes {
+            self.rotate()?;
+            self.file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
+        }
+
+        if let Some(file) = self.file.as_mut() {
+            writeln!(file, "{}", line)?;
+            self.size += len;
+        }
+        Ok(())
+    }
+
+    /// Archivos existentes, del actual al más antiguo
+    pub fn files(&self) -> Vec<PathBuf> {
+        std::iter::once(self.path.clone())
+            .chain((1..=self.max_files).map(|i| self.rotated_path(i)))
+            .filter(|p| p.exists())
+            .collect()
+    }
+}
+
+/// Logger global: consola (env_logger) y archivo JSON lines
+struct Logger {
+    filters: Filters,
+    console: env_logger::Logger,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filters.level_for(metadata.target())
+            || self.console.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.console.matches(record) {
+            self.console.log(record);
+        }
+
+        if record.level() > self.filters.level_for(record.target()) {
+            return;
+        }
+        let Some(file) = &self.file else {
+            return;
+        };
+
+        let line = LogLine {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            level: record.level().to_string(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            request_id: current_request_id(),
+        };
+        if let (Ok(json), Ok(mut file)) = (serde_json::to_string(&line), file.lock()) {
+            let _ = file.write_line(&json);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(Ok(mut file)) = self.file.as_ref().map(|f| f.lock()) {
+            if let Some(f) = file.file.as_mut() {
+                let _ = f.flush();
+            }
+        }
+    }
+}
+
+/// Directorio de los archivos de log
+pub fn log_dir() -> Option<PathBuf> {
+    persistence::get_log_dir()
+}
+
+/// Instalar el logger global (reemplaza a `env_logger::init`)
+pub fn init() {
+    let filters = Filters::parse(&std::env::var(FILTER_ENV).unwrap_or_else(|_| "info".to_string()));
+    let console = env_logger::Builder::from_default_env().build();
+
+    let dir = log_dir().ok_or_else(|| "Could not determine log directory".to_string());
+    let (file, file_error) = match dir.and_then(|dir| persistence::create_private_dir(&dir).map(|()| dir)) {
+        Ok(dir) => (
+            Some(Mutex::new(RotatingFile::new(
+                dir.join(LOG_FILE_NAME),
+                MAX_FILE_BYTES,
+                MAX_ROTATED_FILES,
+            ))),
+            None,
+        ),
+        Err(e) => (None, Some(e)),
+    };
+
+    let max_level = filters.max_level().max(console.filter());
+    let logger = Logger {
+        filters,
+        console,
+        file,
+    };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max_level);
+    }
+
+    // Reportado por el logger ya instalado (consola)
+    if let Some(e) = file_error {
+        log::warn!("File logging disabled: {}", e);
+    }
+}
+
+/// Nivel del log crate para un nivel de la pestaña Logs ("INFO", "WARN", "SUCCESS"…)
+pub fn level_from_str(level: &str) -> Level {
+    match level.to_uppercase().as_str() {
+        "ERROR" => Level::Error,
+        "WARN" | "WARNING" => Level::Warn,
+        "DEBUG" => Level::Debug,
+        "TRACE" => Level::Trace,
+        _ => Level::Info,
+    }
+}
+
+/// Archivos de log actuales y rotados
+pub fn log_files() -> Vec<PathBuf> {
+    match log_dir() {
+        Some(dir) => {
+            RotatingFile::new(dir.join(LOG_FILE_NAME), MAX_FILE_BYTES, MAX_ROTATED_FILES).files()
+        }
+        None => Vec::new(),
+    }
+}
+
+/// Agregar un archivo al paquete de diagnóstico
+fn add_file<W: Write + std::io::Seek>(
+    zip: &mut zip::ZipWriter<W>,
+    name: &str,
+    contents: &[u8],
+) -> Result<(), String> {
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    zip.start_file(name, options)
+        .map_err(|e| format!("Failed to add {} to bundle: {}", name, e))?;
+    zip.write_all(contents)
+        .map_err(|e| format!("Failed to add {} to bundle: {}", name, e))
+}
+
+/// Escribir el paquete de diagnóstico (zip): logs, configuración sin secretos y `summary`
+pub fn write_diagnostics_bundle(
+    destination: &Path,
+    summary: &serde_json::Value,
+    extra_files: &[(String, PathBuf)],
+) -> Result<(), String> {
+    let file = File::create(destination).map_err(|e| format!("Failed to create bundle: {}", e))?;
+    let mut zip = zip::ZipWriter::new(file);
+
+    let summary = serde_json::to_vec_pretty(summary)
+        .map_err(|e| format!("Failed to serialize summary: {}", e))?;
+    add_file(&mut zip, "summary.json", &summary)?;
+
+    for path in log_files() {
+        if let (Some(name), Ok(contents)) = (path.file_name(), fs::read(&path)) {
+            add_file(
+                &mut zip,
+                &format!("logs/{}", name.to_string_lossy()),
+                &contents,
+            )?;
+        }
+    }
+
+    for (name, path) in extra_files {
+        if let Ok(contents) = fs::read(path) {
+            add_file(&mut zip, name, &contents)?;
+        }
+    }
+
+    zip.finish()
+        .map_err(|e| format!("Failed to write bundle: {}", e))?;
+    Ok(())
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters_by_module_prefix() {
+        let filters = Filters::parse(
+            "warn, print_client_lib::http_server=debug,print_client_lib=info,bad=nope",
+        );
+
+        assert_eq!(filters.level_for("reqwest::connect"), LevelFilter::Warn);
+        assert_eq!(
+            filters.level_for("print_client_lib::quota"),
+            LevelFilter::Info
+        );
+        assert_eq!(
+            filters.level_for("print_client_lib::http_server"),
+            LevelFilter::Debug
+        );
+        // Prefijo de módulo, no de texto
+        assert_eq!(
+            filters.level_for("print_client_lib_extra"),
+            LevelFilter::Warn
+        );
+        assert_eq!(filters.max_level(), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn test_rotating_file_keeps_limited_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(dir.path().join(LOG_FILE_NAME), 64, 2);
+
+        for i in 0..20 {
+            file.write_line(&format!(
+                "{{\"line\":{:02},\"pad\":\"xxxxxxxxxxxxxxxx\"}}",
+                i
+            ))
+            .unwrap();
+        }
+
+        let files = file.files();
+        assert_eq!(files.len(), 3);
+        for path in &files {
+            assert!(fs::metadata(path).unwrap().len() <= 64);
+        }
+        let current = fs::read_to_string(&files[0]).unwrap();
+        assert!(current.contains("\"line\":19"));
+    }
+
+    #[test]
+    fn test_log_line_serialization() {
+        let line = LogLine {
+            timestamp: "2026-03-01T10:00:00-04:00".to_string(),
+            level: "INFO".to_string(),
+            target: "print_client_lib::http_server".to_string(),
+            message: "GET /printers 200".to_string(),
+            request_id: None,
+        };
+        let json = serde_json::to_string(&line).unwrap();
+        assert!(!json.contains("request_id"));
+        assert_eq!(serde_json::from_str::<LogLine>(&json).unwrap(), line);
+    }
+
+    #[tokio::test]
+    async fn test_request_id_scope() {
+        assert_eq!(current_request_id(), None);
+        let id = REQUEST_ID
+            .scope("abc123".to_string(), async { current_request_id() })
+            .await;
+        assert_eq!(id.as_deref(), Some("abc123"));
+        assert_eq!(new_request_id().len(), 12);
+    }
+}
@@ -1,6 +1,7 @@
 // Módulo de descubrimiento de impresoras en red
+use crate::ipp;
 use serde::{Deserialize, Serialize};
-use std::net::{IpAddr, TcpStream};
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
 use std::time::Duration;
 use tokio::task;
 
@@ -12,8 +13,52 @@ pub struct NetworkPrinter {
     pub protocol: String, // "ipp", "raw", "http"
     pub name: String,
     pub is_online: bool,
+    /// Ruta del recurso (cola IPP o LPD) validada al agregar por dirección
+    #[serde(default)]
+    pub path: Option<String>,
 }
 
+/// Resultado del sondeo de un protocolo en una dirección ingresada manualmente
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolProbe {
+    #[serde(flatten)]
+    pub printer: NetworkPrinter,
+    pub uri: String,
+    /// true si la cola IPP respondió a Get-Printer-Attributes
+    pub validated: bool,
+    pub make_and_model: Option<String>,
+    pub error: Option<String>,
+}
+
+impl ProtocolProbe {
+    /// Un protocolo IPP solo se puede registrar si la cola fue validada
+    pub fn is_usable(&self) -> bool {
+        match self.printer.protocol.as_str() {
+            "ipp" | "ipps" | "http" => self.validated,
+            _ => self.printer.is_online,
+        }
+    }
+}
+
+/// Dirección de impresora ingresada por el usuario (host, puerto y ruta opcionales)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrinterAddress {
+    pub host: String,
+    pub protocol: Option<String>,
+    pub port: Option<u16>,
+    pub path: Option<String>,
+}
+
+/// Rutas IPP habituales cuando el usuario no indica una
+const IPP_CANDIDATE_PATHS: &[&str] = &["/ipp/print", "/ipp", "/ipp/printer", "/"];
+
+/// Orden de preferencia al registrar sin protocolo explícito
+const PROTOCOL_PREFERENCE: &[&str] = &["ipp", "ipps", "raw", "lpd", "http"];
+
+/// Puertos de impresora que se pueden sondear desde la API HTTP
+/// (HTTP, IPPS, LPD, IPP y raw/JetDirect)
+const PRINTER_PORTS: &[u16] = &[80, 443, 515, 631, 9100, 9101, 9102];
+
 /// Obtener la IP local del dispositivo
 pub fn get_local_ip() -> Result<String, String> {
     local_ip_address::local_ip()
@@ -37,14 +82,13 @@ pub fn get_network_range(local_ip: &str) -> Result<String, String> {
     }
 }
 
-/// Verificar si un puerto está abierto en un host
-fn is_port_open(ip: &str, port: u16, timeout_ms: u64) -> bool {
-    let addr = format!("{}:{}", ip, port);
-    TcpStream::connect_timeout(
-        &addr.parse().unwrap(),
-        Duration::from_millis(timeout_ms),
-    )
-    .is_ok()
+/// Verificar si un puerto está abierto en un host (IP o hostname)
+fn is_port_open(host: &str, port: u16, timeout_ms: u64) -> bool {
+    let addr = match (host, port).to_socket_addrs().ok().and_then(|mut a| a.next()) {
+        Some(a) => a,
+        None => return false,
+    };
+    TcpStream::connect_timeout(&addr, Duration::from_millis(timeout_ms)).is_ok()
 }
 
 /// Escanear un rango de IPs buscando puertos comunes de impresoras
@@ -110,6 +154,7 @@ pub async fn scan_network_for_printers(network_range: &str) -> Result<Vec<Networ
                 protocol: protocol.to_string(),
                 name: name.clone(),
                 is_online: true,
+                path: None,
             });
             
             printer_count += 1;
@@ -121,18 +166,287 @@ pub async fn scan_network_for_printers(network_range: &str) -> Result<Vec<Networ
     Ok(printers)
 }
 
+/// Normalizar el nombre de protocolo/esquema ingresado por el usuario
+fn normalize_protocol(protocol: &str) -> Option<&'static str> {
+    match protocol.to_lowercase().as_str() {
+        "raw" | "socket" | "9100" => Some("raw"),
+        "ipp" => Some("ipp"),
+        "ipps" | "https" => Some("ipps"),
+        "lpd" => Some("lpd"),
+        "http" => Some("http"),
+        _ => None,
+    }
+}
+
+fn default_port(protocol: &str) -> u16 {
+    match protocol {
+        "ipp" | "ipps" => 631,
+        "lpd" => 515,
+        "http" => 80,
+        _ => 9100,
+    }
+}
+
+/// Parsear "host", "host:puerto", "host:puerto/ruta" o "esquema://host:puerto/ruta"
+pub fn parse_printer_address(input: &str) -> Result<PrinterAddress, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("Printer address required".to_string());
+    }
+
+    let (protocol, url_str) = match input.split_once("://") {
+        Some((scheme, _)) => {
+            let protocol = normalize_protocol(scheme)
+                .ok_or_else(|| format!("Unsupported protocol: {}", scheme))?;
+            (Some(protocol.to_string()), input.to_string())
+        }
+        None => (None, format!("probe://{}", input)),
+    };
+
+    let parsed = url::Url::parse(&url_str).map_err(|e| format!("Invalid printer address: {}", e))?;
+    let host = parsed
+        .host_str()
+        .filter(|h| !h.is_empty())
+        .ok_or("Printer address without host")?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+
+    let path = match parsed.path() {
+        "" | "/" => None,
+        p => Some(p.to_string()),
+    };
+
+    Ok(PrinterAddress {
+        host,
+        protocol,
+        port: parsed.port(),
+        path,
+    })
+}
+
+/// Rechazar direcciones con un puerto que no es de impresora: la API HTTP no
+/// debe servir para sondear servicios arbitrarios de la red local
+pub fn check_printer_port(input: &str) -> Result<(), String> {
+    let address = parse_printer_address(input)?;
+    match address.port {
+        Some(port) if !PRINTER_PORTS.contains(&port) => {
+            Err(format!("Port {} is not a printer port", port))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Host listo para usar dentro de un URI (IPv6 entre corchetes)
+fn uri_host(host: &str) -> String {
+    if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    }
+}
+
+/// Construir el URI de dispositivo CUPS para una impresora de red
+pub fn printer_uri(printer: &NetworkPrinter) -> String {
+    let host = uri_host(&printer.ip);
+    match printer.protocol.as_str() {
+        "ipp" | "ipps" | "http" => format!(
+            "{}://{}:{}{}",
+            printer.protocol,
+            host,
+            printer.port,
+            printer.path.as_deref().unwrap_or("/ipp/print")
+        ),
+        "lpd" => format!(
+            "lpd://{}:{}/{}",
+            host,
+            printer.port,
+            printer
+                .path
+                .as_deref()
+                .map(|p| p.trim_start_matches('/'))
+                .filter(|p| !p.is_empty())
+                .unwrap_or("queue")
+        ),
+        _ => format!("socket://{}:{}", host, printer.port),
+    }
+}
+
+/// Convertir un nombre amigable en un nombre de cola válido para CUPS/Windows
+pub fn sanitize_queue_name(name: &str) -> Result<String, String> {
+    let mut queue = String::new();
+    for c in name.trim().chars() {
+        if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+            queue.push(c);
+        } else if !queue.ends_with('_') {
+            queue.push('_');
+        }
+    }
+
+    let queue = queue.trim_matches('_').to_string();
+    if queue.is_empty() {
+        return Err("Invalid printer name".to_string());
+    }
+    // CUPS limita los nombres de cola a 127 caracteres
+    Ok(queue.chars().take(127).collect())
+}
+
+/// Combinaciones protocolo/puerto a sondear para una dirección
+fn probe_candidates(address: &PrinterAddress) -> Vec<(&'static str, u16)> {
+    match (address.protocol.as_deref().and_then(normalize_protocol), address.port) {
+        (Some(protocol), port) => vec![(protocol, port.unwrap_or(default_port(protocol)))],
+        (None, Some(port)) => vec![("ipp", port), ("ipps", port), ("raw", port)],
+        (None, None) => vec![
+            ("raw", 9100),
+            ("ipp", 631),
+            ("ipps", 631),
+            ("ipps", 443),
+            ("lpd", 515),
+            ("http", 80),
+        ],
+    }
+}
+
+/// Sondear un protocolo: conexión TCP y, para IPP, validación de la ruta
+async fn probe_protocol(address: PrinterAddress, protocol: &'static str, port: u16) -> ProtocolProbe {
+    let host = address.host.clone();
+    let reachable = task::spawn_blocking(move || is_port_open(&host, port, 1500))
+        .await
+        .unwrap_or(false);
+
+    let mut probe = ProtocolProbe {
+        printer: NetworkPrinter {
+            ip: address.host.clone(),
+            port,
+            protocol: protocol.to_string(),
+            name: format!("Network_Printer_{}_{}", address.host.replace(['.', ':'], "_"), port),
+            is_online: reachable,
+            path: address.path.clone(),
+        },
+        uri: String::new(),
+        validated: false,
+        make_and_model: None,
+        error: None,
+    };
+
+    if !reachable {
+        probe.error = Some(format!("Port {} not reachable", port));
+        probe.uri = printer_uri(&probe.printer);
+        return probe;
+    }
+
+    if matches!(protocol, "ipp" | "ipps" | "http") {
+        let paths: Vec<String> = match &address.path {
+            Some(p) => vec![p.clone()],
+            None => IPP_CANDIDATE_PATHS.iter().map(|p| p.to_string()).collect(),
+        };
+
+        for path in paths {
+            probe.printer.path = Some(path);
+            let uri = printer_uri(&probe.printer);
+            match ipp::get_printer_attributes(&uri, Duration::from_secs(3)).await {
+                Ok(attrs) => {
+                    probe.validated = true;
+                    probe.make_and_model = attrs.make_and_model;
+                    probe.error = None;
+                    break;
+                }
+                Err(e) => probe.error = Some(e),
+            }
+        }
+
+        if !probe.validated {
+            probe.printer.path = address.path.clone();
+        }
+    }
+
+    probe.uri = printer_uri(&probe.printer);
+    probe
+}
+
+/// Sondear raw, IPP/IPPS, LPD y HTTP en una dirección ingresada manualmente
+pub async fn probe_printer_address(input: &str) -> Result<Vec<ProtocolProbe>, String> {
+    let address = parse_printer_address(input)?;
+    log::info!("Probing printer address: {:?}", address);
+
+    let tasks: Vec<_> = probe_candidates(&address)
+        .into_iter()
+        .map(|(protocol, port)| tokio::spawn(probe_protocol(address.clone(), protocol, port)))
+        .collect();
+
+    let mut probes = Vec::new();
+    for task in tasks {
+        if let Ok(probe) = task.await {
+            probes.push(probe);
+        }
+    }
+
+    Ok(probes)
+}
+
+/// Elegir el sondeo a registrar (protocolo pedido o el preferido disponible)
+pub fn select_probe(probes: &[ProtocolProbe], protocol: Option<&str>) -> Result<ProtocolProbe, String> {
+    if let Some(requested) = protocol.filter(|p| !p.is_empty()) {
+        let requested = normalize_protocol(requested)
+            .ok_or_else(|| format!("Unsupported protocol: {}", requested))?;
+        let candidates: Vec<&ProtocolProbe> = probes
+            .iter()
+            .filter(|p| p.printer.protocol == requested)
+            .collect();
+        return candidates
+            .iter()
+            .find(|p| p.is_usable())
+            .map(|p| (*p).clone())
+            .ok_or_else(|| {
+                let reason = candidates
+                    .iter()
+                    .find_map(|p| p.error.clone())
+                    .unwrap_or_else(|| "not probed".to_string());
+                format!("Protocol {} not available: {}", requested, reason)
+            });
+    }
+
+    PROTOCOL_PREFERENCE
+        .iter()
+        .find_map(|proto| {
+            probes
+                .iter()
+                .find(|p| p.printer.protocol == *proto && p.is_usable())
+        })
+        .cloned()
+        .ok_or_else(|| "No supported printer protocol responded at this address".to_string())
+}
+
+/// Sondear una dirección y registrar la impresora con el nombre elegido
+pub async fn add_printer_by_address(
+    address: &str,
+    name: &str,
+    protocol: Option<&str>,
+) -> Result<(NetworkPrinter, String), String> {
+    let queue_name = sanitize_queue_name(name)?;
+    let probes = probe_printer_address(address).await?;
+    let selected = select_probe(&probes, protocol)?;
+
+    let printer = NetworkPrinter {
+        name: queue_name,
+        ..selected.printer
+    };
+
+    let to_add = printer.clone();
+    let message = task::spawn_blocking(move || add_network_printer_to_cups(&to_add))
+        .await
+        .map_err(|e| format!("Error adding printer: {}", e))??;
+
+    Ok((printer, message))
+}
+
 /// Agregar una impresora de red a CUPS (macOS/Linux)
 #[cfg(any(target_os = "macos", target_os = "linux"))]
 pub fn add_network_printer_to_cups(printer: &NetworkPrinter) -> Result<String, String> {
     use std::process::Command;
     
     // Construir URI según el protocolo
-    let uri = match printer.protocol.as_str() {
-        "ipp" => format!("ipp://{}/ipp/print", printer.ip),
-        "raw" | "9100" => format!("socket://{}:{}", printer.ip, printer.port),
-        "lpd" => format!("lpd://{}/queue", printer.ip),
-        _ => format!("socket://{}:{}", printer.ip, printer.port),
-    };
+    let uri = printer_uri(printer);
     
     log::info!("Adding printer {} with URI: {}", printer.name, uri);
     
@@ -143,6 +457,20 @@ pub fn add_network_printer_to_cups(printer: &NetworkPrinter) -> Result<String, S
     
     if let Ok(output) = check {
         if output.status.success() {
+            // Solo es la misma impresora si apunta a la misma dirección
+            let device = Command::new("lpstat")
+                .args(["-v", &printer.name])
+                .output()
+                .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+                .unwrap_or_default();
+            let existing = device.split_once(": ").map(|(_, uri)| uri.trim());
+            if existing != Some(uri.as_str()) {
+                return Err(format!(
+                    "Printer {} already exists with a different address ({})",
+                    printer.name,
+                    existing.unwrap_or("unknown")
+                ));
+            }
             log::info!("Printer {} already exists, skipping", printer.name);
             return Ok(format!("Printer {} already installed", printer.name));
         }
@@ -222,47 +550,170 @@ pub fn add_network_printer_to_cups(printer: &NetworkPrinter) -> Result<String, S
     Ok(format!("Printer {} added successfully", printer.name))
 }
 
+/// Texto entre comillas simples para PowerShell
+#[cfg(any(target_os = "windows", test))]
+fn ps_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Puerto TCP/IP (RAW) o LPR de Windows. Las impresoras IPP/HTTP se agregan por URL.
+#[cfg(any(target_os = "windows", test))]
+#[derive(Debug, Clone, PartialEq)]
+struct WindowsPort {
+    name: String,
+    host: String,
+    port: u16,
+    /// Cola LPR; `None` en puertos RAW
+    queue: Option<String>,
+}
+
+#[cfg(any(target_os = "windows", test))]
+impl WindowsPort {
+    fn for_printer(printer: &NetworkPrinter) -> Option<Self> {
+        match printer.protocol.as_str() {
+            "lpd" => {
+                let queue = printer
+                    .path
+                    .as_deref()
+                    .map(|p| p.trim_start_matches('/'))
+                    .filter(|p| !p.is_empty())
+                    .unwrap_or("queue")
+                    .to_string();
+                Some(Self {
+                    name: format!("LPR_{}_{}", printer.ip.replace(['.', ':'], "_"), queue),
+                    host: printer.ip.clone(),
+                    port: printer.port,
+                    queue: Some(queue),
+                })
+            }
+            "ipp" | "ipps" | "http" => None,
+            _ => Some(Self {
+                name: format!("IP_{}_{}", printer.ip.replace(['.', ':'], "_"), printer.port),
+                host: printer.ip.clone(),
+                port: printer.port,
+                queue: None,
+            }),
+        }
+    }
+
+    fn create_command(&self) -> String {
+        match &self.queue {
+            Some(queue) => format!(
+                "Add-PrinterPort -Name {} -LprHostAddress {} -LprQueueName {}",
+                ps_quote(&self.name),
+                ps_quote(&self.host),
+                ps_quote(queue)
+            ),
+            None => format!(
+                "Add-PrinterPort -Name {} -PrinterHostAddress {} -PortNumber {}",
+                ps_quote(&self.name),
+                ps_quote(&self.host),
+                self.port
+            ),
+        }
+    }
+
+    /// Consulta que imprime "host|puerto|cola" del puerto existente
+    fn query_command(&self) -> String {
+        format!(
+            "Get-PrinterPort -Name {} -ErrorAction Stop | ForEach-Object {{ \"$($_.PrinterHostAddress)|$($_.PortNumber)|$($_.LprQueueName)\" }}",
+            ps_quote(&self.name)
+        )
+    }
+
+    /// El puerto existente (salida de `query_command`) apunta a la misma impresora
+    fn matches(&self, existing: &str) -> bool {
+        let mut fields = existing.trim().split('|');
+        let host = fields.next().unwrap_or("");
+        let port = fields.next().unwrap_or("");
+        let queue = fields.next().unwrap_or("");
+        if !host.eq_ignore_ascii_case(&self.host) {
+            return false;
+        }
+        match &self.queue {
+            Some(expected) => queue == expected,
+            None => port == self.port.to_string(),
+        }
+    }
+}
+
 /// Agregar impresora de red en Windows
 #[cfg(target_os = "windows")]
 pub fn add_network_printer_to_cups(printer: &NetworkPrinter) -> Result<String, String> {
     use std::process::Command;
-    
-    let port_name = format!("IP_{}_{}", printer.ip.replace('.', "_"), printer.port);
+
+    let powershell = |command: &str| {
+        Command::new("powershell")
+            .args(["-NoProfile", "-Command", command])
+            .output()
+    };
     let printer_name = &printer.name;
-    
-    // Crear puerto de impresora TCP/IP
-    let output = Command::new("powershell")
-        .args([
-            "-Command",
-            &format!(
-                "Add-PrinterPort -Name '{}' -PrinterHostAddress '{}'",
-                port_name, printer.ip
-            ),
-        ])
-        .output()
-        .map_err(|e| format!("Error creating printer port: {}", e))?;
-    
-    if !output.status.success() {
-        log::warn!("Port creation warning (may already exist)");
+    let port = WindowsPort::for_printer(printer);
+
+    // Puerto de la impresora: uno existente solo se reutiliza si apunta al mismo destino
+    if let Some(port) = &port {
+        let existing = powershell(&port.query_command())
+            .map_err(|e| format!("Error checking printer port: {}", e))?;
+        if existing.status.success() {
+            let existing = String::from_utf8_lossy(&existing.stdout).to_string();
+            if !port.matches(&existing) {
+                return Err(format!(
+                    "Printer port {} already exists with a different address ({})",
+                    port.name,
+                    existing.trim()
+                ));
+            }
+        } else {
+            let output = powershell(&port.create_command())
+                .map_err(|e| format!("Error creating printer port: {}", e))?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(format!("Error creating printer port: {}", stderr));
+            }
+        }
     }
-    
+
+    // Impresora existente: solo es la misma si usa el mismo puerto
+    let expected_port = port
+        .as_ref()
+        .map(|p| p.name.clone())
+        .unwrap_or_else(|| printer_uri(printer));
+    let existing = powershell(&format!(
+        "(Get-Printer -Name {} -ErrorAction Stop).PortName",
+        ps_quote(printer_name)
+    ))
+    .map_err(|e| format!("Error checking printer: {}", e))?;
+    if existing.status.success() {
+        let existing_port = String::from_utf8_lossy(&existing.stdout).trim().to_string();
+        if existing_port.eq_ignore_ascii_case(&expected_port) {
+            return Ok(format!("Printer {} already installed", printer_name));
+        }
+        return Err(format!(
+            "Printer {} already exists with a different port ({})",
+            printer_name, existing_port
+        ));
+    }
+
     // Agregar la impresora
-    let output = Command::new("powershell")
-        .args([
-            "-Command",
-            &format!(
-                "Add-Printer -Name '{}' -PortName '{}' -DriverName 'Generic / Text Only'",
-                printer_name, port_name
-            ),
-        ])
-        .output()
-        .map_err(|e| format!("Error adding printer: {}", e))?;
-    
+    let command = match &port {
+        Some(port) => format!(
+            "Add-Printer -Name {} -PortName {} -DriverName 'Generic / Text Only'",
+            ps_quote(printer_name),
+            ps_quote(&port.name)
+        ),
+        None => format!(
+            "Add-Printer -Name {} -IppURL {}",
+            ps_quote(printer_name),
+            ps_quote(&printer_uri(printer))
+        ),
+    };
+    let output = powershell(&command).map_err(|e| format!("Error adding printer: {}", e))?;
+
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
         return Err(format!("Error adding printer: {}", stderr));
     }
-    
+
     Ok(format!("Printer {} added successfully", printer_name))
 }
 
@@ -331,10 +782,133 @@ mod tests {
             protocol: "raw".to_string(),
             name: "Network_Printer_1".to_string(),
             is_online: true,
+            path: None,
         };
         
         let json = serde_json::to_string(&printer).unwrap();
         assert!(json.contains("192.168.1.100"));
         assert!(json.contains("9100"));
     }
+
+    #[test]
+    fn test_network_printer_without_path_deserializes() {
+        let json = r#"{"ip":"10.0.0.2","port":631,"protocol":"ipp","name":"P","is_online":true}"#;
+        let printer: NetworkPrinter = serde_json::from_str(json).unwrap();
+        assert!(printer.path.is_none());
+    }
+
+    #[test]
+    fn test_parse_printer_address() {
+        let addr = parse_printer_address("192.168.1.50").unwrap();
+        assert_eq!(addr.host, "192.168.1.50");
+        assert!(addr.protocol.is_none());
+        assert!(addr.port.is_none());
+        assert!(addr.path.is_none());
+
+        let addr = parse_printer_address("printer.local:8631/ipp/print").unwrap();
+        assert_eq!(addr.host, "printer.local");
+        assert_eq!(addr.port, Some(8631));
+        assert_eq!(addr.path.as_deref(), Some("/ipp/print"));
+
+        let addr = parse_printer_address("socket://10.0.0.9").unwrap();
+        assert_eq!(addr.protocol.as_deref(), Some("raw"));
+
+        assert!(parse_printer_address("").is_err());
+        assert!(parse_printer_address("ftp://10.0.0.9").is_err());
+    }
+
+    #[test]
+    fn test_check_printer_port() {
+        assert!(check_printer_port("192.168.1.50").is_ok());
+        assert!(check_printer_port("192.168.1.50:9100").is_ok());
+        assert!(check_printer_port("ipp://printer.local:631/ipp/print").is_ok());
+        assert!(check_printer_port("192.168.1.50:22").is_err());
+        assert!(check_printer_port("http://10.0.0.1:8080/admin").is_err());
+    }
+
+    #[test]
+    fn test_printer_uri() {
+        let mut printer = NetworkPrinter {
+            ip: "10.0.0.5".to_string(),
+            port: 631,
+            protocol: "ipp".to_string(),
+            name: "P".to_string(),
+            is_online: true,
+            path: None,
+        };
+        assert_eq!(printer_uri(&printer), "ipp://10.0.0.5:631/ipp/print");
+
+        printer.path = Some("/printers/kitchen".to_string());
+        assert_eq!(printer_uri(&printer), "ipp://10.0.0.5:631/printers/kitchen");
+
+        printer.protocol = "lpd".to_string();
+        printer.port = 515;
+        printer.path = None;
+        assert_eq!(printer_uri(&printer), "lpd://10.0.0.5:515/queue");
+
+        printer.protocol = "raw".to_string();
+        printer.port = 9100;
+        assert_eq!(printer_uri(&printer), "socket://10.0.0.5:9100");
+    }
+
+    #[test]
+    fn test_sanitize_queue_name() {
+        assert_eq!(sanitize_queue_name("Caja 1 / Recibos").unwrap(), "Caja_1_Recibos");
+        assert_eq!(sanitize_queue_name("cocina-2").unwrap(), "cocina-2");
+        assert!(sanitize_queue_name("  ## ").is_err());
+    }
+
+    #[test]
+    fn test_select_probe_prefers_validated_ipp() {
+        let probe = |protocol: &str, online: bool, validated: bool| ProtocolProbe {
+            printer: NetworkPrinter {
+                ip: "10.0.0.5".to_string(),
+                port: default_port(protocol),
+                protocol: protocol.to_string(),
+                name: "P".to_string(),
+                is_online: online,
+                path: None,
+            },
+            uri: String::new(),
+            validated,
+            make_and_model: None,
+            error: None,
+        };
+
+        let probes = vec![probe("raw", true, false), probe("ipp", true, false)];
+        // IPP alcanzable pero sin validar no se puede registrar
+        assert_eq!(select_probe(&probes, None).unwrap().printer.protocol, "raw");
+        assert!(select_probe(&probes, Some("ipp")).is_err());
+
+        let probes = vec![probe("raw", true, false), probe("ipp", true, true)];
+        assert_eq!(select_probe(&probes, None).unwrap().printer.protocol, "ipp");
+        assert_eq!(select_probe(&probes, Some("socket")).unwrap().printer.protocol, "raw");
+    }
+
+    #[test]
+    fn test_windows_port_honors_protocol_and_path() {
+        let printer = |protocol: &str, port: u16, path: Option<&str>| NetworkPrinter {
+            ip: "10.0.0.5".to_string(),
+            port,
+            protocol: protocol.to_string(),
+            name: "P".to_string(),
+            is_online: true,
+            path: path.map(str::to_string),
+        };
+
+        let raw = WindowsPort::for_printer(&printer("raw", 9101, None)).unwrap();
+        assert_eq!(raw.name, "IP_10_0_0_5_9101");
+        assert!(raw.create_command().contains("-PortNumber 9101"));
+        assert!(raw.query_command().contains("'IP_10_0_0_5_9101'"));
+        assert!(raw.matches("10.0.0.5|9101|\r\n"));
+        assert!(!raw.matches("10.0.0.9|9101|"));
+        assert!(!raw.matches("10.0.0.5|9100|"));
+
+        let lpd = WindowsPort::for_printer(&printer("lpd", 515, Some("/it's"))).unwrap();
+        assert!(lpd.create_command().contains("-LprQueueName 'it''s'"));
+        assert!(lpd.matches("10.0.0.5|515|it's"));
+        assert!(!lpd.matches("10.0.0.5|515|queue"));
+
+        assert!(WindowsPort::for_printer(&printer("ipp", 631, None)).is_none());
+    }
 }
@@ -0,0 +1,377 @@
+// Lista única de orígenes permitidos (servidor HTTP y comandos) con permisos por origen
+use serde::{Deserialize, Serialize};
+
+/// Dominios permitidos por defecto (los que se guardan en un settings.json nuevo).
+/// Sin loopback: cualquier proceso local puede enviar `Origin: http://localhost`.
+pub const DEFAULT_ORIGINS: &[&str] = &[
+    "*.integrate.com.bo",
+    "*.isipass.net",
+    "*.isipass.com.bo",
+    "*.isipass.app",
+    "*.isipay.me",
+    "*.idematica.net",
+    "*.quickpay.com.bo",
+    "*.adeabordo.com",
+    "*.adeabordo.com.bo",
+    "*.adesite.net",
+];
+
+/// Servidor de desarrollo del frontend, permitido solo en compilaciones de depuración
+const DEV_ORIGINS: &[&str] = &["localhost", "127.0.0.1"];
+
+/// Acción que un origen puede pedir a la API local
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Imprimir, cortar y reintentar trabajos en cola
+    Print,
+    /// Consultar impresoras, historial y cola
+    ListPrinters,
+    /// Limpiar el historial y descartar trabajos en cola
+    ClearJobs,
+    /// Cambiar la configuración y agregar impresoras
+    Configure,
+}
+
+impl Permission {
+    /// Lo que recibe un origen si no se indican permisos: imprimir y consultar
+    pub const DEFAULT: [Permission; 2] = [Permission::Print, Permission::ListPrinters];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::Print => "print",
+            Permission::ListPrinters => "list_printers",
+            Permission::ClearJobs => "clear_jobs",
+            Permission::Configure => "configure",
+        }
+    }
+}
+
+fn default_permissions() -> Vec<Permission> {
+    Permission::DEFAULT.to_vec()
+}
+
+/// Origen permitido: hostname o "*.dominio", con sus permisos
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AllowedOrigin {
+    pub pattern: String,
+    #[serde(default = "default_permissions")]
+    pub permissions: Vec<Permission>,
+}
+
+impl AllowedOrigin {
+    pub fn new(pattern: &str, permissions: Vec<Permission>) -> Self {
+        Self {
+            pattern: pattern.trim().to_lowercase(),
+            permissions,
+        }
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        validate_pattern(&self.pattern)
+    }
+
+    /// Los hostnames exactos tienen prioridad sobre los comodines; entre
+    /// comodines, el dominio más largo
+    fn specificity(&self) -> (bool, usize) {
+        (!self.pattern.starts_with("*."), self.pattern.len())
+    }
+}
+
+/// Validar un patrón de origen: hostname o "*.dominio", sin esquema ni puerto.
+/// Un nombre de una sola etiqueta ("com", "null") solo vale para "localhost".
+pub fn validate_pattern(pattern: &str) -> Result<(), String> {
+    let host = pattern.strip_prefix("*.").unwrap_or(pattern);
+    let valid = !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+        && host.split('.').all(|label| !label.is_empty())
+        && (host.contains('.') || host.eq_ignore_ascii_case("localhost"));
+    if !valid {
+        return Err(format!("Invalid origin pattern: {}", pattern));
+    }
+    Ok(())
+}
+
+/// Lista por defecto: imprimir y consultar impresoras. Configurar y limpiar
+/// hay que concederlo de forma explícita.
+pub fn default_origins() -> Vec<AllowedOrigin> {
+    let dev: &[&str] = if cfg!(debug_assertions) { DEV_ORIGINS } else { &[] };
+    DEFAULT_ORIGINS
+        .iter()
+        .chain(dev)
+        .map(|pattern| AllowedOrigin::new(pattern, default_permissions()))
+        .collect()
+}
+
+/// Hostname de un origen (sin esquema, ruta ni puerto)
+pub fn origin_host(origin: &str) -> &str {
+    let host = if let Some(stripped) = origin.strip_prefix("https://") {
+        stripped.split('/').next().unwrap_or("")
+    } else if let Some(stripped) = origin.strip_prefix("http://") {
+        stripped.split('/').next().unwrap_or("")
+    } else {
+        origin
+    };
+
+    // Quitar el puerto si existe
+    host.split(':').next().unwrap_or(host)
+}
+
+/// Hostname de un origen http(s) que se puede guardar como patrón; `None`
+/// para "null", "file://" y demás orígenes sin un host real
+pub fn web_host(origin: &str) -> Option<&str> {
+    if !origin.starts_with("https://") && !origin.starts_with("http://") {
+        return None;
+    }
+    let host = origin_host(origin);
+    (!host.starts_with("*.") && validate_pattern(host).is_ok()).then_some(host)
+}
+
+/// Compara un hostname con un dominio ("*.dominio" incluye subdominios)
+pub fn host_matches(host: &str, pattern: &str) -> bool {
+    let host = host.to_lowercase();
+    let pattern = pattern.to_lowercase();
+    if let Some(domain) = pattern.strip_prefix("*.") {
+        // Patrón con wildcard: ".integrate.com.bo"
+        host.ends_with(&pattern[1..]) || host == domain
+    } else {
+        host == pattern
+    }
+}
+
+/// Segundos niveles de uso común bajo los dominios de país (com.bo, gob.bo, co.uk...)
+const COUNTRY_SECOND_LEVELS: &[&str] = &["com", "net", "org", "gob", "gov", "edu", "co", "ac", "mil"];
+
+/// Dominio registrable de un origen ("https://pos.tienda.com.bo" → "tienda.com.bo").
+/// Aproximación sin la lista de sufijos públicos; `None` para IPs y nombres sin dominio.
+pub fn registrable_domain(origin: &str) -> Option<String> {
+    let host = origin_host(origin).trim_end_matches('.').to_lowercase();
+    if host.parse::<std::net::IpAddr>().is_ok() {
+        return None;
+    }
+    let labels: Vec<&str> = host.split('.').collect();
+    if labels.len() < 2 || labels.iter().any(|l| l.is_empty()) {
+        return None;
+    }
+    let tld = labels[labels.len() - 1];
+    let second = labels[labels.len() - 2];
+    let count = if tld.len() == 2 && COUNTRY_SECOND_LEVELS.contains(&second) {
+        3
+    } else {
+        2
+    };
+    (labels.len() >= count).then(|| labels[labels.len() - count..].join("."))
+}
+
+/// Resultado de verificar un origen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allowed,
+    /// Origen conocido sin el permiso pedido
+    Forbidden,
+    /// Origen fuera de la lista
+    Unknown,
+}
+
+/// Entrada de la lista que corresponde a un origen (la más específica)
+pub fn find<'a>(origins: &'a [AllowedOrigin], origin: &str) -> Option<&'a AllowedOrigin> {
+    let host = origin_host(origin);
+    if host.is_empty() {
+        return None;
+    }
+    origins
+        .iter()
+        .filter(|o| host_matches(host, &o.pattern))
+        .max_by_key(|o| o.specificity())
+}
+
+fn in_license(license_origins: &[String], origin: &str) -> bool {
+    let host = origin_host(origin);
+    !host.is_empty() && license_origins.iter().any(|p| host_matches(host, p.trim()))
+}
+
+/// Verificar un origen contra la lista de settings y los dominios de la
+/// licencia (estos últimos con todos los permisos)
+pub fn check(
+    origins: &[AllowedOrigin],
+    license_origins: &[String],
+    origin: &str,
+    permission: Permission,
+) -> Decision {
+    match find(origins, origin) {
+        Some(entry) if entry.allows(permission) => Decision::Allowed,
+        Some(_) => Decision::Forbidden,
+        None if in_license(license_origins, origin) => Decision::Allowed,
+        None => Decision::Unknown,
+    }
+}
+
+/// El origen está en la lista, con cualquier permiso
+pub fn is_known(origins: &[AllowedOrigin], license_origins: &[String], origin: &str) -> bool {
+    find(origins, origin).is_some() || in_license(license_origins, origin)
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_origins_cover_both_legacy_lists() {
+        let origins = default_origins();
+        for origin in [
+            "https://app.isipass.net",
+            "https://isipass.net",
+            "https://api.isipay.me",
+            "https://pos.quickpay.com.bo",
+            "https://app.adeabordo.com",
+            "https://adeabordo.com.bo",
+            "https://site.adesite.net",
+        ] {
+            assert_eq!(
+                check(&origins, &[], origin, Permission::Print),
+                Decision::Allowed,
+                "{}",
+                origin
+            );
+        }
+        for origin in [
+            "https://evil.com",
+            "https://fakeisipass.net",
+            "https://isipass.net.evil.com",
+            "not-a-valid-url",
+            "",
+        ] {
+            assert_eq!(
+                check(&origins, &[], origin, Permission::Print),
+                Decision::Unknown,
+                "{}",
+                origin
+            );
+        }
+    }
+
+    #[test]
+    fn test_loopback_only_in_debug_builds() {
+        let origins = default_origins();
+        let expected = if cfg!(debug_assertions) {
+            Decision::Allowed
+        } else {
+            Decision::Unknown
+        };
+        for origin in ["http://localhost:1420", "http://127.0.0.1:7777"] {
+            assert_eq!(check(&origins, &[], origin, Permission::Print), expected);
+        }
+    }
+
+    #[test]
+    fn test_most_specific_entry_wins() {
+        let mut origins = default_origins();
+        origins.push(AllowedOrigin::new(
+            "Kiosko.ISIPASS.net",
+            vec![Permission::Print],
+        ));
+
+        let kiosk = "https://kiosko.isipass.net";
+        assert_eq!(
+            check(&origins, &[], kiosk, Permission::Print),
+            Decision::Allowed
+        );
+        assert_eq!(
+            check(&origins, &[], kiosk, Permission::ClearJobs),
+            Decision::Forbidden
+        );
+        assert_eq!(
+            check(&origins, &[], "https://app.isipass.net", Permission::ListPrinters),
+            Decision::Allowed
+        );
+    }
+
+    #[test]
+    fn test_defaults_cannot_configure() {
+        let origins = default_origins();
+        for permission in [Permission::ClearJobs, Permission::Configure] {
+            assert_eq!(
+                check(&origins, &[], "https://app.isipass.net", permission),
+                Decision::Forbidden
+            );
+        }
+    }
+
+    #[test]
+    fn test_license_origins_allowed() {
+        let license = vec!["*.mitienda.com".to_string()];
+        assert_eq!(
+            check(&[], &license, "https://pos.mitienda.com", Permission::Configure),
+            Decision::Allowed
+        );
+        assert_eq!(
+            check(&[], &[], "https://pos.mitienda.com", Permission::Print),
+            Decision::Unknown
+        );
+    }
+
+    #[test]
+    fn test_registrable_domain() {
+        assert_eq!(
+            registrable_domain("https://shop.example.com:8443/caja").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            registrable_domain("pos.tienda.com.bo").as_deref(),
+            Some("tienda.com.bo")
+        );
+        assert_eq!(registrable_domain("example.com").as_deref(), Some("example.com"));
+        assert_eq!(registrable_domain("app.example.io").as_deref(), Some("example.io"));
+        // Nunca un sufijo público entero
+        assert_eq!(registrable_domain("com.bo"), None);
+        assert_eq!(registrable_domain("localhost"), None);
+        assert_eq!(registrable_domain("192.168.1.20"), None);
+    }
+
+    #[test]
+    fn test_web_host() {
+        assert_eq!(web_host("https://Pos.Tienda.com:8443"), Some("Pos.Tienda.com"));
+        assert_eq!(web_host("http://localhost:1420"), Some("localhost"));
+        for origin in [
+            "null",
+            "file://",
+            "file:///home/caja/ticket.html",
+            "https://",
+            "https://com",
+            "https://*.tienda.com",
+            "tienda.com",
+        ] {
+            assert_eq!(web_host(origin), None, "{}", origin);
+        }
+    }
+
+    #[test]
+    fn test_pattern_validation_and_serde_defaults() {
+        assert!(AllowedOrigin::new("*.tienda.com", vec![]).validate().is_ok());
+        assert!(AllowedOrigin::new("https://tienda.com", vec![]).validate().is_err());
+        assert!(AllowedOrigin::new("*.", vec![]).validate().is_err());
+        // Ni orígenes opacos ni sufijos públicos enteros
+        for pattern in ["null", "com", "*.com", "tienda..com", ".tienda.com"] {
+            assert!(validate_pattern(pattern).is_err(), "{}", pattern);
+        }
+        assert!(validate_pattern("localhost").is_ok());
+        assert!(validate_pattern("127.0.0.1").is_ok());
+
+        let parsed: AllowedOrigin = serde_json::from_str(r#"{"pattern": "pos.local"}"#).unwrap();
+        assert_eq!(parsed.permissions, Permission::DEFAULT.to_vec());
+        let json = serde_json::to_string(&AllowedOrigin::new(
+            "pos.local",
+            vec![Permission::ListPrinters],
+        ))
+        .unwrap();
+        assert!(json.contains("\"list_printers\""));
+    }
+}
@@ -1,7 +1,14 @@
 // Persistence module - Save and load authentication state
-use crate::state::{AuthState, LicenciaProducto};
+use crate::graphql::ApiConfig;
+use crate::license::SignedLicense;
+use crate::quota::QuotaUsage;
+use crate::secrets::{self, EncryptedData, KeySource};
+use crate::settings::Settings;
+use crate::state::{AppState, AuthState, LicenciaProducto};
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::Write;
 use std::path::PathBuf;
 
 /// Saved session data
@@ -9,6 +16,61 @@ use std::path::PathBuf;
 pub struct SavedSession {
     pub auth: AuthState,
     pub licencias: Vec<LicenciaProducto>,
+    /// API the token was issued by (sessions saved before this field default to sandbox)
+    #[serde(default)]
+    pub api: ApiConfig,
+    /// Licenses signed by the server (the plain `licencias` list is not trusted)
+    #[serde(default)]
+    pub signed_license: Option<SignedLicense>,
+    /// Access and refresh tokens, encrypted with the machine key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_tokens: Option<EncryptedData>,
+    /// Where the key that encrypted the tokens is stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_source: Option<KeySource>,
+}
+
+/// Authenticated context of the encrypted tokens
+const TOKENS_CONTEXT: &str = "ISIPRINT session tokens";
+
+/// Tokens stored encrypted inside the session file
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionTokens {
+    token: Option<String>,
+    refresh_token: Option<String>,
+}
+
+impl SavedSession {
+    /// Move the tokens into `encrypted_tokens` so none are left in plaintext
+    fn seal(mut self, source: KeySource, key: &[u8]) -> Result<Self, String> {
+        let tokens = SessionTokens {
+            token: self.auth.token.take(),
+            refresh_token: self.auth.refresh_token.take(),
+        };
+        let json = serde_json::to_vec(&tokens)
+            .map_err(|e| format!("Failed to serialize tokens: {}", e))?;
+        self.encrypted_tokens = Some(secrets::encrypt(key, TOKENS_CONTEXT, &json)?);
+        self.key_source = Some(source);
+        Ok(self)
+    }
+
+    /// Restore the tokens from `encrypted_tokens`
+    fn open(mut self, key: &[u8]) -> Result<Self, String> {
+        if let Some(data) = self.encrypted_tokens.take() {
+            let json = secrets::decrypt(key, TOKENS_CONTEXT, &data)?;
+            let tokens: SessionTokens = serde_json::from_slice(&json)
+                .map_err(|e| format!("Failed to parse tokens: {}", e))?;
+            self.auth.token = tokens.token;
+            self.auth.refresh_token = tokens.refresh_token;
+        }
+        Ok(self)
+    }
+
+    /// Session saved before token encryption (tokens in plaintext)
+    fn is_plaintext(&self) -> bool {
+        self.encrypted_tokens.is_none()
+            && (self.auth.token.is_some() || self.auth.refresh_token.is_some())
+    }
 }
 
 /// Get the path to the session file
@@ -20,25 +82,213 @@ fn get_session_path() -> Option<PathBuf> {
     })
 }
 
+/// Get the path to the API settings file
+fn get_api_config_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|mut path| {
+        path.push("ISIPRINT");
+        path.push("api.json");
+        path
+    })
+}
+
+/// Get the path to the print quota file
+fn get_quota_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|mut path| {
+        path.push("ISIPRINT");
+        path.push("quota.json");
+        path
+    })
+}
+
+/// Get the path to the settings file
+fn get_settings_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|mut path| {
+        path.push("ISIPRINT");
+        path.push("settings.json");
+        path
+    })
+}
+
+/// Get the directory where undelivered print jobs are kept
+pub fn get_spool_dir() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|mut path| {
+        path.push("ISIPRINT");
+        path.push("spool");
+        path
+    })
+}
+
+/// Get the directory of the rotating log files
+pub fn get_log_dir() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|mut path| {
+        path.push("ISIPRINT");
+        path.push("logs");
+        path
+    })
+}
+
+/// Get the directory for the files of jobs being printed
+pub fn get_jobs_dir() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|mut path| {
+        path.push("ISIPRINT");
+        path.push("jobs");
+        path
+    })
+}
+
+/// Get the path to the print job history (JSON lines)
+pub fn get_history_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|mut path| {
+        path.push("ISIPRINT");
+        path.push("history.jsonl");
+        path
+    })
+}
+
+/// Get the directory of the local HTTPS certificates
+pub fn get_tls_dir() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|mut path| {
+        path.push("ISIPRINT");
+        path.push("tls");
+        path
+    })
+}
+
+/// Get the path to a local secret (signing/encryption keys)
+fn get_secret_path(name: &str) -> Option<PathBuf> {
+    dirs::data_local_dir().map(|mut path| {
+        path.push("ISIPRINT");
+        path.push(name);
+        path
+    })
+}
+
+/// Write a file readable only by the current user
+pub fn write_private_file(path: &std::path::Path, contents: &[u8]) -> Result<(), String> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+
+    // The mode only applies on creation; tighten files written by older versions
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Failed to restrict permissions of {:?}: {}", path, e))?;
+    }
+
+    file.write_all(contents)
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+/// Create the data directory, accessible only by the current user
+pub fn create_private_dir(path: &std::path::Path) -> Result<(), String> {
+    fs::create_dir_all(path)
+        .map_err(|e| format!("Failed to create directory: {}", e))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o700))
+            .map_err(|e| format!("Failed to restrict directory permissions: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Load a 32-byte local secret, creating it on first use
+pub fn load_or_create_secret(name: &str) -> Result<Vec<u8>, String> {
+    let path = get_secret_path(name).ok_or("Could not determine data directory")?;
+
+    if let Ok(secret) = fs::read(&path) {
+        if secret.len() >= 32 {
+            return Ok(secret);
+        }
+        log::warn!("Secret {:?} is too short, regenerating", path);
+    }
+
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent)?;
+    }
+
+    let mut secret = vec![0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut secret);
+    write_private_file(&path, &secret)?;
+
+    log::info!("Created local secret {:?}", path);
+    Ok(secret)
+}
+
+/// Load the local API key required from HTTP callers without an origin, creating it on first use
+pub fn load_or_create_api_key() -> Result<String, String> {
+    let path = get_secret_path("api_key").ok_or("Could not determine data directory")?;
+
+    if let Ok(key) = fs::read_to_string(&path) {
+        let key = key.trim();
+        if !key.is_empty() {
+            return Ok(key.to_string());
+        }
+    }
+
+    regenerate_api_key()
+}
+
+/// Replace the local API key (the previous one stops working)
+pub fn regenerate_api_key() -> Result<String, String> {
+    let path = get_secret_path("api_key").ok_or("Could not determine data directory")?;
+
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent)?;
+    }
+
+    let mut bytes = [0u8; 24];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    let key: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    write_private_file(&path, key.as_bytes())?;
+
+    log::info!("Generated local API key");
+    Ok(key)
+}
+
 /// Save session to disk
-pub fn save_session(auth: &AuthState, licencias: &[LicenciaProducto]) -> Result<(), String> {
+pub fn save_session(app: &AppState) -> Result<(), String> {
+    let session = SavedSession {
+        auth: app.auth.clone(),
+        licencias: app.licencias.clone(),
+        api: app.api.clone(),
+        signed_license: app.signed_license.clone(),
+        encrypted_tokens: None,
+        key_source: None,
+    };
+
+    write_session(&session)
+}
+
+/// Encrypt the tokens and write the session file (0600)
+fn write_session(session: &SavedSession) -> Result<(), String> {
     let path = get_session_path().ok_or("Could not determine data directory")?;
     
     // Create directory if it doesn't exist
     if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create directory: {}", e))?;
+        create_private_dir(parent)?;
     }
+
+    let (source, key) = secrets::session_key()?;
+    let sealed = session.clone().seal(source, &key)?;
     
-    let session = SavedSession {
-        auth: auth.clone(),
-        licencias: licencias.to_vec(),
-    };
-    
-    let json = serde_json::to_string_pretty(&session)
+    let json = serde_json::to_string_pretty(&sealed)
         .map_err(|e| format!("Failed to serialize session: {}", e))?;
     
-    fs::write(&path, json)
+    write_private_file(&path, json.as_bytes())
         .map_err(|e| format!("Failed to write session file: {}", e))?;
     
     log::info!("Session saved to {:?}", path);
@@ -57,10 +307,29 @@ pub fn load_session() -> Option<SavedSession> {
     match fs::read_to_string(&path) {
         Ok(json) => {
             match serde_json::from_str::<SavedSession>(&json) {
-                Ok(session) => {
-                    log::info!("Session loaded from {:?}", path);
+                Ok(session) if session.is_plaintext() => {
+                    // Migrate sessions saved before token encryption
+                    log::info!("Encrypting plaintext session tokens in {:?}", path);
+                    if let Err(e) = write_session(&session) {
+                        log::warn!("Failed to migrate session: {}", e);
+                    }
                     Some(session)
                 }
+                Ok(session) => {
+                    // The keyring may have become (un)available since the session was saved
+                    let mut last_error = String::from("no session key available");
+                    for source in secrets::key_sources(session.key_source) {
+                        match secrets::key_from(source).and_then(|key| session.clone().open(&key)) {
+                            Ok(session) => {
+                                log::info!("Session loaded from {:?}", path);
+                                return Some(session);
+                            }
+                            Err(e) => last_error = e,
+                        }
+                    }
+                    log::warn!("Failed to decrypt session tokens: {}", last_error);
+                    None
+                }
                 Err(e) => {
                     log::warn!("Failed to parse session: {}", e);
                     None
@@ -87,6 +356,165 @@ pub fn clear_session() -> Result<(), String> {
     Ok(())
 }
 
+/// Save the selected API environment
+pub fn save_api_config(api: &ApiConfig) -> Result<(), String> {
+    let path = get_api_config_path().ok_or("Could not determine data directory")?;
+
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent)?;
+    }
+
+    let json = serde_json::to_string_pretty(api)
+        .map_err(|e| format!("Failed to serialize API settings: {}", e))?;
+
+    write_private_file(&path, json.as_bytes())
+        .map_err(|e| format!("Failed to write API settings file: {}", e))?;
+
+    log::info!("API settings saved to {:?}", path);
+    Ok(())
+}
+
+/// Save print quota usage for the current license period
+pub fn save_quota(quota: &QuotaUsage) -> Result<(), String> {
+    let path = get_quota_path().ok_or("Could not determine data directory")?;
+
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent)?;
+    }
+
+    let json = serde_json::to_string_pretty(quota)
+        .map_err(|e| format!("Failed to serialize print quota: {}", e))?;
+
+    write_private_file(&path, json.as_bytes())
+        .map_err(|e| format!("Failed to write print quota file: {}", e))
+}
+
+/// Load print quota usage (None if never printed)
+pub fn load_quota() -> Option<QuotaUsage> {
+    let path = get_quota_path()?;
+
+    if !path.exists() {
+        return None;
+    }
+
+    let json = fs::read_to_string(&path)
+        .map_err(|e| log::warn!("Failed to read print quota file: {}", e))
+        .ok()?;
+
+    serde_json::from_str(&json)
+        .map_err(|e| log::warn!("Failed to parse print quota: {}", e))
+        .ok()
+}
+
+/// Load the persistent device ID, generating it on first start
+pub fn load_or_create_device_id() -> Result<String, String> {
+    let path = get_secret_path("device_id").ok_or("Could not determine data directory")?;
+
+    if let Ok(id) = fs::read_to_string(&path) {
+        let id = id.trim();
+        if !id.is_empty() {
+            return Ok(id.to_string());
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent)?;
+    }
+
+    let mut bytes = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    let id: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+    write_private_file(&path, id.as_bytes())
+        .map_err(|e| format!("Failed to write device ID: {}", e))?;
+
+    log::info!("Generated device ID {}", id);
+    Ok(id)
+}
+
+/// Save local settings (printer profiles, defaults, HTTP server, language)
+pub fn save_settings(settings: &Settings) -> Result<(), String> {
+    settings.validate()?;
+
+    let path = get_settings_path().ok_or("Could not determine data directory")?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create directory: {}", e))?;
+    }
+
+    let json = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+
+    // Write to a temp file and rename so a crash never leaves a truncated file
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, json)
+        .map_err(|e| format!("Failed to write settings file: {}", e))?;
+    fs::rename(&tmp, &path)
+        .map_err(|e| format!("Failed to write settings file: {}", e))?;
+
+    log::info!("Settings saved to {:?}", path);
+    Ok(())
+}
+
+/// Load local settings, migrating older versions (defaults if missing or unreadable)
+pub fn load_settings() -> Settings {
+    let Some(path) = get_settings_path() else {
+        return Settings::default();
+    };
+
+    if !path.exists() {
+        return Settings::default();
+    }
+
+    let json = match fs::read_to_string(&path) {
+        Ok(json) => json,
+        Err(e) => {
+            log::warn!("Failed to read settings file: {}", e);
+            return Settings::default();
+        }
+    };
+
+    match Settings::from_json(&json) {
+        Ok(settings) => {
+            log::info!("Settings loaded from {:?}", path);
+            settings
+        }
+        Err(e) => {
+            // Keep the unreadable file for inspection instead of overwriting it
+            let backup = path.with_extension("json.bak");
+            log::warn!("{}; moving it to {:?} and using defaults", e, backup);
+            let _ = fs::rename(&path, &backup);
+            Settings::default()
+        }
+    }
+}
+
+/// Load the selected API environment (None if never configured or invalid)
+pub fn load_api_config() -> Option<ApiConfig> {
+    let path = get_api_config_path()?;
+
+    if !path.exists() {
+        return None;
+    }
+
+    let json = fs::read_to_string(&path)
+        .map_err(|e| log::warn!("Failed to read API settings file: {}", e))
+        .ok()?;
+
+    match serde_json::from_str::<ApiConfig>(&json) {
+        Ok(api) if api.validate().is_ok() => Some(api),
+        Ok(_) => {
+            log::warn!("Ignoring invalid API settings in {:?}", path);
+            None
+        }
+        Err(e) => {
+            log::warn!("Failed to parse API settings: {}", e);
+            None
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,6 +538,10 @@ mod tests {
                 is_logged_in: true,
             },
             licencias: vec![],
+            api: ApiConfig::default(),
+            signed_license: None,
+            encrypted_tokens: None,
+            key_source: None,
         };
         
         let json = serde_json::to_string(&session).unwrap();
@@ -118,5 +550,75 @@ mod tests {
         assert_eq!(parsed.auth.token, Some("test_token".to_string()));
         assert_eq!(parsed.auth.email, Some("test@example.com".to_string()));
         assert!(parsed.auth.is_logged_in);
+        assert_eq!(parsed.api, ApiConfig::default());
+    }
+
+    #[test]
+    fn test_saved_session_without_api_defaults_to_sandbox() {
+        let json = r#"{
+            "auth": {"token": "t", "refresh_token": null, "email": null, "is_logged_in": true},
+            "licencias": []
+        }"#;
+        let parsed: SavedSession = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed.api, ApiConfig::default());
+        assert!(parsed.signed_license.is_none());
+    }
+
+    #[test]
+    fn test_seal_and_open_session_tokens() {
+        let key = b"0123456789abcdef0123456789abcdef";
+        let session = SavedSession {
+            auth: AuthState {
+                token: Some("test_token".to_string()),
+                refresh_token: Some("test_refresh".to_string()),
+                email: Some("test@example.com".to_string()),
+                is_logged_in: true,
+            },
+            licencias: vec![],
+            api: ApiConfig::default(),
+            signed_license: None,
+            encrypted_tokens: None,
+            key_source: None,
+        };
+        assert!(session.is_plaintext());
+
+        let sealed = session.seal(KeySource::File, key).unwrap();
+        assert!(!sealed.is_plaintext());
+        assert_eq!(sealed.key_source, Some(KeySource::File));
+        let json = serde_json::to_string(&sealed).unwrap();
+        assert!(!json.contains("test_token"));
+        assert!(!json.contains("test_refresh"));
+
+        let parsed: SavedSession = serde_json::from_str(&json).unwrap();
+        assert!(parsed.clone().open(b"another-key-another-key-another!!").is_err());
+
+        let opened = parsed.open(key).unwrap();
+        assert_eq!(opened.auth.token.as_deref(), Some("test_token"));
+        assert_eq!(opened.auth.refresh_token.as_deref(), Some("test_refresh"));
+        assert_eq!(opened.auth.email.as_deref(), Some("test@example.com"));
+    }
+
+    #[test]
+    fn test_legacy_plaintext_session_detected() {
+        let json = r#"{
+            "auth": {"token": "t", "refresh_token": "r", "email": null, "is_logged_in": true},
+            "licencias": []
+        }"#;
+        let parsed: SavedSession = serde_json::from_str(json).unwrap();
+        assert!(parsed.is_plaintext());
+    }
+
+    #[test]
+    fn test_get_settings_path() {
+        let path = get_settings_path().unwrap();
+        assert!(path.to_string_lossy().contains("ISIPRINT"));
+        assert!(path.ends_with("settings.json"));
+    }
+
+    #[test]
+    fn test_get_secret_path() {
+        let path = get_secret_path("license.key").unwrap();
+        assert!(path.to_string_lossy().contains("ISIPRINT"));
+        assert!(path.ends_with("license.key"));
     }
 }
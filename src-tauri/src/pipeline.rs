@@ -0,0 +1,863 @@
+// Pipeline de impresión común a todos los puntos de entrada (comandos, HTTP y trabajos remotos):
+// origen → validación → transformación → ruta → envío → verificación → acciones posteriores
+use crate::commands::{self, PrintSettings};
+use crate::history::{self, JobContext, JobResult, JobSource};
+use crate::job_files::{JobFile, JobFiles};
+use crate::pools::{self, PrintFailure};
+use crate::printer;
+use crate::quota;
+use crate::raw_printer;
+use crate::routing::{self, RouteRequest};
+use crate::settings::CutMode;
+use crate::spool::{self, NewJob, SpoolFormat};
+use crate::SharedAppState;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::time::{sleep, Duration};
+
+/// Máximo de copias por trabajo, con o sin límite en la licencia
+pub const MAX_COPIES: u32 = 100;
+
+/// Contenido del trabajo
+#[derive(Debug, Clone)]
+pub enum Content {
+    Bytes(Vec<u8>),
+    /// Archivo local: se imprime tal cual, sin copiarlo ni eliminarlo
+    File(PathBuf),
+    /// PDF a descargar
+    Url(String),
+}
+
+/// Trabajo de impresión tal como llega de un punto de entrada
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub source: JobSource,
+    pub content: Content,
+    pub format: SpoolFormat,
+    /// Impresora o grupo solicitado; si falta decide el enrutamiento
+    pub printer: Option<String>,
+    pub copies: Option<u32>,
+    /// Papel explícito; si falta se usa el perfil de la regla de enrutamiento
+    pub paper: Option<PrintSettings>,
+    pub origin: Option<String>,
+    pub document_type: Option<String>,
+    /// Guardar en la cola de reintentos si la impresora no está disponible
+    pub spool_on_failure: bool,
+}
+
+impl Job {
+    pub fn new(source: JobSource, content: Content, format: SpoolFormat) -> Self {
+        Self {
+            source,
+            content,
+            format,
+            printer: None,
+            copies: None,
+            paper: None,
+            origin: None,
+            document_type: None,
+            spool_on_failure: false,
+        }
+    }
+
+    pub fn with_printer(mut self, printer: Option<String>) -> Self {
+        self.printer = printer.filter(|p| !p.trim().is_empty());
+        self
+    }
+
+    pub fn with_copies(mut self, copies: Option<u32>) -> Self {
+        self.copies = copies;
+        self
+    }
+
+    pub fn with_paper(mut self, paper: PrintSettings) -> Self {
+        self.paper = Some(paper);
+        self
+    }
+
+    pub fn with_request(mut self, origin: Option<String>, document_type: Option<String>) -> Self {
+        self.origin = origin;
+        self.document_type = document_type.filter(|d| !d.trim().is_empty());
+        self
+    }
+
+    pub fn spool_on_failure(mut self) -> Self {
+        self.spool_on_failure = true;
+        self
+    }
+}
+
+/// Etapa en la que se rechazó o falló un trabajo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Licencia inválida o vencida, o función deshabilitada
+    License,
+    Quota,
+    /// Contenido, copias o descarga inválidos
+    Invalid,
+    NoPrinter,
+    /// Ningún candidato aceptó el trabajo
+    Print,
+    /// Enviado, pero la impresora no lo registró
+    Verification,
+    Internal,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl PipelineError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Resultado de un trabajo aceptado
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Printed {
+        printer: String,
+        job_ids: Vec<i32>,
+    },
+    /// No se pudo imprimir y quedó en la cola de reintentos
+    Queued {
+        id: String,
+        error: String,
+        /// Copias impresas antes del fallo (no se vuelven a imprimir)
+        job_ids: Vec<i32>,
+    },
+}
+
+impl Outcome {
+    pub fn message(&self) -> String {
+        match self {
+            Outcome::Printed { printer, job_ids } => {
+                format!(
+                    "Printed successfully on {}. Job ID: {}",
+                    printer,
+                    join_job_ids(job_ids)
+                )
+            }
+            Outcome::Queued { id, error, job_ids } if job_ids.is_empty() => {
+                format!(
+                    "Printer unavailable ({}). Job {} queued for retry",
+                    error, id
+                )
+            }
+            Outcome::Queued { id, error, job_ids } => {
+                format!(
+                    "Printer unavailable ({}). Job {} queued for retry, already printed Job ID: {}",
+                    error,
+                    id,
+                    join_job_ids(job_ids)
+                )
+            }
+        }
+    }
+}
+
+pub fn join_job_ids(job_ids: &[i32]) -> String {
+    job_ids
+        .iter()
+        .map(i32::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Envío ya resuelto: destino, copias, papel y corte
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    /// Impresora o grupo
+    pub printer: String,
+    pub copies: u32,
+    /// Tamaño de papel CUPS
+    pub media: Option<String>,
+    /// Cortar tras cada copia (nunca en impresoras PDF)
+    pub cut: bool,
+    pub format: SpoolFormat,
+    /// Resolución del perfil (PDF)
+    pub dpi: Option<u32>,
+    /// Página de códigos ESC/POS del perfil (RAW)
+    pub code_page: Option<String>,
+}
+
+/// Papel térmico declarado explícitamente (perfil o papel de la petición)
+fn is_thermal(preset: &str) -> bool {
+    matches!(preset.trim().to_lowercase().as_str(), "thermal" | "termico" | "térmico")
+}
+
+/// Si hay que cortar el papel tras imprimir. En modo automático solo los PDF
+/// en papel térmico: el contenido RAW trae sus propios comandos ESC/POS, y sin
+/// papel conocido (`None`) la impresora puede ser de oficina.
+pub fn should_cut(mode: CutMode, format: SpoolFormat, preset: Option<&str>) -> bool {
+    match mode {
+        CutMode::Always => true,
+        CutMode::Never => false,
+        CutMode::Auto => format == SpoolFormat::Pdf && preset.is_some_and(is_thermal),
+    }
+}
+
+// ============ Etapas ============
+
+/// Validación: licencia, cuota e impresión RAW habilitada
+pub async fn validate(state: &SharedAppState, format: SpoolFormat) -> Result<(), PipelineError> {
+    let app_state = state.read().await;
+    app_state
+        .check_license()
+        .map_err(|e| PipelineError::new(ErrorKind::License, e))?;
+    app_state
+        .check_quota()
+        .map_err(|e| PipelineError::new(ErrorKind::Quota, e))?;
+    if format == SpoolFormat::Raw && !app_state.license_config.features.raw_printing {
+        return Err(PipelineError::new(
+            ErrorKind::License,
+            "Raw printing is disabled by your license configuration",
+        ));
+    }
+    Ok(())
+}
+
+/// Copias pedidas: al menos una y nunca más que `MAX_COPIES`
+fn check_copies(copies: u32) -> Result<(), PipelineError> {
+    if copies == 0 {
+        return Err(PipelineError::new(ErrorKind::Invalid, "At least one copy required"));
+    }
+    if copies > MAX_COPIES {
+        return Err(PipelineError::new(
+            ErrorKind::Invalid,
+            format!(
+                "Too many copies requested ({}), the maximum allowed is {}",
+                copies, MAX_COPIES
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Origen: bytes del trabajo
+async fn load(content: &Content) -> Result<Vec<u8>, PipelineError> {
+    let data = match content {
+        Content::Bytes(data) => data.clone(),
+        Content::File(path) => tokio::fs::read(path).await.map_err(|e| {
+            PipelineError::new(ErrorKind::Invalid, format!("Error reading file: {}", e))
+        })?,
+        Content::Url(url) => printer::download_pdf(url)
+            .await
+            .map_err(|e| PipelineError::new(ErrorKind::Invalid, e))?,
+    };
+
+    if data.is_empty() {
+        return Err(PipelineError::new(ErrorKind::Invalid, "Content required"));
+    }
+    Ok(data)
+}
+
+/// Ruta: reglas de enrutamiento, impresora por defecto, copias, papel (explícito, de la regla
+/// o del perfil de la licencia) y corte
+async fn plan(state: &SharedAppState, job: &Job) -> Result<Delivery, PipelineError> {
+    let (route, defaults, copies, license_config) = {
+        let app_state = state.read().await;
+        let request = RouteRequest {
+            origin: job.origin.as_deref(),
+            document_type: job.document_type.as_deref(),
+            time: chrono::Local::now().time(),
+        };
+        let route = routing::route(&app_state.settings, &request);
+
+        let copies = job.copies.or(route.copies).unwrap_or(1);
+        check_copies(copies)?;
+        app_state
+            .license_config
+            .check_copies(copies)
+            .map_err(|e| PipelineError::new(ErrorKind::Invalid, e))?;
+
+        (
+            route,
+            app_state.printer_defaults(job.document_type.as_deref()),
+            copies,
+            app_state.license_config.clone(),
+        )
+    };
+
+    let requested = job.printer.clone().or(route.printer);
+    let printer =
+        tokio::task::spawn_blocking(move || printer::resolve_printer(requested, &defaults))
+            .await
+            .ok()
+            .flatten()
+            .ok_or_else(|| PipelineError::new(ErrorKind::NoPrinter, "No printers available"))?;
+
+    let (paper, cut_mode) = match (&job.paper, &route.profile) {
+        (Some(paper), _) => (Some(paper.clone()), CutMode::Auto),
+        (None, Some(profile)) => (
+            Some(PrintSettings {
+                preset: profile.preset.clone(),
+                width_mm: profile.width_mm,
+                height_mm: profile.height_mm,
+            }),
+            profile.cut,
+        ),
+        // Perfil de la licencia para la impresora elegida
+        (None, None) => (
+            license_config.profile_for(&printer).map(|profile| PrintSettings {
+                preset: profile.preset.clone(),
+                width_mm: profile.width_mm,
+                height_mm: profile.height_mm,
+            }),
+            CutMode::Auto,
+        ),
+    };
+    let cut = should_cut(cut_mode, job.format, paper.as_ref().map(|p| p.preset.as_str()));
+
+    // El contenido RAW no lleva tamaño de papel ni resolución; el PDF, página de códigos
+    let (code_page, dpi) = route
+        .profile
+        .as_ref()
+        .map_or((None, None), |p| (p.code_page.clone(), p.dpi));
+    let (media, dpi, code_page) = match job.format {
+        SpoolFormat::Pdf => (paper.as_ref().map(|p| commands::settings_to_media(p).0), dpi, None),
+        SpoolFormat::Raw => (None, None, code_page),
+    };
+
+    Ok(Delivery {
+        printer,
+        copies,
+        media,
+        cut,
+        format: job.format,
+        dpi,
+        code_page,
+    })
+}
+
+/// Transformación: contenido en un archivo del directorio de trabajos que lee la cola de impresión.
+/// Se elimina al soltarlo.
+fn write_temp(data: &[u8], format: SpoolFormat) -> Result<JobFile, PipelineError> {
+    JobFiles::open()
+        .and_then(|files| files.create(data, format.extension()))
+        .map_err(|e| PipelineError::new(ErrorKind::Internal, e))
+}
+
+/// Envío: copias con failover entre los miembros del grupo; corte tras cada copia.
+/// La cuota se reserva antes (ver `quota::reserve`).
+pub async fn submit(
+    state: &SharedAppState,
+    path: &Path,
+    delivery: &Delivery,
+) -> Result<(String, Vec<i32>), PrintFailure> {
+    let candidates = pools::candidates(state, &delivery.printer).await;
+    let path = path.to_string_lossy().to_string();
+    let media = delivery.media.clone();
+    let dpi = delivery.dpi;
+    let cut = delivery.cut;
+    let raw = delivery.format == SpoolFormat::Raw;
+
+    pools::print_with_failover(state, &candidates, delivery.copies, move |name: &str| {
+        let pdf_printer = commands::is_pdf_printer(name);
+        let job_id = if raw {
+            // ESC/POS directo a las impresoras socket://, como la prueba RAW
+            printer::print_raw_file(&path, name)?
+        } else if pdf_printer {
+            // Las impresoras PDF virtuales suelen rechazar tamaños personalizados
+            printer::print_file(&path, name).map_err(|e| printer::queue_error(name, e))?
+        } else {
+            printer::print_file_with_options(&path, name, media.as_deref(), dpi)
+                .map_err(|e| printer::queue_error(name, e))?
+        };
+
+        if cut && !pdf_printer {
+            if let Err(e) = printer::send_cut(name) {
+                log::warn!("Error sending cut to {}: {}", name, e);
+            }
+        }
+        Ok(job_id)
+    })
+    .await
+}
+
+/// Verificación: la cola registró el trabajo (o PDFwriter generó el archivo).
+/// Evita reportar éxito cuando CUPS descarta el trabajo en silencio.
+pub async fn verify(printer_name: &str, job_id: i32, since: SystemTime) -> Result<(), String> {
+    if is_pdfwriter(printer_name) {
+        let out = verify_pdfwriter_output_visible(since, Duration::from_secs(8)).await?;
+        log::info!("PDFwriter output detected: {}", out);
+        Ok(())
+    } else {
+        verify_cups_job_visible(printer_name, job_id, Duration::from_secs(3)).await
+    }
+}
+
+/// Ejecutar un trabajo completo. Los errores quedan en el log de la aplicación.
+pub async fn run(state: &SharedAppState, job: Job) -> Result<Outcome, PipelineError> {
+    let result = execute(state, job).await;
+    if let Err(e) = &result {
+        state
+            .write()
+            .await
+            .add_log("ERROR", &format!("Print error: {}", e));
+    }
+    result
+}
+
+async fn execute(state: &SharedAppState, job: Job) -> Result<Outcome, PipelineError> {
+    validate(state, job.format).await?;
+    let data = load(&job.content).await?;
+    let delivery = plan(state, &job).await?;
+
+    let context = JobContext::new(job.source, job.format.as_str(), &data, delivery.copies)
+        .with_request(job.origin.clone(), job.document_type.clone());
+
+    // Página de códigos del perfil al inicio del contenido RAW
+    let code_page = delivery
+        .code_page
+        .as_deref()
+        .and_then(raw_printer::code_page_command);
+    let data = match &code_page {
+        Some(command) => raw_printer::with_code_page(&data, command),
+        None => data,
+    };
+
+    let (path, file) = match &job.content {
+        Content::File(path) if code_page.is_none() => (path.clone(), None),
+        _ => {
+            let file = write_temp(&data, job.format)?;
+            (file.path().to_path_buf(), Some(file))
+        }
+    };
+    drop(data);
+
+    state.write().await.add_log(
+        "INFO",
+        &format!(
+            "Printing {} on {} ({} copies)",
+            job.format.as_str().to_uppercase(),
+            delivery.printer,
+            delivery.copies
+        ),
+    );
+
+    // Las copias se descuentan de la cuota antes de enviarlas
+    quota::reserve(state, delivery.copies)
+        .await
+        .map_err(|e| PipelineError::new(ErrorKind::Quota, e))?;
+
+    let since = SystemTime::now();
+    let submitted = submit(state, &path, &delivery).await;
+    let printed = match &submitted {
+        Ok(_) => delivery.copies,
+        Err(failure) => failure.printed(),
+    };
+    quota::settle(state, delivery.copies, printed).await;
+
+    let failure = match submitted {
+        Ok((printer_name, job_ids)) => {
+            let last_job = job_ids.last().copied().unwrap_or(0);
+            if let Err(e) = verify(&printer_name, last_job, since).await {
+                // Ya se envió: reintentarlo podría imprimirlo dos veces
+                history::record(context.finish(&printer_name, JobResult::Failed, Some(e.clone())))
+                    .await;
+                return Err(PipelineError::new(ErrorKind::Verification, e));
+            }
+
+            // Acciones posteriores. Las impresoras PDF virtuales (y el spooler de
+            // Windows) leen el archivo después de que el comando retorna: se
+            // conserva hasta el barrido por TTL. En los demás casos se elimina aquí.
+            if let Some(file) = file {
+                if commands::is_pdf_printer(&printer_name) || cfg!(windows) {
+                    file.retain();
+                }
+            }
+            history::record(context.finish(&printer_name, JobResult::Printed, None)).await;
+            let outcome = Outcome::Printed {
+                printer: printer_name,
+                job_ids,
+            };
+            state.write().await.add_log("SUCCESS", &outcome.message());
+            return Ok(outcome);
+        }
+        Err(failure) => failure,
+    };
+
+    // Solo se encola si la impresora no estaba disponible: un trabajo rechazado
+    // fallaría igual en cada reintento
+    if job.spool_on_failure && failure.transient {
+        let pending = NewJob {
+            printer: delivery.printer.clone(),
+            format: job.format,
+            copies: delivery.copies - failure.printed(),
+            media: delivery.media.clone(),
+            dpi: delivery.dpi,
+            cut: delivery.cut,
+            origin: job.origin.clone(),
+            document_type: job.document_type.clone(),
+            error: failure.message.clone(),
+        };
+        match spool::enqueue(state, &path, pending).await {
+            Ok(spooled) => {
+                history::record(context.finish(
+                    &delivery.printer,
+                    JobResult::Queued,
+                    Some(failure.message.clone()),
+                ))
+                .await;
+                return Ok(Outcome::Queued {
+                    id: spooled.id,
+                    error: failure.message,
+                    job_ids: failure.job_ids,
+                });
+            }
+            Err(e) => log::error!("Could not spool failed job: {}", e),
+        }
+    }
+
+    // Las copias ya impresas se informan con sus IDs de trabajo
+    let message = if failure.job_ids.is_empty() {
+        failure.message
+    } else {
+        format!(
+            "{} ({} of {} copies printed, Job ID: {})",
+            failure.message,
+            failure.job_ids.len(),
+            delivery.copies,
+            join_job_ids(&failure.job_ids)
+        )
+    };
+    history::record(context.finish(&delivery.printer, JobResult::Failed, Some(message.clone())))
+        .await;
+    Err(PipelineError::new(ErrorKind::Print, message))
+}
+
+// ============ Verificación ============
+
+fn is_pdfwriter(printer_name: &str) -> bool {
+    printer_name.to_lowercase().contains("pdfwriter")
+}
+
+fn try_find_latest_pdfwriter_output() -> Option<String> {
+    // RWTS PDFwriter typically writes into: /private/var/spool/pdfwriter/<user>/
+    // We best-effort pick the most recently modified .pdf.
+    let user = std::env::var("USER").ok()?;
+    let dir = std::path::Path::new("/private/var/spool/pdfwriter").join(user);
+    let entries = std::fs::read_dir(&dir).ok()?;
+
+    let mut newest: Option<(std::time::SystemTime, std::path::PathBuf)> = None;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.eq_ignore_ascii_case("pdf"))
+            != Some(true)
+        {
+            continue;
+        }
+        let meta = match std::fs::metadata(&path) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+        let modified = match meta.modified() {
+            Ok(t) => t,
+            Err(_) => continue,
+        };
+
+        match &newest {
+            None => newest = Some((modified, path)),
+            Some((best_time, _)) if modified > *best_time => newest = Some((modified, path)),
+            _ => {}
+        }
+    }
+
+    newest.map(|(_, p)| p.to_string_lossy().to_string())
+}
+
+fn try_find_latest_pdfwriter_output_since(since: SystemTime) -> Option<String> {
+    let user = std::env::var("USER").ok()?;
+    let dir = std::path::Path::new("/private/var/spool/pdfwriter").join(user);
+    let entries = std::fs::read_dir(&dir).ok()?;
+
+    let mut newest: Option<(std::time::SystemTime, std::path::PathBuf)> = None;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.eq_ignore_ascii_case("pdf"))
+            != Some(true)
+        {
+            continue;
+        }
+
+        let meta = match std::fs::metadata(&path) {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+        let modified = match meta.modified() {
+            Ok(t) => t,
+            Err(_) => continue,
+        };
+
+        // Must be newer than (or equal to) our start time.
+        if modified < since {
+            continue;
+        }
+
+        match &newest {
+            None => newest = Some((modified, path)),
+            Some((best_time, _)) if modified > *best_time => newest = Some((modified, path)),
+            _ => {}
+        }
+    }
+
+    newest.map(|(_, p)| p.to_string_lossy().to_string())
+}
+
+fn cups_job_key(printer_name: &str, job_id: i32) -> Option<String> {
+    if job_id <= 0 {
+        return None;
+    }
+    Some(format!("{}-{}", printer_name, job_id))
+}
+
+fn cups_job_seen_in_queue(printer_name: &str, job_id: i32) -> Result<bool, String> {
+    use std::process::Command;
+
+    let key = match cups_job_key(printer_name, job_id) {
+        Some(k) => k,
+        None => return Ok(true), // can't verify, don't block
+    };
+
+    let output = Command::new("lpstat")
+        .args(["-o", printer_name])
+        .output()
+        .map_err(|e| format!("Error executing lpstat: {}", e))?;
+
+    // If lpstat returns non-zero for an empty queue, treat as not seen.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.contains(&key))
+}
+
+fn cups_job_seen_in_completed(printer_name: &str, job_id: i32) -> Result<bool, String> {
+    use std::process::Command;
+
+    let key = match cups_job_key(printer_name, job_id) {
+        Some(k) => k,
+        None => return Ok(true), // can't verify, don't block
+    };
+
+    let output = Command::new("lpstat")
+        .args(["-W", "completed"])
+        .output()
+        .map_err(|e| format!("Error executing lpstat: {}", e))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.contains(&key))
+}
+
+async fn verify_cups_job_visible(
+    printer_name: &str,
+    job_id: i32,
+    timeout: Duration,
+) -> Result<(), String> {
+    // Best-effort: only validates that CUPS has *record* of the job (queued or completed).
+    // This avoids returning a false "success" when CUPS silently drops a job.
+    let key = match cups_job_key(printer_name, job_id) {
+        Some(k) => k,
+        None => return Ok(()),
+    };
+
+    let step = Duration::from_millis(250);
+    let mut waited = Duration::from_millis(0);
+
+    while waited < timeout {
+        let in_queue = match cups_job_seen_in_queue(printer_name, job_id) {
+            Ok(v) => v,
+            Err(_e) => {
+                return Ok(());
+            }
+        };
+
+        let in_completed = match cups_job_seen_in_completed(printer_name, job_id) {
+            Ok(v) => v,
+            Err(_e) => {
+                return Ok(());
+            }
+        };
+
+        if in_queue || in_completed {
+            return Ok(());
+        }
+
+        sleep(step).await;
+        waited += step;
+    }
+
+    Err(format!(
+        "Print job {} was submitted but not observed in CUPS queue/completed within {:?}",
+        key, timeout
+    ))
+}
+
+async fn verify_pdfwriter_output_visible(
+    since: SystemTime,
+    timeout: Duration,
+) -> Result<String, String> {
+    let step = Duration::from_millis(250);
+    let mut waited = Duration::from_millis(0);
+
+    while waited < timeout {
+        if let Some(out) =
+            try_find_latest_pdfwriter_output_since(since).or_else(try_find_latest_pdfwriter_output)
+        {
+            return Ok(out);
+        }
+        sleep(step).await;
+        waited += step;
+    }
+
+    Err(format!(
+        "PDFwriter accepted the job, but no output PDF was detected in spool within {:?}",
+        timeout
+    ))
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppState;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[test]
+    fn test_should_cut() {
+        assert!(should_cut(CutMode::Auto, SpoolFormat::Pdf, Some("thermal")));
+        assert!(should_cut(CutMode::Auto, SpoolFormat::Pdf, Some("Thermal")));
+        assert!(!should_cut(CutMode::Auto, SpoolFormat::Pdf, Some("carta")));
+        assert!(!should_cut(CutMode::Auto, SpoolFormat::Pdf, Some("a4")));
+        assert!(!should_cut(CutMode::Auto, SpoolFormat::Raw, Some("thermal")));
+        assert!(should_cut(CutMode::Always, SpoolFormat::Raw, Some("oficio")));
+        assert!(!should_cut(CutMode::Never, SpoolFormat::Pdf, Some("thermal")));
+    }
+
+    #[test]
+    fn test_outcome_message() {
+        let printed = Outcome::Printed {
+            printer: "Caja".to_string(),
+            job_ids: vec![12, 13],
+        };
+        assert_eq!(
+            printed.message(),
+            "Printed successfully on Caja. Job ID: 12, 13"
+        );
+
+        let queued = Outcome::Queued {
+            id: "abc".to_string(),
+            error: "Printer offline".to_string(),
+            job_ids: vec![],
+        };
+        assert_eq!(
+            queued.message(),
+            "Printer unavailable (Printer offline). Job abc queued for retry"
+        );
+
+        let partial = Outcome::Queued {
+            id: "abc".to_string(),
+            error: "Printer offline".to_string(),
+            job_ids: vec![12],
+        };
+        assert_eq!(
+            partial.message(),
+            "Printer unavailable (Printer offline). Job abc queued for retry, already printed Job ID: 12"
+        );
+    }
+
+    #[test]
+    fn test_job_builder_ignores_blank_values() {
+        let job = Job::new(
+            JobSource::App,
+            Content::Bytes(b"x".to_vec()),
+            SpoolFormat::Raw,
+        )
+        .with_printer(Some(" ".to_string()))
+        .with_request(None, Some(String::new()));
+        assert_eq!(job.printer, None);
+        assert_eq!(job.document_type, None);
+        assert!(!job.spool_on_failure);
+    }
+
+    #[tokio::test]
+    async fn test_run_rejects_without_license() {
+        let state: SharedAppState = Arc::new(RwLock::new(AppState::default()));
+        let job = Job::new(
+            JobSource::App,
+            Content::Bytes(b"%PDF-1.4".to_vec()),
+            SpoolFormat::Pdf,
+        );
+
+        let err = run(&state, job).await.unwrap_err();
+        assert_eq!(err.kind, ErrorKind::License);
+        assert_eq!(state.read().await.logs.back().unwrap().level, "ERROR");
+    }
+
+    #[tokio::test]
+    async fn test_plan_rejects_zero_and_too_many_copies() {
+        let state: SharedAppState = Arc::new(RwLock::new(AppState::default()));
+        let job = |copies| {
+            Job::new(JobSource::Http, Content::Bytes(b"%PDF-1.4".to_vec()), SpoolFormat::Pdf)
+                .with_printer(Some("Oficina".to_string()))
+                .with_copies(Some(copies))
+        };
+
+        for copies in [0, MAX_COPIES + 1, u32::MAX] {
+            let error = plan(&state, &job(copies)).await.unwrap_err();
+            assert_eq!(error.kind, ErrorKind::Invalid);
+        }
+        assert_eq!(plan(&state, &job(MAX_COPIES)).await.unwrap().copies, MAX_COPIES);
+    }
+
+    #[tokio::test]
+    async fn test_plan_without_profile_does_not_cut_pdf() {
+        let state: SharedAppState = Arc::new(RwLock::new(AppState::default()));
+        let job = Job::new(
+            JobSource::Http,
+            Content::Bytes(b"%PDF-1.4".to_vec()),
+            SpoolFormat::Pdf,
+        )
+        .with_printer(Some("Oficina".to_string()));
+
+        let delivery = plan(&state, &job).await.unwrap();
+        assert_eq!(delivery.printer, "Oficina");
+        assert_eq!(delivery.media, None);
+        assert!(!delivery.cut);
+
+        // Papel térmico explícito: corte automático
+        let thermal = job.with_paper(PrintSettings {
+            preset: "thermal".to_string(),
+            width_mm: Some(80.0),
+            height_mm: None,
+        });
+        assert!(plan(&state, &thermal).await.unwrap().cut);
+    }
+
+    #[tokio::test]
+    async fn test_load_rejects_empty_content() {
+        let err = load(&Content::Bytes(Vec::new())).await.unwrap_err();
+        assert_eq!(
+            err,
+            PipelineError::new(ErrorKind::Invalid, "Content required")
+        );
+    }
+}
@@ -0,0 +1,366 @@
+// Grupos de impresoras: balanceo de carga y failover entre miembros
+use crate::printer::{self, SendError};
+use crate::settings::Settings;
+use crate::state::HealthStatus;
+use crate::SharedAppState;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Cómo se reparte el trabajo entre los miembros del grupo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolStrategy {
+    #[default]
+    RoundRobin,
+    /// El miembro con menos trabajos en cola
+    LeastBusy,
+}
+
+/// Grupo de impresoras direccionable por nombre (p. ej. "cocina")
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrinterPool {
+    pub name: String,
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub strategy: PoolStrategy,
+}
+
+impl PrinterPool {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Printer pool name required".to_string());
+        }
+        if self.members.iter().all(|m| m.trim().is_empty()) {
+            return Err(format!("Printer pool {} has no members", self.name));
+        }
+        Ok(())
+    }
+}
+
+/// Orden en que se prueban los miembros: según la estrategia, y los que el
+/// monitor de salud ve caídos al final (solo se usan si fallan los demás)
+pub fn order_members(
+    pool: &PrinterPool,
+    cursor: usize,
+    queued: &HashMap<String, usize>,
+    unhealthy: &HashSet<String>,
+) -> Vec<String> {
+    let mut members: Vec<String> = pool
+        .members
+        .iter()
+        .filter(|m| !m.trim().is_empty())
+        .cloned()
+        .collect();
+    if members.is_empty() {
+        return members;
+    }
+
+    match pool.strategy {
+        PoolStrategy::RoundRobin => {
+            let len = members.len();
+            members.rotate_left(cursor % len);
+        }
+        PoolStrategy::LeastBusy => {
+            members.sort_by_key(|m| queued.get(m).copied().unwrap_or(0));
+        }
+    }
+
+    // sort_by_key es estable: conserva el orden anterior dentro de cada grupo
+    members.sort_by_key(|m| unhealthy.contains(m));
+    members
+}
+
+/// Impresoras a probar para un destino: los miembros si es un grupo, o la impresora sola
+pub async fn candidates(state: &SharedAppState, destination: &str) -> Vec<String> {
+    let (pool, cursor, unhealthy) = {
+        let mut app_state = state.write().await;
+        let Some(pool) = app_state.settings.pool(destination).cloned() else {
+            return vec![destination.to_string()];
+        };
+
+        let cursor = app_state.pool_cursors.entry(pool.name.to_lowercase()).or_insert(0);
+        let current = *cursor;
+        *cursor = cursor.wrapping_add(1);
+
+        let unhealthy: HashSet<String> = app_state
+            .printer_health
+            .iter()
+            .filter(|(_, h)| matches!(h.status, HealthStatus::Offline | HealthStatus::Error))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        (pool, current, unhealthy)
+    };
+
+    let queued = if pool.strategy == PoolStrategy::LeastBusy {
+        tokio::task::spawn_blocking(queued_jobs).await.unwrap_or_default()
+    } else {
+        HashMap::new()
+    };
+
+    order_members(&pool, cursor, &queued, &unhealthy)
+}
+
+/// Verificar antes de guardar que ningún grupo oculte una impresora instalada
+pub async fn check_installed_printers(settings: &Settings) -> Result<(), String> {
+    if settings.printer_pools.is_empty() {
+        return Ok(());
+    }
+    // Sin lista de impresoras (CUPS detenido) no se bloquea el guardado
+    let listed = tokio::task::spawn_blocking(printer::list_printers)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|printers| printers);
+    let printers = match listed {
+        Ok(printers) => printers,
+        Err(e) => {
+            log::warn!("Could not list printers to check pool names: {}", e);
+            return Ok(());
+        }
+    };
+    settings.check_pool_names(&printers)
+}
+
+/// Trabajos en cola por impresora
+fn queued_jobs() -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    match printer::get_jobs() {
+        Ok(jobs) => {
+            for job in jobs {
+                *counts.entry(job.printer).or_insert(0) += 1;
+            }
+        }
+        Err(e) => log::warn!("Could not read print queues for pool balancing: {}", e),
+    }
+    counts
+}
+
+/// Imprimir una copia probando los candidatos en orden.
+/// Retorna el índice del que imprimió, el ID del trabajo y los fallos previos.
+/// Si ninguno imprime, el error es reintentable cuando alguno falló por no estar disponible.
+async fn print_once<F>(candidates: &[String], print: &F) -> (Result<(usize, i32), SendError>, Vec<String>)
+where
+    F: Fn(&str) -> Result<i32, SendError> + Clone + Send + 'static,
+{
+    let mut failures = Vec::new();
+    let mut last_error = SendError::permanent("No printers available");
+    let mut transient = false;
+
+    for (index, name) in candidates.iter().enumerate() {
+        let print = print.clone();
+        let target = name.clone();
+        let result = tokio::task::spawn_blocking(move || print(&target))
+            .await
+            .unwrap_or_else(|e| Err(SendError::permanent(format!("Print task failed: {}", e))));
+
+        match result {
+            Ok(job_id) => return (Ok((index, job_id)), failures),
+            Err(e) => {
+                failures.push(format!("Printer {} failed: {}", name, e.message));
+                transient |= e.transient;
+                last_error = e;
+            }
+        }
+    }
+
+    last_error.transient = transient;
+    (Err(last_error), failures)
+}
+
+/// Fallo de impresión tras probar todos los candidatos
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintFailure {
+    /// IDs de trabajo de las copias que sí se imprimieron antes del fallo
+    pub job_ids: Vec<i32>,
+    pub message: String,
+    /// Las impresoras no estaban disponibles: el trabajo puede ir a la cola de reintentos
+    pub transient: bool,
+}
+
+impl PrintFailure {
+    /// Copias impresas antes del fallo
+    pub fn printed(&self) -> u32 {
+        self.job_ids.len() as u32
+    }
+}
+
+/// Imprimir `copies` copias con failover: si un candidato falla se pasa al
+/// siguiente. `PrintFailure::job_ids` lista las copias ya impresas (y usadas de la cuota reservada).
+/// Retorna la impresora que imprimió la última copia y los IDs de trabajo.
+pub async fn print_with_failover<F>(
+    state: &SharedAppState,
+    candidates: &[String],
+    copies: u32,
+    print: F,
+) -> Result<(String, Vec<i32>), PrintFailure>
+where
+    F: Fn(&str) -> Result<i32, SendError> + Clone + Send + 'static,
+{
+    if copies == 0 {
+        return Err(PrintFailure {
+            job_ids: Vec::new(),
+            message: "At least one copy required".to_string(),
+            transient: false,
+        });
+    }
+
+    let mut current = 0;
+    let mut job_ids = Vec::new();
+
+    for _ in 0..copies {
+        let (result, failures) = print_once(&candidates[current..], &print).await;
+        if !failures.is_empty() {
+            let mut app_state = state.write().await;
+            for failure in &failures {
+                app_state.add_log("WARN", failure);
+            }
+        }
+
+        let (offset, job_id) = match result {
+            Ok(printed) => printed,
+            Err(e) => {
+                return Err(PrintFailure {
+                    job_ids,
+                    message: e.message,
+                    transient: e.transient,
+                })
+            }
+        };
+        current += offset;
+        job_ids.push(job_id);
+    }
+
+    Ok((candidates[current].clone(), job_ids))
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppState;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    fn pool(strategy: PoolStrategy) -> PrinterPool {
+        PrinterPool {
+            name: "cocina".to_string(),
+            members: vec!["Cocina-1".to_string(), "Cocina-2".to_string(), "Cocina-3".to_string()],
+            strategy,
+        }
+    }
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_round_robin_rotates() {
+        let pool = pool(PoolStrategy::RoundRobin);
+        let none = HashMap::new();
+        assert_eq!(order_members(&pool, 0, &none, &HashSet::new()), names(&["Cocina-1", "Cocina-2", "Cocina-3"]));
+        assert_eq!(order_members(&pool, 1, &none, &HashSet::new()), names(&["Cocina-2", "Cocina-3", "Cocina-1"]));
+        assert_eq!(order_members(&pool, 5, &none, &HashSet::new()), names(&["Cocina-3", "Cocina-1", "Cocina-2"]));
+    }
+
+    #[test]
+    fn test_least_busy_and_unhealthy_last() {
+        let pool = pool(PoolStrategy::LeastBusy);
+        let queued = HashMap::from([("Cocina-1".to_string(), 4), ("Cocina-2".to_string(), 1)]);
+        assert_eq!(
+            order_members(&pool, 0, &queued, &HashSet::new()),
+            names(&["Cocina-3", "Cocina-2", "Cocina-1"])
+        );
+
+        let unhealthy = HashSet::from(["Cocina-3".to_string()]);
+        assert_eq!(
+            order_members(&pool, 0, &queued, &unhealthy),
+            names(&["Cocina-2", "Cocina-1", "Cocina-3"])
+        );
+    }
+
+    #[test]
+    fn test_pool_validation() {
+        assert!(pool(PoolStrategy::RoundRobin).validate().is_ok());
+        let mut empty = pool(PoolStrategy::RoundRobin);
+        empty.members = vec![" ".to_string()];
+        assert!(empty.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_print_once_moves_to_next_member() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let print = move |name: &str| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            if name == "Caida" {
+                Err(SendError::transient("Printer offline"))
+            } else {
+                Ok(7)
+            }
+        };
+
+        let (result, failures) = print_once(&names(&["Caida", "Cocina-2"]), &print).await;
+        assert_eq!(result.unwrap(), (1, 7));
+        assert_eq!(failures, vec!["Printer Caida failed: Printer offline".to_string()]);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_print_with_failover_all_fail() {
+        let state: SharedAppState = Arc::new(RwLock::new(AppState::default()));
+        let result = print_with_failover(&state, &names(&["A", "B"]), 1, |_: &str| {
+            Err(SendError::permanent("Unsupported document"))
+        })
+        .await;
+        assert_eq!(
+            result.unwrap_err(),
+            PrintFailure {
+                job_ids: vec![],
+                message: "Unsupported document".to_string(),
+                transient: false,
+            }
+        );
+        assert_eq!(state.read().await.logs.len(), 2);
+
+        let result = print_with_failover(&state, &[], 1, |_: &str| Ok(1)).await;
+        assert!(!result.unwrap_err().transient);
+
+        // Cero copias: error, no una copia sin contar en la cuota
+        let result = print_with_failover(&state, &names(&["A"]), 0, |_: &str| Ok(1)).await;
+        assert_eq!(result.unwrap_err().job_ids, Vec::<i32>::new());
+    }
+
+    #[tokio::test]
+    async fn test_failure_is_transient_if_any_member_was_unavailable() {
+        let state: SharedAppState = Arc::new(RwLock::new(AppState::default()));
+        let print = |name: &str| {
+            if name == "A" {
+                Err(SendError::transient("Connection failed"))
+            } else {
+                Err(SendError::permanent("Unsupported document"))
+            }
+        };
+
+        let failure = print_with_failover(&state, &names(&["A", "B"]), 1, print).await.unwrap_err();
+        assert!(failure.transient);
+        assert_eq!(failure.message, "Unsupported document");
+    }
+
+    #[tokio::test]
+    async fn test_print_with_failover_keeps_printed_job_ids() {
+        let state: SharedAppState = Arc::new(RwLock::new(AppState::default()));
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let print = move |_: &str| match counter.fetch_add(1, Ordering::SeqCst) {
+            0 => Ok(41),
+            1 => Ok(42),
+            _ => Err(SendError::transient("Printer offline")),
+        };
+
+        let failure = print_with_failover(&state, &names(&["A"]), 3, print).await.unwrap_err();
+        assert_eq!(failure.job_ids, vec![41, 42]);
+        assert_eq!(failure.printed(), 2);
+    }
+}
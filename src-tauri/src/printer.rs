@@ -96,11 +96,13 @@ pub fn print_file(file_path: &str, printer_name: &str) -> Result<i32, String> {
     Ok(job_id)
 }
 
+/// Imprimir con tamaño de papel y resolución del perfil
 #[cfg(any(target_os = "macos", target_os = "linux"))]
-pub fn print_file_with_media(
+pub fn print_file_with_options(
     file_path: &str,
     printer_name: &str,
     media: Option<&str>,
+    dpi: Option<u32>,
 ) -> Result<i32, String> {
     use std::process::Command;
 
@@ -117,6 +119,9 @@ pub fn print_file_with_media(
         // CUPS option: media=<name>
         cmd.args(["-o", &format!("media={}", media)]);
     }
+    if let Some(dpi) = dpi {
+        cmd.args(["-o", &format!("printer-resolution={}dpi", dpi)]);
+    }
     cmd.arg(file_path);
     
     let output = cmd
@@ -194,6 +199,57 @@ pub fn get_jobs() -> Result<Vec<PrintJob>, String> {
     Ok(jobs)
 }
 
+/// Parsear la salida de `lpstat -v` ("device for NAME: URI")
+pub fn parse_lpstat_devices(stdout: &str) -> Vec<(String, String)> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix("device for ")?;
+            let (name, uri) = rest.split_once(": ")?;
+            Some((name.trim().to_string(), uri.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Listar impresoras con su URI de dispositivo
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+pub fn list_printer_uris() -> Result<Vec<(String, String)>, String> {
+    use std::process::Command;
+
+    // Forzar locale C: el formato de lpstat -v se traduce
+    let output = Command::new("lpstat")
+        .env("LC_ALL", "C")
+        .args(["-v"])
+        .output()
+        .map_err(|e| format!("Error executing lpstat: {}", e))?;
+
+    if !output.status.success() {
+        return Ok(vec![]);
+    }
+
+    Ok(parse_lpstat_devices(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Verificar si la cola está habilitada en CUPS
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+pub fn is_printer_enabled(printer_name: &str) -> Result<bool, String> {
+    use std::process::Command;
+
+    let output = Command::new("lpstat")
+        .env("LC_ALL", "C")
+        .args(["-p", printer_name])
+        .output()
+        .map_err(|e| format!("Error executing lpstat: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Error getting printer status: {}", stderr));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(!stdout.contains("disabled"))
+}
+
 #[cfg(any(target_os = "macos", target_os = "linux"))]
 pub fn clear_jobs() -> Result<(), String> {
     use std::process::Command;
@@ -260,6 +316,18 @@ pub fn print_file(file_path: &str, printer_name: &str) -> Result<i32, String> {
     Ok(0) // Windows doesn't easily return job ID
 }
 
+/// El comando `print` de Windows no recibe opciones: papel y resolución son los del
+/// controlador de la impresora
+#[cfg(target_os = "windows")]
+pub fn print_file_with_options(
+    file_path: &str,
+    printer_name: &str,
+    _media: Option<&str>,
+    _dpi: Option<u32>,
+) -> Result<i32, String> {
+    print_file(file_path, printer_name)
+}
+
 #[cfg(target_os = "windows")]
 pub fn send_cut(printer_name: &str) -> Result<(), String> {
     let mut temp_file = NamedTempFile::new()
@@ -310,6 +378,76 @@ pub fn get_jobs() -> Result<Vec<PrintJob>, String> {
     Ok(jobs)
 }
 
+/// Listar impresoras con un URI socket:// construido desde su puerto TCP/IP
+#[cfg(target_os = "windows")]
+pub fn list_printer_uris() -> Result<Vec<(String, String)>, String> {
+    use std::process::Command;
+
+    let output = Command::new("powershell")
+        .args([
+            "-Command",
+            "Get-Printer | ForEach-Object { $p = Get-PrinterPort -Name $_.PortName -ErrorAction SilentlyContinue; \"$($_.Name)|$($p.PrinterHostAddress)|$($p.PortNumber)\" }",
+        ])
+        .output()
+        .map_err(|e| format!("Error executing powershell: {}", e))?;
+
+    if !output.status.success() {
+        return Ok(vec![]);
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let printers = stdout
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.trim().split('|');
+            let name = parts.next()?.trim().to_string();
+            if name.is_empty() {
+                return None;
+            }
+            let host = parts.next().unwrap_or("").trim();
+            let port = parts
+                .next()
+                .and_then(|p| p.trim().parse::<u16>().ok())
+                .filter(|p| *p > 0)
+                .unwrap_or(9100);
+            let uri = if host.is_empty() {
+                String::new()
+            } else {
+                format!("socket://{}:{}", host, port)
+            };
+            Some((name, uri))
+        })
+        .collect();
+
+    Ok(printers)
+}
+
+/// Verificar que la impresora no esté pausada, fuera de línea o con error
+#[cfg(target_os = "windows")]
+pub fn is_printer_enabled(printer_name: &str) -> Result<bool, String> {
+    use std::process::Command;
+
+    let output = Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            &format!(
+                "(Get-Printer -Name '{}' -ErrorAction Stop).PrinterStatus",
+                printer_name.replace('\'', "''")
+            ),
+        ])
+        .output()
+        .map_err(|e| format!("Error executing Get-Printer: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Error getting printer status: {}", stderr));
+    }
+
+    let status = String::from_utf8_lossy(&output.stdout).trim().to_lowercase();
+    Ok(!matches!(status.as_str(), "paused" | "offline" | "error"))
+}
+
 #[cfg(target_os = "windows")]
 pub fn clear_jobs() -> Result<(), String> {
     use std::process::Command;
@@ -332,6 +470,208 @@ pub fn clear_jobs() -> Result<(), String> {
     Ok(())
 }
 
+/// Extraer el destino de `lpstat -d` ("system default destination: NAME")
+pub fn parse_lpstat_default(stdout: &str) -> Option<String> {
+    stdout.lines().find_map(|line| {
+        let name = line.trim().strip_prefix("system default destination:")?.trim();
+        (!name.is_empty()).then(|| name.to_string())
+    })
+}
+
+/// Impresora por defecto del sistema (`lpstat -d` consulta CUPS-Get-Default)
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+pub fn system_default_printer() -> Option<String> {
+    use std::process::Command;
+
+    // lp da prioridad a LPDEST y PRINTER sobre el destino de CUPS
+    for var in ["LPDEST", "PRINTER"] {
+        if let Some(p) = std::env::var(var).ok().filter(|p| !p.trim().is_empty()) {
+            return Some(p.trim().to_string());
+        }
+    }
+
+    // Forzar locale C: el texto de lpstat -d se traduce
+    let output = Command::new("lpstat")
+        .env("LC_ALL", "C")
+        .args(["-d"])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    parse_lpstat_default(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Impresora por defecto del sistema (spooler de Windows)
+#[cfg(target_os = "windows")]
+pub fn system_default_printer() -> Option<String> {
+    use std::process::Command;
+
+    let output = Command::new("wmic")
+        .args(["printer", "where", "Default=TRUE", "get", "Name"])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .skip(1) // Skip header
+        .map(|s| s.trim().to_string())
+        .find(|s| !s.is_empty())
+}
+
+/// Origen de la impresora elegida
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrinterSource {
+    /// Indicada en la petición
+    Requested,
+    /// Por defecto de ISIPRINT (settings.json, por tipo de documento)
+    Settings,
+    /// Por defecto de la configuración de licencia
+    License,
+    /// Por defecto del sistema operativo
+    System,
+    /// Sin ninguna por defecto: la primera instalada
+    FirstAvailable,
+}
+
+/// Impresora por defecto efectiva
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DefaultPrinter {
+    pub name: String,
+    pub source: PrinterSource,
+}
+
+/// Impresoras por defecto configuradas en ISIPRINT
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrinterDefaults {
+    pub settings: Option<String>,
+    pub license: Option<String>,
+}
+
+/// Elegir impresora: solicitada > settings > licencia > sistema > primera instalada.
+/// `system` y `installed` solo se consultan si no hay ninguna configurada.
+pub fn choose_printer(
+    requested: Option<String>,
+    defaults: &PrinterDefaults,
+    system: impl FnOnce() -> Option<String>,
+    installed: impl FnOnce() -> Vec<String>,
+) -> Option<(String, PrinterSource)> {
+    let configured = [
+        (requested, PrinterSource::Requested),
+        (defaults.settings.clone(), PrinterSource::Settings),
+        (defaults.license.clone(), PrinterSource::License),
+    ];
+    for (printer, source) in configured {
+        if let Some(p) = printer.filter(|p| !p.is_empty()) {
+            return Some((p, source));
+        }
+    }
+
+    if let Some(p) = system().filter(|p| !p.is_empty()) {
+        return Some((p, PrinterSource::System));
+    }
+    installed()
+        .into_iter()
+        .next()
+        .map(|p| (p, PrinterSource::FirstAvailable))
+}
+
+/// Impresora a usar y de dónde sale
+pub fn resolve_printer_with_source(
+    requested: Option<String>,
+    defaults: &PrinterDefaults,
+) -> Option<(String, PrinterSource)> {
+    choose_printer(requested, defaults, system_default_printer, || {
+        list_printers().unwrap_or_default()
+    })
+}
+
+/// Impresora a usar: la solicitada, la por defecto de ISIPRINT o la del sistema
+pub fn resolve_printer(requested: Option<String>, defaults: &PrinterDefaults) -> Option<String> {
+    resolve_printer_with_source(requested, defaults).map(|(p, _)| p)
+}
+
+/// Host y puerto de una impresora socket:// (AppSocket/JetDirect)
+pub fn socket_address(uri: &str) -> Option<(String, u16)> {
+    let parsed = url::Url::parse(uri).ok()?;
+    if parsed.scheme() != "socket" {
+        return None;
+    }
+    let host = parsed
+        .host_str()?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    Some((host, parsed.port().unwrap_or(9100)))
+}
+
+/// Error al enviar un trabajo a una impresora
+#[derive(Debug, Clone, PartialEq)]
+pub struct SendError {
+    pub message: String,
+    /// La impresora no está disponible ahora (apagada, sin red, cola detenida):
+    /// reintentar más tarde puede funcionar. Si no, el trabajo o el destino no sirven.
+    pub transient: bool,
+}
+
+impl SendError {
+    pub fn transient(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            transient: true,
+        }
+    }
+
+    pub fn permanent(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            transient: false,
+        }
+    }
+}
+
+/// Clasificar un fallo de la cola del sistema por su estado: reintentable si la
+/// cola existe pero está detenida o fuera de línea; si no existe o está habilitada
+/// y aun así rechazó el trabajo, reintentar no cambia nada.
+pub fn queue_error(printer_name: &str, message: String) -> SendError {
+    match is_printer_enabled(printer_name) {
+        Ok(false) => SendError::transient(message),
+        _ => SendError::permanent(message),
+    }
+}
+
+/// Imprimir contenido RAW (ESC/POS): directo por TCP a las impresoras socket://,
+/// sin los filtros de la cola; las demás colas lo reciben con `print_file`.
+/// El envío directo no tiene ID de trabajo (0).
+pub fn print_raw_file(file_path: &str, printer_name: &str) -> Result<i32, SendError> {
+    let address = list_printer_uris()
+        .unwrap_or_default()
+        .into_iter()
+        .find(|(name, _)| name == printer_name)
+        .and_then(|(_, uri)| socket_address(&uri));
+
+    match address {
+        Some((host, port)) => {
+            if !std::path::Path::new(file_path).exists() {
+                return Err(SendError::permanent(format!("File {} does not exist", file_path)));
+            }
+            // Sin conexión con la impresora: apagada o fuera de la red
+            crate::raw_printer::RawPrinter::new(&host, port)
+                .print_file(file_path)
+                .map_err(SendError::transient)?;
+            Ok(0)
+        }
+        None => print_file(file_path, printer_name).map_err(|e| queue_error(printer_name, e)),
+    }
+}
+
 /// Download PDF from URL
 pub async fn download_pdf(url: &str) -> Result<Vec<u8>, String> {
     let client = reqwest::Client::new();
@@ -440,6 +780,70 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_lpstat_default() {
+        assert_eq!(
+            parse_lpstat_default("system default destination: Caja\n"),
+            Some("Caja".to_string())
+        );
+        assert_eq!(parse_lpstat_default("no system default destination\n"), None);
+    }
+
+    #[test]
+    fn test_choose_printer_precedence() {
+        let installed = || vec!["Primera".to_string(), "Segunda".to_string()];
+        let system = || Some("Sistema".to_string());
+        let mut defaults = PrinterDefaults {
+            settings: Some("Local".to_string()),
+            license: Some("Licencia".to_string()),
+        };
+
+        assert_eq!(
+            choose_printer(Some("Pedida".to_string()), &defaults, system, installed),
+            Some(("Pedida".to_string(), PrinterSource::Requested))
+        );
+        assert_eq!(
+            choose_printer(Some(String::new()), &defaults, system, installed),
+            Some(("Local".to_string(), PrinterSource::Settings))
+        );
+
+        defaults.settings = None;
+        assert_eq!(
+            choose_printer(None, &defaults, system, installed),
+            Some(("Licencia".to_string(), PrinterSource::License))
+        );
+
+        defaults.license = None;
+        assert_eq!(
+            choose_printer(None, &defaults, system, installed),
+            Some(("Sistema".to_string(), PrinterSource::System))
+        );
+        assert_eq!(
+            choose_printer(None, &defaults, || None, installed),
+            Some(("Primera".to_string(), PrinterSource::FirstAvailable))
+        );
+        assert_eq!(choose_printer(None, &defaults, || None, Vec::new), None);
+    }
+
+    #[test]
+    fn test_parse_lpstat_devices() {
+        let stdout = "device for Caja: socket://192.168.1.20:9100\n\
+                      device for Oficina: ipp://192.168.1.30/ipp/print\n\
+                      garbage line\n";
+        let devices = parse_lpstat_devices(stdout);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0], ("Caja".to_string(), "socket://192.168.1.20:9100".to_string()));
+        assert_eq!(devices[1].1, "ipp://192.168.1.30/ipp/print");
+    }
+
+    #[test]
+    fn test_socket_address() {
+        assert_eq!(socket_address("socket://192.168.1.20:9100"), Some(("192.168.1.20".to_string(), 9100)));
+        assert_eq!(socket_address("socket://10.0.0.5"), Some(("10.0.0.5".to_string(), 9100)));
+        assert_eq!(socket_address("ipp://192.168.1.30/ipp/print"), None);
+        assert_eq!(socket_address(""), None);
+    }
+
     #[tokio::test]
     async fn test_download_pdf_invalid_url() {
         // Invalid URL
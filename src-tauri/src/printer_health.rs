@@ -0,0 +1,266 @@
+// Monitor de salud de impresoras configuradas (se ejecuta en segundo plano)
+use crate::ipp;
+use crate::printer;
+use crate::raw_printer::{EscPosStatus, RawPrinter};
+use crate::state::HealthStatus;
+use crate::SharedAppState;
+use serde::Serialize;
+use tauri::image::Image;
+use tauri::{AppHandle, Emitter};
+use tokio::time::{sleep, Duration};
+
+/// Intervalo entre chequeos
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Evento emitido al frontend en cada transición
+pub const HEALTH_EVENT: &str = "printer-health-changed";
+
+/// Payload del evento de transición
+#[derive(Debug, Clone, Serialize)]
+pub struct PrinterHealthEvent {
+    pub printer: String,
+    pub previous: HealthStatus,
+    pub status: HealthStatus,
+    pub message: Option<String>,
+}
+
+/// Resultado de sondear una impresora
+type ProbeResult = (HealthStatus, Option<String>);
+
+/// Extraer host y puerto de un URI (socket://, lpd://, ipp://)
+fn uri_host_port(uri: &str, default_port: u16) -> Option<(String, u16)> {
+    let parsed = url::Url::parse(uri).ok()?;
+    let host = parsed
+        .host_str()?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    Some((host, parsed.port().unwrap_or(default_port)))
+}
+
+/// Impresora raw ESC/POS: conexión TCP y consulta DLE EOT
+fn probe_escpos(host: &str, port: u16) -> ProbeResult {
+    match RawPrinter::new(host, port).query_status() {
+        Ok(EscPosStatus::Ready) | Ok(EscPosStatus::NoResponse) => (HealthStatus::Online, None),
+        Ok(EscPosStatus::Problem(msg)) => (HealthStatus::Error, Some(msg)),
+        Err(e) => (HealthStatus::Offline, Some(e)),
+    }
+}
+
+/// Impresora LPD o raw sin perfil ESC/POS: solo conexión TCP (las PCL/PostScript
+/// imprimirían la consulta de estado)
+fn probe_tcp(host: &str, port: u16) -> ProbeResult {
+    use std::net::{TcpStream, ToSocketAddrs};
+
+    let addr = match (host, port).to_socket_addrs().ok().and_then(|mut a| a.next()) {
+        Some(a) => a,
+        None => return (HealthStatus::Offline, Some(format!("Could not resolve {}", host))),
+    };
+    match TcpStream::connect_timeout(&addr, std::time::Duration::from_secs(3)) {
+        Ok(_) => (HealthStatus::Online, None),
+        Err(e) => (HealthStatus::Offline, Some(format!("Connection failed: {}", e))),
+    }
+}
+
+/// Impresora IPP: consultar printer-state
+async fn probe_ipp(uri: &str) -> ProbeResult {
+    match ipp::get_printer_attributes(uri, Duration::from_secs(5)).await {
+        Ok(attrs) => match attrs.state {
+            ipp::PrinterState::Stopped => (
+                HealthStatus::Error,
+                Some(attrs.state_message.unwrap_or_else(|| "Printer stopped".to_string())),
+            ),
+            _ => (HealthStatus::Online, attrs.state_message),
+        },
+        Err(e) => (HealthStatus::Offline, Some(e)),
+    }
+}
+
+/// Cola local (USB, drivers, etc.): estado de la cola en el sistema
+fn probe_queue(printer_name: &str) -> ProbeResult {
+    match printer::is_printer_enabled(printer_name) {
+        Ok(true) => (HealthStatus::Online, None),
+        Ok(false) => (HealthStatus::Error, Some("Queue disabled".to_string())),
+        Err(e) => (HealthStatus::Unknown, Some(e)),
+    }
+}
+
+/// Sondear una impresora según el esquema de su URI de dispositivo
+async fn probe_printer(name: String, uri: String, escpos: bool) -> ProbeResult {
+    let scheme = uri.split("://").next().unwrap_or("").to_lowercase();
+
+    match scheme.as_str() {
+        "ipp" | "ipps" | "http" | "https" => probe_ipp(&uri).await,
+        "socket" | "lpd" => {
+            let default_port = if scheme == "lpd" { 515 } else { 9100 };
+            let (host, port) = match uri_host_port(&uri, default_port) {
+                Some(hp) => hp,
+                None => return (HealthStatus::Unknown, Some(format!("Invalid URI: {}", uri))),
+            };
+            tokio::task::spawn_blocking(move || {
+                if scheme == "socket" && escpos {
+                    probe_escpos(&host, port)
+                } else {
+                    probe_tcp(&host, port)
+                }
+            })
+            .await
+            .unwrap_or((HealthStatus::Unknown, None))
+        }
+        _ => tokio::task::spawn_blocking(move || probe_queue(&name))
+            .await
+            .unwrap_or((HealthStatus::Unknown, None)),
+    }
+}
+
+/// Icono del tray atenuado y teñido de rojo para indicar impresora offline
+fn offline_icon(icon: &Image<'_>) -> Image<'static> {
+    let rgba: Vec<u8> = icon
+        .rgba()
+        .chunks(4)
+        .flat_map(|px| {
+            let gray = ((px[0] as u16 + px[1] as u16 + px[2] as u16) / 3) as u8;
+            [gray.saturating_add(90), gray / 2, gray / 2, px[3]]
+        })
+        .collect();
+    Image::new_owned(rgba, icon.width(), icon.height())
+}
+
+/// Actualizar tooltip e icono del tray según el estado de la impresora de recibos
+fn update_tray(app: &AppHandle, receipt_printer: &str, status: HealthStatus, message: Option<&str>) {
+    let Some(tray) = app.tray_by_id(crate::TRAY_ID) else {
+        return;
+    };
+
+    let icon = match Image::from_bytes(crate::TRAY_ICON) {
+        Ok(i) => i,
+        Err(e) => {
+            log::warn!("Error loading tray icon: {}", e);
+            return;
+        }
+    };
+
+    let (tooltip, icon) = match status {
+        HealthStatus::Offline | HealthStatus::Error => (
+            format!(
+                "ISIPRINT - {} offline{}",
+                receipt_printer,
+                message.map(|m| format!(": {}", m)).unwrap_or_default()
+            ),
+            offline_icon(&icon),
+        ),
+        _ => ("ISIPRINT".to_string(), icon),
+    };
+
+    let _ = tray.set_tooltip(Some(&tooltip));
+    let _ = tray.set_icon(Some(icon));
+}
+
+/// Ejecutar un ciclo de chequeo sobre todas las impresoras configuradas
+async fn check_all(app: &AppHandle, state: &SharedAppState) {
+    let printers = match tokio::task::spawn_blocking(printer::list_printer_uris).await {
+        Ok(Ok(p)) => p,
+        Ok(Err(e)) => {
+            log::warn!("Health monitor could not list printers: {}", e);
+            return;
+        }
+        Err(_) => return,
+    };
+
+    // Impresoras eliminadas del sistema
+    let names: Vec<String> = printers.iter().map(|(name, _)| name.clone()).collect();
+    state.write().await.prune_printer_health(&names);
+
+    // Impresora de recibos: la por defecto de ISIPRINT o del sistema
+    let (defaults, settings) = {
+        let app_state = state.read().await;
+        (app_state.printer_defaults(None), app_state.settings.clone())
+    };
+    let receipt_printer = tokio::task::spawn_blocking(move || printer::resolve_printer(None, &defaults))
+        .await
+        .ok()
+        .flatten();
+
+    for (name, uri) in printers {
+        let escpos = settings.is_escpos_printer(&name);
+        let (status, message) = probe_printer(name.clone(), uri, escpos).await;
+
+        let previous = {
+            let mut app_state = state.write().await;
+            let previous = app_state.record_printer_health(&name, status, message.clone());
+            if let Some(prev) = previous {
+                // La primera medición (Unknown → X) no se registra como incidente
+                if prev != HealthStatus::Unknown || status != HealthStatus::Online {
+                    let level = if status == HealthStatus::Online { "INFO" } else { "WARN" };
+                    app_state.add_log(
+                        level,
+                        &format!("Printer {} is now {}", name, status.as_str()),
+                    );
+                }
+            }
+            previous
+        };
+
+        if let Some(previous) = previous {
+            let _ = app.emit(
+                HEALTH_EVENT,
+                PrinterHealthEvent {
+                    printer: name.clone(),
+                    previous,
+                    status,
+                    message: message.clone(),
+                },
+            );
+
+            if receipt_printer.as_deref() == Some(name.as_str()) {
+                update_tray(app, &name, status, message.as_deref());
+            }
+        }
+    }
+}
+
+/// Bucle del monitor: chequea periódicamente todas las impresoras
+pub async fn run(app: AppHandle, state: SharedAppState) {
+    log::info!("Printer health monitor started");
+    loop {
+        check_all(&app, &state).await;
+        sleep(CHECK_INTERVAL).await;
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uri_host_port() {
+        assert_eq!(
+            uri_host_port("socket://192.168.1.20:9100", 9100),
+            Some(("192.168.1.20".to_string(), 9100))
+        );
+        assert_eq!(
+            uri_host_port("lpd://10.0.0.3/queue", 515),
+            Some(("10.0.0.3".to_string(), 515))
+        );
+        assert!(uri_host_port("not a uri", 9100).is_none());
+    }
+
+    #[test]
+    fn test_probe_tcp_unreachable() {
+        let (status, message) = probe_tcp("127.0.0.1", 1);
+        assert_eq!(status, HealthStatus::Offline);
+        assert!(message.is_some());
+    }
+
+    #[test]
+    fn test_offline_icon_keeps_size_and_alpha() {
+        let icon = Image::new_owned(vec![200, 200, 200, 255, 0, 0, 0, 0], 2, 1);
+        let offline = offline_icon(&icon);
+        assert_eq!(offline.width(), 2);
+        assert_eq!(offline.height(), 1);
+        assert_eq!(offline.rgba()[3], 255);
+        assert_eq!(offline.rgba()[7], 0);
+    }
+}
@@ -0,0 +1,268 @@
+// Cuota de impresiones por período de licencia, persistida y sincronizada con el backend
+use crate::auth;
+use crate::graphql::{self, GraphQLResult};
+use crate::persistence;
+use crate::state::{AppState, LicenciaProducto};
+use crate::SharedAppState;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Duration};
+
+/// Intervalo de sincronización en segundo plano (reintenta lo que quedó pendiente)
+const SYNC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Evita reportar dos veces las mismas impresiones
+static SYNC_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Impresiones consumidas en el período actual de una licencia
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuotaUsage {
+    pub license_id: String,
+    /// Vencimiento de la licencia: una renovación inicia un período nuevo
+    pub period_end: String,
+    /// Impresiones del período (confirmadas + pendientes)
+    pub used: i32,
+    /// Parte de `used` ya confirmada por el backend
+    pub reported: i32,
+}
+
+impl QuotaUsage {
+    /// Contador vacío para el período actual de una licencia
+    pub fn for_license(licencia: &LicenciaProducto) -> Self {
+        Self {
+            license_id: licencia._id.clone(),
+            period_end: licencia.fecha_vencimiento.clone(),
+            used: 0,
+            reported: 0,
+        }
+    }
+
+    /// Verificar si el contador corresponde al período actual de la licencia
+    pub fn is_period_of(&self, licencia: &LicenciaProducto) -> bool {
+        self.license_id == licencia._id && self.period_end == licencia.fecha_vencimiento
+    }
+
+    /// Impresiones aún no reportadas al backend
+    pub fn pending(&self) -> i32 {
+        (self.used - self.reported).max(0)
+    }
+
+    /// Aplicar el consumo total devuelto por el backend tras reportar `sent` impresiones.
+    /// Las impresiones hechas mientras la petición estaba en curso siguen pendientes.
+    pub fn apply_server_usage(&mut self, sent: i32, server_used: i32) {
+        let unsent = (self.used - self.reported - sent).max(0);
+        self.reported = server_used;
+        self.used = server_used + unsent;
+    }
+}
+
+/// Estado de la cuota expuesto al frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct QuotaStatus {
+    pub license_id: String,
+    pub period_end: String,
+    pub used: i32,
+    pub limit: i32,
+    pub remaining: i32,
+    pub pending_sync: i32,
+}
+
+/// Iniciar un período nuevo si las licencias cambiaron (login, renovación)
+pub fn align_period(app_state: &mut AppState) {
+    if app_state.sync_quota_period() {
+        log::info!(
+            "Starting print quota period for license {} (until {})",
+            app_state.quota.license_id,
+            app_state.quota.period_end
+        );
+        save(app_state);
+    }
+}
+
+fn save(app_state: &AppState) {
+    if let Err(e) = persistence::save_quota(&app_state.quota) {
+        log::warn!("Failed to save print quota: {}", e);
+    }
+}
+
+/// Reservar `count` impresiones antes de enviarlas (verificación y registro
+/// bajo el mismo lock). Se confirman o devuelven con `settle`.
+pub async fn reserve(state: &SharedAppState, count: u32) -> Result<(), String> {
+    let mut app_state = state.write().await;
+    app_state.reserve_prints(count)?;
+    save(&app_state);
+    Ok(())
+}
+
+/// Cerrar una reserva: devolver las impresiones que no se hicieron y reportar las hechas
+pub async fn settle(state: &SharedAppState, reserved: u32, printed: u32) {
+    if printed < reserved {
+        let mut app_state = state.write().await;
+        app_state.release_prints(reserved - printed);
+        save(&app_state);
+    }
+    if printed == 0 {
+        return;
+    }
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(e) = sync_usage(&state).await {
+            log::info!("Print usage not reported yet: {}", e);
+        }
+    });
+}
+
+/// Adoptar el consumo del período registrado en el backend (login y re-verificación):
+/// borrar quota.json no devuelve impresiones ya reportadas
+pub async fn pull_usage(state: &SharedAppState) -> GraphQLResult<()> {
+    let _guard = SYNC_LOCK.lock().await;
+
+    let (api, quota) = {
+        let app_state = state.read().await;
+        if !app_state.auth.is_logged_in || app_state.quota.license_id.is_empty() {
+            return Ok(());
+        }
+        (app_state.api.clone(), app_state.quota.clone())
+    };
+
+    let usage = auth::with_token_refresh(state, |token| {
+        let api = api.clone();
+        let license_id = quota.license_id.clone();
+        async move { graphql::get_print_usage(&api, &token, &license_id).await }
+    })
+    .await?;
+
+    let mut app_state = state.write().await;
+    if app_state.quota.license_id != quota.license_id || app_state.quota.period_end != quota.period_end {
+        return Ok(());
+    }
+    if usage.usadas != app_state.quota.reported {
+        log::info!(
+            "Print usage from server: {} of {} (local count had {} reported)",
+            usage.usadas,
+            usage.maximo_conexiones,
+            app_state.quota.reported
+        );
+    }
+    app_state.quota.apply_server_usage(0, usage.usadas);
+    save(&app_state);
+    Ok(())
+}
+
+/// Reportar las impresiones pendientes y adoptar el consumo total del backend
+pub async fn sync_usage(state: &SharedAppState) -> GraphQLResult<()> {
+    let _guard = SYNC_LOCK.lock().await;
+
+    let (api, quota) = {
+        let app_state = state.read().await;
+        if !app_state.auth.is_logged_in {
+            return Ok(());
+        }
+        (app_state.api.clone(), app_state.quota.clone())
+    };
+
+    let pending = quota.pending();
+    if pending == 0 || quota.license_id.is_empty() {
+        return Ok(());
+    }
+
+    let usage = auth::with_token_refresh(state, |token| {
+        let api = api.clone();
+        let license_id = quota.license_id.clone();
+        async move { graphql::report_print_usage(&api, &token, &license_id, pending).await }
+    })
+    .await?;
+
+    let mut app_state = state.write().await;
+
+    // El período cambió mientras se reportaba: el contador nuevo no se toca
+    if app_state.quota.license_id != quota.license_id || app_state.quota.period_end != quota.period_end {
+        return Ok(());
+    }
+
+    app_state.quota.apply_server_usage(pending, usage.usadas);
+    save(&app_state);
+
+    log::info!(
+        "Reported {} prints, {} of {} used in the current period",
+        pending,
+        usage.usadas,
+        usage.maximo_conexiones
+    );
+    Ok(())
+}
+
+/// Tarea de fondo: reintentar el reporte de impresiones pendientes
+pub async fn run_sync_loop(state: SharedAppState) {
+    loop {
+        sleep(SYNC_INTERVAL).await;
+
+        if let Err(e) = sync_usage(&state).await {
+            if !e.is_unreachable() {
+                log::warn!("Print usage sync failed: {}", e);
+            }
+        }
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_license(id: &str, fecha_vencimiento: &str) -> LicenciaProducto {
+        LicenciaProducto {
+            _id: id.to_string(),
+            tipo_producto: "IMPRESION".to_string(),
+            maximo_conexiones: 100,
+            fecha_vencimiento: fecha_vencimiento.to_string(),
+            delegado: false,
+            configuracion: None,
+            state: "ACTIVADO".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_period_changes_on_renewal() {
+        let licencia = test_license("lic1", "01/01/2030 00:00:00");
+        let quota = QuotaUsage::for_license(&licencia);
+        assert!(quota.is_period_of(&licencia));
+
+        let renewed = test_license("lic1", "01/01/2031 00:00:00");
+        assert!(!quota.is_period_of(&renewed));
+        assert!(!quota.is_period_of(&test_license("lic2", "01/01/2030 00:00:00")));
+    }
+
+    #[test]
+    fn test_apply_server_usage() {
+        let mut quota = QuotaUsage {
+            license_id: "lic1".to_string(),
+            period_end: "01/01/2030 00:00:00".to_string(),
+            used: 5,
+            reported: 2,
+        };
+        assert_eq!(quota.pending(), 3);
+
+        // Se reportaron 3; mientras tanto se imprimió 1 más. Otras instalaciones llevan 40.
+        quota.used += 1;
+        quota.apply_server_usage(3, 45);
+        assert_eq!(quota.reported, 45);
+        assert_eq!(quota.used, 46);
+        assert_eq!(quota.pending(), 1);
+    }
+
+    #[test]
+    fn test_quota_usage_serialization_roundtrip() {
+        let quota = QuotaUsage {
+            license_id: "lic1".to_string(),
+            period_end: "01/01/2030 00:00:00".to_string(),
+            used: 10,
+            reported: 8,
+        };
+        let json = serde_json::to_string(&quota).unwrap();
+        let parsed: QuotaUsage = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, quota);
+    }
+}
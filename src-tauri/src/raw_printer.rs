@@ -7,6 +7,8 @@ use std::io::Read;
 /// Constantes ESC/POS
 const ESC: u8 = 0x1B;
 const GS: u8 = 0x1D;
+const DLE: u8 = 0x10;
+const EOT: u8 = 0x04;
 
 /// Inicializar impresora
 pub const INIT: &[u8] = &[ESC, b'@'];
@@ -23,6 +25,72 @@ pub const ALIGN_LEFT: &[u8] = &[ESC, b'a', 0];
 /// Alineación Derecha
 pub const ALIGN_RIGHT: &[u8] = &[ESC, b'a', 2];
 
+/// Tablas de caracteres ESC/POS (ESC t n) por página de códigos
+const CODE_PAGES: &[(u16, u8)] = &[
+    (437, 0),
+    (850, 2),
+    (860, 3),
+    (863, 4),
+    (865, 5),
+    (1252, 16),
+    (866, 17),
+    (852, 18),
+    (858, 19),
+];
+
+/// Comando ESC t n para una página de códigos ("CP850", "PC850" o "850")
+pub fn code_page_command(name: &str) -> Option<[u8; 3]> {
+    let number: u16 = name
+        .trim()
+        .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+        .parse()
+        .ok()?;
+    CODE_PAGES
+        .iter()
+        .find(|(page, _)| *page == number)
+        .map(|(_, table)| [ESC, b't', *table])
+}
+
+/// Seleccionar la página de códigos antes del contenido (tras ESC @, que la restablece)
+pub fn with_code_page(data: &[u8], command: &[u8]) -> Vec<u8> {
+    let start = if data.starts_with(INIT) { INIT.len() } else { 0 };
+    let mut out = Vec::with_capacity(data.len() + command.len());
+    out.extend_from_slice(&data[..start]);
+    out.extend_from_slice(command);
+    out.extend_from_slice(&data[start..]);
+    out
+}
+
+/// Estado en tiempo real (DLE EOT n)
+pub const STATUS_PRINTER: &[u8] = &[DLE, EOT, 1];
+pub const STATUS_PAPER: &[u8] = &[DLE, EOT, 4];
+
+/// Estado reportado por una impresora ESC/POS
+#[derive(Debug, Clone, PartialEq)]
+pub enum EscPosStatus {
+    /// La impresora respondió y está lista
+    Ready,
+    /// La impresora respondió con un problema (offline, tapa abierta, sin papel)
+    Problem(String),
+    /// Acepta conexiones pero no respondió a DLE EOT (no es ESC/POS)
+    NoResponse,
+}
+
+/// Interpretar las respuestas de DLE EOT 1 y DLE EOT 4
+pub fn parse_escpos_status(printer_status: u8, paper_status: Option<u8>) -> EscPosStatus {
+    // Bit 3 de DLE EOT 1: impresora offline
+    if printer_status & 0x08 != 0 {
+        return EscPosStatus::Problem("Printer offline".to_string());
+    }
+    // Bits 5-6 de DLE EOT 4: sin papel
+    if let Some(paper) = paper_status {
+        if paper & 0x60 == 0x60 {
+            return EscPosStatus::Problem("Paper end".to_string());
+        }
+    }
+    EscPosStatus::Ready
+}
+
 /// Estructura para conexión directa a impresora
 pub struct RawPrinter {
     address: String,
@@ -58,6 +126,34 @@ impl RawPrinter {
         Ok(())
     }
 
+    /// Consultar el estado en tiempo real con DLE EOT.
+    /// Falla solo si no se puede conectar.
+    pub fn query_status(&self) -> Result<EscPosStatus, String> {
+        let addr = self.address.to_socket_addrs()
+            .map_err(|e| format!("Invalid address: {}", e))?
+            .next()
+            .ok_or("Could not resolve address")?;
+
+        let mut stream = TcpStream::connect_timeout(&addr, self.timeout)
+            .map_err(|e| format!("Connection failed: {}", e))?;
+        let _ = stream.set_read_timeout(Some(Duration::from_millis(1500)));
+        let _ = stream.set_write_timeout(Some(self.timeout));
+
+        let mut byte = [0u8; 1];
+        if stream.write_all(STATUS_PRINTER).is_err() || stream.read_exact(&mut byte).is_err() {
+            return Ok(EscPosStatus::NoResponse);
+        }
+        let printer_status = byte[0];
+
+        let paper_status = if stream.write_all(STATUS_PAPER).is_ok() && stream.read_exact(&mut byte).is_ok() {
+            Some(byte[0])
+        } else {
+            None
+        };
+
+        Ok(parse_escpos_status(printer_status, paper_status))
+    }
+
     /// Imprimir archivo local raw (enviar bytes tal cual)
     pub fn print_file(&self, path: &str) -> Result<(), String> {
         let mut file = File::open(path)
@@ -128,3 +224,49 @@ impl RawPrinter {
         self.print_bytes(&buffer)
     }
 }
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_commands() {
+        assert_eq!(STATUS_PRINTER, &[0x10, 0x04, 0x01]);
+        assert_eq!(STATUS_PAPER, &[0x10, 0x04, 0x04]);
+    }
+
+    #[test]
+    fn test_parse_escpos_status() {
+        // 0x12: bits fijos del protocolo, sin errores
+        assert_eq!(parse_escpos_status(0x12, Some(0x12)), EscPosStatus::Ready);
+        assert_eq!(
+            parse_escpos_status(0x1A, None),
+            EscPosStatus::Problem("Printer offline".to_string())
+        );
+        assert_eq!(
+            parse_escpos_status(0x12, Some(0x72)),
+            EscPosStatus::Problem("Paper end".to_string())
+        );
+    }
+
+    #[test]
+    fn test_code_page_command() {
+        assert_eq!(code_page_command("CP850"), Some([0x1B, b't', 2]));
+        assert_eq!(code_page_command("1252"), Some([0x1B, b't', 16]));
+        assert_eq!(code_page_command("CP999"), None);
+        assert_eq!(code_page_command("utf-8"), None);
+
+        let cmd = code_page_command("PC858").unwrap();
+        assert_eq!(with_code_page(b"\x1B@Hola", &cmd), b"\x1B@\x1Bt\x13Hola");
+        assert_eq!(with_code_page(b"Hola", &cmd), b"\x1Bt\x13Hola");
+    }
+
+    #[test]
+    fn test_query_status_unreachable() {
+        // Puerto cerrado en localhost: debe fallar la conexión
+        let printer = RawPrinter::new("127.0.0.1", 1);
+        assert!(printer.query_status().is_err());
+    }
+}
@@ -0,0 +1,292 @@
+// Trabajos de impresión remotos: se consultan al backend, se imprimen y se confirman
+use crate::auth;
+use crate::device;
+use crate::graphql::{self, GraphQLResult, RemoteJobStatus, RemotePrintJob};
+use crate::history::JobSource;
+use crate::pipeline::{self, Content, Job};
+use crate::printer;
+use crate::spool::SpoolFormat;
+use crate::SharedAppState;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::collections::{HashMap, HashSet, VecDeque};
+use tokio::time::{sleep, Duration};
+
+/// Intervalo entre consultas de trabajos pendientes
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Espera cuando el backend no responde
+const OFFLINE_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Trabajos confirmados que se recuerdan para no reimprimirlos
+const MAX_COMPLETED: usize = 500;
+
+/// Resultado de un trabajo, pendiente de confirmar al backend
+#[derive(Debug, Clone, PartialEq)]
+struct JobOutcome {
+    status: RemoteJobStatus,
+    message: Option<String>,
+}
+
+/// Trabajos ya ejecutados en esta sesión.
+/// Un trabajo nunca se imprime dos veces aunque el backend lo siga devolviendo.
+#[derive(Debug, Default)]
+pub struct JobTracker {
+    unacked: HashMap<String, JobOutcome>,
+    completed: HashSet<String>,
+    completed_order: VecDeque<String>,
+}
+
+impl JobTracker {
+    fn is_known(&self, job_id: &str) -> bool {
+        self.completed.contains(job_id) || self.unacked.contains_key(job_id)
+    }
+
+    fn mark_acked(&mut self, job_id: &str) {
+        self.unacked.remove(job_id);
+        if self.completed.insert(job_id.to_string()) {
+            self.completed_order.push_back(job_id.to_string());
+            if self.completed_order.len() > MAX_COMPLETED {
+                if let Some(old) = self.completed_order.pop_front() {
+                    self.completed.remove(&old);
+                }
+            }
+        }
+    }
+}
+
+/// Contenido a imprimir: base64 embebido o descarga desde `url`
+async fn job_payload(job: &RemotePrintJob) -> Result<Vec<u8>, String> {
+    if let Some(content) = job.contenido.as_deref().filter(|c| !c.is_empty()) {
+        return STANDARD
+            .decode(content.trim())
+            .map_err(|e| format!("Invalid job content: {}", e));
+    }
+
+    match job.url.as_deref().filter(|u| !u.is_empty()) {
+        Some(url) if job.formato.eq_ignore_ascii_case("PDF") => printer::download_pdf(url).await,
+        Some(_) => Err("URL is only supported for PDF jobs".to_string()),
+        None => Err("Job has no content".to_string()),
+    }
+}
+
+/// Imprimir un trabajo remoto con el mismo pipeline que las impresiones locales
+async fn execute_job(state: &SharedAppState, job: &RemotePrintJob) -> Result<String, String> {
+    let format = if job.formato.eq_ignore_ascii_case("RAW") {
+        SpoolFormat::Raw
+    } else if job.formato.eq_ignore_ascii_case("PDF") {
+        SpoolFormat::Pdf
+    } else {
+        return Err(format!("Unsupported job format: {}", job.formato));
+    };
+
+    // Sin licencia no se descarga el contenido
+    pipeline::validate(state, format).await.map_err(|e| e.message)?;
+    let data = job_payload(job).await?;
+
+    let print_job = Job::new(JobSource::Remote, Content::Bytes(data), format)
+        .with_printer(job.impresora.clone())
+        .with_copies(Some(job.copias));
+    pipeline::run(state, print_job)
+        .await
+        .map(|outcome| outcome.message())
+        .map_err(|e| e.message)
+}
+
+/// Confirmar al backend el resultado de un trabajo
+async fn ack(state: &SharedAppState, device_id: &str, job_id: &str, outcome: &JobOutcome) -> GraphQLResult<()> {
+    let api = state.read().await.api.clone();
+    auth::with_token_refresh(state, |token| {
+        let api = api.clone();
+        async move {
+            graphql::ack_print_job(&api, &token, device_id, job_id, outcome.status, outcome.message.as_deref()).await
+        }
+    })
+    .await
+}
+
+/// Confirmar los resultados pendientes, consultar trabajos nuevos e imprimirlos.
+/// Retorna la cantidad de trabajos ejecutados.
+pub async fn process_pending(state: &SharedAppState, device_id: &str, tracker: &mut JobTracker) -> GraphQLResult<usize> {
+    // Confirmaciones que fallaron en un ciclo anterior
+    let retries: Vec<(String, JobOutcome)> = tracker.unacked.iter().map(|(id, o)| (id.clone(), o.clone())).collect();
+    for (job_id, outcome) in retries {
+        ack(state, device_id, &job_id, &outcome).await?;
+        tracker.mark_acked(&job_id);
+    }
+
+    let api = state.read().await.api.clone();
+    let jobs = auth::with_token_refresh(state, |token| {
+        let api = api.clone();
+        async move { graphql::pending_print_jobs(&api, &token, device_id).await }
+    })
+    .await?;
+
+    let mut executed = 0;
+    for job in jobs {
+        if tracker.is_known(&job.id) {
+            continue;
+        }
+
+        let outcome = match execute_job(state, &job).await {
+            Ok(message) => {
+                state.write().await.add_log("INFO", &format!("Remote job {}: {}", job.id, message));
+                JobOutcome {
+                    status: RemoteJobStatus::Completed,
+                    message: Some(message),
+                }
+            }
+            Err(e) => {
+                state.write().await.add_log("ERROR", &format!("Remote job {} failed: {}", job.id, e));
+                JobOutcome {
+                    status: RemoteJobStatus::Failed,
+                    message: Some(e),
+                }
+            }
+        };
+        executed += 1;
+
+        // Se registra antes de confirmar: si la confirmación falla no se reimprime
+        tracker.unacked.insert(job.id.clone(), outcome.clone());
+        ack(state, device_id, &job.id, &outcome).await?;
+        tracker.mark_acked(&job.id);
+    }
+
+    Ok(executed)
+}
+
+/// Tarea de fondo: atender trabajos remotos mientras haya sesión.
+/// El dispositivo se registra al iniciar sesión (`device::register`).
+pub async fn run(state: SharedAppState) {
+    let device_id = match device::device_id() {
+        Ok(id) => id,
+        Err(e) => {
+            log::error!("Remote print jobs disabled, no device ID: {}", e);
+            return;
+        }
+    };
+
+    let mut tracker = JobTracker::default();
+
+    loop {
+        if !state.read().await.auth.is_logged_in {
+            sleep(POLL_INTERVAL).await;
+            continue;
+        }
+
+        match process_pending(&state, &device_id, &mut tracker).await {
+            Ok(_) => sleep(POLL_INTERVAL).await,
+            Err(e) => {
+                if !e.is_unreachable() {
+                    log::warn!("Remote print jobs failed: {}", e);
+                }
+                sleep(OFFLINE_BACKOFF).await;
+            }
+        }
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppState;
+    use crate::test_backend::TestBackend;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    fn job(id: &str, formato: &str, contenido: Option<&str>) -> RemotePrintJob {
+        RemotePrintJob {
+            id: id.to_string(),
+            formato: formato.to_string(),
+            impresora: None,
+            url: None,
+            contenido: contenido.map(str::to_string),
+            copias: 1,
+        }
+    }
+
+    async fn logged_in_state(backend: &TestBackend) -> SharedAppState {
+        let mut app_state = AppState::default();
+        app_state.api = backend.api.clone();
+        app_state.auth.is_logged_in = true;
+        app_state.auth.token = Some("test-token".to_string());
+        Arc::new(RwLock::new(app_state))
+    }
+
+    #[tokio::test]
+    async fn test_job_payload_base64() {
+        let data = job_payload(&job("j1", "RAW", Some("SG9sYQ=="))).await.unwrap();
+        assert_eq!(data, b"Hola");
+
+        assert!(job_payload(&job("j2", "RAW", Some("%%%"))).await.is_err());
+        assert!(job_payload(&job("j3", "PDF", None)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_job_is_acked_once() {
+        let backend = TestBackend::start(|operation, _| match operation {
+            "TRABAJOS_IMPRESION_PENDIENTES" => serde_json::json!({
+                "data": { "trabajosImpresionPendientes": [
+                    { "_id": "job1", "formato": "RAW", "impresora": "Caja", "url": null, "contenido": "SG9sYQ==" }
+                ]}
+            }),
+            "CONFIRMAR_TRABAJO_IMPRESION" => serde_json::json!({
+                "data": { "confirmarTrabajoImpresion": { "_id": "job1" } }
+            }),
+            _ => serde_json::json!({ "errors": [{ "message": "unexpected operation" }] }),
+        })
+        .await;
+        let state = logged_in_state(&backend).await;
+        let mut tracker = JobTracker::default();
+
+        // Sin licencia: el trabajo se confirma como fallido, sin imprimir
+        let executed = process_pending(&state, "device-1", &mut tracker).await.unwrap();
+        assert_eq!(executed, 1);
+
+        let acks = backend.requests("CONFIRMAR_TRABAJO_IMPRESION");
+        assert_eq!(acks.len(), 1);
+        assert_eq!(acks[0].variables["id"], "job1");
+        assert_eq!(acks[0].variables["dispositivoId"], "device-1");
+        assert_eq!(acks[0].variables["estado"], "FALLIDO");
+        assert!(acks[0].variables["mensaje"].as_str().unwrap().contains("License"));
+        assert_eq!(acks[0].authorization.as_deref(), Some("Bearer test-token"));
+
+        // El backend sigue devolviéndolo: no se procesa de nuevo
+        let executed = process_pending(&state, "device-1", &mut tracker).await.unwrap();
+        assert_eq!(executed, 0);
+        assert_eq!(backend.requests("CONFIRMAR_TRABAJO_IMPRESION").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_ack_is_retried_without_reprinting() {
+        let ack_attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let attempts = ack_attempts.clone();
+        let backend = TestBackend::start(move |operation, _| match operation {
+            "TRABAJOS_IMPRESION_PENDIENTES" => serde_json::json!({
+                "data": { "trabajosImpresionPendientes": [
+                    { "_id": "job1", "formato": "ZPL", "impresora": null, "url": null, "contenido": "eA==" }
+                ]}
+            }),
+            _ => {
+                if attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                    serde_json::json!({ "errors": [{ "message": "temporarily unavailable" }] })
+                } else {
+                    serde_json::json!({ "data": { "confirmarTrabajoImpresion": { "_id": "job1" } } })
+                }
+            }
+        })
+        .await;
+        let state = logged_in_state(&backend).await;
+        let mut tracker = JobTracker::default();
+
+        assert!(process_pending(&state, "device-1", &mut tracker).await.is_err());
+        assert!(tracker.is_known("job1"));
+
+        // Segundo ciclo: se reintenta la confirmación y el trabajo no se vuelve a ejecutar
+        let executed = process_pending(&state, "device-1", &mut tracker).await.unwrap();
+        assert_eq!(executed, 0);
+        assert_eq!(ack_attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,274 @@
+// Reglas de enrutamiento: tipo de documento / origen / horario → impresora, perfil y copias
+use crate::origins::{host_matches, origin_host};
+use crate::pipeline::MAX_COPIES;
+use crate::settings::{PrintProfile, Settings};
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+/// Franja horaria "HH:MM"–"HH:MM" (si `from` > `to` cruza la medianoche)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeWindow {
+    pub from: String,
+    pub to: String,
+}
+
+impl TimeWindow {
+    fn bounds(&self) -> Result<(NaiveTime, NaiveTime), String> {
+        let parse = |s: &str| {
+            NaiveTime::parse_from_str(s.trim(), "%H:%M")
+                .map_err(|_| format!("Invalid time (expected HH:MM): {}", s))
+        };
+        Ok((parse(&self.from)?, parse(&self.to)?))
+    }
+
+    /// La hora está dentro de la franja (inicio incluido, fin excluido)
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        match self.bounds() {
+            Ok((from, to)) if from <= to => from <= time && time < to,
+            Ok((from, to)) => time >= from || time < to,
+            Err(_) => false,
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Regla de enrutamiento; los criterios vacíos coinciden con todo
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoutingRule {
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Dominio de origen ("*.dominio" incluye subdominios)
+    #[serde(default)]
+    pub origin: Option<String>,
+    /// Tipo de documento o etiqueta (p. ej. "factura", "cocina")
+    #[serde(default, alias = "documentType")]
+    pub document_type: Option<String>,
+    #[serde(default)]
+    pub time: Option<TimeWindow>,
+    #[serde(default)]
+    pub printer: Option<String>,
+    /// Nombre de un perfil de settings
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub copies: Option<u32>,
+}
+
+impl RoutingRule {
+    /// Validar la regla contra los perfiles existentes
+    pub fn validate(&self, settings: &Settings) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Routing rule name required".to_string());
+        }
+        if let Some(time) = &self.time {
+            time.bounds()?;
+        }
+        if let Some(profile) = &self.profile {
+            if settings.profile(profile).is_none() {
+                return Err(format!("Routing rule {}: unknown profile {}", self.name, profile));
+            }
+        }
+        match self.copies {
+            Some(0) => {
+                return Err(format!("Routing rule {}: copies must be at least 1", self.name))
+            }
+            Some(copies) if copies > MAX_COPIES => {
+                return Err(format!(
+                    "Routing rule {}: at most {} copies",
+                    self.name, MAX_COPIES
+                ))
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// La regla aplica a la petición
+    pub fn matches(&self, request: &RouteRequest) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let origin_ok = match (&self.origin, request.origin) {
+            (None, _) => true,
+            (Some(pattern), Some(origin)) => host_matches(origin_host(origin), pattern.trim()),
+            (Some(_), None) => false,
+        };
+
+        let document_ok = match (&self.document_type, request.document_type) {
+            (None, _) => true,
+            (Some(expected), Some(actual)) => expected.trim().eq_ignore_ascii_case(actual.trim()),
+            (Some(_), None) => false,
+        };
+
+        let time_ok = self.time.as_ref().map_or(true, |t| t.contains(request.time));
+
+        origin_ok && document_ok && time_ok
+    }
+}
+
+/// Datos de la petición usados para elegir la regla
+#[derive(Debug, Clone, Copy)]
+pub struct RouteRequest<'a> {
+    pub origin: Option<&'a str>,
+    pub document_type: Option<&'a str>,
+    pub time: NaiveTime,
+}
+
+/// Resultado del enrutamiento
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Route {
+    /// Regla aplicada (None si ninguna coincide)
+    pub rule: Option<String>,
+    pub printer: Option<String>,
+    pub profile: Option<PrintProfile>,
+    pub copies: Option<u32>,
+}
+
+/// Primera regla que coincide, en el orden configurado
+pub fn route(settings: &Settings, request: &RouteRequest) -> Route {
+    let Some(rule) = settings.routing_rules.iter().find(|r| r.matches(request)) else {
+        return Route::default();
+    };
+
+    let profile = rule
+        .profile
+        .as_deref()
+        .and_then(|name| settings.profile(name))
+        .cloned();
+
+    // La impresora de la regla tiene prioridad sobre la del perfil
+    let printer = rule
+        .printer
+        .clone()
+        .filter(|p| !p.trim().is_empty())
+        .or_else(|| profile.as_ref().and_then(|p| p.printer.clone()));
+
+    Route {
+        rule: Some(rule.name.clone()),
+        printer,
+        profile,
+        copies: rule.copies,
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{CutMode, OutputMode};
+
+    fn at(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn rule(name: &str) -> RoutingRule {
+        RoutingRule {
+            name: name.to_string(),
+            enabled: true,
+            origin: None,
+            document_type: None,
+            time: None,
+            printer: None,
+            profile: None,
+            copies: None,
+        }
+    }
+
+    fn settings() -> Settings {
+        let mut settings = Settings::default();
+        settings.upsert_profile(PrintProfile {
+            name: "Ticket".to_string(),
+            printer: Some("Cocina-1".to_string()),
+            preset: "thermal".to_string(),
+            width_mm: Some(80.0),
+            height_mm: None,
+            code_page: None,
+            cut: CutMode::Auto,
+            mode: OutputMode::Cups,
+            dpi: None,
+        });
+
+        let mut noche = rule("Cocina noche");
+        noche.document_type = Some("cocina".to_string());
+        noche.time = Some(TimeWindow { from: "22:00".to_string(), to: "06:00".to_string() });
+        noche.printer = Some("Barra".to_string());
+
+        let mut cocina = rule("Cocina");
+        cocina.document_type = Some("cocina".to_string());
+        cocina.profile = Some("ticket".to_string());
+
+        let mut facturas = rule("Facturas");
+        facturas.origin = Some("*.isipass.net".to_string());
+        facturas.document_type = Some("factura".to_string());
+        facturas.printer = Some("Oficina".to_string());
+        facturas.copies = Some(2);
+
+        settings.routing_rules = vec![noche, cocina, facturas];
+        settings
+    }
+
+    fn request<'a>(origin: Option<&'a str>, document_type: Option<&'a str>, time: NaiveTime) -> RouteRequest<'a> {
+        RouteRequest { origin, document_type, time }
+    }
+
+    #[test]
+    fn test_time_window_overnight() {
+        let window = TimeWindow { from: "22:00".to_string(), to: "06:00".to_string() };
+        assert!(window.contains(at(23, 30)));
+        assert!(window.contains(at(5, 59)));
+        assert!(!window.contains(at(6, 0)));
+        assert!(!window.contains(at(12, 0)));
+    }
+
+    #[test]
+    fn test_route_first_matching_rule() {
+        let settings = settings();
+
+        let night = route(&settings, &request(None, Some("Cocina"), at(23, 0)));
+        assert_eq!(night.rule.as_deref(), Some("Cocina noche"));
+        assert_eq!(night.printer.as_deref(), Some("Barra"));
+
+        // Sin impresora en la regla se usa la del perfil
+        let day = route(&settings, &request(None, Some("cocina"), at(13, 0)));
+        assert_eq!(day.rule.as_deref(), Some("Cocina"));
+        assert_eq!(day.printer.as_deref(), Some("Cocina-1"));
+        assert_eq!(day.profile.unwrap().width_mm, Some(80.0));
+    }
+
+    #[test]
+    fn test_route_by_origin() {
+        let settings = settings();
+
+        let invoice = route(&settings, &request(Some("https://caja.isipass.net"), Some("factura"), at(10, 0)));
+        assert_eq!(invoice.printer.as_deref(), Some("Oficina"));
+        assert_eq!(invoice.copies, Some(2));
+
+        let other = route(&settings, &request(Some("https://example.com"), Some("factura"), at(10, 0)));
+        assert_eq!(other, Route::default());
+        assert_eq!(route(&settings, &request(None, None, at(10, 0))), Route::default());
+    }
+
+    #[test]
+    fn test_disabled_and_invalid_rules() {
+        let mut settings = settings();
+        settings.routing_rules[0].enabled = false;
+        let night = route(&settings, &request(None, Some("cocina"), at(23, 0)));
+        assert_eq!(night.rule.as_deref(), Some("Cocina"));
+
+        let mut bad = rule("Mala");
+        bad.time = Some(TimeWindow { from: "25:00".to_string(), to: "06:00".to_string() });
+        assert!(bad.validate(&settings).is_err());
+
+        let mut bad = rule("Mala");
+        bad.profile = Some("inexistente".to_string());
+        assert!(bad.validate(&settings).is_err());
+
+        assert!(settings.routing_rules.iter().all(|r| r.validate(&settings).is_ok()));
+    }
+}
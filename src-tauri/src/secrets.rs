@@ -0,0 +1,197 @@
+// Cifrado de datos sensibles en reposo con una clave ligada a la máquina
+use crate::persistence;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// Servicio y cuenta de la clave en el llavero del sistema
+const KEYRING_SERVICE: &str = "ISIPRINT";
+const KEYRING_ACCOUNT: &str = "session-key";
+
+/// Archivo de clave (0600) cuando no hay llavero disponible
+const KEY_FILE_NAME: &str = "session.key";
+
+/// Versión del formato cifrado
+const ENCRYPTION_VERSION: u8 = 1;
+
+/// Datos cifrados con XChaCha20-Poly1305
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncryptedData {
+    pub version: u8,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// Almacén de la clave de sesión. Se guarda junto a los datos cifrados: si el llavero
+/// deja de estar disponible (o vuelve) entre ejecuciones, la sesión se sigue descifrando.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    Keyring,
+    File,
+}
+
+/// Leer la clave del llavero del sistema, creándola si no existe
+fn keyring_key() -> Result<Vec<u8>, String> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_ACCOUNT)
+        .map_err(|e| format!("Keyring unavailable: {}", e))?;
+
+    match entry.get_password() {
+        Ok(encoded) => STANDARD
+            .decode(encoded.trim())
+            .ok()
+            .filter(|k| k.len() == 32)
+            .ok_or_else(|| "Invalid key stored in keyring".to_string()),
+        Err(keyring::Error::NoEntry) => {
+            let mut key = vec![0u8; 32];
+            rand::rngs::OsRng.fill_bytes(&mut key);
+            entry
+                .set_password(&STANDARD.encode(&key))
+                .map_err(|e| format!("Failed to store key in keyring: {}", e))?;
+            log::info!("Created session key in system keyring");
+            Ok(key)
+        }
+        Err(e) => Err(format!("Keyring unavailable: {}", e)),
+    }
+}
+
+fn file_key() -> Result<Vec<u8>, String> {
+    let key = persistence::load_or_create_secret(KEY_FILE_NAME)?;
+    Ok(key[..32].to_vec())
+}
+
+/// Clave para cifrar la sesión: llavero del sistema o, si no hay, archivo local 0600
+pub fn session_key() -> Result<(KeySource, Vec<u8>), String> {
+    match keyring_key() {
+        Ok(key) => Ok((KeySource::Keyring, key)),
+        Err(e) => {
+            log::info!("{}, using key file", e);
+            Ok((KeySource::File, file_key()?))
+        }
+    }
+}
+
+/// Clave de un almacén concreto, para descifrar con la misma que cifró
+pub fn key_from(source: KeySource) -> Result<Vec<u8>, String> {
+    match source {
+        KeySource::Keyring => keyring_key(),
+        KeySource::File => file_key(),
+    }
+}
+
+/// Almacenes a probar al descifrar: primero el registrado (si lo hay), luego el resto
+pub fn key_sources(recorded: Option<KeySource>) -> Vec<KeySource> {
+    let mut sources: Vec<KeySource> = recorded.into_iter().collect();
+    for source in [KeySource::Keyring, KeySource::File] {
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+    sources
+}
+
+/// Cifrar `plaintext`; `context` queda autenticado (evita mover datos entre archivos)
+pub fn encrypt(key: &[u8], context: &str, plaintext: &[u8]) -> Result<EncryptedData, String> {
+    if key.len() != 32 {
+        return Err("Encryption key must be 32 bytes".to_string());
+    }
+
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    let mut nonce = [0u8; 24];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+    let ciphertext = cipher
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: context.as_bytes(),
+            },
+        )
+        .map_err(|_| "Encryption failed".to_string())?;
+
+    Ok(EncryptedData {
+        version: ENCRYPTION_VERSION,
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+}
+
+/// Descifrar datos producidos por `encrypt` con la misma clave y contexto
+pub fn decrypt(key: &[u8], context: &str, data: &EncryptedData) -> Result<Vec<u8>, String> {
+    if key.len() != 32 {
+        return Err("Encryption key must be 32 bytes".to_string());
+    }
+    if data.version != ENCRYPTION_VERSION {
+        return Err(format!("Unsupported encryption version {}", data.version));
+    }
+
+    let nonce = STANDARD
+        .decode(&data.nonce)
+        .ok()
+        .filter(|n| n.len() == 24)
+        .ok_or("Invalid nonce")?;
+    let ciphertext = STANDARD
+        .decode(&data.ciphertext)
+        .map_err(|_| "Invalid ciphertext")?;
+
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    cipher
+        .decrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: context.as_bytes(),
+            },
+        )
+        .map_err(|_| "Decryption failed (wrong key or corrupted data)".to_string())
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8; 32] = b"0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let data = encrypt(KEY, "session", b"secret-token").unwrap();
+        assert!(!data.ciphertext.contains("secret-token"));
+        assert_eq!(decrypt(KEY, "session", &data).unwrap(), b"secret-token");
+    }
+
+    #[test]
+    fn test_nonce_is_random() {
+        let a = encrypt(KEY, "session", b"same").unwrap();
+        let b = encrypt(KEY, "session", b"same").unwrap();
+        assert_ne!(a.nonce, b.nonce);
+        assert_ne!(a.ciphertext, b.ciphertext);
+    }
+
+    #[test]
+    fn test_decrypt_rejects_wrong_key_context_or_tampering() {
+        let data = encrypt(KEY, "session", b"secret-token").unwrap();
+
+        assert!(decrypt(b"another-key-another-key-another!!", "session", &data).is_err());
+        assert!(decrypt(KEY, "other", &data).is_err());
+
+        let mut tampered = data.clone();
+        let mut bytes = STANDARD.decode(&tampered.ciphertext).unwrap();
+        bytes[0] ^= 0xff;
+        tampered.ciphertext = STANDARD.encode(bytes);
+        assert!(decrypt(KEY, "session", &tampered).is_err());
+
+        assert!(encrypt(b"short", "session", b"x").is_err());
+    }
+
+    #[test]
+    fn test_key_sources_try_recorded_first() {
+        assert_eq!(key_sources(Some(KeySource::File)), vec![KeySource::File, KeySource::Keyring]);
+        assert_eq!(key_sources(None), vec![KeySource::Keyring, KeySource::File]);
+    }
+}
@@ -0,0 +1,637 @@
+// Configuración local persistente (settings.json, versionada)
+use crate::origins::{self, AllowedOrigin};
+use crate::pools::PrinterPool;
+use crate::raw_printer;
+use crate::routing::RoutingRule;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Versión actual del formato de settings.json
+pub const SETTINGS_VERSION: u32 = 1;
+
+/// Clave de `default_printers` para la impresora general
+pub const DEFAULT_DOCUMENT_TYPE: &str = "default";
+
+/// Idiomas soportados por la interfaz y la página de prueba
+const LANGUAGES: &[&str] = &["es", "en", "fr"];
+
+/// Cuándo enviar el comando de corte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CutMode {
+    /// Solo en impresoras térmicas (no en impresoras PDF)
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Cómo se envía el documento a la impresora
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMode {
+    /// Cola del sistema (CUPS / spooler de Windows)
+    #[default]
+    Cups,
+    /// Bytes directos al puerto de la impresora (ESC/POS)
+    Raw,
+}
+
+fn default_preset() -> String {
+    "thermal".to_string()
+}
+
+/// Perfil de impresión con nombre
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrintProfile {
+    pub name: String,
+    #[serde(default)]
+    pub printer: Option<String>,
+    /// thermal, carta, oficio o custom
+    #[serde(default = "default_preset")]
+    pub preset: String,
+    #[serde(default)]
+    pub width_mm: Option<f64>,
+    #[serde(default)]
+    pub height_mm: Option<f64>,
+    /// Página de códigos ESC/POS (p. ej. "CP850")
+    #[serde(default)]
+    pub code_page: Option<String>,
+    #[serde(default)]
+    pub cut: CutMode,
+    #[serde(default)]
+    pub mode: OutputMode,
+    #[serde(default)]
+    pub dpi: Option<u32>,
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_port() -> u16 {
+    7777
+}
+
+fn default_fallback_port() -> Option<u16> {
+    Some(7778)
+}
+
+/// Dirección de escucha adicional (p. ej. la interfaz LAN para usar ISIPRINT como servidor de impresión)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpListener {
+    pub host: String,
+    pub port: u16,
+}
+
+/// Opciones del servidor HTTP local (se aplican al reiniciar)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpSettings {
+    #[serde(default = "default_host")]
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Puerto alternativo si `port` está ocupado (`null` para desactivarlo)
+    #[serde(default = "default_fallback_port")]
+    pub fallback_port: Option<u16>,
+    #[serde(default)]
+    pub listeners: Vec<HttpListener>,
+    /// Puerto HTTPS en `host` con el certificado local (desactivado si es `None`)
+    #[serde(default)]
+    pub https_port: Option<u16>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            host: default_host(),
+            port: default_port(),
+            fallback_port: default_fallback_port(),
+            listeners: Vec::new(),
+            https_port: None,
+        }
+    }
+}
+
+impl HttpSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        let check = |host: &str, port: u16| {
+            if port == 0 {
+                return Err(format!("Invalid HTTP port: {}", port));
+            }
+            host.parse::<std::net::IpAddr>()
+                .map(|ip| std::net::SocketAddr::new(ip, port))
+                .map_err(|_| format!("Invalid HTTP host: {}", host))
+        };
+
+        let mut addresses = std::collections::HashSet::new();
+        addresses.insert(check(&self.host, self.port)?);
+        if let Some(port) = self.fallback_port {
+            if !addresses.insert(check(&self.host, port)?) {
+                return Err("HTTP fallback port must differ from the main port".to_string());
+            }
+        }
+        if let Some(port) = self.https_port {
+            if !addresses.insert(check(&self.host, port)?) {
+                return Err("HTTPS port must differ from the HTTP ports".to_string());
+            }
+        }
+        for listener in &self.listeners {
+            if !addresses.insert(check(&listener.host, listener.port)?) {
+                return Err(format!(
+                    "Duplicate HTTP listener: {}:{}",
+                    listener.host, listener.port
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_language() -> String {
+    "es".to_string()
+}
+
+/// Contenido de settings.json
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    pub version: u32,
+    #[serde(default)]
+    pub profiles: Vec<PrintProfile>,
+    /// Tipo de documento → impresora ("default" para la general)
+    #[serde(default)]
+    pub default_printers: BTreeMap<String, String>,
+    /// Reglas de enrutamiento, evaluadas en orden
+    #[serde(default)]
+    pub routing_rules: Vec<RoutingRule>,
+    /// Grupos de impresoras direccionables por nombre
+    #[serde(default)]
+    pub printer_pools: Vec<PrinterPool>,
+    #[serde(default)]
+    pub http: HttpSettings,
+    /// Orígenes web que pueden usar la API local y los comandos, con sus permisos
+    #[serde(default = "origins::default_origins")]
+    pub allowed_origins: Vec<AllowedOrigin>,
+    /// Orígenes rechazados por el usuario (no se vuelve a preguntar)
+    #[serde(default)]
+    pub denied_origins: Vec<String>,
+    #[serde(default = "default_language")]
+    pub language: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            profiles: Vec::new(),
+            default_printers: BTreeMap::new(),
+            routing_rules: Vec::new(),
+            printer_pools: Vec::new(),
+            http: HttpSettings::default(),
+            allowed_origins: origins::default_origins(),
+            denied_origins: Vec::new(),
+            language: default_language(),
+        }
+    }
+}
+
+impl Settings {
+    /// Leer settings.json migrando formatos anteriores
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let value: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| format!("Invalid settings: {}", e))?;
+        let settings = migrate(value)?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Validar perfiles, reglas, grupos, servidor HTTP, orígenes e idioma
+    pub fn validate(&self) -> Result<(), String> {
+        let mut names = std::collections::HashSet::new();
+        for profile in &self.profiles {
+            let name = profile.name.trim().to_lowercase();
+            if name.is_empty() {
+                return Err("Profile name required".to_string());
+            }
+            if !names.insert(name) {
+                return Err(format!("Duplicate profile name: {}", profile.name));
+            }
+            for size in [profile.width_mm, profile.height_mm].into_iter().flatten() {
+                if !size.is_finite() || size <= 0.0 {
+                    return Err(format!("Invalid paper size in profile {}", profile.name));
+                }
+            }
+            if profile.dpi == Some(0) {
+                return Err(format!("Invalid DPI in profile {}", profile.name));
+            }
+            if let Some(code_page) = &profile.code_page {
+                if raw_printer::code_page_command(code_page).is_none() {
+                    return Err(format!(
+                        "Unsupported code page {} in profile {}",
+                        code_page, profile.name
+                    ));
+                }
+            }
+        }
+
+        let mut rule_names = std::collections::HashSet::new();
+        for rule in &self.routing_rules {
+            rule.validate(self)?;
+            if !rule_names.insert(rule.name.trim().to_lowercase()) {
+                return Err(format!("Duplicate routing rule name: {}", rule.name));
+            }
+        }
+
+        let mut pool_names = std::collections::HashSet::new();
+        for pool in &self.printer_pools {
+            pool.validate()?;
+            if !pool_names.insert(pool.name.trim().to_lowercase()) {
+                return Err(format!("Duplicate printer pool name: {}", pool.name));
+            }
+        }
+        // Los miembros de los grupos son impresoras
+        let members: Vec<String> = self
+            .printer_pools
+            .iter()
+            .flat_map(|p| p.members.iter().cloned())
+            .collect();
+        self.check_pool_names(&members)?;
+
+        self.http.validate()?;
+
+        let mut patterns = std::collections::HashSet::new();
+        for origin in &self.allowed_origins {
+            origin.validate()?;
+            if !patterns.insert(origin.pattern.to_lowercase()) {
+                return Err(format!("Duplicate allowed origin: {}", origin.pattern));
+            }
+        }
+        for pattern in &self.denied_origins {
+            origins::validate_pattern(pattern)?;
+        }
+
+        if !LANGUAGES.contains(&self.language.as_str()) {
+            return Err(format!("Unsupported language: {}", self.language));
+        }
+
+        Ok(())
+    }
+
+    /// Buscar un perfil por nombre (sin distinguir mayúsculas)
+    pub fn profile(&self, name: &str) -> Option<&PrintProfile> {
+        self.profiles
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// La impresora tiene un perfil ESC/POS (salida RAW o preset térmico): solo a
+    /// esas se les puede enviar comandos ESC/POS como la consulta de estado
+    pub fn is_escpos_printer(&self, printer: &str) -> bool {
+        self.profiles.iter().any(|p| {
+            p.printer
+                .as_deref()
+                .is_some_and(|name| name.eq_ignore_ascii_case(printer))
+                && (p.mode == OutputMode::Raw || p.preset == "thermal")
+        })
+    }
+
+    /// Un grupo con el nombre de una impresora la ocultaría (los nombres se resuelven
+    /// primero como grupo)
+    pub fn check_pool_names(&self, printers: &[String]) -> Result<(), String> {
+        match self.printer_pools.iter().find(|pool| {
+            printers
+                .iter()
+                .any(|p| p.trim().eq_ignore_ascii_case(pool.name.trim()))
+        }) {
+            Some(pool) => Err(format!(
+                "Printer pool {} has the same name as a printer",
+                pool.name
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Buscar un grupo de impresoras por nombre (sin distinguir mayúsculas)
+    pub fn pool(&self, name: &str) -> Option<&PrinterPool> {
+        self.printer_pools
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Crear o reemplazar un perfil
+    pub fn upsert_profile(&mut self, profile: PrintProfile) {
+        match self.profiles.iter_mut().find(|p| p.name.eq_ignore_ascii_case(&profile.name)) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+
+    /// Eliminar un perfil; retorna false si no existía
+    pub fn remove_profile(&mut self, name: &str) -> bool {
+        let before = self.profiles.len();
+        self.profiles.retain(|p| !p.name.eq_ignore_ascii_case(name.trim()));
+        self.profiles.len() != before
+    }
+
+    /// Agregar un origen permitido o reemplazar sus permisos (deja de estar rechazado)
+    pub fn upsert_allowed_origin(&mut self, origin: AllowedOrigin) {
+        self.denied_origins
+            .retain(|d| !d.eq_ignore_ascii_case(&origin.pattern));
+        match self
+            .allowed_origins
+            .iter_mut()
+            .find(|o| o.pattern.eq_ignore_ascii_case(&origin.pattern))
+        {
+            Some(existing) => *existing = origin,
+            None => self.allowed_origins.push(origin),
+        }
+    }
+
+    /// Quitar un origen permitido; retorna false si no existía
+    pub fn remove_allowed_origin(&mut self, pattern: &str) -> bool {
+        let before = self.allowed_origins.len();
+        self.allowed_origins
+            .retain(|o| !o.pattern.eq_ignore_ascii_case(pattern.trim()));
+        self.allowed_origins.len() != before
+    }
+
+    /// Rechazar un origen: se quita de los permitidos y no se vuelve a preguntar
+    pub fn deny_origin(&mut self, pattern: &str) {
+        let pattern = pattern.trim().to_lowercase();
+        self.remove_allowed_origin(&pattern);
+        if !self.denied_origins.contains(&pattern) {
+            self.denied_origins.push(pattern);
+        }
+    }
+
+    /// Conservar lo que la API HTTP no puede cambiar: orígenes permitidos y
+    /// rechazados y las direcciones del servidor (solo desde la aplicación)
+    pub fn keep_access_settings(&mut self, current: &Settings) {
+        self.allowed_origins = current.allowed_origins.clone();
+        self.denied_origins = current.denied_origins.clone();
+        self.http = current.http.clone();
+    }
+
+    /// Impresora por defecto para un tipo de documento (o la general)
+    pub fn default_printer_for(&self, document_type: Option<&str>) -> Option<&str> {
+        document_type
+            .map(|t| t.trim().to_lowercase())
+            .and_then(|t| self.default_printers.get(&t))
+            .or_else(|| self.default_printers.get(DEFAULT_DOCUMENT_TYPE))
+            .map(String::as_str)
+    }
+
+    /// Asignar (o quitar con `None`) la impresora por defecto de un tipo de documento
+    pub fn set_default_printer(&mut self, document_type: Option<&str>, printer: Option<String>) {
+        let key = document_type
+            .map(|t| t.trim().to_lowercase())
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| DEFAULT_DOCUMENT_TYPE.to_string());
+
+        match printer.filter(|p| !p.trim().is_empty()) {
+            Some(p) => {
+                self.default_printers.insert(key, p);
+            }
+            None => {
+                self.default_printers.remove(&key);
+            }
+        }
+    }
+}
+
+/// Llevar un settings.json de cualquier versión anterior a la actual
+fn migrate(mut value: serde_json::Value) -> Result<Settings, String> {
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+
+    if version > SETTINGS_VERSION {
+        return Err(format!(
+            "settings.json version {} is newer than supported version {}",
+            version, SETTINGS_VERSION
+        ));
+    }
+
+    // v0: archivo sin versión; mismos campos que v1
+    if version == 0 {
+        value["version"] = serde_json::json!(1);
+    }
+
+    serde_json::from_value(value).map_err(|e| format!("Invalid settings: {}", e))
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str) -> PrintProfile {
+        PrintProfile {
+            name: name.to_string(),
+            printer: Some("Caja".to_string()),
+            preset: "thermal".to_string(),
+            width_mm: Some(80.0),
+            height_mm: None,
+            code_page: Some("CP850".to_string()),
+            cut: CutMode::Always,
+            mode: OutputMode::Raw,
+            dpi: Some(203),
+        }
+    }
+
+    #[test]
+    fn test_default_settings_are_valid() {
+        let settings = Settings::default();
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.http.port, 7777);
+        assert_eq!(settings.language, "es");
+    }
+
+    #[test]
+    fn test_roundtrip_and_unversioned_migration() {
+        let mut settings = Settings::default();
+        settings.upsert_profile(profile("Ticket"));
+        let json = serde_json::to_string_pretty(&settings).unwrap();
+        assert_eq!(Settings::from_json(&json).unwrap(), settings);
+
+        let legacy = r#"{"profiles": [{"name": "Factura", "preset": "carta"}], "language": "en"}"#;
+        let migrated = Settings::from_json(legacy).unwrap();
+        assert_eq!(migrated.version, SETTINGS_VERSION);
+        assert_eq!(migrated.profiles[0].cut, CutMode::Auto);
+        assert_eq!(migrated.profiles[0].mode, OutputMode::Cups);
+        assert_eq!(migrated.http, HttpSettings::default());
+    }
+
+    #[test]
+    fn test_newer_version_rejected() {
+        assert!(Settings::from_json(r#"{"version": 99}"#).is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_values() {
+        let mut settings = Settings::default();
+        settings.profiles = vec![profile("Ticket"), profile("ticket")];
+        assert!(settings.validate().is_err());
+
+        let mut settings = Settings::default();
+        let mut unknown_code_page = profile("Ticket");
+        unknown_code_page.code_page = Some("UTF-8".to_string());
+        settings.profiles = vec![unknown_code_page];
+        assert!(settings.validate().is_err());
+
+        let mut settings = Settings::default();
+        settings.language = "de".to_string();
+        assert!(settings.validate().is_err());
+
+        let mut settings = Settings::default();
+        settings.http.host = "not-an-ip".to_string();
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_http_listeners_validation() {
+        let mut settings = Settings::default();
+        settings.http.listeners.push(HttpListener {
+            host: "192.168.1.20".to_string(),
+            port: 7777,
+        });
+        assert!(settings.validate().is_ok());
+
+        // Misma dirección que el puerto de respaldo
+        settings.http.listeners.push(HttpListener {
+            host: "127.0.0.1".to_string(),
+            port: 7778,
+        });
+        assert!(settings.validate().is_err());
+
+        let mut settings = Settings::default();
+        settings.http.fallback_port = Some(7777);
+        assert!(settings.validate().is_err());
+        settings.http.fallback_port = None;
+        assert!(settings.validate().is_ok());
+        settings.http.https_port = Some(7777);
+        assert!(settings.validate().is_err());
+        settings.http.https_port = Some(7443);
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_profiles_upsert_and_remove() {
+        let mut settings = Settings::default();
+        settings.upsert_profile(profile("Ticket"));
+        let mut updated = profile("TICKET");
+        updated.width_mm = Some(58.0);
+        settings.upsert_profile(updated);
+
+        assert_eq!(settings.profiles.len(), 1);
+        assert_eq!(settings.profile("ticket").unwrap().width_mm, Some(58.0));
+        assert!(settings.remove_profile("Ticket"));
+        assert!(!settings.remove_profile("Ticket"));
+    }
+
+    #[test]
+    fn test_escpos_printer_needs_profile() {
+        let mut settings = Settings::default();
+        settings.upsert_profile(profile("Ticket"));
+        let mut office = profile("Carta");
+        office.printer = Some("Oficina".to_string());
+        office.preset = "carta".to_string();
+        office.mode = OutputMode::Cups;
+        settings.upsert_profile(office);
+
+        assert!(settings.is_escpos_printer("caja"));
+        assert!(!settings.is_escpos_printer("Oficina"));
+        assert!(!settings.is_escpos_printer("Sin perfil"));
+    }
+
+    #[test]
+    fn test_allowed_origins_upsert_and_remove() {
+        use crate::origins::Permission;
+
+        let legacy = Settings::from_json(r#"{"version": 1}"#).unwrap();
+        assert_eq!(legacy.allowed_origins, origins::default_origins());
+
+        let mut settings = Settings::default();
+        settings.upsert_allowed_origin(AllowedOrigin::new("pos.local", vec![Permission::Print]));
+        settings.upsert_allowed_origin(AllowedOrigin::new(
+            "POS.local",
+            vec![Permission::Print, Permission::ListPrinters],
+        ));
+        assert!(settings.validate().is_ok());
+        let entry = settings.allowed_origins.last().unwrap();
+        assert_eq!(entry.permissions.len(), 2);
+
+        settings.allowed_origins.push(AllowedOrigin::new("pos.local", vec![]));
+        assert!(settings.validate().is_err());
+        settings.allowed_origins.pop();
+
+        assert!(settings.remove_allowed_origin("pos.local"));
+        assert!(!settings.remove_allowed_origin("pos.local"));
+
+        // Rechazar y volver a permitir
+        settings.upsert_allowed_origin(AllowedOrigin::new("shop.example.com", vec![]));
+        settings.deny_origin("Shop.example.com");
+        settings.deny_origin("shop.example.com");
+        assert_eq!(settings.denied_origins, vec!["shop.example.com"]);
+        assert!(settings.allowed_origins.iter().all(|o| o.pattern != "shop.example.com"));
+        settings.upsert_allowed_origin(AllowedOrigin::new("shop.example.com", vec![]));
+        assert!(settings.denied_origins.is_empty());
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_keep_access_settings() {
+        use crate::origins::Permission;
+
+        let mut current = Settings::default();
+        current.deny_origin("evil.com");
+        let mut incoming = Settings::default();
+        incoming.language = "en".to_string();
+        incoming.upsert_allowed_origin(AllowedOrigin::new("*.com", vec![Permission::Configure]));
+        incoming.http.host = "0.0.0.0".to_string();
+
+        incoming.keep_access_settings(&current);
+        assert_eq!(incoming.language, "en");
+        assert_eq!(incoming.allowed_origins, current.allowed_origins);
+        assert_eq!(incoming.denied_origins, vec!["evil.com"]);
+        assert_eq!(incoming.http, HttpSettings::default());
+    }
+
+    #[test]
+    fn test_pool_cannot_shadow_a_printer() {
+        use crate::pools::{PoolStrategy, PrinterPool};
+
+        let mut settings = Settings::default();
+        settings.printer_pools.push(PrinterPool {
+            name: "Cocina".to_string(),
+            members: vec!["Cocina-1".to_string(), "Cocina-2".to_string()],
+            strategy: PoolStrategy::RoundRobin,
+        });
+        assert!(settings.validate().is_ok());
+        assert!(settings.check_pool_names(&["Caja".to_string()]).is_ok());
+        assert!(settings.check_pool_names(&["cocina".to_string()]).is_err());
+
+        settings.printer_pools.push(PrinterPool {
+            name: "Cocina-1".to_string(),
+            members: vec!["Cocina-3".to_string()],
+            strategy: PoolStrategy::RoundRobin,
+        });
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_default_printer_per_document_type() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.default_printer_for(None), None);
+
+        settings.set_default_printer(None, Some("General".to_string()));
+        settings.set_default_printer(Some("Cocina"), Some("Cocina-1".to_string()));
+
+        assert_eq!(settings.default_printer_for(Some("cocina")), Some("Cocina-1"));
+        assert_eq!(settings.default_printer_for(Some("factura")), Some("General"));
+        assert_eq!(settings.default_printer_for(None), Some("General"));
+
+        settings.set_default_printer(Some("cocina"), None);
+        assert_eq!(settings.default_printer_for(Some("cocina")), Some("General"));
+    }
+}
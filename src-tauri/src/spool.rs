@@ -0,0 +1,554 @@
+// Cola persistente de trabajos no entregados (impresora apagada o con error)
+use crate::history::{self, JobContext, JobResult, JobSource};
+use crate::persistence;
+use crate::pipeline::{self, Delivery, ErrorKind, PipelineError};
+use crate::quota;
+use crate::SharedAppState;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Duration};
+
+/// Intervalo del bucle de reintentos
+const RETRY_TICK: Duration = Duration::from_secs(15);
+
+/// Primer reintento y máximo entre reintentos (segundos)
+const BACKOFF_BASE_SECS: i64 = 15;
+const BACKOFF_MAX_SECS: i64 = 10 * 60;
+
+/// Antigüedad máxima: pasada, el trabajo sale de la cola como fallido. Los intentos
+/// no tienen límite: solo se reintentan los fallos por impresora no disponible.
+const MAX_AGE_SECS: i64 = 24 * 60 * 60;
+
+/// Evita entregar dos veces el mismo trabajo (bucle y "reintentar ahora")
+static DELIVERY_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Tipo de contenido del trabajo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpoolFormat {
+    Pdf,
+    Raw,
+}
+
+impl SpoolFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpoolFormat::Pdf => "pdf",
+            SpoolFormat::Raw => "raw",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SpoolFormat::Pdf => "pdf",
+            SpoolFormat::Raw => "bin",
+        }
+    }
+}
+
+/// Trabajo pendiente de entrega (metadatos en `<id>.json`, contenido en `<id>.pdf|bin`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpooledJob {
+    pub id: String,
+    /// Segundos Unix
+    pub created_at: i64,
+    /// Impresora o grupo de destino
+    pub printer: String,
+    pub format: SpoolFormat,
+    pub copies: u32,
+    /// Tamaño de papel CUPS (perfil de la regla de enrutamiento)
+    #[serde(default)]
+    pub media: Option<String>,
+    /// Resolución del perfil
+    #[serde(default)]
+    pub dpi: Option<u32>,
+    /// Cortar el papel tras cada copia
+    #[serde(default)]
+    pub cut: bool,
+    #[serde(default)]
+    pub origin: Option<String>,
+    #[serde(default)]
+    pub document_type: Option<String>,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    pub next_attempt_at: i64,
+}
+
+impl SpooledJob {
+    /// Ya no se reintenta: demasiado antiguo
+    pub fn is_expired(&self, now: i64) -> bool {
+        now - self.created_at >= MAX_AGE_SECS
+    }
+}
+
+/// Datos de un trabajo nuevo
+#[derive(Debug, Clone)]
+pub struct NewJob {
+    pub printer: String,
+    pub format: SpoolFormat,
+    pub copies: u32,
+    pub media: Option<String>,
+    pub dpi: Option<u32>,
+    pub cut: bool,
+    pub origin: Option<String>,
+    pub document_type: Option<String>,
+    pub error: String,
+}
+
+/// Error de las operaciones sobre la cola
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpoolError {
+    /// No hay un trabajo con ese ID
+    NotFound(String),
+    /// El reintento no se imprimió (la etapa indica por qué)
+    Delivery(PipelineError),
+    /// Lectura o escritura del directorio de la cola
+    Storage(String),
+}
+
+impl std::fmt::Display for SpoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpoolError::NotFound(message) | SpoolError::Storage(message) => f.write_str(message),
+            SpoolError::Delivery(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<String> for SpoolError {
+    fn from(message: String) -> Self {
+        SpoolError::Storage(message)
+    }
+}
+
+fn not_found(id: &str) -> SpoolError {
+    SpoolError::NotFound(format!("Spooled job {} not found", id))
+}
+
+/// Espera antes del siguiente intento: 15 s, 30 s, 1 min… hasta 10 min
+pub fn backoff_secs(attempts: u32) -> i64 {
+    BACKOFF_BASE_SECS
+        .saturating_mul(1i64 << attempts.saturating_sub(1).min(16))
+        .min(BACKOFF_MAX_SECS)
+}
+
+/// Directorio de la cola
+pub struct Spool {
+    dir: PathBuf,
+}
+
+impl Spool {
+    /// Cola en el directorio de datos de ISIPRINT
+    pub fn open() -> Result<Self, String> {
+        let dir = persistence::get_spool_dir().ok_or("Could not determine spool directory")?;
+        Self::at(dir)
+    }
+
+    pub fn at(dir: PathBuf) -> Result<Self, String> {
+        persistence::create_private_dir(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn meta_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// Archivo con el contenido del trabajo
+    pub fn payload_path(&self, job: &SpooledJob) -> PathBuf {
+        self.dir.join(format!("{}.{}", job.id, job.format.extension()))
+    }
+
+    fn save(&self, job: &SpooledJob) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(job).map_err(|e| format!("Failed to serialize job: {}", e))?;
+        // Escritura atómica: un corte de luz no deja metadatos a medias
+        let tmp = self.dir.join(format!("{}.json.tmp", job.id));
+        fs::write(&tmp, json).map_err(|e| format!("Failed to write spooled job: {}", e))?;
+        fs::rename(&tmp, self.meta_path(&job.id)).map_err(|e| format!("Failed to write spooled job: {}", e))
+    }
+
+    /// Guardar en la cola una copia de `source` con sus metadatos
+    pub fn enqueue(&self, source: &Path, job: NewJob, now: i64) -> Result<SpooledJob, String> {
+        let spooled = SpooledJob {
+            id: format!("{}-{:08x}", now, rand::random::<u32>()),
+            created_at: now,
+            printer: job.printer,
+            format: job.format,
+            copies: job.copies.max(1),
+            media: job.media,
+            dpi: job.dpi,
+            cut: job.cut,
+            origin: job.origin,
+            document_type: job.document_type,
+            attempts: 1,
+            last_error: Some(job.error),
+            next_attempt_at: now + backoff_secs(1),
+        };
+
+        // Primero el contenido: un .json sin contenido sería un trabajo roto
+        fs::copy(source, self.payload_path(&spooled)).map_err(|e| format!("Failed to spool job: {}", e))?;
+        self.save(&spooled)?;
+        Ok(spooled)
+    }
+
+    /// Trabajos en cola, del más antiguo al más nuevo
+    pub fn list(&self) -> Vec<SpooledJob> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+
+        let mut jobs: Vec<SpooledJob> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|p| {
+                let json = fs::read_to_string(&p).ok()?;
+                match serde_json::from_str::<SpooledJob>(&json) {
+                    Ok(job) => Some(job),
+                    Err(e) => {
+                        log::warn!("Ignoring unreadable spooled job {:?}: {}", p, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        jobs.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        jobs
+    }
+
+    pub fn get(&self, id: &str) -> Option<SpooledJob> {
+        // Los IDs son nombres de archivo: rechazar rutas
+        if id.is_empty() || id.contains(['/', '\\', '.']) {
+            return None;
+        }
+        let json = fs::read_to_string(self.meta_path(id)).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    /// Quitar un trabajo de la cola (entregado o descartado)
+    pub fn remove(&self, id: &str) -> Result<SpooledJob, SpoolError> {
+        let job = self.get(id).ok_or_else(|| not_found(id))?;
+        let _ = fs::remove_file(self.payload_path(&job));
+        fs::remove_file(self.meta_path(id))
+            .map_err(|e| SpoolError::Storage(format!("Failed to remove spooled job: {}", e)))?;
+        Ok(job)
+    }
+
+    /// Datos del trabajo para el historial
+    fn history_context(&self, job: &SpooledJob) -> JobContext {
+        let data = fs::read(self.payload_path(job)).unwrap_or_default();
+        JobContext::new(JobSource::Spool, job.format.as_str(), &data, job.copies)
+            .with_request(job.origin.clone(), job.document_type.clone())
+    }
+
+    /// Cambiar la impresora de destino y reintentar en el próximo ciclo
+    pub fn reroute(&self, id: &str, printer: &str, now: i64) -> Result<SpooledJob, SpoolError> {
+        if printer.trim().is_empty() {
+            return Err(SpoolError::Delivery(PipelineError::new(ErrorKind::NoPrinter, "Printer required")));
+        }
+        let mut job = self.get(id).ok_or_else(|| not_found(id))?;
+        job.printer = printer.trim().to_string();
+        job.next_attempt_at = now;
+        self.save(&job)?;
+        Ok(job)
+    }
+
+    /// Registrar un intento fallido y programar el siguiente
+    pub fn record_failure(&self, job: &mut SpooledJob, printed: u32, error: &str, now: i64) -> Result<(), String> {
+        job.copies = job.copies.saturating_sub(printed).max(1);
+        job.attempts += 1;
+        job.last_error = Some(error.to_string());
+        job.next_attempt_at = now + backoff_secs(job.attempts);
+        self.save(job)
+    }
+
+    /// Trabajos cuyo próximo intento ya venció
+    pub fn due(&self, now: i64) -> Vec<SpooledJob> {
+        self.list().into_iter().filter(|j| j.next_attempt_at <= now).collect()
+    }
+}
+
+fn now_secs() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Guardar en la cola un trabajo que no se pudo imprimir
+pub async fn enqueue(state: &SharedAppState, source: &Path, job: NewJob) -> Result<SpooledJob, String> {
+    let source = source.to_path_buf();
+    let spooled = tokio::task::spawn_blocking(move || Spool::open()?.enqueue(&source, job, now_secs()))
+        .await
+        .map_err(|e| format!("Spool task failed: {}", e))??;
+
+    state.write().await.add_log(
+        "WARN",
+        &format!(
+            "Job {} for {} queued for retry: {}",
+            spooled.id,
+            spooled.printer,
+            spooled.last_error.as_deref().unwrap_or("")
+        ),
+    );
+    Ok(spooled)
+}
+
+/// Quitar de la cola un trabajo que ya no se reintentará y registrarlo como fallido
+async fn drop_failed(state: &SharedAppState, spool: &Spool, job: &SpooledJob, reason: String) -> Result<(), SpoolError> {
+    let context = spool.history_context(job);
+    spool.remove(&job.id)?;
+    history::record(context.finish(&job.printer, JobResult::Failed, Some(reason.clone()))).await;
+    state.write().await.add_log(
+        "ERROR",
+        &format!("Spooled job {} for {} dropped: {}", job.id, job.printer, reason),
+    );
+    Ok(())
+}
+
+/// Intentar entregar un trabajo con las mismas etapas que el primer intento (licencia,
+/// cuota, envío, verificación e historial). Sale de la cola si se imprime, si la impresora
+/// lo rechaza o si agotó sus intentos.
+async fn deliver(state: &SharedAppState, spool: &Spool, mut job: SpooledJob) -> Result<String, SpoolError> {
+    // Sin licencia o sin cuota sigue en cola hasta vencer
+    pipeline::validate(state, job.format).await.map_err(SpoolError::Delivery)?;
+    quota::reserve(state, job.copies)
+        .await
+        .map_err(|e| SpoolError::Delivery(PipelineError::new(ErrorKind::Quota, e)))?;
+
+    let payload = spool.payload_path(&job);
+    let context = spool.history_context(&job);
+    let delivery = Delivery {
+        printer: job.printer.clone(),
+        copies: job.copies,
+        media: job.media.clone(),
+        cut: job.cut,
+        format: job.format,
+        dpi: job.dpi,
+        // Ya aplicada al contenido guardado
+        code_page: None,
+    };
+
+    let since = SystemTime::now();
+    let submitted = pipeline::submit(state, &payload, &delivery).await;
+    let printed = match &submitted {
+        Ok(_) => job.copies,
+        Err(failure) => failure.printed(),
+    };
+    quota::settle(state, job.copies, printed).await;
+
+    match submitted {
+        Ok((used, job_ids)) => {
+            spool.remove(&job.id)?;
+            // Ya se envió: si no se puede verificar no se vuelve a encolar
+            let last_job = job_ids.last().copied().unwrap_or(0);
+            if let Err(e) = pipeline::verify(&used, last_job, since).await {
+                history::record(context.finish(&used, JobResult::Failed, Some(e.clone()))).await;
+                return Err(SpoolError::Delivery(PipelineError::new(ErrorKind::Verification, e)));
+            }
+
+            history::record(context.finish(&used, JobResult::Printed, None)).await;
+            let msg = format!("Spooled job {} printed on {}", job.id, used);
+            state.write().await.add_log("INFO", &msg);
+            Ok(msg)
+        }
+        Err(failure) => {
+            let now = now_secs();
+            spool.record_failure(&mut job, failure.printed(), &failure.message, now)?;
+            if !failure.transient {
+                // Rechazado por la impresora: fallaría igual en cada reintento
+                drop_failed(state, spool, &job, failure.message.clone()).await?;
+            } else if job.is_expired(now) {
+                let reason = format!("Expired after {} attempts: {}", job.attempts, failure.message);
+                drop_failed(state, spool, &job, reason).await?;
+            }
+            Err(SpoolError::Delivery(PipelineError::new(ErrorKind::Print, failure.message)))
+        }
+    }
+}
+
+/// Reintentar ya un trabajo de la cola
+pub async fn retry_now(state: &SharedAppState, id: &str) -> Result<String, SpoolError> {
+    let _guard = DELIVERY_LOCK.lock().await;
+    let spool = Spool::open()?;
+    let job = spool.get(id).ok_or_else(|| not_found(id))?;
+    deliver(state, &spool, job).await
+}
+
+/// Trabajos en cola
+pub fn list_jobs() -> Result<Vec<SpooledJob>, SpoolError> {
+    Ok(Spool::open()?.list())
+}
+
+/// Enviar un trabajo de la cola a otra impresora o grupo
+pub async fn reroute(state: &SharedAppState, id: &str, printer: &str) -> Result<SpooledJob, SpoolError> {
+    let _guard = DELIVERY_LOCK.lock().await;
+    let job = Spool::open()?.reroute(id, printer, now_secs())?;
+    state
+        .write()
+        .await
+        .add_log("INFO", &format!("Spooled job {} rerouted to {}", job.id, job.printer));
+    Ok(job)
+}
+
+/// Descartar un trabajo de la cola sin imprimirlo
+pub async fn discard(state: &SharedAppState, id: &str) -> Result<SpooledJob, SpoolError> {
+    let _guard = DELIVERY_LOCK.lock().await;
+    let spool = Spool::open()?;
+    let job = spool.get(id).ok_or_else(|| not_found(id))?;
+    let context = spool.history_context(&job);
+    spool.remove(id)?;
+    history::record(context.finish(&job.printer, JobResult::Discarded, job.last_error.clone())).await;
+    state
+        .write()
+        .await
+        .add_log("WARN", &format!("Spooled job {} for {} discarded", job.id, job.printer));
+    Ok(job)
+}
+
+/// Reintentar los trabajos vencidos y descartar los que agotaron su plazo.
+/// Retorna cuántos se imprimieron.
+pub async fn process_due(state: &SharedAppState) -> Result<usize, SpoolError> {
+    let _guard = DELIVERY_LOCK.lock().await;
+    let spool = Spool::open()?;
+
+    let mut delivered = 0;
+    let now = now_secs();
+    for job in spool.due(now) {
+        let id = job.id.clone();
+        if job.is_expired(now) {
+            let reason = format!(
+                "Expired after {} attempts: {}",
+                job.attempts,
+                job.last_error.as_deref().unwrap_or("not printed")
+            );
+            if let Err(e) = drop_failed(state, &spool, &job, reason).await {
+                log::warn!("Could not drop expired job {}: {}", id, e);
+            }
+            continue;
+        }
+        match deliver(state, &spool, job).await {
+            Ok(_) => delivered += 1,
+            Err(e) => log::info!("Spooled job {} still pending: {}", id, e),
+        }
+    }
+    Ok(delivered)
+}
+
+/// Tarea de fondo: reintentar periódicamente los trabajos en cola
+pub async fn run(state: SharedAppState) {
+    loop {
+        if let Err(e) = process_due(&state).await {
+            log::warn!("Spool retry failed: {}", e);
+        }
+        sleep(RETRY_TICK).await;
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_job() -> NewJob {
+        NewJob {
+            printer: "Caja".to_string(),
+            format: SpoolFormat::Pdf,
+            copies: 2,
+            media: None,
+            dpi: None,
+            cut: false,
+            origin: Some("https://caja.isipass.net".to_string()),
+            document_type: Some("factura".to_string()),
+            error: "Printer offline".to_string(),
+        }
+    }
+
+    fn spool_with_job() -> (tempfile::TempDir, Spool, SpooledJob) {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = Spool::at(dir.path().join("spool")).unwrap();
+        let source = dir.path().join("job.pdf");
+        fs::write(&source, b"%PDF-1.4").unwrap();
+        let job = spool.enqueue(&source, new_job(), 1_000).unwrap();
+        (dir, spool, job)
+    }
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        assert_eq!(backoff_secs(1), 15);
+        assert_eq!(backoff_secs(2), 30);
+        assert_eq!(backoff_secs(3), 60);
+        assert_eq!(backoff_secs(100), BACKOFF_MAX_SECS);
+    }
+
+    #[test]
+    fn test_enqueue_persists_payload_and_metadata() {
+        let (_dir, spool, job) = spool_with_job();
+
+        assert_eq!(fs::read(spool.payload_path(&job)).unwrap(), b"%PDF-1.4");
+        assert_eq!(spool.list(), vec![job.clone()]);
+        assert_eq!(job.next_attempt_at, 1_015);
+        assert!(spool.due(1_014).is_empty());
+        assert_eq!(spool.due(1_015).len(), 1);
+    }
+
+    #[test]
+    fn test_failure_reschedules_remaining_copies() {
+        let (_dir, spool, mut job) = spool_with_job();
+
+        spool.record_failure(&mut job, 1, "Paper out", 2_000).unwrap();
+        let stored = spool.get(&job.id).unwrap();
+        assert_eq!(stored.copies, 1);
+        assert_eq!(stored.attempts, 2);
+        assert_eq!(stored.last_error.as_deref(), Some("Paper out"));
+        assert_eq!(stored.next_attempt_at, 2_030);
+    }
+
+    #[test]
+    fn test_reroute_and_discard() {
+        let (_dir, spool, job) = spool_with_job();
+
+        let rerouted = spool.reroute(&job.id, "Cocina-2", 1_001).unwrap();
+        assert_eq!(rerouted.printer, "Cocina-2");
+        assert_eq!(spool.due(1_001).len(), 1);
+        assert!(spool.reroute(&job.id, " ", 1_001).is_err());
+
+        let payload = spool.payload_path(&job);
+        spool.remove(&job.id).unwrap();
+        assert!(spool.list().is_empty());
+        assert!(!payload.exists());
+        assert!(spool.remove(&job.id).is_err());
+    }
+
+    #[test]
+    fn test_jobs_expire_by_age_only() {
+        let (_dir, spool, mut job) = spool_with_job();
+        assert!(!job.is_expired(1_000));
+        assert!(job.is_expired(1_000 + MAX_AGE_SECS));
+
+        // Impresora caída durante horas: muchos intentos, pero el recibo no se pierde
+        for _ in 0..50 {
+            spool.record_failure(&mut job, 0, "Printer offline", 2_000).unwrap();
+        }
+        assert_eq!(job.attempts, 51);
+        assert!(!job.is_expired(1_000 + MAX_AGE_SECS - 1));
+    }
+
+    #[test]
+    fn test_missing_job_is_not_found() {
+        let (_dir, spool, job) = spool_with_job();
+        spool.remove(&job.id).unwrap();
+        assert!(matches!(spool.remove(&job.id), Err(SpoolError::NotFound(_))));
+        assert!(matches!(spool.reroute(&job.id, "Caja", 1_001), Err(SpoolError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_get_rejects_paths() {
+        let (_dir, spool, _job) = spool_with_job();
+        assert!(spool.get("../session").is_none());
+        assert!(spool.get("").is_none());
+    }
+}
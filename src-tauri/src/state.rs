@@ -1,6 +1,14 @@
 // Estado global de la aplicación
+use crate::approvals::Approvals;
+use crate::graphql::ApiConfig;
+use crate::http_server::HttpServerStatus;
+use crate::license::{LicenseConfig, SignedLicense};
+use crate::logging;
+use crate::printer::PrinterDefaults;
+use crate::quota::{QuotaStatus, QuotaUsage};
+use crate::settings::Settings;
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 /// Información de licencia del producto
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,24 +40,93 @@ pub struct LogEntry {
     pub timestamp: String,
     pub level: String,
     pub message: String,
+    /// ID de la petición HTTP que originó el mensaje
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
+/// Estado de salud de una impresora configurada
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    #[default]
+    Unknown,
+    Online,
+    Offline,
+    Error,
+}
+
+impl HealthStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthStatus::Unknown => "unknown",
+            HealthStatus::Online => "online",
+            HealthStatus::Offline => "offline",
+            HealthStatus::Error => "error",
+        }
+    }
+}
+
+/// Cambio de estado registrado por el monitor de salud
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthEvent {
+    pub timestamp: String,
+    pub status: HealthStatus,
+    pub message: Option<String>,
+}
+
+/// Estado actual e historial de transiciones de una impresora
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PrinterHealth {
+    pub status: HealthStatus,
+    pub message: Option<String>,
+    pub last_checked: Option<String>,
+    pub history: VecDeque<HealthEvent>,
+}
+
+/// Máximo de transiciones guardadas por impresora
+const MAX_HEALTH_HISTORY: usize = 50;
+
 /// Estado global de la aplicación
 #[derive(Debug, Default)]
 pub struct AppState {
     pub auth: AuthState,
     pub licencias: Vec<LicenciaProducto>,
     pub logs: VecDeque<LogEntry>,
-    pub print_count: i32,
+    /// Impresiones del período actual de la licencia (persistidas y reportadas al backend)
+    pub quota: QuotaUsage,
+    pub printer_health: HashMap<String, PrinterHealth>,
+    /// Backend GraphQL en uso (sandbox, producción o custom)
+    pub api: ApiConfig,
+    /// Última verificación exitosa de licencias con el backend (segundos Unix)
+    pub licenses_verified_at: Option<i64>,
+    /// Licencias firmadas por el servidor, las únicas que se restauran sin conexión
+    pub signed_license: Option<SignedLicense>,
+    /// Configuración centralizada recibida con la licencia de impresión
+    pub license_config: LicenseConfig,
+    /// Configuración local (settings.json)
+    pub settings: Settings,
+    /// Próximo miembro de cada grupo de impresoras (round-robin)
+    pub pool_cursors: HashMap<String, usize>,
+    /// Direcciones abiertas por el servidor HTTP local
+    pub http_server: HttpServerStatus,
+    /// Orígenes desconocidos esperando la aprobación del usuario
+    pub origin_approvals: Approvals,
+    /// Clave de API para peticiones HTTP sin origen
+    pub api_key: Option<String>,
 }
 
 impl AppState {
     /// Agregar un log al estado
+    /// También se escribe en el archivo de log (ver `logging`)
     pub fn add_log(&mut self, level: &str, message: &str) {
+        log::log!(target: "print_client_lib::app", logging::level_from_str(level), "{}", message);
+
         let entry = LogEntry {
             timestamp: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
             level: level.to_string(),
             message: message.to_string(),
+            request_id: logging::current_request_id(),
         };
         
         // Mantener solo los últimos 100 logs
@@ -59,6 +136,44 @@ impl AppState {
         self.logs.push_back(entry);
     }
 
+    /// Registrar el resultado de un chequeo de salud.
+    /// Retorna el estado anterior si hubo una transición.
+    pub fn record_printer_health(
+        &mut self,
+        printer: &str,
+        status: HealthStatus,
+        message: Option<String>,
+    ) -> Option<HealthStatus> {
+        let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let health = self.printer_health.entry(printer.to_string()).or_default();
+        let previous = health.status;
+
+        health.last_checked = Some(now.clone());
+        health.message = message.clone();
+
+        if previous == status {
+            return None;
+        }
+
+        health.status = status;
+        if health.history.len() >= MAX_HEALTH_HISTORY {
+            health.history.pop_front();
+        }
+        health.history.push_back(HealthEvent {
+            timestamp: now,
+            status,
+            message,
+        });
+
+        Some(previous)
+    }
+
+    /// Olvidar el estado de las impresoras que ya no existen en el sistema
+    pub fn prune_printer_health(&mut self, printers: &[String]) {
+        self.printer_health
+            .retain(|name, _| printers.iter().any(|p| p == name));
+    }
+
     /// Verificar si la licencia está vigente
     pub fn is_license_valid(&self) -> bool {
         if let Some(licencia) = self.licencias.iter().find(|l| l.tipo_producto == "IMPRESION") {
@@ -78,12 +193,115 @@ impl AppState {
         false
     }
 
-    /// Verificar si se puede imprimir (límite de conexiones)
-    pub fn can_print(&self) -> bool {
-        if let Some(licencia) = self.licencias.iter().find(|l| l.tipo_producto == "IMPRESION") {
-            return self.print_count < licencia.maximo_conexiones;
+    /// Verificar licencia vigente y verificada con el backend dentro del período de gracia
+    pub fn check_license(&self) -> Result<(), String> {
+        if !self.is_license_valid() {
+            return Err("License expired or invalid".to_string());
         }
-        false
+
+        let grace = crate::license::grace_period_secs();
+        let now = chrono::Utc::now().timestamp();
+        if !crate::license::is_within_grace(self.licenses_verified_at, now, grace) {
+            return Err(format!(
+                "License could not be verified with the server in the last {} hours",
+                grace / 3600
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Licencia de impresión del usuario
+    pub fn print_license(&self) -> Option<&LicenciaProducto> {
+        self.licencias.iter().find(|l| l.tipo_producto == "IMPRESION")
+    }
+
+    /// Alinear el contador con el período de la licencia actual.
+    /// Retorna true si se inició un período nuevo.
+    pub fn sync_quota_period(&mut self) -> bool {
+        match self.print_license() {
+            Some(licencia) if !self.quota.is_period_of(licencia) => {
+                self.quota = QuotaUsage::for_license(licencia);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Impresiones usadas en el período de la licencia actual
+    fn quota_used(&self, licencia: &LicenciaProducto) -> i32 {
+        if self.quota.is_period_of(licencia) {
+            self.quota.used
+        } else {
+            0
+        }
+    }
+
+    /// Verificar si se puede imprimir (cuota de impresiones del período)
+    pub fn check_quota(&self) -> Result<(), String> {
+        let licencia = self.print_license().ok_or("No print license found")?;
+        let used = self.quota_used(licencia);
+
+        if used >= licencia.maximo_conexiones {
+            return Err(format!(
+                "Print quota exhausted: {} of {} prints used for the license period ending {}",
+                used, licencia.maximo_conexiones, licencia.fecha_vencimiento
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reservar impresiones del período actual: la verificación y el conteo se
+    /// hacen juntos para que impresiones simultáneas no superen el límite
+    pub fn reserve_prints(&mut self, count: u32) -> Result<(), String> {
+        self.check_quota()?;
+        self.sync_quota_period();
+        let limit = self.print_license().map(|l| l.maximo_conexiones).unwrap_or(0);
+        let remaining = limit - self.quota.used;
+        let requested = i32::try_from(count)
+            .map_err(|_| format!("Invalid number of prints: {}", count))?;
+        if requested > remaining {
+            return Err(format!(
+                "Not enough print quota: {} prints requested, {} remaining",
+                count, remaining
+            ));
+        }
+        self.quota.used += requested;
+        Ok(())
+    }
+
+    /// Devolver impresiones reservadas que no se hicieron (nunca las ya reportadas)
+    pub fn release_prints(&mut self, count: u32) {
+        let count = i32::try_from(count).unwrap_or(i32::MAX);
+        self.quota.used = self.quota.used.saturating_sub(count).max(self.quota.reported);
+    }
+
+    /// Impresoras por defecto de ISIPRINT para un tipo de documento
+    pub fn printer_defaults(&self, document_type: Option<&str>) -> PrinterDefaults {
+        PrinterDefaults {
+            settings: self.settings.default_printer_for(document_type).map(str::to_string),
+            license: self.license_config.default_printer.clone(),
+        }
+    }
+
+    /// Estado de la cuota para mostrar al usuario
+    pub fn quota_status(&self) -> Option<QuotaStatus> {
+        let licencia = self.print_license()?;
+        let used = self.quota_used(licencia);
+        let pending_sync = if self.quota.is_period_of(licencia) {
+            self.quota.pending()
+        } else {
+            0
+        };
+
+        Some(QuotaStatus {
+            license_id: licencia._id.clone(),
+            period_end: licencia.fecha_vencimiento.clone(),
+            used,
+            limit: licencia.maximo_conexiones,
+            remaining: (licencia.maximo_conexiones - used).max(0),
+            pending_sync,
+        })
     }
 }
 
@@ -113,7 +331,7 @@ mod tests {
         assert!(state.auth.token.is_none());
         assert!(state.licencias.is_empty());
         assert!(state.logs.is_empty());
-        assert_eq!(state.print_count, 0);
+        assert_eq!(state.quota.used, 0);
     }
 
     #[test]
@@ -194,48 +412,163 @@ mod tests {
     }
 
     #[test]
-    fn test_can_print_within_limit() {
+    fn test_check_license_requires_recent_verification() {
+        let mut state = AppState::default();
+        state.licencias.push(create_test_license(
+            "01/01/2030 00:00:00",
+            "ACTIVADO",
+            100
+        ));
+
+        // Nunca verificada con el backend
+        assert!(state.check_license().is_err());
+
+        state.licenses_verified_at = Some(chrono::Utc::now().timestamp());
+        assert!(state.check_license().is_ok());
+
+        // Verificada hace más que el período de gracia
+        state.licenses_verified_at = Some(chrono::Utc::now().timestamp() - 365 * 24 * 3600);
+        assert!(state.check_license().is_err());
+    }
+
+    #[test]
+    fn test_check_quota_within_limit() {
         let mut state = AppState::default();
         state.licencias.push(create_test_license(
             "01/01/2030 00:00:00",
             "ACTIVADO",
             100
         ));
-        state.print_count = 50;
+        state.sync_quota_period();
+        state.quota.used = 50;
         
-        assert!(state.can_print(), "Debería poder imprimir (50 < 100)");
+        assert!(state.check_quota().is_ok(), "Debería poder imprimir (50 < 100)");
     }
 
     #[test]
-    fn test_can_print_at_limit() {
+    fn test_check_quota_at_limit() {
         let mut state = AppState::default();
         state.licencias.push(create_test_license(
             "01/01/2030 00:00:00",
             "ACTIVADO",
             100
         ));
-        state.print_count = 100;
+        state.sync_quota_period();
+        state.quota.used = 100;
         
-        assert!(!state.can_print(), "NO debería poder imprimir (100 >= 100)");
+        assert!(state.check_quota().is_err(), "NO debería poder imprimir (100 >= 100)");
+    }
+
+    #[test]
+    fn test_reserve_and_release_prints() {
+        let mut state = AppState::default();
+        state.licencias.push(create_test_license("01/01/2030 00:00:00", "ACTIVADO", 10));
+        state.sync_quota_period();
+        state.quota.used = 7;
+        state.quota.reported = 6;
+
+        // Más copias que las restantes: no se reserva nada
+        assert!(state.reserve_prints(4).is_err());
+        assert_eq!(state.quota.used, 7);
+
+        assert!(state.reserve_prints(3).is_ok());
+        assert_eq!(state.quota.used, 10);
+        assert!(state.reserve_prints(1).is_err());
+
+        state.release_prints(2);
+        assert_eq!(state.quota.used, 8);
+        // Lo ya reportado al backend no se devuelve
+        state.release_prints(10);
+        assert_eq!(state.quota.used, 6);
+
+        // Cantidades que no caben en i32 no dan la vuelta a negativo
+        assert!(state.reserve_prints(u32::MAX).is_err());
+        assert!(state.reserve_prints(i32::MAX as u32 + 1).is_err());
+        assert_eq!(state.quota.used, 6);
+        state.release_prints(u32::MAX);
+        assert_eq!(state.quota.used, 6);
     }
 
     #[test]
-    fn test_can_print_over_limit() {
+    fn test_check_quota_over_limit() {
         let mut state = AppState::default();
         state.licencias.push(create_test_license(
             "01/01/2030 00:00:00",
             "ACTIVADO",
             100
         ));
-        state.print_count = 150;
+        state.sync_quota_period();
+        state.quota.used = 150;
         
-        assert!(!state.can_print(), "NO debería poder imprimir (150 > 100)");
+        assert!(state.check_quota().is_err(), "NO debería poder imprimir (150 > 100)");
     }
 
     #[test]
-    fn test_can_print_no_license() {
+    fn test_check_quota_no_license() {
         let state = AppState::default();
-        assert!(!state.can_print(), "Sin licencia NO debería poder imprimir");
+        assert!(state.check_quota().is_err(), "Sin licencia NO debería poder imprimir");
+    }
+
+    #[test]
+    fn test_quota_resets_on_license_renewal() {
+        let mut state = AppState::default();
+        state.licencias.push(create_test_license("01/01/2030 00:00:00", "ACTIVADO", 100));
+        state.sync_quota_period();
+        state.quota.used = 100;
+        assert!(state.check_quota().is_err());
+
+        // Renovación: nuevo vencimiento, nuevo período
+        state.licencias[0].fecha_vencimiento = "01/01/2031 00:00:00".to_string();
+        assert!(state.check_quota().is_ok());
+        assert!(state.sync_quota_period());
+
+        state.reserve_prints(1).unwrap();
+        let status = state.quota_status().unwrap();
+        assert_eq!(status.used, 1);
+        assert_eq!(status.remaining, 99);
+        assert_eq!(status.pending_sync, 1);
+    }
+
+    #[test]
+    fn test_record_printer_health_transitions() {
+        let mut state = AppState::default();
+
+        assert_eq!(
+            state.record_printer_health("Caja", HealthStatus::Online, None),
+            Some(HealthStatus::Unknown)
+        );
+        // Mismo estado: no es transición
+        assert_eq!(state.record_printer_health("Caja", HealthStatus::Online, None), None);
+        assert_eq!(
+            state.record_printer_health("Caja", HealthStatus::Offline, Some("timeout".to_string())),
+            Some(HealthStatus::Online)
+        );
+
+        let health = &state.printer_health["Caja"];
+        assert_eq!(health.status, HealthStatus::Offline);
+        assert_eq!(health.history.len(), 2);
+        assert_eq!(health.message.as_deref(), Some("timeout"));
+    }
+
+    #[test]
+    fn test_printer_health_history_capped() {
+        let mut state = AppState::default();
+        for i in 0..120 {
+            let status = if i % 2 == 0 { HealthStatus::Online } else { HealthStatus::Offline };
+            state.record_printer_health("Cocina", status, None);
+        }
+        assert_eq!(state.printer_health["Cocina"].history.len(), MAX_HEALTH_HISTORY);
+    }
+
+    #[test]
+    fn test_prune_printer_health() {
+        let mut state = AppState::default();
+        state.record_printer_health("Caja", HealthStatus::Online, None);
+        state.record_printer_health("Eliminada", HealthStatus::Offline, None);
+
+        state.prune_printer_health(&["Caja".to_string()]);
+        assert!(state.printer_health.contains_key("Caja"));
+        assert!(!state.printer_health.contains_key("Eliminada"));
     }
 
     #[test]
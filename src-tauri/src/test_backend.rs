@@ -0,0 +1,88 @@
+// Backend GraphQL local para tests (sustituye al servidor real)
+use crate::graphql::{ApiConfig, ApiEnvironment};
+use axum::{extract::State, routing::post, Json, Router};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+
+/// Petición recibida: nombre de la operación y variables
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub operation_name: String,
+    pub variables: Value,
+    pub authorization: Option<String>,
+}
+
+type Handler = dyn Fn(&str, &Value) -> Value + Send + Sync;
+
+#[derive(Clone)]
+struct BackendState {
+    handler: Arc<Handler>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+/// Servidor GraphQL en 127.0.0.1 con puerto efímero.
+/// `handler` recibe (operationName, variables) y devuelve el cuerpo JSON de la respuesta.
+pub struct TestBackend {
+    pub api: ApiConfig,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl TestBackend {
+    pub async fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&str, &Value) -> Value + Send + Sync + 'static,
+    {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let state = BackendState {
+            handler: Arc::new(handler),
+            requests: requests.clone(),
+        };
+
+        let app = Router::new().route("/api", post(graphql)).with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        Self {
+            api: ApiConfig {
+                environment: ApiEnvironment::Custom,
+                api_url: format!("http://{}/api", addr),
+                shop: "test".to_string(),
+            },
+            requests,
+        }
+    }
+
+    /// Peticiones recibidas con una operación dada
+    pub fn requests(&self, operation_name: &str) -> Vec<RecordedRequest> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.operation_name == operation_name)
+            .cloned()
+            .collect()
+    }
+}
+
+async fn graphql(
+    State(state): State<BackendState>,
+    headers: axum::http::HeaderMap,
+    Json(body): Json<Value>,
+) -> Json<Value> {
+    let operation_name = body["operationName"].as_str().unwrap_or("").to_string();
+    let variables = body["variables"].clone();
+
+    state.requests.lock().unwrap().push(RecordedRequest {
+        operation_name: operation_name.clone(),
+        variables: variables.clone(),
+        authorization: headers
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+    });
+
+    Json((state.handler)(&operation_name, &variables))
+}
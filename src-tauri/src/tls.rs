@@ -0,0 +1,383 @@
+// HTTPS local: CA propia de un solo uso, certificado del servidor y renovación
+use crate::persistence;
+use crate::settings::HttpSettings;
+use crate::SharedAppState;
+use axum_server::tls_rustls::RustlsConfig;
+use chrono::{DateTime, Datelike, Duration, Utc};
+use rcgen::{
+    BasicConstraints, CertificateParams, CidrSubnet, DnType, ExtendedKeyUsagePurpose,
+    GeneralSubtree, IsCa, KeyPair, KeyUsagePurpose, NameConstraints, SerialNumber,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Validez del certificado del servidor (los navegadores rechazan más de 398 días).
+/// La CA vale lo mismo: solo firma ese certificado y su clave no se guarda.
+const CERT_VALIDITY_DAYS: i64 = 365;
+
+/// Se renueva cuando falta menos que esto para el vencimiento
+const RENEW_BEFORE_DAYS: i64 = 30;
+
+/// Intervalo de revisión del vencimiento
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(12 * 60 * 60);
+
+const CA_NAME: &str = "ISIPRINT Local CA";
+
+/// Vencimiento y nombres del material generado (tls.json)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TlsState {
+    cert_expires_at: i64,
+    names: Vec<String>,
+}
+
+/// Resultado de `TlsFiles::ensure`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Issued {
+    /// El certificado vigente sigue sirviendo
+    Current,
+    /// Primera emisión: la CA se instala una vez
+    New,
+    /// Se generó una CA nueva en lugar de la anterior: hay que volver a instalarla
+    Replaced,
+}
+
+/// Nombres del certificado del servidor: loopback más las direcciones configuradas
+pub fn server_names(http: &HttpSettings) -> Vec<String> {
+    let mut names = vec![
+        "localhost".to_string(),
+        "127.0.0.1".to_string(),
+        "::1".to_string(),
+    ];
+    let hosts =
+        std::iter::once(http.host.as_str()).chain(http.listeners.iter().map(|l| l.host.as_str()));
+    for host in hosts {
+        let unspecified = host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_unspecified());
+        if !unspecified && !names.iter().any(|n| n == host) {
+            names.push(host.to_string());
+        }
+    }
+    names
+}
+
+/// Validez desde ayer (relojes desfasados) hasta `days` días
+fn set_validity(params: &mut CertificateParams, now: DateTime<Utc>, days: i64) {
+    let date = |d: DateTime<Utc>| rcgen::date_time_ymd(d.year(), d.month() as u8, d.day() as u8);
+    params.not_before = date(now - Duration::days(1));
+    params.not_after = date(now + Duration::days(days));
+}
+
+fn random_serial() -> SerialNumber {
+    // Positivo: el primer bit en cero
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[0] &= 0x7f;
+    SerialNumber::from_slice(&bytes)
+}
+
+/// Restringir la CA a los nombres del servidor: aunque alguien obtuviera la clave,
+/// no serviría para firmar certificados de otros sitios
+fn name_constraints(names: &[String]) -> NameConstraints {
+    let permitted_subtrees = names
+        .iter()
+        .map(|name| match name.parse::<std::net::IpAddr>() {
+            Ok(ip) => {
+                let prefix = if ip.is_ipv4() { 32 } else { 128 };
+                GeneralSubtree::IpAddress(CidrSubnet::from_addr_prefix(ip, prefix))
+            }
+            Err(_) => GeneralSubtree::DnsName(name.clone()),
+        })
+        .collect();
+    NameConstraints {
+        permitted_subtrees,
+        excluded_subtrees: Vec::new(),
+    }
+}
+
+/// Parámetros de la CA, limitada a los nombres del certificado del servidor
+fn ca_params(names: &[String], now: DateTime<Utc>) -> Result<CertificateParams, String> {
+    let mut params = CertificateParams::new(Vec::<String>::new()).map_err(|e| e.to_string())?;
+    params.distinguished_name.push(DnType::CommonName, CA_NAME);
+    params
+        .distinguished_name
+        .push(DnType::OrganizationName, "ISIPRINT");
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    params.name_constraints = Some(name_constraints(names));
+    params.serial_number = Some(random_serial());
+    set_validity(&mut params, now, CERT_VALIDITY_DAYS);
+    Ok(params)
+}
+
+/// Certificados de la API local en el directorio de datos
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    dir: PathBuf,
+}
+
+impl TlsFiles {
+    pub fn open() -> Result<Self, String> {
+        let dir = persistence::get_tls_dir().ok_or("Could not find data directory")?;
+        Self::at(dir)
+    }
+
+    pub fn at(dir: PathBuf) -> Result<Self, String> {
+        persistence::create_private_dir(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Certificado de la CA, el que se instala en el sistema o el navegador
+    pub fn ca_path(&self) -> PathBuf {
+        self.dir.join("ca.pem")
+    }
+
+    /// Clave de la CA que guardaban las versiones anteriores (se borra)
+    fn legacy_ca_key_path(&self) -> PathBuf {
+        self.dir.join("ca.key")
+    }
+
+    /// Certificado del servidor con la cadena (servidor + CA)
+    pub fn cert_path(&self) -> PathBuf {
+        self.dir.join("cert.pem")
+    }
+
+    pub fn key_path(&self) -> PathBuf {
+        self.dir.join("key.pem")
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.dir.join("tls.json")
+    }
+
+    fn load_state(&self) -> Option<TlsState> {
+        let files = [self.ca_path(), self.cert_path(), self.key_path()];
+        if !files.iter().all(|f| f.exists()) {
+            return None;
+        }
+        let json = fs::read_to_string(self.state_path()).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    fn write(&self, path: PathBuf, contents: &str) -> Result<(), String> {
+        persistence::write_private_file(&path, contents.as_bytes())
+    }
+
+    /// Generar el certificado del servidor si falta, está por vencer o cambiaron los nombres.
+    /// Cada emisión usa una CA nueva cuya clave se descarta después de firmar.
+    /// Una CA de versiones anteriores (clave guardada, sin restricciones) se reemplaza.
+    pub fn ensure(&self, names: &[String], now: DateTime<Utc>) -> Result<Issued, String> {
+        let legacy_key = self.legacy_ca_key_path();
+        let legacy = legacy_key.exists();
+        if legacy {
+            fs::remove_file(&legacy_key)
+                .map_err(|e| format!("Error removing local CA key: {}", e))?;
+        }
+
+        let renew_at = (now + Duration::days(RENEW_BEFORE_DAYS)).timestamp();
+        let state = self.load_state();
+        let valid = !legacy
+            && state
+                .as_ref()
+                .is_some_and(|s| s.cert_expires_at > renew_at && s.names == names);
+        if valid {
+            return Ok(Issued::Current);
+        }
+
+        let error = |e: rcgen::Error| format!("Error generating HTTPS certificate: {}", e);
+        let ca_key = KeyPair::generate().map_err(error)?;
+        let ca_cert = ca_params(names, now)?.self_signed(&ca_key).map_err(error)?;
+
+        let mut params = CertificateParams::new(names.to_vec()).map_err(error)?;
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "ISIPRINT Local API");
+        params.is_ca = IsCa::NoCa;
+        params.key_usages = vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyEncipherment,
+        ];
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        params.use_authority_key_identifier_extension = true;
+        params.serial_number = Some(random_serial());
+        set_validity(&mut params, now, CERT_VALIDITY_DAYS);
+
+        let key = KeyPair::generate().map_err(error)?;
+        let cert = params.signed_by(&key, &ca_cert, &ca_key).map_err(error)?;
+        // La clave de la CA nunca se escribe en disco
+        drop(ca_key);
+
+        let ca_pem = ca_cert.pem();
+        self.write(self.ca_path(), &ca_pem)?;
+        self.write(self.key_path(), &key.serialize_pem())?;
+        self.write(self.cert_path(), &format!("{}{}", cert.pem(), ca_pem))?;
+
+        let new_state = TlsState {
+            cert_expires_at: (now + Duration::days(CERT_VALIDITY_DAYS)).timestamp(),
+            names: names.to_vec(),
+        };
+        let json = serde_json::to_string_pretty(&new_state).map_err(|e| e.to_string())?;
+        self.write(self.state_path(), &json)?;
+
+        if state.is_some() || legacy {
+            Ok(Issued::Replaced)
+        } else {
+            Ok(Issued::New)
+        }
+    }
+}
+
+/// Configuración TLS del servidor con el certificado actual
+pub async fn load_config(files: &TlsFiles) -> Result<RustlsConfig, String> {
+    // Varias dependencias activan proveedores distintos de rustls: se fija ring
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    RustlsConfig::from_pem_file(files.cert_path(), files.key_path())
+        .await
+        .map_err(|e| format!("Could not load HTTPS certificate: {}", e))
+}
+
+/// Revisar periódicamente el vencimiento y recargar el certificado renovado sin reiniciar
+pub async fn run_renewal(
+    files: TlsFiles,
+    names: Vec<String>,
+    config: RustlsConfig,
+    state: SharedAppState,
+) {
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+
+        let (check, names_for_check) = (files.clone(), names.clone());
+        let renewed =
+            tokio::task::spawn_blocking(move || check.ensure(&names_for_check, Utc::now()))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|r| r);
+
+        match renewed {
+            Ok(Issued::Current) => {}
+            Ok(_) => match config
+                .reload_from_pem_file(files.cert_path(), files.key_path())
+                .await
+            {
+                Ok(()) => {
+                    let mut app_state = state.write().await;
+                    app_state.http_server.ca_reinstall = true;
+                    app_state.add_log(
+                        "WARN",
+                        "HTTPS certificate renewed with a new local CA: export and install it again",
+                    );
+                }
+                Err(e) => state.write().await.add_log(
+                    "ERROR",
+                    &format!("Could not load renewed HTTPS certificate: {}", e),
+                ),
+            },
+            Err(e) => state
+                .write()
+                .await
+                .add_log("ERROR", &format!("HTTPS certificate renewal failed: {}", e)),
+        }
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        server_names(&HttpSettings::default())
+    }
+
+    #[test]
+    fn test_server_names_skip_unspecified() {
+        let mut http = HttpSettings::default();
+        http.host = "0.0.0.0".to_string();
+        http.listeners.push(crate::settings::HttpListener {
+            host: "192.168.1.20".to_string(),
+            port: 7777,
+        });
+        assert_eq!(
+            server_names(&http),
+            vec!["localhost", "127.0.0.1", "::1", "192.168.1.20"]
+        );
+    }
+
+    #[test]
+    fn test_generates_once_and_replaces_ca_on_renewal() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = TlsFiles::at(dir.path().to_path_buf()).unwrap();
+        let now = Utc::now();
+
+        assert_eq!(files.ensure(&names(), now).unwrap(), Issued::New);
+        let ca = fs::read_to_string(files.ca_path()).unwrap();
+        let cert = fs::read_to_string(files.cert_path()).unwrap();
+        assert!(ca.starts_with("-----BEGIN CERTIFICATE-----"));
+        // Cadena: servidor + CA
+        assert_eq!(cert.matches("BEGIN CERTIFICATE").count(), 2);
+        assert!(cert.ends_with(&ca));
+        // La clave de la CA no queda en disco
+        assert!(!files.legacy_ca_key_path().exists());
+
+        // Vigente: no cambia
+        assert_eq!(files.ensure(&names(), now).unwrap(), Issued::Current);
+
+        // Por vencer: certificado y CA nuevos, hay que volver a instalarla
+        let later = now + Duration::days(CERT_VALIDITY_DAYS - RENEW_BEFORE_DAYS + 1);
+        assert_eq!(files.ensure(&names(), later).unwrap(), Issued::Replaced);
+        assert_ne!(fs::read_to_string(files.ca_path()).unwrap(), ca);
+        assert_ne!(fs::read_to_string(files.cert_path()).unwrap(), cert);
+    }
+
+    #[test]
+    fn test_replaces_ca_with_stored_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = TlsFiles::at(dir.path().to_path_buf()).unwrap();
+        let now = Utc::now();
+
+        files.ensure(&names(), now).unwrap();
+        let ca = fs::read_to_string(files.ca_path()).unwrap();
+        // CA de una versión anterior, con la clave en disco
+        fs::write(files.legacy_ca_key_path(), "clave").unwrap();
+        assert_eq!(files.ensure(&names(), now).unwrap(), Issued::Replaced);
+        assert!(!files.legacy_ca_key_path().exists());
+        assert_ne!(fs::read_to_string(files.ca_path()).unwrap(), ca);
+        assert_eq!(files.ensure(&names(), now).unwrap(), Issued::Current);
+    }
+
+    #[test]
+    fn test_ca_is_limited_to_server_names() {
+        let constraints = name_constraints(&names());
+        assert_eq!(
+            constraints.permitted_subtrees,
+            vec![
+                GeneralSubtree::DnsName("localhost".to_string()),
+                GeneralSubtree::IpAddress(CidrSubnet::V4([127, 0, 0, 1], [255; 4])),
+                GeneralSubtree::IpAddress(CidrSubnet::V6(
+                    std::net::Ipv6Addr::LOCALHOST.octets(),
+                    [255; 16]
+                )),
+            ]
+        );
+        assert!(constraints.excluded_subtrees.is_empty());
+    }
+
+    #[test]
+    fn test_renews_when_names_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = TlsFiles::at(dir.path().to_path_buf()).unwrap();
+        let now = Utc::now();
+
+        files.ensure(&names(), now).unwrap();
+        let mut lan = names();
+        lan.push("192.168.1.20".to_string());
+        assert_eq!(files.ensure(&lan, now).unwrap(), Issued::Replaced);
+        assert_eq!(files.ensure(&lan, now).unwrap(), Issued::Current);
+    }
+}
@@ -7,6 +7,7 @@ import { LoginScreen } from "./components/LoginScreen";
 import { AccountTab } from "./components/AccountTab";
 import { PrintersTab } from "./components/PrintersTab";
 import { LogsTab } from "./components/LogsTab";
+import { OriginApprovalDialog } from "./components/OriginApprovalDialog";
 import { SimpleIcon } from "./components/LordIcon";
 import { StaticLogo, AnimatedLogo } from "./components/AnimatedLogo";
 import type { AuthState } from "./types";
@@ -151,7 +152,12 @@ function App() {
 
     // Show login screen if not authenticated
     if (!authState.is_logged_in) {
-        return <LoginScreen onLoginSuccess={setAuthState} />;
+        return (
+            <>
+                <LoginScreen onLoginSuccess={setAuthState} />
+                <OriginApprovalDialog />
+            </>
+        );
     }
 
     // Main app (authenticated)
@@ -437,6 +443,8 @@ function App() {
                     </motion.div>
                 </AnimatePresence>
             </main>
+
+            <OriginApprovalDialog />
         </div>
     );
 }
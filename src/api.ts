@@ -1,12 +1,29 @@
 import { invoke as tauriInvoke } from "@tauri-apps/api/core";
 import type {
+  ApiConfig,
+  ApiEnvironment,
+  ApprovalChoice,
   AuthState,
   CommandResponse,
+  DefaultPrinter,
+  DeviceInfo,
+  HistoryPage,
+  HistoryQuery,
+  HttpServerStatus,
   LicenciaProducto,
+  LicenseConfig,
   LogEntry,
   NetworkPrinter,
+  OriginPermission,
+  PendingOrigin,
   PrintJob,
+  PrintProfile,
+  PrinterHealth,
   PrintSettings,
+  ProtocolProbe,
+  QuotaStatus,
+  Settings,
+  SpooledJob,
 } from "./types";
 
 // Check if we're running inside Tauri
@@ -52,6 +69,18 @@ export async function verifySession(): Promise<CommandResponse<AuthState>> {
   return await invoke("verify_session");
 }
 
+export async function getApiConfig(): Promise<ApiConfig> {
+  return await invoke("get_api_config");
+}
+
+export async function setApiConfig(
+  environment: ApiEnvironment,
+  apiUrl?: string,
+  shop?: string
+): Promise<CommandResponse<ApiConfig>> {
+  return await invoke("set_api_config", { environment, apiUrl, shop });
+}
+
 export async function getLicencias(): Promise<CommandResponse<LicenciaProducto[]>> {
   return await invoke("get_licencias");
 }
@@ -60,6 +89,10 @@ export async function getPrinters(): Promise<CommandResponse<string[]>> {
   return await invoke("get_printers");
 }
 
+export async function getDefaultPrinter(documentType?: string): Promise<CommandResponse<DefaultPrinter>> {
+  return await invoke("get_default_printer", { documentType: documentType ?? null });
+}
+
 export async function printPdf(filePath: string, printerName: string): Promise<CommandResponse<string>> {
   return await invoke("print_pdf", { filePath, printerName });
 }
@@ -108,6 +141,11 @@ export async function getLogs(): Promise<LogEntry[]> {
   return await invoke("get_logs");
 }
 
+/** Save a diagnostics zip (logs, settings, printers) and return its path */
+export async function exportDiagnostics(): Promise<CommandResponse<string>> {
+  return await invoke("export_diagnostics");
+}
+
 export async function validateDomain(origin: string): Promise<boolean> {
   return await invoke("validate_domain", { origin });
 }
@@ -128,3 +166,119 @@ export async function addNetworkPrinter(printer: NetworkPrinter): Promise<Comman
 export async function removeNetworkPrinter(printerName: string): Promise<CommandResponse<string>> {
   return await invoke("remove_network_printer", { printerName });
 }
+
+export async function probePrinterAddress(address: string): Promise<CommandResponse<ProtocolProbe[]>> {
+  return await invoke("probe_printer_address", { address });
+}
+
+export async function addPrinterByAddress(
+  address: string,
+  name: string,
+  protocol?: string
+): Promise<CommandResponse<NetworkPrinter>> {
+  return await invoke("add_printer_by_address", { address, name, protocol });
+}
+
+export async function getPrinterHealth(): Promise<CommandResponse<Record<string, PrinterHealth>>> {
+  return await invoke("get_printer_health");
+}
+
+export async function getHttpServerStatus(): Promise<CommandResponse<HttpServerStatus>> {
+  return await invoke("get_http_server_status");
+}
+
+export async function exportHttpsCa(): Promise<CommandResponse<string>> {
+  return await invoke("export_https_ca");
+}
+
+export async function getSettings(): Promise<Settings> {
+  return await invoke("get_settings");
+}
+
+export async function updateSettings(settings: Settings): Promise<CommandResponse<Settings>> {
+  return await invoke("update_settings", { settings });
+}
+
+export async function savePrintProfile(profile: PrintProfile): Promise<CommandResponse<Settings>> {
+  return await invoke("save_print_profile", { profile });
+}
+
+export async function deletePrintProfile(name: string): Promise<CommandResponse<Settings>> {
+  return await invoke("delete_print_profile", { name });
+}
+
+export async function setDefaultPrinter(
+  documentType: string | null,
+  printerName: string | null
+): Promise<CommandResponse<Settings>> {
+  return await invoke("set_default_printer", { documentType, printerName });
+}
+
+export async function saveAllowedOrigin(
+  pattern: string,
+  permissions: OriginPermission[]
+): Promise<CommandResponse<Settings>> {
+  return await invoke("save_allowed_origin", { pattern, permissions });
+}
+
+export async function removeAllowedOrigin(pattern: string): Promise<CommandResponse<Settings>> {
+  return await invoke("remove_allowed_origin", { pattern });
+}
+
+export async function getPendingOrigins(): Promise<CommandResponse<PendingOrigin[]>> {
+  return await invoke("get_pending_origins");
+}
+
+export async function resolveOriginApproval(
+  origin: string,
+  choice: ApprovalChoice
+): Promise<CommandResponse<Settings>> {
+  return await invoke("resolve_origin_approval", { origin, choice });
+}
+
+export async function getApiKey(): Promise<CommandResponse<string>> {
+  return await invoke("get_api_key");
+}
+
+export async function regenerateApiKey(): Promise<CommandResponse<string>> {
+  return await invoke("regenerate_api_key");
+}
+
+export async function getJobHistory(query?: HistoryQuery): Promise<CommandResponse<HistoryPage>> {
+  return await invoke("get_job_history", { query: query ?? null });
+}
+
+export async function exportJobHistory(
+  format: "csv" | "json",
+  query?: HistoryQuery
+): Promise<CommandResponse<string>> {
+  return await invoke("export_job_history", { query: query ?? null, format });
+}
+
+export async function getSpooledJobs(): Promise<CommandResponse<SpooledJob[]>> {
+  return await invoke("get_spooled_jobs");
+}
+
+export async function retrySpooledJob(id: string): Promise<CommandResponse<string>> {
+  return await invoke("retry_spooled_job", { id });
+}
+
+export async function rerouteSpooledJob(id: string, printerName: string): Promise<CommandResponse<SpooledJob>> {
+  return await invoke("reroute_spooled_job", { id, printerName });
+}
+
+export async function discardSpooledJob(id: string): Promise<CommandResponse<SpooledJob>> {
+  return await invoke("discard_spooled_job", { id });
+}
+
+export async function getDeviceInfo(): Promise<CommandResponse<DeviceInfo>> {
+  return await invoke("get_device_info");
+}
+
+export async function getLicenseConfig(): Promise<LicenseConfig> {
+  return await invoke("get_license_config");
+}
+
+export async function getQuotaStatus(): Promise<CommandResponse<QuotaStatus>> {
+  return await invoke("get_quota_status");
+}
@@ -1,10 +1,10 @@
 import { useState, useEffect } from 'react';
 import { motion } from 'framer-motion';
 import { useTranslation } from 'react-i18next';
-import { getLicencias } from '../api';
+import { getLicencias, getHttpServerStatus, exportHttpsCa, getApiKey, regenerateApiKey } from '../api';
 import { SimpleIcon } from './LordIcon';
 import { AnimatedLogo } from './AnimatedLogo';
-import type { AuthState, LicenciaProducto } from '../types';
+import type { AuthState, HttpServerStatus, LicenciaProducto } from '../types';
 import './AccountTab.css';
 
 interface AccountTabProps {
@@ -15,6 +15,10 @@ export function AccountTab({ authState }: AccountTabProps) {
   const { t } = useTranslation();
   const [licencias, setLicencias] = useState<LicenciaProducto[]>([]);
   const [isLoading, setIsLoading] = useState(true);
+  const [server, setServer] = useState<HttpServerStatus | null>(null);
+  const [caMessage, setCaMessage] = useState<string | null>(null);
+  const [apiKey, setApiKey] = useState<string | null>(null);
+  const [showApiKey, setShowApiKey] = useState(false);
 
   useEffect(() => {
     const loadLicencias = async () => {
@@ -30,8 +34,48 @@ export function AccountTab({ authState }: AccountTabProps) {
       }
     };
     loadLicencias();
+
+    getHttpServerStatus()
+      .then((response) => {
+        if (response.success && response.data) {
+          setServer(response.data);
+        }
+      })
+      .catch(() => {});
+
+    getApiKey()
+      .then((response) => {
+        if (response.success && response.data) {
+          setApiKey(response.data);
+        }
+      })
+      .catch(() => {});
   }, []);
 
+  const handleRegenerateApiKey = async () => {
+    if (!window.confirm(t('account.apiKeyRegenerateConfirm'))) return;
+    try {
+      const response = await regenerateApiKey();
+      if (response.success && response.data) {
+        setApiKey(response.data);
+      }
+    } catch (err) {}
+  };
+
+  const handleExportCa = async () => {
+    try {
+      const response = await exportHttpsCa();
+      setCaMessage(response.success && response.data
+        ? t('account.caExported', { path: response.data })
+        : `${t('account.caExportError')}: ${response.error ?? ''}`);
+      if (response.success) {
+        setServer((current) => current && { ...current, ca_reinstall: false });
+      }
+    } catch (err) {
+      setCaMessage(`${t('account.caExportError')}: ${err}`);
+    }
+  };
+
   const getStatusBadge = (licencia: LicenciaProducto) => {
     if (licencia.state !== 'ACTIVADO') {
       return <span className="badge badge-error" style={{ background: 'rgba(248,253,103,0.2)', color: '#F8FD67', padding: '4px 12px', borderRadius: '20px', fontSize: '12px' }}>{t('status.inactive')}</span>;
@@ -196,6 +240,82 @@ export function AccountTab({ authState }: AccountTabProps) {
           </div>
         )}
       </motion.div>
+
+      {/* Local API */}
+      {server && (
+        <motion.div className="card" style={{ background: '#111111', border: `1px solid ${server.listening.length === 0 || server.exposed.length > 0 ? 'rgba(248,253,103,0.4)' : 'rgba(255,255,255,0.1)'}`, borderRadius: '16px', padding: '24px' }} variants={itemVariants}>
+          <div className="card-header" style={{ marginBottom: '16px' }}>
+            <h3 className="card-title" style={{ fontSize: '18px', fontWeight: 600, color: '#ffffff' }}>{t('account.localApi')}</h3>
+          </div>
+          {server.listening.length === 0 ? (
+            <p style={{ color: '#F8FD67', fontSize: '14px', marginBottom: '8px' }}>{t('account.localApiUnavailable')}</p>
+          ) : (
+            <div style={{ display: 'flex', flexDirection: 'column', gap: '8px', marginBottom: '8px' }}>
+              {server.listening.map((address) => (
+                <div key={address} style={{ display: 'flex', alignItems: 'center', gap: '8px', color: '#ffffff', fontSize: '14px' }}>
+                  <span className="status-dot" style={{ width: '8px', height: '8px', background: '#88FCA4', borderRadius: '50%' }} />
+                  <span>http://{address}</span>
+                </div>
+              ))}
+              {server.https && (
+                <div style={{ display: 'flex', alignItems: 'center', gap: '8px', color: '#ffffff', fontSize: '14px' }}>
+                  <span className="status-dot" style={{ width: '8px', height: '8px', background: '#88FCA4', borderRadius: '50%' }} />
+                  <span>https://{server.https}</span>
+                </div>
+              )}
+              {server.fallback && (
+                <p style={{ color: '#F8FD67', fontSize: '13px' }}>{t('account.localApiFallback', { port: server.port })}</p>
+              )}
+            </div>
+          )}
+          {server.exposed.length > 0 && (
+            <p style={{ color: '#F8FD67', fontSize: '13px', marginBottom: '8px' }}>{t('account.localApiExposed', { addresses: server.exposed.join(', ') })}</p>
+          )}
+          {server.errors.map((error) => (
+            <p key={error} style={{ color: 'rgba(255,255,255,0.5)', fontSize: '12px' }}>{error}</p>
+          ))}
+          {server.ca_reinstall && (
+            <p style={{ color: '#F8FD67', fontSize: '13px', marginTop: '16px' }}>{t('account.caReinstall')}</p>
+          )}
+          <div style={{ display: 'flex', alignItems: 'center', gap: '12px', marginTop: '16px' }}>
+            <motion.button
+              onClick={handleExportCa}
+              style={{ padding: '8px 16px', background: 'rgba(136, 252, 164, 0.1)', border: '1px solid rgba(136, 252, 164, 0.3)', borderRadius: '10px', color: '#88FCA4', cursor: 'pointer', fontSize: '13px' }}
+              whileHover={{ scale: 1.02 }}
+              whileTap={{ scale: 0.98 }}
+            >
+              {t('account.exportCa')}
+            </motion.button>
+            {caMessage && <span style={{ color: 'rgba(255,255,255,0.7)', fontSize: '12px' }}>{caMessage}</span>}
+          </div>
+          {apiKey && (
+            <div style={{ marginTop: '16px' }}>
+              <p style={{ color: 'rgba(255,255,255,0.5)', fontSize: '12px', marginBottom: '8px' }}>{t('account.apiKeyHint')}</p>
+              <div style={{ display: 'flex', alignItems: 'center', gap: '12px' }}>
+                <code style={{ flex: 1, minWidth: 0, overflow: 'hidden', textOverflow: 'ellipsis', padding: '8px 12px', background: '#1a1a1a', borderRadius: '8px', color: '#ffffff', fontSize: '12px' }}>
+                  {showApiKey ? apiKey : '•'.repeat(24)}
+                </code>
+                <motion.button
+                  onClick={() => setShowApiKey(!showApiKey)}
+                  style={{ padding: '8px 12px', background: 'transparent', border: '1px solid rgba(255,255,255,0.15)', borderRadius: '10px', color: '#ffffff', cursor: 'pointer', fontSize: '13px' }}
+                  whileHover={{ scale: 1.02 }}
+                  whileTap={{ scale: 0.98 }}
+                >
+                  {showApiKey ? t('account.apiKeyHide') : t('account.apiKeyShow')}
+                </motion.button>
+                <motion.button
+                  onClick={handleRegenerateApiKey}
+                  style={{ padding: '8px 12px', background: 'rgba(248, 253, 103, 0.1)', border: '1px solid rgba(248, 253, 103, 0.3)', borderRadius: '10px', color: '#F8FD67', cursor: 'pointer', fontSize: '13px' }}
+                  whileHover={{ scale: 1.02 }}
+                  whileTap={{ scale: 0.98 }}
+                >
+                  {t('account.apiKeyRegenerate')}
+                </motion.button>
+              </div>
+            </div>
+          )}
+        </motion.div>
+      )}
     </motion.div>
   );
 }
@@ -1,10 +1,10 @@
 import { useState, useEffect } from 'react';
 import { motion, AnimatePresence } from 'framer-motion';
 import { useTranslation } from 'react-i18next';
-import { getLogs, clearPrintJobs } from '../api';
+import { getLogs, clearPrintJobs, exportDiagnostics, getSpooledJobs, retrySpooledJob, rerouteSpooledJob, discardSpooledJob } from '../api';
 import { SimpleIcon } from './LordIcon';
 import { AnimatedLogo } from './AnimatedLogo';
-import type { LogEntry } from '../types';
+import type { LogEntry, SpooledJob } from '../types';
 import './LogsTab.css';
 
 export function LogsTab() {
@@ -12,6 +12,9 @@ export function LogsTab() {
   const [logs, setLogs] = useState<LogEntry[]>([]);
   const [isLoading, setIsLoading] = useState(true);
   const [isClearing, setIsClearing] = useState(false);
+  const [spooledJobs, setSpooledJobs] = useState<SpooledJob[]>([]);
+  const [busyJob, setBusyJob] = useState<string | null>(null);
+  const [isExporting, setIsExporting] = useState(false);
 
   useEffect(() => {
     loadLogs();
@@ -25,6 +28,8 @@ export function LogsTab() {
       if (!isLoading) setIsLoading(false); // Don't show loading on refresh
       const logsList = await getLogs();
       setLogs(logsList.reverse()); // Newest first
+      const spool = await getSpooledJobs();
+      if (spool.success && spool.data) setSpooledJobs(spool.data);
     } catch (err) {
     } finally {
       setIsLoading(false);
@@ -44,6 +49,45 @@ export function LogsTab() {
     }
   };
 
+  const handleExportDiagnostics = async () => {
+    try {
+      setIsExporting(true);
+      const result = await exportDiagnostics();
+      if (result.success && result.data) {
+        alert(t('logs.diagnosticsSaved', { path: result.data }));
+      } else {
+        alert(result.error ?? t('logs.diagnosticsError'));
+      }
+    } catch (err) {
+      alert(t('logs.diagnosticsError'));
+    } finally {
+      setIsExporting(false);
+      loadLogs();
+    }
+  };
+
+  const runJobAction = async (id: string, action: () => Promise<unknown>) => {
+    try {
+      setBusyJob(id);
+      await action();
+    } catch (err) {
+    } finally {
+      setBusyJob(null);
+      loadLogs();
+    }
+  };
+
+  const handleReroute = (job: SpooledJob) => {
+    const printer = prompt(t('spool.reroutePrompt'), job.printer);
+    if (!printer) return;
+    runJobAction(job.id, () => rerouteSpooledJob(job.id, printer));
+  };
+
+  const handleDiscard = (job: SpooledJob) => {
+    if (!confirm(t('spool.discardConfirm'))) return;
+    runJobAction(job.id, () => discardSpooledJob(job.id));
+  };
+
   const getLevelColor = (level: string) => {
     switch (level.toUpperCase()) {
       case 'ERROR':
@@ -107,6 +151,16 @@ export function LogsTab() {
             <SimpleIcon icon="refresh" size={18} color="#94a3b8" />
             {t('logs.refresh')}
           </motion.button>
+          <motion.button
+            className="btn btn-secondary"
+            onClick={handleExportDiagnostics}
+            disabled={isExporting}
+            whileHover={{ scale: 1.02 }}
+            whileTap={{ scale: 0.98 }}
+          >
+            <SimpleIcon icon={isExporting ? 'loading' : 'logs'} size={18} color="#94a3b8" />
+            {t('logs.diagnostics')}
+          </motion.button>
           <motion.button
             className="btn btn-danger"
             onClick={handleClear}
@@ -127,6 +181,47 @@ export function LogsTab() {
         </div>
       </motion.div>
 
+      {/* Retry queue */}
+      {spooledJobs.length > 0 && (
+        <motion.div className="card logs-card spool-card" variants={itemVariants}>
+          <h3 className="spool-title">{t('spool.title')} ({spooledJobs.length})</h3>
+          <div className="logs-list">
+            {spooledJobs.map((job) => (
+              <div key={job.id} className="log-item">
+                <div className="log-level-indicator" style={{ backgroundColor: '#F8FD67' }} />
+                <div className="log-content">
+                  <div className="log-header">
+                    <span className="log-level" style={{ color: '#F8FD67' }}>
+                      {job.printer} · {job.format.toUpperCase()} · {job.copies}x
+                    </span>
+                    <span className="log-time">{new Date(job.created_at * 1000).toLocaleString()}</span>
+                  </div>
+                  <p className="log-message">
+                    {t('spool.attempts', { count: job.attempts })}
+                    {job.last_error ? ` — ${job.last_error}` : ''}
+                  </p>
+                  <div className="spool-actions">
+                    <button
+                      className="btn btn-secondary"
+                      disabled={busyJob === job.id}
+                      onClick={() => runJobAction(job.id, () => retrySpooledJob(job.id))}
+                    >
+                      {t('spool.retry')}
+                    </button>
+                    <button className="btn btn-secondary" disabled={busyJob === job.id} onClick={() => handleReroute(job)}>
+                      {t('spool.reroute')}
+                    </button>
+                    <button className="btn btn-danger" disabled={busyJob === job.id} onClick={() => handleDiscard(job)}>
+                      {t('spool.discard')}
+                    </button>
+                  </div>
+                </div>
+              </div>
+            ))}
+          </div>
+        </motion.div>
+      )}
+
       {/* Logs List */}
       <motion.div className="card logs-card" variants={itemVariants}>
         {isLoading ? (
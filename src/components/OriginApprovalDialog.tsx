@@ -0,0 +1,184 @@
+import { useState, useEffect } from "react";
+import { motion, AnimatePresence } from "framer-motion";
+import { useTranslation } from "react-i18next";
+import { getPendingOrigins, resolveOriginApproval, isTauri } from "../api";
+import type { ApprovalChoice, PendingOrigin } from "../types";
+
+// Asks the user whether an unknown web origin may use the local API
+export function OriginApprovalDialog() {
+    const { t } = useTranslation();
+    const [pending, setPending] = useState<PendingOrigin[]>([]);
+    const [busy, setBusy] = useState(false);
+    const [error, setError] = useState<string | null>(null);
+
+    useEffect(() => {
+        if (!isTauri()) return;
+
+        let unlistenFn: (() => void) | null = null;
+
+        const load = async () => {
+            try {
+                const result = await getPendingOrigins();
+                if (result.success && result.data) {
+                    setPending(result.data);
+                }
+            } catch (err) {}
+        };
+
+        const setupListener = async () => {
+            try {
+                const { listen } = await import("@tauri-apps/api/event");
+                unlistenFn = await listen<PendingOrigin>(
+                    "origin-approval-requested",
+                    (event) => {
+                        setPending((current) =>
+                            current.some((p) => p.origin === event.payload.origin)
+                                ? current
+                                : [...current, event.payload]
+                        );
+                    }
+                );
+            } catch (err) {}
+        };
+
+        load();
+        setupListener();
+
+        return () => {
+            if (unlistenFn) unlistenFn();
+        };
+    }, []);
+
+    const current = pending[0];
+
+    const handleChoice = async (choice: ApprovalChoice) => {
+        if (!current) return;
+        setBusy(true);
+        setError(null);
+        try {
+            const result = await resolveOriginApproval(current.origin, choice);
+            // The request may have timed out already: drop it either way
+            setPending((list) => list.filter((p) => p.origin !== current.origin));
+            if (!result.success && result.error) {
+                setError(result.error);
+            }
+        } catch (err) {
+            setError(String(err));
+        } finally {
+            setBusy(false);
+        }
+    };
+
+    const buttonStyle = (color: string, filled: boolean) => ({
+        padding: "10px 16px",
+        borderRadius: "10px",
+        border: `1px solid ${color}`,
+        background: filled ? color : "transparent",
+        color: filled ? "#0a0a0a" : color,
+        cursor: busy ? "wait" : "pointer",
+        fontSize: "13px",
+        fontWeight: 600,
+    });
+
+    return (
+        <AnimatePresence>
+            {current && (
+                <motion.div
+                    key="origin-approval"
+                    initial={{ opacity: 0 }}
+                    animate={{ opacity: 1 }}
+                    exit={{ opacity: 0 }}
+                    style={{
+                        position: "fixed",
+                        inset: 0,
+                        background: "rgba(0,0,0,0.7)",
+                        display: "flex",
+                        alignItems: "center",
+                        justifyContent: "center",
+                        zIndex: 1000,
+                    }}
+                >
+                    <motion.div
+                        initial={{ scale: 0.95, y: 10 }}
+                        animate={{ scale: 1, y: 0 }}
+                        style={{
+                            width: "420px",
+                            maxWidth: "90vw",
+                            background: "#111111",
+                            border: "1px solid rgba(255,255,255,0.1)",
+                            borderRadius: "16px",
+                            padding: "24px",
+                            color: "#ffffff",
+                        }}
+                    >
+                        <h3 style={{ margin: "0 0 12px", fontSize: "18px" }}>
+                            {t("approval.title", { origin: current.origin })}
+                        </h3>
+                        <p
+                            style={{
+                                margin: "0 0 8px",
+                                fontSize: "13px",
+                                color: "rgba(255,255,255,0.7)",
+                            }}
+                        >
+                            {t("approval.description")}
+                        </p>
+                        <p
+                            style={{
+                                margin: "0 0 20px",
+                                fontSize: "12px",
+                                color: "rgba(255,255,255,0.5)",
+                            }}
+                        >
+                            {t("approval.permissions")}:{" "}
+                            {current.permissions
+                                .map((p) => t(`approval.permission.${p}`))
+                                .join(", ")}
+                        </p>
+                        {error && (
+                            <p
+                                style={{
+                                    margin: "0 0 12px",
+                                    fontSize: "12px",
+                                    color: "#F8FD67",
+                                }}
+                            >
+                                {error}
+                            </p>
+                        )}
+                        <div
+                            style={{
+                                display: "flex",
+                                gap: "8px",
+                                justifyContent: "flex-end",
+                                flexWrap: "wrap",
+                            }}
+                        >
+                            <button
+                                style={buttonStyle("#F8FD67", false)}
+                                disabled={busy}
+                                onClick={() => handleChoice("deny")}
+                            >
+                                {t("approval.deny")}
+                            </button>
+                            <button
+                                style={buttonStyle("#88FCA4", false)}
+                                disabled={busy}
+                                onClick={() => handleChoice("allow_once")}
+                            >
+                                {t("approval.allowOnce")}
+                            </button>
+                            <button
+                                style={buttonStyle("#88FCA4", true)}
+                                disabled={busy}
+                                onClick={() => handleChoice("allow_always")}
+                            >
+                                {t("approval.allowAlways")}
+                            </button>
+                        </div>
+                    </motion.div>
+                </motion.div>
+            )}
+        </AnimatePresence>
+    );
+}
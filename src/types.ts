@@ -7,6 +7,14 @@ export interface AuthState {
   is_logged_in: boolean;
 }
 
+export type ApiEnvironment = 'sandbox' | 'production' | 'custom';
+
+export interface ApiConfig {
+  environment: ApiEnvironment;
+  api_url: string;
+  shop: string;
+}
+
 export interface LicenciaProducto {
   _id: string;
   tipo_producto: string;
@@ -29,6 +37,7 @@ export interface LogEntry {
   timestamp: string;
   level: string;
   message: string;
+  request_id?: string;
 }
 
 export interface CommandResponse<T> {
@@ -51,4 +60,221 @@ export interface NetworkPrinter {
   protocol: string;
   name: string;
   is_online: boolean;
+  path?: string | null;
+}
+
+export interface ProtocolProbe extends NetworkPrinter {
+  uri: string;
+  validated: boolean;
+  make_and_model: string | null;
+  error: string | null;
+}
+
+export type HealthStatus = 'unknown' | 'online' | 'offline' | 'error';
+
+export interface HealthEvent {
+  timestamp: string;
+  status: HealthStatus;
+  message: string | null;
+}
+
+export interface PrinterHealth {
+  status: HealthStatus;
+  message: string | null;
+  last_checked: string | null;
+  history: HealthEvent[];
+}
+
+export interface PrinterHealthEvent {
+  printer: string;
+  previous: HealthStatus;
+  status: HealthStatus;
+  message: string | null;
+}
+
+export interface QuotaStatus {
+  license_id: string;
+  period_end: string;
+  used: number;
+  limit: number;
+  remaining: number;
+  pending_sync: number;
+}
+
+export interface FeatureToggles {
+  raw_printing: boolean;
+  network_scan: boolean;
+}
+
+export interface PrinterProfile {
+  name: string;
+  printer: string | null;
+  preset: string;
+  width_mm: number | null;
+  height_mm: number | null;
+}
+
+export interface LicenseConfig {
+  allowed_origins: string[];
+  default_printer: string | null;
+  printer_profiles: PrinterProfile[];
+  max_copies: number | null;
+  allowed_endpoints: string[] | null;
+  features: FeatureToggles;
+}
+
+export interface DeviceInfo {
+  dispositivoId: string;
+  nombre: string;
+  sistemaOperativo: string;
+  version: string;
+  impresoras: string[];
+}
+
+export type CutMode = "auto" | "always" | "never";
+export type OutputMode = "cups" | "raw";
+
+export interface PrintProfile {
+  name: string;
+  printer: string | null;
+  preset: string;
+  width_mm: number | null;
+  height_mm: number | null;
+  code_page: string | null;
+  cut: CutMode;
+  mode: OutputMode;
+  dpi: number | null;
+}
+
+export interface TimeWindow {
+  from: string;
+  to: string;
+}
+
+export interface RoutingRule {
+  name: string;
+  enabled: boolean;
+  origin: string | null;
+  document_type: string | null;
+  time: TimeWindow | null;
+  printer: string | null;
+  profile: string | null;
+  copies: number | null;
+}
+
+export type PoolStrategy = "round_robin" | "least_busy";
+
+export interface PrinterPool {
+  name: string;
+  members: string[];
+  strategy: PoolStrategy;
+}
+
+export interface HttpListener {
+  host: string;
+  port: number;
+}
+
+export interface HttpSettings {
+  host: string;
+  port: number;
+  fallback_port: number | null;
+  listeners: HttpListener[];
+  https_port: number | null;
+}
+
+export interface HttpServerStatus {
+  listening: string[];
+  port: number | null;
+  fallback: boolean;
+  https: string | null;
+  ca_reinstall: boolean;
+  exposed: string[];
+  errors: string[];
+}
+
+export type OriginPermission = "print" | "list_printers" | "clear_jobs" | "configure";
+
+export interface AllowedOrigin {
+  pattern: string;
+  permissions: OriginPermission[];
+}
+
+export type ApprovalChoice = "allow_once" | "allow_always" | "deny";
+
+export interface PendingOrigin {
+  origin: string;
+  permissions: OriginPermission[];
+  requested_at: string;
+}
+
+export interface Settings {
+  version: number;
+  profiles: PrintProfile[];
+  default_printers: Record<string, string>;
+  routing_rules: RoutingRule[];
+  printer_pools: PrinterPool[];
+  http: HttpSettings;
+  allowed_origins: AllowedOrigin[];
+  denied_origins: string[];
+  language: string;
+}
+
+export type PrinterSource = "requested" | "settings" | "license" | "system" | "first_available";
+
+export interface DefaultPrinter {
+  name: string;
+  source: PrinterSource;
+}
+
+export type SpoolFormat = "pdf" | "raw";
+
+export interface SpooledJob {
+  id: string;
+  created_at: number;
+  printer: string;
+  format: SpoolFormat;
+  copies: number;
+  media: string | null;
+  cut: boolean;
+  origin: string | null;
+  document_type: string | null;
+  attempts: number;
+  last_error: string | null;
+  next_attempt_at: number;
+}
+
+export type JobSource = "app" | "http" | "remote" | "spool";
+export type JobResult = "printed" | "failed" | "queued" | "discarded";
+
+export interface JobRecord {
+  id: string;
+  timestamp: string;
+  source: JobSource;
+  origin: string | null;
+  printer: string;
+  document_type: string | null;
+  format: string;
+  pages: number | null;
+  copies: number;
+  size_bytes: number;
+  duration_ms: number;
+  result: JobResult;
+  error: string | null;
+}
+
+export interface HistoryQuery {
+  from?: string;
+  to?: string;
+  printer?: string;
+  result?: JobResult;
+  page?: number;
+  page_size?: number;
+}
+
+export interface HistoryPage {
+  total: number;
+  page: number;
+  page_size: number;
+  jobs: JobRecord[];
 }